@@ -1,8 +1,22 @@
 use std::env;
+use std::path::PathBuf;
+
+/// Where objects are stored (`STORAGE_BACKEND`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageKind {
+    /// An S3 bucket (or MinIO) — the default.
+    S3,
+    /// A directory on this machine, with the server serving its own signed URLs.
+    Local,
+    /// Like `Local`, but in memory and gone on exit: for tests and client development.
+    Memory,
+}
 
 #[derive(Debug, Clone)]
 pub struct AppConfig {
     pub port: u16,
+    pub storage_backend: StorageKind,
+    /// Required for the S3 backend only.
     pub s3_bucket: String,
     pub api_key: String,
     pub aws_region: String,
@@ -12,16 +26,43 @@ pub struct AppConfig {
     pub s3_force_path_style: bool,
     /// Public endpoint URL for presigned URLs (e.g. "http://localhost:9000")
     pub s3_public_endpoint_url: Option<String>,
+    /// Root directory of the local backend (required when it is selected)
+    pub local_storage_dir: Option<PathBuf>,
+    /// URL clients reach this server at, for the local and memory backends' signed URLs
+    /// (default "http://localhost:{port}")
+    pub public_url: Option<String>,
+    /// Multipart uploads older than this are aborted, parts and all (default 24)
+    pub multipart_max_age_hours: u64,
 }
 
 impl AppConfig {
+    /// Base URL for signed URLs the server serves itself: `PUBLIC_URL`, or localhost.
+    pub fn public_base_url(&self) -> String {
+        self.public_url
+            .clone()
+            .unwrap_or_else(|| format!("http://localhost:{}", self.port))
+    }
+
     pub fn from_env() -> Self {
+        let storage_backend = match env::var("STORAGE_BACKEND").as_deref() {
+            Ok("local") => StorageKind::Local,
+            Ok("memory") => StorageKind::Memory,
+            Ok("s3") | Err(_) => StorageKind::S3,
+            Ok(other) => {
+                panic!("STORAGE_BACKEND must be \"s3\", \"local\" or \"memory\", got {other:?}")
+            }
+        };
+        let s3_bucket = match storage_backend {
+            StorageKind::S3 => env::var("S3_BUCKET").expect("S3_BUCKET must be set"),
+            StorageKind::Local | StorageKind::Memory => env::var("S3_BUCKET").unwrap_or_default(),
+        };
         Self {
             port: env::var("PORT")
                 .ok()
                 .and_then(|p| p.parse().ok())
                 .unwrap_or(3000),
-            s3_bucket: env::var("S3_BUCKET").expect("S3_BUCKET must be set"),
+            storage_backend,
+            s3_bucket,
             api_key: env::var("API_KEY").expect("API_KEY must be set"),
             aws_region: env::var("AWS_REGION").unwrap_or_else(|_| "ap-northeast-1".into()),
             s3_endpoint_url: env::var("S3_ENDPOINT_URL").ok(),
@@ -29,6 +70,12 @@ impl AppConfig {
                 .map(|v| v == "true" || v == "1")
                 .unwrap_or(false),
             s3_public_endpoint_url: env::var("S3_PUBLIC_ENDPOINT_URL").ok(),
+            local_storage_dir: env::var_os("LOCAL_STORAGE_DIR").map(PathBuf::from),
+            public_url: env::var("PUBLIC_URL").ok(),
+            multipart_max_age_hours: env::var("MULTIPART_MAX_AGE_HOURS")
+                .ok()
+                .and_then(|h| h.parse().ok())
+                .unwrap_or(24),
         }
     }
 }
@@ -3,3 +3,5 @@ pub mod error;
 pub mod middleware;
 pub mod routes;
 pub mod s3_client;
+pub mod storage;
+pub mod testing;
@@ -1,14 +1,14 @@
 use std::net::SocketAddr;
+use std::time::Duration;
 
 use axum::Router;
 use tower_http::trace::TraceLayer;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-mod config;
-mod error;
-mod middleware;
-mod routes;
-mod s3_client;
+use solidrop_api_server::{config, routes, storage};
+
+/// How often to look for multipart uploads to abort.
+const STALE_UPLOAD_CHECK_INTERVAL: Duration = Duration::from_secs(3600);
 
 #[tokio::main]
 async fn main() {
@@ -21,13 +21,27 @@ async fn main() {
         .init();
 
     let config = config::AppConfig::from_env();
-    tracing::debug!(bucket = %config.s3_bucket, has_api_key = !config.api_key.is_empty(), "loaded app config");
-    let s3 = s3_client::create_s3_client(&config).await;
+    tracing::debug!(backend = ?config.storage_backend, bucket = %config.s3_bucket, has_api_key = !config.api_key.is_empty(), "loaded app config");
     let state = routes::AppState {
-        s3,
+        storage: storage::from_config(&config).await,
         config: config.clone(),
     };
 
+    // Abort multipart uploads that clients gave up on.
+    let storage = state.storage.clone();
+    let max_age = Duration::from_secs(config.multipart_max_age_hours * 3600);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(STALE_UPLOAD_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            match storage::abort_stale_uploads(&*storage, max_age).await {
+                Ok(0) => {}
+                Ok(aborted) => tracing::info!("aborted {aborted} stale multipart uploads"),
+                Err(err) => tracing::warn!("cleaning up stale multipart uploads failed: {err}"),
+            }
+        }
+    });
+
     let app = Router::new()
         .merge(routes::router_with_auth(state.clone()))
         .layer(TraceLayer::new_for_http())
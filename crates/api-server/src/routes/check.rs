@@ -0,0 +1,63 @@
+use axum::{extract::State, routing::post, Json, Router};
+use serde::{Deserialize, Serialize};
+use solidrop_crypto::hash::ContentFingerprint;
+
+use super::AppState;
+use crate::error::AppError;
+use crate::storage::{content_index, StorageError};
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/api/v1/files/check", post(check_file))
+}
+
+#[derive(Deserialize)]
+struct CheckRequest {
+    /// Where the client means to upload.
+    path: String,
+    /// Keyed fingerprint of the plaintext, as sent to `/presign/upload`.
+    content_hash: String,
+}
+
+/// Whether an upload is needed.
+#[derive(Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+enum CheckResponse {
+    /// `path` already holds this content.
+    AlreadyUploaded,
+    /// Another object holds this content.
+    ExistsAtOtherPath {
+        existing_path: String,
+    },
+    NeedsUpload,
+}
+
+async fn check_file(
+    State(state): State<AppState>,
+    Json(body): Json<CheckRequest>,
+) -> Result<Json<CheckResponse>, AppError> {
+    if body.path.is_empty() {
+        return Err(AppError::BadRequest("path must not be empty".into()));
+    }
+    if ContentFingerprint::parse(&body.content_hash).is_none() {
+        return Err(AppError::BadRequest(
+            "content_hash must be a keyed fingerprint (hmac-sha256:<64 hex chars>)".into(),
+        ));
+    }
+
+    match state.storage.head(&body.path).await {
+        Ok(head) if head.metadata.get("content-hash") == Some(&body.content_hash) => {
+            return Ok(Json(CheckResponse::AlreadyUploaded));
+        }
+        Ok(_) | Err(StorageError::NotFound(_)) => {}
+        Err(err) => return Err(err.into()),
+    }
+
+    // `path` itself only comes back if it was uploaded since the HEAD above.
+    let found = content_index::lookup(&*state.storage, &body.content_hash)
+        .await?
+        .filter(|existing_path| *existing_path != body.path);
+    Ok(Json(match found {
+        Some(existing_path) => CheckResponse::ExistsAtOtherPath { existing_path },
+        None => CheckResponse::NeedsUpload,
+    }))
+}
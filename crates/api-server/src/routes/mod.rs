@@ -1,43 +1,74 @@
-use aws_sdk_s3::Client;
+use std::sync::Arc;
+
 use axum::{middleware::from_fn_with_state, Router};
 
 use crate::config::AppConfig;
+use crate::error::AppError;
 use crate::middleware::require_auth;
+use crate::storage::{content_index, StorageBackend};
 
 pub mod cache;
+pub mod check;
 pub mod delete;
 pub mod file_move;
 pub mod files;
 mod health;
+pub mod multipart;
 pub mod presign;
+pub mod versions;
 
 #[derive(Clone)]
 pub struct AppState {
-    pub s3: Client,
+    pub storage: Arc<dyn StorageBackend>,
     pub config: AppConfig,
 }
 
+/// Refuse a client write or delete under the content index's prefix.
+fn reject_reserved(path: &str) -> Result<(), AppError> {
+    if content_index::is_reserved(path) {
+        return Err(AppError::BadRequest(format!(
+            "paths under {} are reserved",
+            content_index::RESERVED_PREFIX
+        )));
+    }
+    Ok(())
+}
+
 /// Router without auth — used by integration tests that need to test auth behavior.
-pub fn router() -> Router<AppState> {
+pub fn router(state: &AppState) -> Router<AppState> {
     let authenticated = Router::new()
         .merge(presign::router())
         .merge(files::router())
+        .merge(check::router())
+        .merge(multipart::router())
         .merge(delete::router())
         .merge(file_move::router())
+        .merge(versions::router())
         .merge(cache::router());
 
-    Router::new().merge(health::router()).merge(authenticated)
+    Router::new()
+        .merge(health::router())
+        .merge(state.storage.clone().routes())
+        .merge(authenticated)
 }
 
 /// Build the router with auth middleware applied to all endpoints except health.
+/// The storage backend's own routes carry their own signatures and stay outside auth too.
 pub fn router_with_auth(state: AppState) -> Router<AppState> {
+    let storage = state.storage.clone().routes();
     let authenticated = Router::new()
         .merge(presign::router())
         .merge(files::router())
+        .merge(check::router())
+        .merge(multipart::router())
         .merge(delete::router())
         .merge(file_move::router())
+        .merge(versions::router())
         .merge(cache::router())
         .route_layer(from_fn_with_state(state, require_auth));
 
-    Router::new().merge(health::router()).merge(authenticated)
+    Router::new()
+        .merge(health::router())
+        .merge(storage)
+        .merge(authenticated)
 }
@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+
+use axum::{extract::State, routing::post, Json, Router};
+use serde::{Deserialize, Serialize};
+
+use super::presign::{upload_metadata, URL_EXPIRY};
+use super::AppState;
+use crate::error::AppError;
+use crate::storage::content_index;
+
+/// Part numbers run from 1 to this, as in S3.
+const MAX_PARTS: i32 = 10_000;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/api/v1/multipart/initiate", post(initiate))
+        .route("/api/v1/multipart/presign-part", post(presign_part))
+        .route("/api/v1/multipart/list-parts", post(list_parts))
+        .route("/api/v1/multipart/complete", post(complete))
+        .route("/api/v1/multipart/abort", post(abort))
+}
+
+#[derive(Deserialize)]
+struct InitiateRequest {
+    path: String,
+    /// Keyed fingerprint of the plaintext, as for `/presign/upload`.
+    content_hash: String,
+    size_bytes: u64,
+}
+
+#[derive(Serialize)]
+struct InitiateResponse {
+    upload_id: String,
+}
+
+#[derive(Deserialize)]
+struct PresignPartRequest {
+    path: String,
+    upload_id: String,
+    part_number: i32,
+}
+
+#[derive(Serialize)]
+struct PresignPartResponse {
+    upload_url: String,
+}
+
+/// Names an upload, for `/list-parts` and `/abort`.
+#[derive(Deserialize)]
+struct UploadRequest {
+    path: String,
+    upload_id: String,
+}
+
+#[derive(Serialize)]
+struct PartEntry {
+    part_number: i32,
+    size: i64,
+}
+
+#[derive(Serialize)]
+struct ListPartsResponse {
+    parts: Vec<PartEntry>,
+}
+
+#[derive(Deserialize)]
+struct CompleteRequest {
+    path: String,
+    upload_id: String,
+    /// Parts 1 to `part_count` make up the object; each must have been uploaded.
+    part_count: i32,
+}
+
+#[derive(Serialize)]
+struct CompleteResponse {
+    completed: bool,
+}
+
+#[derive(Serialize)]
+struct AbortResponse {
+    aborted: bool,
+}
+
+fn check_part_number(part_number: i32, name: &str) -> Result<(), AppError> {
+    match (1..=MAX_PARTS).contains(&part_number) {
+        true => Ok(()),
+        false => Err(AppError::BadRequest(format!(
+            "{name} must be between 1 and {MAX_PARTS}"
+        ))),
+    }
+}
+
+async fn initiate(
+    State(state): State<AppState>,
+    Json(body): Json<InitiateRequest>,
+) -> Result<Json<InitiateResponse>, AppError> {
+    let metadata = upload_metadata(&body.path, body.content_hash, body.size_bytes)?;
+    let upload_id = state
+        .storage
+        .create_multipart_upload(&body.path, &metadata)
+        .await?;
+    content_index::record(&*state.storage, &metadata["content-hash"], &body.path).await;
+    Ok(Json(InitiateResponse { upload_id }))
+}
+
+async fn presign_part(
+    State(state): State<AppState>,
+    Json(body): Json<PresignPartRequest>,
+) -> Result<Json<PresignPartResponse>, AppError> {
+    check_part_number(body.part_number, "part_number")?;
+    let url = state
+        .storage
+        .presign_upload_part(&body.path, &body.upload_id, body.part_number, URL_EXPIRY)
+        .await?;
+    Ok(Json(PresignPartResponse { upload_url: url }))
+}
+
+async fn list_parts(
+    State(state): State<AppState>,
+    Json(body): Json<UploadRequest>,
+) -> Result<Json<ListPartsResponse>, AppError> {
+    let parts = state
+        .storage
+        .list_parts(&body.path, &body.upload_id)
+        .await?
+        .into_iter()
+        .map(|part| PartEntry {
+            part_number: part.part_number,
+            size: part.size,
+        })
+        .collect();
+    Ok(Json(ListPartsResponse { parts }))
+}
+
+/// Complete with the parts as the backend lists them, so clients need not keep ETags
+/// across an interruption.
+async fn complete(
+    State(state): State<AppState>,
+    Json(body): Json<CompleteRequest>,
+) -> Result<Json<CompleteResponse>, AppError> {
+    check_part_number(body.part_count, "part_count")?;
+    let mut uploaded: HashMap<_, _> = state
+        .storage
+        .list_parts(&body.path, &body.upload_id)
+        .await?
+        .into_iter()
+        .map(|part| (part.part_number, part))
+        .collect();
+    let parts = (1..=body.part_count)
+        .map(|part_number| {
+            uploaded.remove(&part_number).ok_or_else(|| {
+                AppError::BadRequest(format!("part {part_number} has not been uploaded"))
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    state
+        .storage
+        .complete_multipart_upload(&body.path, &body.upload_id, &parts)
+        .await?;
+    Ok(Json(CompleteResponse { completed: true }))
+}
+
+async fn abort(
+    State(state): State<AppState>,
+    Json(body): Json<UploadRequest>,
+) -> Result<Json<AbortResponse>, AppError> {
+    state
+        .storage
+        .abort_multipart_upload(&body.path, &body.upload_id)
+        .await?;
+    Ok(Json(AbortResponse { aborted: true }))
+}
@@ -1,10 +1,14 @@
-use aws_sdk_s3::presigning::PresigningConfig;
 use axum::{extract::State, routing::post, Json, Router};
 use serde::{Deserialize, Serialize};
+use solidrop_crypto::hash::ContentFingerprint;
 use std::time::Duration;
 
 use super::AppState;
 use crate::error::AppError;
+use crate::storage::{content_index, Metadata};
+
+/// How long presigned URLs stay valid.
+pub(crate) const URL_EXPIRY: Duration = Duration::from_secs(3600);
 
 pub fn router() -> Router<AppState> {
     Router::new()
@@ -15,6 +19,7 @@ pub fn router() -> Router<AppState> {
 #[derive(Deserialize)]
 struct UploadRequest {
     path: String,
+    /// Keyed fingerprint of the plaintext (`hmac-sha256:<hex>`), stored as metadata.
     content_hash: String,
     size_bytes: u64,
 }
@@ -27,6 +32,8 @@ struct UploadResponse {
 #[derive(Deserialize)]
 struct DownloadRequest {
     path: String,
+    /// A version other than the current one, from `GET /files/versions`.
+    version_id: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -34,29 +41,40 @@ struct DownloadResponse {
     download_url: String,
 }
 
+/// Check an upload's path and fingerprint, and build the metadata the object is stored
+/// with. Shared with multipart uploads.
+pub(crate) fn upload_metadata(
+    path: &str,
+    content_hash: String,
+    size_bytes: u64,
+) -> Result<Metadata, AppError> {
+    if path.is_empty() {
+        return Err(AppError::BadRequest("path must not be empty".into()));
+    }
+    super::reject_reserved(path)?;
+    // A plain SHA-256 of the plaintext would let anyone with bucket access test for known
+    // files, so only keyed fingerprints are stored.
+    if ContentFingerprint::parse(&content_hash).is_none() {
+        return Err(AppError::BadRequest(
+            "content_hash must be a keyed fingerprint (hmac-sha256:<64 hex chars>)".into(),
+        ));
+    }
+    Ok(Metadata::from([
+        ("content-hash".to_string(), content_hash),
+        ("original-size".to_string(), size_bytes.to_string()),
+    ]))
+}
+
 async fn presign_upload(
     State(state): State<AppState>,
     Json(body): Json<UploadRequest>,
 ) -> Result<Json<UploadResponse>, AppError> {
-    if body.path.is_empty() {
-        return Err(AppError::BadRequest("path must not be empty".into()));
-    }
-
-    let presigning_config = PresigningConfig::expires_in(Duration::from_secs(3600))
-        .map_err(|e| AppError::Internal(e.to_string()))?;
-
-    let presigned = state
-        .s3
-        .put_object()
-        .bucket(&state.config.s3_bucket)
-        .key(&body.path)
-        .metadata("content-hash", &body.content_hash)
-        .metadata("original-size", body.size_bytes.to_string())
-        .presigned(presigning_config)
-        .await
-        .map_err(|e| AppError::Internal(e.to_string()))?;
-
-    let url = maybe_rewrite_url(presigned.uri().to_string(), &state);
+    let metadata = upload_metadata(&body.path, body.content_hash, body.size_bytes)?;
+    let url = state
+        .storage
+        .presign_upload(&body.path, &metadata, URL_EXPIRY)
+        .await?;
+    content_index::record(&*state.storage, &metadata["content-hash"], &body.path).await;
 
     Ok(Json(UploadResponse { upload_url: url }))
 }
@@ -69,36 +87,20 @@ async fn presign_download(
         return Err(AppError::BadRequest("path must not be empty".into()));
     }
 
-    let presigning_config = PresigningConfig::expires_in(Duration::from_secs(3600))
-        .map_err(|e| AppError::Internal(e.to_string()))?;
-
-    let presigned = state
-        .s3
-        .get_object()
-        .bucket(&state.config.s3_bucket)
-        .key(&body.path)
-        .presigned(presigning_config)
-        .await
-        .map_err(|e| AppError::Internal(e.to_string()))?;
-
-    let url = maybe_rewrite_url(presigned.uri().to_string(), &state);
+    let url = match &body.version_id {
+        Some(version_id) => {
+            state
+                .storage
+                .presign_download_version(&body.path, version_id, URL_EXPIRY)
+                .await?
+        }
+        None => {
+            state
+                .storage
+                .presign_download(&body.path, URL_EXPIRY)
+                .await?
+        }
+    };
 
     Ok(Json(DownloadResponse { download_url: url }))
 }
-
-/// If both `s3_public_endpoint_url` and `s3_endpoint_url` are configured,
-/// rewrite the presigned URL so it is accessible from outside the Docker network.
-fn maybe_rewrite_url(url: String, state: &AppState) -> String {
-    if let (Some(public_endpoint), Some(internal_endpoint)) = (
-        &state.config.s3_public_endpoint_url,
-        &state.config.s3_endpoint_url,
-    ) {
-        crate::s3_client::rewrite_presigned_url_for_public_access(
-            &url,
-            internal_endpoint,
-            public_endpoint,
-        )
-    } else {
-        url
-    }
-}
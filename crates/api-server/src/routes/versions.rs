@@ -0,0 +1,122 @@
+use axum::{
+    extract::{Query, State},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+
+use super::AppState;
+use crate::error::AppError;
+use crate::storage::{content_index, StorageError};
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/api/v1/files/versions", get(list_versions))
+        .route("/api/v1/files/restore", post(restore_version))
+}
+
+#[derive(Deserialize)]
+struct VersionsParams {
+    path: String,
+}
+
+#[derive(Serialize)]
+struct VersionEntry {
+    version_id: String,
+    size: i64,
+    last_modified: Option<String>,
+    is_latest: bool,
+    is_delete_marker: bool,
+    /// `None` for delete markers.
+    content_hash: Option<String>,
+}
+
+#[derive(Serialize)]
+struct VersionsResponse {
+    /// Newest first.
+    versions: Vec<VersionEntry>,
+}
+
+#[derive(Deserialize)]
+struct RestoreRequest {
+    path: String,
+    version_id: String,
+}
+
+#[derive(Serialize)]
+struct RestoreResponse {
+    restored: bool,
+}
+
+async fn list_versions(
+    State(state): State<AppState>,
+    Query(params): Query<VersionsParams>,
+) -> Result<Json<VersionsResponse>, AppError> {
+    if params.path.is_empty() {
+        return Err(AppError::BadRequest("path must not be empty".into()));
+    }
+    let versions = state.storage.list_versions(&params.path).await?;
+    if versions.is_empty() {
+        return Err(StorageError::NotFound(params.path).into());
+    }
+
+    // Listings carry no metadata: one HEAD per version, as `GET /files` does per object.
+    let mut entries = Vec::with_capacity(versions.len());
+    for version in versions {
+        let content_hash = match version.is_delete_marker {
+            true => None,
+            false => match state
+                .storage
+                .head_version(&params.path, &version.version_id)
+                .await
+            {
+                Ok(head) => head.metadata.get("content-hash").cloned(),
+                Err(_) => None,
+            },
+        };
+        entries.push(VersionEntry {
+            version_id: version.version_id,
+            size: version.size,
+            last_modified: version.last_modified,
+            is_latest: version.is_latest,
+            is_delete_marker: version.is_delete_marker,
+            content_hash,
+        });
+    }
+
+    Ok(Json(VersionsResponse { versions: entries }))
+}
+
+/// Copy an old version over the current one. The versions in between stay, so a restore
+/// can itself be undone.
+async fn restore_version(
+    State(state): State<AppState>,
+    Json(body): Json<RestoreRequest>,
+) -> Result<Json<RestoreResponse>, AppError> {
+    if body.path.is_empty() {
+        return Err(AppError::BadRequest("path must not be empty".into()));
+    }
+    super::reject_reserved(&body.path)?;
+    let version = state
+        .storage
+        .list_versions(&body.path)
+        .await?
+        .into_iter()
+        .find(|version| version.version_id == body.version_id)
+        .ok_or_else(|| StorageError::NoSuchVersion(body.version_id.clone()))?;
+    if version.is_delete_marker {
+        return Err(AppError::BadRequest(
+            "a delete marker cannot be restored; restore the version before it".into(),
+        ));
+    }
+
+    // Restoring the current version would only add a copy of it.
+    if !version.is_latest {
+        state
+            .storage
+            .restore_version(&body.path, &body.version_id)
+            .await?;
+        content_index::record_object(&*state.storage, &body.path).await;
+    }
+    Ok(Json(RestoreResponse { restored: true }))
+}
@@ -0,0 +1,131 @@
+//! Where content is stored, by fingerprint, so `POST /files/check` can find a copy without
+//! scanning the bucket. Each entry is an empty object at `.solidrop/by-hash/<hex>` whose
+//! `path` metadata names an object stored with that fingerprint.
+//!
+//! Entries are written when an upload is presigned or started, and again when an object
+//! is moved or restored. One may therefore name an object that was never uploaded, was
+//! deleted, or now holds other content; [`lookup`] HEADs the object it names and only
+//! trusts the entry if the object's `content-hash` still matches. Objects stored before
+//! the index existed have no entry.
+
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use solidrop_crypto::hash::{ContentFingerprint, FINGERPRINT_PREFIX};
+
+use super::{Metadata, StorageBackend, StorageError};
+
+/// Keys the server keeps for itself; clients can neither list, write nor delete them.
+pub const RESERVED_PREFIX: &str = ".solidrop/";
+
+const ENTRY_PREFIX: &str = ".solidrop/by-hash/";
+
+/// S3 metadata values must be ASCII, so the path is percent-encoded.
+const PATH_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'/')
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+pub fn is_reserved(key: &str) -> bool {
+    key.starts_with(RESERVED_PREFIX)
+}
+
+/// Key of the entry for `content_hash`, if it is a keyed fingerprint.
+fn entry_key(content_hash: &str) -> Option<String> {
+    let fingerprint = ContentFingerprint::parse(content_hash)?;
+    let hex = fingerprint.as_str().strip_prefix(FINGERPRINT_PREFIX)?;
+    Some(format!("{ENTRY_PREFIX}{}", hex.to_ascii_lowercase()))
+}
+
+/// Point the entry for `content_hash` at `path`. Failures are logged, not returned: an
+/// upload must not fail because the index could not be updated.
+pub async fn record(storage: &dyn StorageBackend, content_hash: &str, path: &str) {
+    let Some(key) = entry_key(content_hash) else {
+        return;
+    };
+    let metadata = Metadata::from([(
+        "path".to_string(),
+        utf8_percent_encode(path, PATH_ENCODE_SET).to_string(),
+    )]);
+    if let Err(err) = storage.put_empty(&key, &metadata).await {
+        tracing::warn!(path, error = %err, "content index: entry not written");
+    }
+}
+
+/// Like [`record`], with the fingerprint `path` is stored with.
+pub async fn record_object(storage: &dyn StorageBackend, path: &str) {
+    match storage.head(path).await {
+        Ok(head) => {
+            if let Some(content_hash) = head.metadata.get("content-hash") {
+                record(storage, content_hash, path).await;
+            }
+        }
+        Err(err) => tracing::warn!(path, error = %err, "content index: entry not written"),
+    }
+}
+
+/// An object stored with `content_hash`, if the index knows of one that still holds it.
+/// At most two HEADs.
+pub async fn lookup(
+    storage: &dyn StorageBackend,
+    content_hash: &str,
+) -> Result<Option<String>, StorageError> {
+    let Some(key) = entry_key(content_hash) else {
+        return Ok(None);
+    };
+    let entry = match storage.head(&key).await {
+        Ok(head) => head,
+        Err(StorageError::NotFound(_)) => return Ok(None),
+        Err(err) => return Err(err),
+    };
+    let Some(path) = entry
+        .metadata
+        .get("path")
+        .and_then(|path| percent_decode_str(path).decode_utf8().ok())
+        .map(|path| path.into_owned())
+    else {
+        return Ok(None);
+    };
+    match storage.head(&path).await {
+        Ok(head) if head.metadata.get("content-hash").map(String::as_str) == Some(content_hash) => {
+            Ok(Some(path))
+        }
+        // Never uploaded, deleted or overwritten since the entry was written.
+        Ok(_) | Err(StorageError::NotFound(_)) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryBackend;
+
+    fn fingerprint(byte: char) -> String {
+        format!("{FINGERPRINT_PREFIX}{}", byte.to_string().repeat(64))
+    }
+
+    #[tokio::test]
+    async fn test_lookup_trusts_only_live_matching_objects() {
+        let storage = MemoryBackend::new("http://localhost");
+        let hash = fingerprint('a');
+        let stored = |hash: &str| Metadata::from([("content-hash".to_string(), hash.to_string())]);
+
+        // Recorded at presign time, never uploaded.
+        record(&storage, &hash, "active/b ü.bin").await;
+        assert_eq!(lookup(&storage, &hash).await.unwrap(), None);
+
+        storage.put("active/b ü.bin", "data", stored(&hash));
+        assert_eq!(
+            lookup(&storage, &hash).await.unwrap().as_deref(),
+            Some("active/b ü.bin")
+        );
+
+        // Overwritten with other content.
+        storage.put("active/b ü.bin", "other", stored(&fingerprint('b')));
+        assert_eq!(lookup(&storage, &hash).await.unwrap(), None);
+
+        assert_eq!(lookup(&storage, &fingerprint('c')).await.unwrap(), None);
+        assert!(is_reserved(&entry_key(&hash).unwrap()));
+    }
+}
@@ -0,0 +1,765 @@
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use axum::body::Body;
+use axum::extract::{Path as UrlPath, Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use http_body_util::BodyExt;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio_util::io::ReaderStream;
+
+use super::signed_url::{is_part_upload, new_upload_id, part_etag, read_response, UrlSigner};
+use super::{
+    format_time, ListPage, Metadata, MultipartUpload, ObjectHead, ObjectInfo, StorageBackend,
+    StorageError, UploadedPart,
+};
+use crate::config::AppConfig;
+use crate::error::AppError;
+use crate::routes::AppState;
+
+fn io_error(context: &'static str, key: &str) -> impl FnOnce(io::Error) -> StorageError {
+    let key = key.to_string();
+    move |err| StorageError::Backend(format!("{context} failed for {key}: {err}"))
+}
+
+/// What `uploads/<id>/upload.json` records about a multipart upload.
+#[derive(Serialize, Deserialize)]
+struct UploadRecord {
+    key: String,
+    metadata: Metadata,
+    /// Seconds since the Unix epoch.
+    initiated: u64,
+}
+
+/// Objects as files under a directory, with the server itself standing in for S3's
+/// presigned URLs: it hands out HMAC-signed, expiring `/storage/<key>` URLs and serves
+/// PUT and GET (with `Range`) on them.
+///
+/// Layout: `objects/<key>` holds the data, `metadata/<key>.json` its metadata, and
+/// uploads are written to `tmp/` first and renamed into place, so readers never see a
+/// partial object. A multipart upload collects its parts in `uploads/<id>/`, as `<n>` with
+/// the part's ETag in `<n>.etag`, beside `upload.json`.
+pub struct LocalBackend {
+    root: PathBuf,
+    signer: UrlSigner,
+}
+
+impl LocalBackend {
+    pub fn new(root: impl Into<PathBuf>, public_url: &str) -> io::Result<Self> {
+        let root = root.into();
+        for dir in ["objects", "metadata", "tmp", "uploads"] {
+            std::fs::create_dir_all(root.join(dir))?;
+        }
+        Ok(Self {
+            root,
+            signer: UrlSigner::new(public_url),
+        })
+    }
+
+    pub fn from_config(config: &AppConfig) -> io::Result<Self> {
+        let root = config
+            .local_storage_dir
+            .as_ref()
+            .expect("LOCAL_STORAGE_DIR must be set for the local backend");
+        Self::new(root, &config.public_base_url())
+    }
+
+    /// Reject keys that would leave `objects/` or that a filesystem cannot hold.
+    fn check_key(key: &str) -> Result<(), StorageError> {
+        let valid = !key.is_empty()
+            && !key.contains('\\')
+            && !key.contains('\0')
+            && key
+                .split('/')
+                .all(|part| !part.is_empty() && part != "." && part != "..");
+        match valid {
+            true => Ok(()),
+            false => Err(StorageError::InvalidKey(key.to_string())),
+        }
+    }
+
+    fn object_path(&self, key: &str) -> Result<PathBuf, StorageError> {
+        Self::check_key(key)?;
+        Ok(self.root.join("objects").join(key))
+    }
+
+    fn metadata_path(&self, key: &str) -> Result<PathBuf, StorageError> {
+        Self::check_key(key)?;
+        Ok(self.root.join("metadata").join(format!("{key}.json")))
+    }
+
+    fn temp_path(&self) -> PathBuf {
+        let mut name = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut name);
+        self.root.join("tmp").join(hex::encode(name))
+    }
+
+    /// Directory of upload `upload_id`; IDs that this backend cannot have issued are unknown.
+    fn upload_dir(&self, upload_id: &str) -> Result<PathBuf, StorageError> {
+        let valid = upload_id.len() == 32 && upload_id.bytes().all(|b| b.is_ascii_hexdigit());
+        match valid {
+            true => Ok(self.root.join("uploads").join(upload_id)),
+            false => Err(StorageError::NoSuchUpload(upload_id.to_string())),
+        }
+    }
+
+    /// The record of upload `upload_id`, which must be to `key`.
+    async fn read_upload(&self, key: &str, upload_id: &str) -> Result<UploadRecord, StorageError> {
+        let path = self.upload_dir(upload_id)?.join("upload.json");
+        let record: UploadRecord = match tokio::fs::read(&path).await {
+            Ok(data) => serde_json::from_slice(&data).map_err(|e| {
+                StorageError::Backend(format!("corrupt upload record {upload_id}: {e}"))
+            })?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Err(StorageError::NoSuchUpload(upload_id.to_string()))
+            }
+            Err(err) => return Err(io_error("reading upload", key)(err)),
+        };
+        match record.key == key {
+            true => Ok(record),
+            false => Err(StorageError::NoSuchUpload(upload_id.to_string())),
+        }
+    }
+
+    /// Write a request body to a new file in `tmp/`; returns its path and SHA-256.
+    async fn receive(&self, key: &str, mut body: Body) -> Result<(PathBuf, [u8; 32]), AppError> {
+        let temp = self.temp_path();
+        let mut hasher = Sha256::new();
+        let written = async {
+            let mut file = tokio::fs::File::create(&temp).await?;
+            while let Some(frame) = body.frame().await {
+                let frame = frame.map_err(io::Error::other)?;
+                if let Ok(data) = frame.into_data() {
+                    hasher.update(&data);
+                    file.write_all(&data).await?;
+                }
+            }
+            file.sync_all().await
+        }
+        .await;
+        if let Err(err) = written {
+            let _ = tokio::fs::remove_file(&temp).await;
+            return Err(io_error("receiving upload", key)(err).into());
+        }
+        Ok((temp, hasher.finalize().into()))
+    }
+
+    fn router<S: Clone + Send + Sync + 'static>(self: Arc<Self>) -> Router<S> {
+        Router::new()
+            .route("/storage/*key", get(get_object).put(put_object))
+            .with_state(self)
+    }
+
+    /// All keys in directory `dir` of `objects` (`""` for all of them, else ending in `/`),
+    /// sorted.
+    fn walk_keys(objects: &Path, dir: &str) -> io::Result<Vec<String>> {
+        fn walk(dir: &Path, prefix: &str, keys: &mut Vec<String>) -> io::Result<()> {
+            for entry in std::fs::read_dir(dir)? {
+                let entry = entry?;
+                let Ok(name) = entry.file_name().into_string() else {
+                    continue;
+                };
+                let file_type = entry.file_type()?;
+                if file_type.is_dir() {
+                    walk(&entry.path(), &format!("{prefix}{name}/"), keys)?;
+                } else if file_type.is_file() {
+                    keys.push(format!("{prefix}{name}"));
+                }
+            }
+            Ok(())
+        }
+
+        let mut keys = Vec::new();
+        let start = objects.join(dir);
+        if start.is_dir() {
+            walk(&start, dir, &mut keys)?;
+        }
+        keys.sort();
+        Ok(keys)
+    }
+
+    async fn read_metadata(&self, key: &str) -> Result<Metadata, StorageError> {
+        match tokio::fs::read(self.metadata_path(key)?).await {
+            Ok(data) => serde_json::from_slice(&data)
+                .map_err(|e| StorageError::Backend(format!("corrupt metadata for {key}: {e}"))),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Metadata::new()),
+            Err(err) => Err(io_error("reading metadata", key)(err)),
+        }
+    }
+
+    async fn write_metadata(&self, key: &str, metadata: &Metadata) -> Result<(), StorageError> {
+        let path = self.metadata_path(key)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(io_error("creating metadata directory", key))?;
+        }
+        let data = serde_json::to_vec(metadata).expect("string maps serialize");
+        tokio::fs::write(&path, data)
+            .await
+            .map_err(io_error("writing metadata", key))
+    }
+
+    /// Move a finished file from `tmp/` to `key`, replacing what was there.
+    async fn commit(&self, temp: &Path, key: &str) -> Result<(), StorageError> {
+        let path = self.object_path(key)?;
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(io_error("creating object directory", key))?;
+        }
+        tokio::fs::rename(temp, &path)
+            .await
+            .map_err(io_error("storing object", key))
+    }
+}
+
+#[async_trait]
+impl StorageBackend for LocalBackend {
+    async fn list(
+        &self,
+        prefix: Option<&str>,
+        limit: i32,
+        next_token: Option<&str>,
+    ) -> Result<ListPage, StorageError> {
+        // Only the directory the prefix ends in can hold matching keys, so a listing of
+        // `active/` does not walk `archived/`. It is still walked once per page.
+        let dir = match prefix.unwrap_or("").rsplit_once('/') {
+            Some((dir, _)) if Self::check_key(dir).is_ok() => format!("{dir}/"),
+            Some(_) => return Ok(ListPage::default()),
+            None => String::new(),
+        };
+        let objects = self.root.join("objects");
+        let keys = tokio::task::spawn_blocking(move || Self::walk_keys(&objects, &dir))
+            .await
+            .map_err(|e| StorageError::Backend(format!("listing failed: {e}")))?
+            .map_err(io_error("listing", prefix.unwrap_or("")))?;
+        let mut matching = keys
+            .into_iter()
+            .filter(|key| key.starts_with(prefix.unwrap_or("")))
+            .filter(|key| next_token.is_none_or(|token| key.as_str() > token));
+
+        let mut objects = Vec::new();
+        for key in matching.by_ref().take(limit.max(1) as usize) {
+            let Ok(info) = tokio::fs::metadata(self.object_path(&key)?).await else {
+                continue; // deleted since the walk
+            };
+            objects.push(ObjectInfo {
+                size: info.len() as i64,
+                last_modified: info.modified().ok().map(format_time),
+                key,
+            });
+        }
+        let next_token = match matching.next() {
+            Some(_) => objects.last().map(|object| object.key.clone()),
+            None => None,
+        };
+        Ok(ListPage {
+            objects,
+            next_token,
+        })
+    }
+
+    async fn head(&self, key: &str) -> Result<ObjectHead, StorageError> {
+        let info = match tokio::fs::metadata(self.object_path(key)?).await {
+            Ok(info) if info.is_file() => info,
+            Ok(_) => return Err(StorageError::NotFound(key.to_string())),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                return Err(StorageError::NotFound(key.to_string()))
+            }
+            Err(err) => return Err(io_error("reading object", key)(err)),
+        };
+        Ok(ObjectHead {
+            size: info.len() as i64,
+            last_modified: info.modified().ok().map(format_time),
+            metadata: self.read_metadata(key).await?,
+        })
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        for path in [self.object_path(key)?, self.metadata_path(key)?] {
+            match tokio::fs::remove_file(&path).await {
+                Ok(()) => {}
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(io_error("deleting", key)(err)),
+            }
+        }
+        Ok(())
+    }
+
+    async fn copy(&self, from: &str, to: &str) -> Result<(), StorageError> {
+        let metadata = self.head(from).await?.metadata;
+        self.object_path(to)?;
+        let temp = self.temp_path();
+        if let Err(err) = tokio::fs::copy(self.object_path(from)?, &temp).await {
+            let _ = tokio::fs::remove_file(&temp).await;
+            return Err(io_error("copying", from)(err));
+        }
+        self.write_metadata(to, &metadata).await?;
+        self.commit(&temp, to).await
+    }
+
+    async fn put_empty(&self, key: &str, metadata: &Metadata) -> Result<(), StorageError> {
+        self.object_path(key)?;
+        let temp = self.temp_path();
+        tokio::fs::write(&temp, b"")
+            .await
+            .map_err(io_error("storing object", key))?;
+        self.write_metadata(key, metadata).await?;
+        self.commit(&temp, key).await
+    }
+
+    async fn presign_upload(
+        &self,
+        key: &str,
+        metadata: &Metadata,
+        expires_in: Duration,
+    ) -> Result<String, StorageError> {
+        Self::check_key(key)?;
+        Ok(self.signer.sign("PUT", key, metadata, expires_in))
+    }
+
+    async fn presign_download(
+        &self,
+        key: &str,
+        expires_in: Duration,
+    ) -> Result<String, StorageError> {
+        Self::check_key(key)?;
+        Ok(self.signer.sign("GET", key, &Metadata::new(), expires_in))
+    }
+
+    async fn create_multipart_upload(
+        &self,
+        key: &str,
+        metadata: &Metadata,
+    ) -> Result<String, StorageError> {
+        Self::check_key(key)?;
+        let upload_id = new_upload_id();
+        let dir = self.upload_dir(&upload_id)?;
+        tokio::fs::create_dir_all(&dir)
+            .await
+            .map_err(io_error("creating upload", key))?;
+        let record = UploadRecord {
+            key: key.to_string(),
+            metadata: metadata.clone(),
+            initiated: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |age| age.as_secs()),
+        };
+        let data = serde_json::to_vec(&record).expect("upload records serialize");
+        tokio::fs::write(dir.join("upload.json"), data)
+            .await
+            .map_err(io_error("creating upload", key))?;
+        Ok(upload_id)
+    }
+
+    async fn presign_upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        part_number: i32,
+        expires_in: Duration,
+    ) -> Result<String, StorageError> {
+        self.read_upload(key, upload_id).await?;
+        Ok(self
+            .signer
+            .sign_part(key, upload_id, part_number, expires_in))
+    }
+
+    async fn list_parts(
+        &self,
+        key: &str,
+        upload_id: &str,
+    ) -> Result<Vec<UploadedPart>, StorageError> {
+        self.read_upload(key, upload_id).await?;
+        let dir = self.upload_dir(upload_id)?;
+        let mut entries = tokio::fs::read_dir(&dir)
+            .await
+            .map_err(io_error("listing parts", key))?;
+        let mut parts = Vec::new();
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .map_err(io_error("listing parts", key))?
+        {
+            let Some(part_number) = entry
+                .file_name()
+                .to_str()
+                .and_then(|name| name.parse::<i32>().ok())
+            else {
+                continue;
+            };
+            let (Ok(info), Ok(etag)) = (
+                entry.metadata().await,
+                tokio::fs::read_to_string(dir.join(format!("{part_number}.etag"))).await,
+            ) else {
+                continue; // being replaced
+            };
+            parts.push(UploadedPart {
+                part_number,
+                size: info.len() as i64,
+                etag,
+            });
+        }
+        parts.sort_by_key(|part| part.part_number);
+        Ok(parts)
+    }
+
+    async fn complete_multipart_upload(
+        &self,
+        key: &str,
+        upload_id: &str,
+        parts: &[UploadedPart],
+    ) -> Result<(), StorageError> {
+        let record = self.read_upload(key, upload_id).await?;
+        let dir = self.upload_dir(upload_id)?;
+        let temp = self.temp_path();
+        let joined = async {
+            let mut file = tokio::fs::File::create(&temp).await?;
+            for part in parts {
+                let mut part =
+                    tokio::fs::File::open(dir.join(part.part_number.to_string())).await?;
+                tokio::io::copy(&mut part, &mut file).await?;
+            }
+            file.sync_all().await
+        }
+        .await;
+        if let Err(err) = joined {
+            let _ = tokio::fs::remove_file(&temp).await;
+            return Err(io_error("joining parts", key)(err));
+        }
+
+        self.write_metadata(key, &record.metadata).await?;
+        self.commit(&temp, key).await?;
+        tokio::fs::remove_dir_all(&dir)
+            .await
+            .map_err(io_error("removing upload", key))
+    }
+
+    async fn abort_multipart_upload(&self, key: &str, upload_id: &str) -> Result<(), StorageError> {
+        self.read_upload(key, upload_id).await?;
+        tokio::fs::remove_dir_all(self.upload_dir(upload_id)?)
+            .await
+            .map_err(io_error("removing upload", key))
+    }
+
+    async fn list_multipart_uploads(&self) -> Result<Vec<MultipartUpload>, StorageError> {
+        let mut entries = tokio::fs::read_dir(self.root.join("uploads"))
+            .await
+            .map_err(io_error("listing uploads", ""))?;
+        let mut uploads = Vec::new();
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .map_err(io_error("listing uploads", ""))?
+        {
+            let Ok(data) = tokio::fs::read(entry.path().join("upload.json")).await else {
+                continue; // just created or removed
+            };
+            let Ok(record) = serde_json::from_slice::<UploadRecord>(&data) else {
+                continue;
+            };
+            uploads.push(MultipartUpload {
+                key: record.key,
+                upload_id: entry.file_name().to_string_lossy().into_owned(),
+                initiated: UNIX_EPOCH + Duration::from_secs(record.initiated),
+            });
+        }
+        Ok(uploads)
+    }
+
+    fn routes(self: Arc<Self>) -> Router<AppState> {
+        self.router()
+    }
+}
+
+/// `PUT /storage/*key` with a signed upload URL: store the body with the signed metadata,
+/// or as a part of a multipart upload.
+async fn put_object(
+    State(backend): State<Arc<LocalBackend>>,
+    UrlPath(key): UrlPath<String>,
+    Query(query): Query<HashMap<String, String>>,
+    body: Body,
+) -> Result<Response, AppError> {
+    if is_part_upload(&query) {
+        let (upload_id, part_number) = backend.signer.verify_part(&key, &query)?;
+        backend.read_upload(&key, &upload_id).await?;
+        let dir = backend.upload_dir(&upload_id)?;
+        let (temp, digest) = backend.receive(&key, body).await?;
+        let etag = part_etag(digest);
+        let stored = async {
+            tokio::fs::write(dir.join(format!("{part_number}.etag")), &etag).await?;
+            tokio::fs::rename(&temp, dir.join(part_number.to_string())).await
+        }
+        .await;
+        if let Err(err) = stored {
+            let _ = tokio::fs::remove_file(&temp).await;
+            return Err(io_error("storing part", &key)(err).into());
+        }
+        return Ok([(header::ETAG, etag)].into_response());
+    }
+
+    let metadata = backend.signer.verify("PUT", &key, &query)?;
+    let (temp, _) = backend.receive(&key, body).await?;
+    backend.write_metadata(&key, &metadata).await?;
+    backend.commit(&temp, &key).await?;
+    Ok(StatusCode::OK.into_response())
+}
+
+/// `GET /storage/*key` with a signed download URL.
+async fn get_object(
+    State(backend): State<Arc<LocalBackend>>,
+    UrlPath(key): UrlPath<String>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    backend.signer.verify("GET", &key, &query)?;
+
+    let path = backend.object_path(&key)?;
+    let mut file = match tokio::fs::File::open(&path).await {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            return Err(StorageError::NotFound(key).into())
+        }
+        Err(err) => return Err(io_error("reading object", &key)(err).into()),
+    };
+    let size = file
+        .metadata()
+        .await
+        .map_err(io_error("reading object", &key))?
+        .len();
+
+    let (start, len, response) = match read_response(&headers, size) {
+        Ok(plan) => plan,
+        Err(unsatisfiable) => return Ok(unsatisfiable.into_response()),
+    };
+    file.seek(io::SeekFrom::Start(start))
+        .await
+        .map_err(io_error("reading object", &key))?;
+    response
+        .body(Body::from_stream(ReaderStream::new(file.take(len))))
+        .map_err(|e| AppError::Internal(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::{header, Request};
+    use hmac::Mac;
+    use tower::ServiceExt;
+
+    const FINGERPRINT: &str =
+        "hmac-sha256:00112233445566778899aabbccddeeff00112233445566778899aabbccddeeff";
+
+    fn backend(dir: &Path) -> Arc<LocalBackend> {
+        Arc::new(LocalBackend::new(dir, "http://localhost:3000/").unwrap())
+    }
+
+    /// Send `method` to the path and query of a presigned `url`.
+    async fn send(
+        backend: &Arc<LocalBackend>,
+        method: &str,
+        url: &str,
+        range: Option<&str>,
+        body: &'static [u8],
+    ) -> Response {
+        let uri = url.strip_prefix("http://localhost:3000").unwrap();
+        let mut request = Request::builder().method(method).uri(uri);
+        if let Some(range) = range {
+            request = request.header(header::RANGE, range);
+        }
+        Arc::clone(backend)
+            .router::<()>()
+            .oneshot(request.body(Body::from(body)).unwrap())
+            .await
+            .unwrap()
+    }
+
+    async fn body(response: Response) -> Vec<u8> {
+        axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap()
+            .to_vec()
+    }
+
+    #[tokio::test]
+    async fn test_signed_upload_and_ranged_download() {
+        let dir = tempfile::tempdir().unwrap();
+        let backend = backend(dir.path());
+        let key = "active/2026-02/my drawing (1).png.enc";
+        let metadata = Metadata::from([
+            ("content-hash".to_string(), FINGERPRINT.to_string()),
+            ("original-size".to_string(), "11".to_string()),
+        ]);
+        let expiry = Duration::from_secs(60);
+
+        let upload = backend
+            .presign_upload(key, &metadata, expiry)
+            .await
+            .unwrap();
+        assert!(upload.starts_with("http://localhost:3000/storage/active/2026-02/my%20drawing"));
+        let response = send(&backend, "PUT", &upload, None, b"hello world").await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let head = backend.head(key).await.unwrap();
+        assert_eq!(head.size, 11);
+        assert_eq!(head.metadata, metadata);
+
+        let download = backend.presign_download(key, expiry).await.unwrap();
+        let response = send(&backend, "GET", &download, None, b"").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body(response).await, b"hello world");
+
+        let response = send(&backend, "GET", &download, Some("bytes=0-4"), b"").await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers()[header::CONTENT_RANGE], "bytes 0-4/11");
+        assert_eq!(body(response).await, b"hello");
+        let response = send(&backend, "GET", &download, Some("bytes=-5"), b"").await;
+        assert_eq!(body(response).await, b"world");
+        let response = send(&backend, "GET", &download, Some("bytes=20-"), b"").await;
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+
+        // The download URL does not allow uploads, and tampered metadata breaks the signature.
+        let response = send(&backend, "PUT", &download, None, b"overwrite").await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let tampered = upload.replace("original-size=11", "original-size=12");
+        let response = send(&backend, "PUT", &tampered, None, b"hello world!").await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        // Correctly signed, but for a moment long past.
+        let signature = hex::encode(
+            backend
+                .signer
+                .mac("GET", key, 1, &Metadata::new())
+                .finalize()
+                .into_bytes(),
+        );
+        let expired = download
+            .split_once('?')
+            .map(|(path, _)| format!("{path}?expires=1&signature={signature}"))
+            .unwrap();
+        let response = send(&backend, "GET", &expired, None, b"").await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_list_copy_delete() {
+        let dir = tempfile::tempdir().unwrap();
+        let backend = backend(dir.path());
+        let expiry = Duration::from_secs(60);
+        for key in [
+            "active/a.enc",
+            "active/b.enc",
+            "active/c.enc",
+            "transfer/d.enc",
+        ] {
+            let url = backend
+                .presign_upload(key, &Metadata::new(), expiry)
+                .await
+                .unwrap();
+            assert_eq!(
+                send(&backend, "PUT", &url, None, b"data").await.status(),
+                StatusCode::OK
+            );
+        }
+
+        let page = backend.list(Some("active/"), 2, None).await.unwrap();
+        let keys: Vec<_> = page.objects.iter().map(|o| o.key.as_str()).collect();
+        assert_eq!(keys, ["active/a.enc", "active/b.enc"]);
+        let token = page.next_token.unwrap();
+        let page = backend
+            .list(Some("active/"), 2, Some(&token))
+            .await
+            .unwrap();
+        assert_eq!(page.objects.len(), 1);
+        assert_eq!(page.objects[0].key, "active/c.enc");
+        assert!(page.next_token.is_none());
+        assert_eq!(page.objects[0].size, 4);
+
+        backend
+            .copy("active/a.enc", "archived/a.enc")
+            .await
+            .unwrap();
+        backend.delete("active/a.enc").await.unwrap();
+        assert!(matches!(
+            backend.head("active/a.enc").await,
+            Err(StorageError::NotFound(_))
+        ));
+        assert_eq!(backend.head("archived/a.enc").await.unwrap().size, 4);
+        assert!(matches!(
+            backend.copy("missing.enc", "x.enc").await,
+            Err(StorageError::NotFound(_))
+        ));
+        for key in ["../escape.enc", "/abs.enc", "a//b.enc", "a/./b.enc", ""] {
+            assert!(matches!(
+                backend.head(key).await,
+                Err(StorageError::InvalidKey(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_multipart_upload() {
+        let dir = tempfile::tempdir().unwrap();
+        let backend = backend(dir.path());
+        let key = "active/2026-02/big file.enc";
+        let metadata = Metadata::from([("content-hash".to_string(), FINGERPRINT.to_string())]);
+        let expiry = Duration::from_secs(60);
+
+        let upload_id = backend
+            .create_multipart_upload(key, &metadata)
+            .await
+            .unwrap();
+        for (part_number, data) in [(2, &b"world"[..]), (1, b"hello "), (2, b"world")] {
+            let url = backend
+                .presign_upload_part(key, &upload_id, part_number, expiry)
+                .await
+                .unwrap();
+            let response = send(&backend, "PUT", &url, None, data).await;
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+        let uploads = backend.list_multipart_uploads().await.unwrap();
+        assert_eq!(uploads.len(), 1);
+        assert_eq!(
+            (uploads[0].key.as_str(), &uploads[0].upload_id),
+            (key, &upload_id)
+        );
+
+        let parts = backend.list_parts(key, &upload_id).await.unwrap();
+        assert_eq!(
+            parts
+                .iter()
+                .map(|p| (p.part_number, p.size))
+                .collect::<Vec<_>>(),
+            [(1, 6), (2, 5)]
+        );
+        backend
+            .complete_multipart_upload(key, &upload_id, &parts)
+            .await
+            .unwrap();
+        let head = backend.head(key).await.unwrap();
+        assert_eq!((head.size, head.metadata), (11, metadata));
+        let download = backend.presign_download(key, expiry).await.unwrap();
+        assert_eq!(
+            body(send(&backend, "GET", &download, None, b"").await).await,
+            b"hello world"
+        );
+        assert!(backend.list_multipart_uploads().await.unwrap().is_empty());
+
+        for upload_id in [upload_id.as_str(), "../../objects"] {
+            assert!(matches!(
+                backend.abort_multipart_upload(key, upload_id).await,
+                Err(StorageError::NoSuchUpload(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,616 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use axum::body::{Body, Bytes};
+use axum::extract::{Path as UrlPath, Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use sha2::{Digest, Sha256};
+
+use super::signed_url::{is_part_upload, new_upload_id, part_etag, read_response, UrlSigner};
+use super::{
+    format_time, ListPage, Metadata, MultipartUpload, ObjectHead, ObjectInfo, ObjectVersion,
+    StorageBackend, StorageError, UploadedPart,
+};
+use crate::config::AppConfig;
+use crate::error::AppError;
+use crate::routes::AppState;
+
+/// One stored version of a key; `data` is `None` for a delete marker.
+struct Version {
+    id: String,
+    data: Option<Bytes>,
+    metadata: Metadata,
+    last_modified: SystemTime,
+}
+
+impl Version {
+    fn size(&self) -> i64 {
+        self.data.as_ref().map_or(0, |data| data.len() as i64)
+    }
+}
+
+/// A multipart upload in progress.
+struct Upload {
+    key: String,
+    metadata: Metadata,
+    initiated: SystemTime,
+    parts: BTreeMap<i32, Bytes>,
+}
+
+/// Objects in memory, behaving like an S3 bucket with versioning enabled: every write adds
+/// a version and deleting adds a delete marker, so nothing is lost until the process
+/// exits. Serves the same signed `/storage/<key>` URLs as [`LocalBackend`](super::LocalBackend).
+///
+/// For hermetic tests, and for running the server with nothing to set up
+/// (`STORAGE_BACKEND=memory`).
+pub struct MemoryBackend {
+    signer: UrlSigner,
+    /// Versions of each key, oldest first.
+    objects: Mutex<BTreeMap<String, Vec<Version>>>,
+    next_version: AtomicU64,
+    /// Multipart uploads in progress, by upload ID.
+    uploads: Mutex<HashMap<String, Upload>>,
+}
+
+impl MemoryBackend {
+    pub fn new(public_url: &str) -> Self {
+        Self {
+            signer: UrlSigner::new(public_url),
+            objects: Mutex::new(BTreeMap::new()),
+            next_version: AtomicU64::new(1),
+            uploads: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn from_config(config: &AppConfig) -> Self {
+        Self::new(&config.public_base_url())
+    }
+
+    fn objects(&self) -> MutexGuard<'_, BTreeMap<String, Vec<Version>>> {
+        self.objects
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Data, metadata and time of version `version_id` of `key`, unless it is a delete
+    /// marker.
+    fn version(
+        &self,
+        key: &str,
+        version_id: &str,
+    ) -> Result<(Bytes, Metadata, SystemTime), StorageError> {
+        let objects = self.objects();
+        objects
+            .get(key)
+            .and_then(|versions| versions.iter().find(|version| version.id == version_id))
+            .and_then(|version| {
+                let data = version.data.clone()?;
+                Some((data, version.metadata.clone(), version.last_modified))
+            })
+            .ok_or_else(|| StorageError::NoSuchVersion(version_id.to_string()))
+    }
+
+    /// Run `f` on upload `upload_id`, which must be to `key`.
+    fn with_upload<T>(
+        &self,
+        key: &str,
+        upload_id: &str,
+        f: impl FnOnce(&mut Upload) -> T,
+    ) -> Result<T, StorageError> {
+        let mut uploads = self
+            .uploads
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        match uploads.get_mut(upload_id) {
+            Some(upload) if upload.key == key => Ok(f(upload)),
+            _ => Err(StorageError::NoSuchUpload(upload_id.to_string())),
+        }
+    }
+
+    /// Remove upload `upload_id`, which must be to `key`.
+    fn take_upload(&self, key: &str, upload_id: &str) -> Result<Upload, StorageError> {
+        let mut uploads = self
+            .uploads
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        match uploads.get(upload_id) {
+            Some(upload) if upload.key == key => Ok(uploads.remove(upload_id).unwrap()),
+            _ => Err(StorageError::NoSuchUpload(upload_id.to_string())),
+        }
+    }
+
+    /// The current version of a key, unless it is deleted.
+    fn live(versions: &[Version]) -> Option<&Version> {
+        versions.last().filter(|version| version.data.is_some())
+    }
+
+    fn add_version(&self, key: &str, data: Option<Bytes>, metadata: Metadata) -> String {
+        let id = format!("{:016}", self.next_version.fetch_add(1, Ordering::Relaxed));
+        self.objects()
+            .entry(key.to_string())
+            .or_default()
+            .push(Version {
+                id: id.clone(),
+                data,
+                metadata,
+                last_modified: SystemTime::now(),
+            });
+        id
+    }
+
+    /// Store `data` as the new current version of `key`; returns its version ID.
+    pub fn put(&self, key: &str, data: impl Into<Bytes>, metadata: Metadata) -> String {
+        self.add_version(key, Some(data.into()), metadata)
+    }
+
+    /// The current contents of `key`, unless it is missing or deleted.
+    pub fn get(&self, key: &str) -> Option<Bytes> {
+        Self::live(self.objects().get(key)?)?.data.clone()
+    }
+
+    /// Every version of `key`, delete markers included, newest first.
+    pub fn versions(&self, key: &str) -> Vec<ObjectVersion> {
+        let objects = self.objects();
+        let Some(versions) = objects.get(key) else {
+            return Vec::new();
+        };
+        versions
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(i, version)| ObjectVersion {
+                version_id: version.id.clone(),
+                size: version.size(),
+                last_modified: Some(format_time(version.last_modified)),
+                is_latest: i == 0,
+                is_delete_marker: version.data.is_none(),
+            })
+            .collect()
+    }
+
+    fn router<S: Clone + Send + Sync + 'static>(self: Arc<Self>) -> Router<S> {
+        Router::new()
+            .route("/storage/*key", get(get_object).put(put_object))
+            .with_state(self)
+    }
+}
+
+#[async_trait]
+impl StorageBackend for MemoryBackend {
+    async fn list(
+        &self,
+        prefix: Option<&str>,
+        limit: i32,
+        next_token: Option<&str>,
+    ) -> Result<ListPage, StorageError> {
+        let objects = self.objects();
+        let mut matching = objects
+            .iter()
+            .filter(|(key, _)| key.starts_with(prefix.unwrap_or("")))
+            .filter(|(key, _)| next_token.is_none_or(|token| key.as_str() > token))
+            .filter_map(|(key, versions)| Some((key, Self::live(versions)?)));
+
+        let objects: Vec<ObjectInfo> = matching
+            .by_ref()
+            .take(limit.max(1) as usize)
+            .map(|(key, version)| ObjectInfo {
+                key: key.clone(),
+                size: version.size(),
+                last_modified: Some(format_time(version.last_modified)),
+            })
+            .collect();
+        let next_token = match matching.next() {
+            Some(_) => objects.last().map(|object| object.key.clone()),
+            None => None,
+        };
+        Ok(ListPage {
+            objects,
+            next_token,
+        })
+    }
+
+    async fn head(&self, key: &str) -> Result<ObjectHead, StorageError> {
+        let objects = self.objects();
+        let version = objects
+            .get(key)
+            .and_then(|versions| Self::live(versions))
+            .ok_or_else(|| StorageError::NotFound(key.to_string()))?;
+        Ok(ObjectHead {
+            size: version.size(),
+            last_modified: Some(format_time(version.last_modified)),
+            metadata: version.metadata.clone(),
+        })
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        if self.get(key).is_some() {
+            self.add_version(key, None, Metadata::new());
+        }
+        Ok(())
+    }
+
+    async fn copy(&self, from: &str, to: &str) -> Result<(), StorageError> {
+        let (data, metadata) = {
+            let objects = self.objects();
+            let version = objects
+                .get(from)
+                .and_then(|versions| Self::live(versions))
+                .ok_or_else(|| StorageError::NotFound(from.to_string()))?;
+            (version.data.clone(), version.metadata.clone())
+        };
+        self.add_version(to, data, metadata);
+        Ok(())
+    }
+
+    async fn put_empty(&self, key: &str, metadata: &Metadata) -> Result<(), StorageError> {
+        self.add_version(key, Some(Bytes::new()), metadata.clone());
+        Ok(())
+    }
+
+    async fn presign_upload(
+        &self,
+        key: &str,
+        metadata: &Metadata,
+        expires_in: Duration,
+    ) -> Result<String, StorageError> {
+        Ok(self.signer.sign("PUT", key, metadata, expires_in))
+    }
+
+    async fn presign_download(
+        &self,
+        key: &str,
+        expires_in: Duration,
+    ) -> Result<String, StorageError> {
+        Ok(self.signer.sign("GET", key, &Metadata::new(), expires_in))
+    }
+
+    async fn list_versions(&self, key: &str) -> Result<Vec<ObjectVersion>, StorageError> {
+        Ok(self.versions(key))
+    }
+
+    async fn head_version(&self, key: &str, version_id: &str) -> Result<ObjectHead, StorageError> {
+        let (data, metadata, last_modified) = self.version(key, version_id)?;
+        Ok(ObjectHead {
+            size: data.len() as i64,
+            last_modified: Some(format_time(last_modified)),
+            metadata,
+        })
+    }
+
+    async fn presign_download_version(
+        &self,
+        key: &str,
+        version_id: &str,
+        expires_in: Duration,
+    ) -> Result<String, StorageError> {
+        Ok(self.signer.sign_version(key, version_id, expires_in))
+    }
+
+    async fn restore_version(&self, key: &str, version_id: &str) -> Result<(), StorageError> {
+        let (data, metadata, _) = self.version(key, version_id)?;
+        self.add_version(key, Some(data), metadata);
+        Ok(())
+    }
+
+    async fn create_multipart_upload(
+        &self,
+        key: &str,
+        metadata: &Metadata,
+    ) -> Result<String, StorageError> {
+        let upload_id = new_upload_id();
+        self.uploads
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .insert(
+                upload_id.clone(),
+                Upload {
+                    key: key.to_string(),
+                    metadata: metadata.clone(),
+                    initiated: SystemTime::now(),
+                    parts: BTreeMap::new(),
+                },
+            );
+        Ok(upload_id)
+    }
+
+    async fn presign_upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        part_number: i32,
+        expires_in: Duration,
+    ) -> Result<String, StorageError> {
+        self.with_upload(key, upload_id, |_| ())?;
+        Ok(self
+            .signer
+            .sign_part(key, upload_id, part_number, expires_in))
+    }
+
+    async fn list_parts(
+        &self,
+        key: &str,
+        upload_id: &str,
+    ) -> Result<Vec<UploadedPart>, StorageError> {
+        self.with_upload(key, upload_id, |upload| {
+            upload
+                .parts
+                .iter()
+                .map(|(&part_number, data)| UploadedPart {
+                    part_number,
+                    size: data.len() as i64,
+                    etag: part_etag(Sha256::digest(data)),
+                })
+                .collect()
+        })
+    }
+
+    async fn complete_multipart_upload(
+        &self,
+        key: &str,
+        upload_id: &str,
+        parts: &[UploadedPart],
+    ) -> Result<(), StorageError> {
+        let upload = self.take_upload(key, upload_id)?;
+        let mut data = Vec::new();
+        for part in parts {
+            let part_data = upload.parts.get(&part.part_number).ok_or_else(|| {
+                StorageError::Backend(format!(
+                    "part {} of upload {upload_id} is missing",
+                    part.part_number
+                ))
+            })?;
+            data.extend_from_slice(part_data);
+        }
+        self.put(key, data, upload.metadata);
+        Ok(())
+    }
+
+    async fn abort_multipart_upload(&self, key: &str, upload_id: &str) -> Result<(), StorageError> {
+        self.take_upload(key, upload_id).map(drop)
+    }
+
+    async fn list_multipart_uploads(&self) -> Result<Vec<MultipartUpload>, StorageError> {
+        let uploads = self
+            .uploads
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        Ok(uploads
+            .iter()
+            .map(|(upload_id, upload)| MultipartUpload {
+                key: upload.key.clone(),
+                upload_id: upload_id.clone(),
+                initiated: upload.initiated,
+            })
+            .collect())
+    }
+
+    fn routes(self: Arc<Self>) -> Router<AppState> {
+        self.router()
+    }
+}
+
+/// `PUT /storage/*key` with a signed upload URL: store the body with the signed metadata,
+/// or as a part of a multipart upload.
+async fn put_object(
+    State(backend): State<Arc<MemoryBackend>>,
+    UrlPath(key): UrlPath<String>,
+    Query(query): Query<HashMap<String, String>>,
+    body: Body,
+) -> Result<Response, AppError> {
+    let receive = |body: Body| async {
+        axum::body::to_bytes(body, usize::MAX)
+            .await
+            .map_err(|e| AppError::Internal(format!("receiving upload failed for {key}: {e}")))
+    };
+
+    if is_part_upload(&query) {
+        let (upload_id, part_number) = backend.signer.verify_part(&key, &query)?;
+        let data = receive(body).await?;
+        let etag = part_etag(Sha256::digest(&data));
+        backend.with_upload(&key, &upload_id, |upload| {
+            upload.parts.insert(part_number, data);
+        })?;
+        return Ok([(header::ETAG, etag)].into_response());
+    }
+
+    let metadata = backend.signer.verify("PUT", &key, &query)?;
+    let data = receive(body).await?;
+    backend.put(&key, data, metadata);
+    Ok(StatusCode::OK.into_response())
+}
+
+/// `GET /storage/*key` with a signed download URL, of the current or a given version.
+async fn get_object(
+    State(backend): State<Arc<MemoryBackend>>,
+    UrlPath(key): UrlPath<String>,
+    Query(query): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let data = match backend.signer.verify_download(&key, &query)? {
+        Some(version_id) => backend.version(&key, &version_id)?.0,
+        None => backend
+            .get(&key)
+            .ok_or_else(|| StorageError::NotFound(key.clone()))?,
+    };
+
+    let (start, len, response) = match read_response(&headers, data.len() as u64) {
+        Ok(plan) => plan,
+        Err(unsatisfiable) => return Ok(unsatisfiable.into_response()),
+    };
+    let range = start as usize..(start + len) as usize;
+    response
+        .body(Body::from(data.slice(range)))
+        .map_err(|e| AppError::Internal(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::abort_stale_uploads;
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_versions_and_delete_markers() {
+        let backend = MemoryBackend::new("http://localhost:3000");
+        let metadata = Metadata::from([("content-hash".to_string(), "h1".to_string())]);
+        let first = backend.put("active/a.enc", "one", metadata.clone());
+        backend.put("active/a.enc", "two!", Metadata::new());
+        assert_eq!(backend.get("active/a.enc").unwrap(), "two!");
+
+        backend
+            .copy("active/a.enc", "archived/a.enc")
+            .await
+            .unwrap();
+        backend.delete("active/a.enc").await.unwrap();
+        assert!(matches!(
+            backend.head("active/a.enc").await,
+            Err(StorageError::NotFound(_))
+        ));
+        assert!(backend.get("active/a.enc").is_none());
+        assert_eq!(backend.head("archived/a.enc").await.unwrap().size, 4);
+
+        let versions = backend.versions("active/a.enc");
+        assert_eq!(versions.len(), 3);
+        assert!(versions[0].is_latest && versions[0].is_delete_marker);
+        assert_eq!(versions[1].size, 4);
+        assert_eq!(versions[2].version_id, first);
+        assert!(!versions[2].is_latest && !versions[2].is_delete_marker);
+
+        let page = backend.list(None, 100, None).await.unwrap();
+        let keys: Vec<_> = page.objects.iter().map(|o| o.key.as_str()).collect();
+        assert_eq!(keys, ["archived/a.enc"]);
+    }
+
+    #[tokio::test]
+    async fn test_signed_urls() {
+        let backend = Arc::new(MemoryBackend::new("http://localhost:3000"));
+        let metadata = Metadata::from([("original-size".to_string(), "11".to_string())]);
+        let expiry = Duration::from_secs(60);
+        let send = |method: &str, url: &str, range: Option<&str>, body: &'static str| {
+            let uri = url.strip_prefix("http://localhost:3000").unwrap();
+            let mut request = Request::builder().method(method).uri(uri);
+            if let Some(range) = range {
+                request = request.header(header::RANGE, range);
+            }
+            Arc::clone(&backend)
+                .router::<()>()
+                .oneshot(request.body(Body::from(body)).unwrap())
+        };
+
+        let upload = backend
+            .presign_upload("my file.enc", &metadata, expiry)
+            .await
+            .unwrap();
+        let response = send("PUT", &upload, None, "hello world").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            backend.head("my file.enc").await.unwrap().metadata,
+            metadata
+        );
+
+        let download = backend
+            .presign_download("my file.enc", expiry)
+            .await
+            .unwrap();
+        let response = send("GET", &download, Some("bytes=6-"), "").await.unwrap();
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, "world");
+
+        let response = send("PUT", &download, None, "overwrite").await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let other_key = download.replace("my%20file", "other");
+        let response = send("GET", &other_key, None, "").await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let first = backend.versions("my file.enc")[0].version_id.clone();
+        backend.put("my file.enc", "replaced", Metadata::new());
+        let old = backend.presign_download_version("my file.enc", &first, expiry);
+        let old = old.await.unwrap();
+        let response = send("GET", &old, None, "").await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, "hello world");
+        let other_version = old.replace(&first, "0000000000000099");
+        let response = send("GET", &other_version, None, "").await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let upload_id = backend
+            .create_multipart_upload("big.enc", &metadata)
+            .await
+            .unwrap();
+        let part = backend
+            .presign_upload_part("big.enc", &upload_id, 1, expiry)
+            .await
+            .unwrap();
+        let response = send("PUT", &part, None, "part one").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let other_part = part.replace("partNumber=1", "partNumber=2");
+        let response = send("PUT", &other_part, None, "part two").await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        // A part URL does not store a whole object.
+        let as_object = part.replace(&format!("&uploadId={upload_id}"), "");
+        let response = send("PUT", &as_object, None, "whole").await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_multipart_upload_and_stale_cleanup() {
+        let backend = MemoryBackend::new("http://localhost:3000");
+        let metadata = Metadata::from([("content-hash".to_string(), "h1".to_string())]);
+        let upload_id = backend
+            .create_multipart_upload("big.enc", &metadata)
+            .await
+            .unwrap();
+        backend
+            .with_upload("big.enc", &upload_id, |upload| {
+                upload.parts.insert(2, Bytes::from("world"));
+                upload.parts.insert(1, Bytes::from("hello "));
+            })
+            .unwrap();
+        assert!(matches!(
+            backend.list_parts("other.enc", &upload_id).await,
+            Err(StorageError::NoSuchUpload(_))
+        ));
+        let parts = backend.list_parts("big.enc", &upload_id).await.unwrap();
+        assert_eq!(
+            parts
+                .iter()
+                .map(|p| (p.part_number, p.size))
+                .collect::<Vec<_>>(),
+            [(1, 6), (2, 5)]
+        );
+        backend
+            .complete_multipart_upload("big.enc", &upload_id, &parts)
+            .await
+            .unwrap();
+        assert_eq!(backend.get("big.enc").unwrap(), "hello world");
+        assert_eq!(backend.head("big.enc").await.unwrap().metadata, metadata);
+        assert!(backend.list_multipart_uploads().await.unwrap().is_empty());
+
+        let stale = backend
+            .create_multipart_upload("stale.enc", &Metadata::new())
+            .await
+            .unwrap();
+        let hour = Duration::from_secs(3600);
+        assert_eq!(abort_stale_uploads(&backend, hour).await.unwrap(), 0);
+        assert_eq!(
+            abort_stale_uploads(&backend, Duration::ZERO).await.unwrap(),
+            1
+        );
+        assert!(matches!(
+            backend.abort_multipart_upload("stale.enc", &stale).await,
+            Err(StorageError::NoSuchUpload(_))
+        ));
+    }
+}
@@ -0,0 +1,297 @@
+//! Where objects live. Routes only talk to a [`StorageBackend`]; [`S3Backend`] is the
+//! production one, [`LocalBackend`] keeps objects in a directory and serves its own
+//! signed URLs, for running without S3, and [`MemoryBackend`] does the same in memory,
+//! for tests.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use axum::Router;
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use crate::config::{AppConfig, StorageKind};
+use crate::error::AppError;
+use crate::routes::AppState;
+
+pub mod content_index;
+pub mod local;
+pub mod memory;
+pub mod s3;
+mod signed_url;
+
+pub use local::LocalBackend;
+pub use memory::MemoryBackend;
+pub use s3::S3Backend;
+
+/// User metadata stored with an object (`content-hash`, `original-size`), by name.
+pub type Metadata = BTreeMap<String, String>;
+
+/// A timestamp the way S3 reports `last_modified`.
+fn format_time(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time).to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// One entry of a listing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectInfo {
+    pub key: String,
+    pub size: i64,
+    /// RFC 3339, UTC, whole seconds (as S3 reports it).
+    pub last_modified: Option<String>,
+}
+
+/// One page of a listing, in key order.
+#[derive(Debug, Clone, Default)]
+pub struct ListPage {
+    pub objects: Vec<ObjectInfo>,
+    /// Pass back to get the next page; `None` on the last one.
+    pub next_token: Option<String>,
+}
+
+/// Version ID of the only version of an object in a backend without versioning, as an
+/// unversioned S3 bucket reports it.
+pub const UNVERSIONED_ID: &str = "null";
+
+/// One version of an object, in a backend that keeps them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectVersion {
+    pub version_id: String,
+    pub size: i64,
+    pub last_modified: Option<String>,
+    pub is_latest: bool,
+    /// The version records a delete; there is no data.
+    pub is_delete_marker: bool,
+}
+
+/// A multipart upload that has been started but not completed or aborted.
+#[derive(Debug, Clone)]
+pub struct MultipartUpload {
+    pub key: String,
+    pub upload_id: String,
+    pub initiated: SystemTime,
+}
+
+/// A part received for a multipart upload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UploadedPart {
+    /// 1-based, as in S3.
+    pub part_number: i32,
+    pub size: i64,
+    /// Identifies the part's contents; S3 needs it to complete the upload.
+    pub etag: String,
+}
+
+/// What a HEAD on an object reports.
+#[derive(Debug, Clone)]
+pub struct ObjectHead {
+    pub size: i64,
+    pub last_modified: Option<String>,
+    pub metadata: Metadata,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum StorageError {
+    #[error("not found: {0}")]
+    NotFound(String),
+
+    /// The key cannot name an object in this backend (e.g. `..` for the local backend).
+    #[error("invalid key: {0}")]
+    InvalidKey(String),
+
+    /// The key has no version with this ID (or it is a delete marker, which has no data).
+    #[error("no such version: {0}")]
+    NoSuchVersion(String),
+
+    /// No multipart upload with this ID is in progress for the key.
+    #[error("no such upload: {0}")]
+    NoSuchUpload(String),
+
+    #[error("{0}")]
+    Backend(String),
+}
+
+impl From<StorageError> for AppError {
+    fn from(err: StorageError) -> Self {
+        match err {
+            StorageError::NotFound(key) => AppError::NotFound(format!("file not found: {key}")),
+            StorageError::InvalidKey(_) => AppError::BadRequest(err.to_string()),
+            StorageError::NoSuchVersion(id) => {
+                AppError::NotFound(format!("version not found: {id}"))
+            }
+            StorageError::NoSuchUpload(id) => {
+                AppError::NotFound(format!("multipart upload not found: {id}"))
+            }
+            StorageError::Backend(msg) => AppError::Internal(msg),
+        }
+    }
+}
+
+/// Object storage as the routes use it. Data never passes through these calls: clients
+/// move bytes with the presigned URLs.
+#[async_trait]
+pub trait StorageBackend: Send + Sync {
+    /// Up to `limit` objects whose keys start with `prefix`, continuing after `next_token`.
+    async fn list(
+        &self,
+        prefix: Option<&str>,
+        limit: i32,
+        next_token: Option<&str>,
+    ) -> Result<ListPage, StorageError>;
+
+    async fn head(&self, key: &str) -> Result<ObjectHead, StorageError>;
+
+    /// Delete `key`. Deleting a missing object is not an error, as in S3.
+    async fn delete(&self, key: &str) -> Result<(), StorageError>;
+
+    /// Copy `from` to `to` with its metadata, replacing any object at `to`.
+    async fn copy(&self, from: &str, to: &str) -> Result<(), StorageError>;
+
+    /// Store an empty object at `key` with `metadata`, replacing any object there. For the
+    /// server's own bookkeeping ([`content_index`]); client data always goes through a
+    /// presigned URL.
+    async fn put_empty(&self, key: &str, metadata: &Metadata) -> Result<(), StorageError>;
+
+    /// URL a client can PUT the object to until `expires_in` passes; the object is stored
+    /// with `metadata`.
+    async fn presign_upload(
+        &self,
+        key: &str,
+        metadata: &Metadata,
+        expires_in: Duration,
+    ) -> Result<String, StorageError>;
+
+    /// URL a client can GET (also with a `Range` header) until `expires_in` passes.
+    async fn presign_download(
+        &self,
+        key: &str,
+        expires_in: Duration,
+    ) -> Result<String, StorageError>;
+
+    /// Every version of `key`, delete markers included, newest first; empty if the key never
+    /// existed. Without versioning, an object has one version, [`UNVERSIONED_ID`].
+    async fn list_versions(&self, key: &str) -> Result<Vec<ObjectVersion>, StorageError> {
+        match self.head(key).await {
+            Ok(head) => Ok(vec![ObjectVersion {
+                version_id: UNVERSIONED_ID.to_string(),
+                size: head.size,
+                last_modified: head.last_modified,
+                is_latest: true,
+                is_delete_marker: false,
+            }]),
+            Err(StorageError::NotFound(_)) => Ok(Vec::new()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// What a HEAD on version `version_id` of `key` reports.
+    async fn head_version(&self, key: &str, version_id: &str) -> Result<ObjectHead, StorageError> {
+        match version_id {
+            UNVERSIONED_ID => self.head(key).await,
+            _ => Err(StorageError::NoSuchVersion(version_id.to_string())),
+        }
+    }
+
+    /// Like [`presign_download`](Self::presign_download), for one version.
+    async fn presign_download_version(
+        &self,
+        key: &str,
+        version_id: &str,
+        expires_in: Duration,
+    ) -> Result<String, StorageError> {
+        match version_id {
+            UNVERSIONED_ID => self.presign_download(key, expires_in).await,
+            _ => Err(StorageError::NoSuchVersion(version_id.to_string())),
+        }
+    }
+
+    /// Make a copy of version `version_id` the current version of `key`, undeleting it if
+    /// it was deleted. The versions in between are kept.
+    async fn restore_version(&self, key: &str, version_id: &str) -> Result<(), StorageError> {
+        // Without versioning the only version is the current one.
+        self.head_version(key, version_id).await.map(drop)
+    }
+
+    /// Start a multipart upload to `key`; the object is stored with `metadata` when the
+    /// upload completes. Returns the upload ID.
+    async fn create_multipart_upload(
+        &self,
+        key: &str,
+        metadata: &Metadata,
+    ) -> Result<String, StorageError>;
+
+    /// URL a client can PUT part `part_number` of the upload to until `expires_in` passes.
+    /// Uploading a part number again replaces the part.
+    async fn presign_upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        part_number: i32,
+        expires_in: Duration,
+    ) -> Result<String, StorageError>;
+
+    /// The parts received so far, by part number.
+    async fn list_parts(
+        &self,
+        key: &str,
+        upload_id: &str,
+    ) -> Result<Vec<UploadedPart>, StorageError>;
+
+    /// Join `parts`, in the order given, into the object at `key` and end the upload.
+    async fn complete_multipart_upload(
+        &self,
+        key: &str,
+        upload_id: &str,
+        parts: &[UploadedPart],
+    ) -> Result<(), StorageError>;
+
+    /// End the upload and discard its parts.
+    async fn abort_multipart_upload(&self, key: &str, upload_id: &str) -> Result<(), StorageError>;
+
+    /// Every multipart upload in progress.
+    async fn list_multipart_uploads(&self) -> Result<Vec<MultipartUpload>, StorageError>;
+
+    /// Routes the backend serves itself, outside bearer auth (the local backend's signed
+    /// URLs). None by default.
+    fn routes(self: Arc<Self>) -> Router<AppState> {
+        Router::new()
+    }
+}
+
+/// The backend selected by `STORAGE_BACKEND`.
+pub async fn from_config(config: &AppConfig) -> Arc<dyn StorageBackend> {
+    match config.storage_backend {
+        StorageKind::S3 => Arc::new(S3Backend::from_config(config).await),
+        StorageKind::Local => Arc::new(
+            LocalBackend::from_config(config).expect("failed to prepare LOCAL_STORAGE_DIR"),
+        ),
+        StorageKind::Memory => Arc::new(MemoryBackend::from_config(config)),
+    }
+}
+
+/// Abort the multipart uploads started at least `max_age` ago, which a client gave up on
+/// (the parts would otherwise be kept, and billed, forever). Returns how many there were.
+pub async fn abort_stale_uploads(
+    storage: &dyn StorageBackend,
+    max_age: Duration,
+) -> Result<usize, StorageError> {
+    let now = SystemTime::now();
+    let mut aborted = 0;
+    for upload in storage.list_multipart_uploads().await? {
+        let age = now.duration_since(upload.initiated).unwrap_or_default();
+        if age < max_age {
+            continue;
+        }
+        match storage
+            .abort_multipart_upload(&upload.key, &upload.upload_id)
+            .await
+        {
+            Ok(()) => aborted += 1,
+            // Completed or aborted since the listing.
+            Err(StorageError::NoSuchUpload(_)) => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(aborted)
+}
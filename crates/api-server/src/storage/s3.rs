@@ -0,0 +1,538 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use aws_sdk_s3::error::{DisplayErrorContext, SdkError};
+use aws_sdk_s3::operation::head_object::HeadObjectOutput;
+use aws_sdk_s3::presigning::PresigningConfig;
+use aws_sdk_s3::primitives::{ByteStream, DateTime, DateTimeFormat};
+use aws_sdk_s3::types::{CompletedMultipartUpload, CompletedPart};
+use aws_sdk_s3::Client;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+
+use super::{
+    ListPage, Metadata, MultipartUpload, ObjectHead, ObjectInfo, ObjectVersion, StorageBackend,
+    StorageError, UploadedPart, UNVERSIONED_ID,
+};
+use crate::config::AppConfig;
+use crate::s3_client::{create_s3_client, rewrite_presigned_url_for_public_access};
+
+/// Characters to percent-encode in S3 copy_source keys.
+/// Per RFC 3986, unreserved characters (ALPHA, DIGIT, '-', '.', '_', '~') are
+/// left as-is. '/' is also preserved since it serves as S3's path delimiter.
+const S3_KEY_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'/')
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// Check if an S3 SDK error is a 404 (object not found).
+/// Only `ServiceError` with HTTP 404 qualifies; timeouts, auth failures, etc. do not.
+fn is_not_found<E>(err: &SdkError<E>) -> bool {
+    matches!(err, SdkError::ServiceError(e) if e.raw().status().as_u16() == 404)
+}
+
+fn backend_error<E>(operation: &'static str, key: &str) -> impl FnOnce(SdkError<E>) -> StorageError
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    let key = key.to_string();
+    move |err| {
+        StorageError::Backend(format!(
+            "S3 {operation} failed for {key}: {}",
+            DisplayErrorContext(err)
+        ))
+    }
+}
+
+fn format_time(time: Option<&DateTime>) -> Option<String> {
+    time.and_then(|dt| dt.fmt(DateTimeFormat::DateTime).ok())
+}
+
+fn object_head(head: &HeadObjectOutput) -> ObjectHead {
+    ObjectHead {
+        size: head.content_length().unwrap_or(0),
+        last_modified: format_time(head.last_modified()),
+        metadata: head
+            .metadata()
+            .map(|m| m.iter().map(|(k, v)| (k.clone(), v.clone())).collect())
+            .unwrap_or_default(),
+    }
+}
+
+/// Objects in one S3 bucket (or MinIO).
+pub struct S3Backend {
+    client: Client,
+    bucket: String,
+    /// `(internal, public)` endpoints: presigned URLs are rewritten from the first to the
+    /// second, for a server inside Docker handing out URLs to clients outside it.
+    url_rewrite: Option<(String, String)>,
+}
+
+impl S3Backend {
+    pub fn new(client: Client, config: &AppConfig) -> Self {
+        let url_rewrite = match (&config.s3_endpoint_url, &config.s3_public_endpoint_url) {
+            (Some(internal), Some(public)) => Some((internal.clone(), public.clone())),
+            _ => None,
+        };
+        Self {
+            client,
+            bucket: config.s3_bucket.clone(),
+            url_rewrite,
+        }
+    }
+
+    pub async fn from_config(config: &AppConfig) -> Self {
+        Self::new(create_s3_client(config).await, config)
+    }
+
+    /// Map a 404 (`NoSuchUpload`) to [`StorageError::NoSuchUpload`].
+    fn upload_error<E>(
+        operation: &'static str,
+        key: &str,
+        upload_id: &str,
+    ) -> impl FnOnce(SdkError<E>) -> StorageError
+    where
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        let key = key.to_string();
+        let upload_id = upload_id.to_string();
+        move |err| {
+            if is_not_found(&err) {
+                StorageError::NoSuchUpload(upload_id)
+            } else {
+                backend_error(operation, &key)(err)
+            }
+        }
+    }
+
+    fn public_url(&self, url: String) -> String {
+        match &self.url_rewrite {
+            Some((internal, public)) => {
+                rewrite_presigned_url_for_public_access(&url, internal, public)
+            }
+            None => url,
+        }
+    }
+}
+
+#[async_trait]
+impl StorageBackend for S3Backend {
+    async fn list(
+        &self,
+        prefix: Option<&str>,
+        limit: i32,
+        next_token: Option<&str>,
+    ) -> Result<ListPage, StorageError> {
+        let output = self
+            .client
+            .list_objects_v2()
+            .bucket(&self.bucket)
+            .max_keys(limit)
+            .set_prefix(prefix.map(str::to_string))
+            .set_continuation_token(next_token.map(str::to_string))
+            .send()
+            .await
+            .map_err(backend_error("list_objects_v2", prefix.unwrap_or("")))?;
+
+        let objects = output
+            .contents()
+            .iter()
+            .filter_map(|obj| {
+                Some(ObjectInfo {
+                    key: obj.key()?.to_string(),
+                    size: obj.size().unwrap_or(0),
+                    last_modified: format_time(obj.last_modified()),
+                })
+            })
+            .collect();
+
+        Ok(ListPage {
+            objects,
+            next_token: output.next_continuation_token().map(str::to_string),
+        })
+    }
+
+    async fn head(&self, key: &str) -> Result<ObjectHead, StorageError> {
+        let head = self
+            .client
+            .head_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+            .map_err(|e| {
+                if is_not_found(&e) {
+                    StorageError::NotFound(key.to_string())
+                } else {
+                    backend_error("head_object", key)(e)
+                }
+            })?;
+
+        Ok(object_head(&head))
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StorageError> {
+        self.client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+            .map_err(backend_error("delete_object", key))?;
+        Ok(())
+    }
+
+    async fn copy(&self, from: &str, to: &str) -> Result<(), StorageError> {
+        let encoded_from = utf8_percent_encode(from, S3_KEY_ENCODE_SET);
+        self.client
+            .copy_object()
+            .bucket(&self.bucket)
+            .copy_source(format!("{}/{encoded_from}", self.bucket))
+            .key(to)
+            .send()
+            .await
+            .map_err(|e| {
+                if is_not_found(&e) {
+                    StorageError::NotFound(from.to_string())
+                } else {
+                    backend_error("copy_object", from)(e)
+                }
+            })?;
+        Ok(())
+    }
+
+    async fn put_empty(&self, key: &str, metadata: &Metadata) -> Result<(), StorageError> {
+        metadata
+            .iter()
+            .fold(
+                self.client
+                    .put_object()
+                    .bucket(&self.bucket)
+                    .key(key)
+                    .body(ByteStream::from_static(b"")),
+                |request, (name, value)| request.metadata(name, value),
+            )
+            .send()
+            .await
+            .map_err(backend_error("put_object", key))?;
+        Ok(())
+    }
+
+    async fn presign_upload(
+        &self,
+        key: &str,
+        metadata: &Metadata,
+        expires_in: Duration,
+    ) -> Result<String, StorageError> {
+        let presigning_config = PresigningConfig::expires_in(expires_in)
+            .map_err(|e| StorageError::Backend(e.to_string()))?;
+        let request = metadata.iter().fold(
+            self.client.put_object().bucket(&self.bucket).key(key),
+            |request, (name, value)| request.metadata(name, value),
+        );
+        let presigned = request
+            .presigned(presigning_config)
+            .await
+            .map_err(backend_error("presign put_object", key))?;
+        Ok(self.public_url(presigned.uri().to_string()))
+    }
+
+    async fn presign_download(
+        &self,
+        key: &str,
+        expires_in: Duration,
+    ) -> Result<String, StorageError> {
+        let presigning_config = PresigningConfig::expires_in(expires_in)
+            .map_err(|e| StorageError::Backend(e.to_string()))?;
+        let presigned = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .presigned(presigning_config)
+            .await
+            .map_err(backend_error("presign get_object", key))?;
+        Ok(self.public_url(presigned.uri().to_string()))
+    }
+
+    async fn list_versions(&self, key: &str) -> Result<Vec<ObjectVersion>, StorageError> {
+        // Versions and delete markers come in two lists; merge them by time, newest first.
+        let mut versions = Vec::new();
+        let (mut key_marker, mut version_id_marker) = (None, None);
+        loop {
+            let output = self
+                .client
+                .list_object_versions()
+                .bucket(&self.bucket)
+                .prefix(key)
+                .set_key_marker(key_marker)
+                .set_version_id_marker(version_id_marker)
+                .send()
+                .await
+                .map_err(backend_error("list_object_versions", key))?;
+            let objects = output
+                .versions()
+                .iter()
+                .filter(|version| version.key() == Some(key))
+                .map(|version| {
+                    (
+                        version.last_modified(),
+                        version.version_id(),
+                        version.size().unwrap_or(0),
+                        version.is_latest(),
+                        false,
+                    )
+                });
+            let markers = output
+                .delete_markers()
+                .iter()
+                .filter(|marker| marker.key() == Some(key))
+                .map(|marker| {
+                    (
+                        marker.last_modified(),
+                        marker.version_id(),
+                        0,
+                        marker.is_latest(),
+                        true,
+                    )
+                });
+            for (time, version_id, size, is_latest, is_delete_marker) in objects.chain(markers) {
+                let order = time.map(|dt| (dt.secs(), dt.subsec_nanos()));
+                versions.push((
+                    Reverse((order, is_latest == Some(true))),
+                    ObjectVersion {
+                        version_id: version_id.unwrap_or(UNVERSIONED_ID).to_string(),
+                        size,
+                        last_modified: format_time(time),
+                        is_latest: is_latest == Some(true),
+                        is_delete_marker,
+                    },
+                ));
+            }
+            key_marker = output.next_key_marker().map(str::to_string);
+            version_id_marker = output.next_version_id_marker().map(str::to_string);
+            if output.is_truncated() != Some(true) {
+                break;
+            }
+        }
+        versions.sort_by_key(|(order, _)| *order);
+        Ok(versions.into_iter().map(|(_, version)| version).collect())
+    }
+
+    async fn head_version(&self, key: &str, version_id: &str) -> Result<ObjectHead, StorageError> {
+        let head = self
+            .client
+            .head_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .version_id(version_id)
+            .send()
+            .await
+            .map_err(|e| {
+                if is_not_found(&e) {
+                    StorageError::NoSuchVersion(version_id.to_string())
+                } else {
+                    backend_error("head_object", key)(e)
+                }
+            })?;
+        Ok(object_head(&head))
+    }
+
+    async fn presign_download_version(
+        &self,
+        key: &str,
+        version_id: &str,
+        expires_in: Duration,
+    ) -> Result<String, StorageError> {
+        let presigning_config = PresigningConfig::expires_in(expires_in)
+            .map_err(|e| StorageError::Backend(e.to_string()))?;
+        let presigned = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .version_id(version_id)
+            .presigned(presigning_config)
+            .await
+            .map_err(backend_error("presign get_object", key))?;
+        Ok(self.public_url(presigned.uri().to_string()))
+    }
+
+    async fn restore_version(&self, key: &str, version_id: &str) -> Result<(), StorageError> {
+        let encoded_key = utf8_percent_encode(key, S3_KEY_ENCODE_SET);
+        let encoded_version = utf8_percent_encode(version_id, S3_KEY_ENCODE_SET);
+        self.client
+            .copy_object()
+            .bucket(&self.bucket)
+            .copy_source(format!(
+                "{}/{encoded_key}?versionId={encoded_version}",
+                self.bucket
+            ))
+            .key(key)
+            .send()
+            .await
+            .map_err(|e| {
+                if is_not_found(&e) {
+                    StorageError::NoSuchVersion(version_id.to_string())
+                } else {
+                    backend_error("copy_object", key)(e)
+                }
+            })?;
+        Ok(())
+    }
+
+    async fn create_multipart_upload(
+        &self,
+        key: &str,
+        metadata: &Metadata,
+    ) -> Result<String, StorageError> {
+        let metadata: HashMap<String, String> = metadata.clone().into_iter().collect();
+        let output = self
+            .client
+            .create_multipart_upload()
+            .bucket(&self.bucket)
+            .key(key)
+            .set_metadata(Some(metadata))
+            .send()
+            .await
+            .map_err(backend_error("create_multipart_upload", key))?;
+        output
+            .upload_id()
+            .map(str::to_string)
+            .ok_or_else(|| StorageError::Backend(format!("S3 returned no upload ID for {key}")))
+    }
+
+    async fn presign_upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        part_number: i32,
+        expires_in: Duration,
+    ) -> Result<String, StorageError> {
+        let presigning_config = PresigningConfig::expires_in(expires_in)
+            .map_err(|e| StorageError::Backend(e.to_string()))?;
+        let presigned = self
+            .client
+            .upload_part()
+            .bucket(&self.bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .part_number(part_number)
+            .presigned(presigning_config)
+            .await
+            .map_err(backend_error("presign upload_part", key))?;
+        Ok(self.public_url(presigned.uri().to_string()))
+    }
+
+    async fn list_parts(
+        &self,
+        key: &str,
+        upload_id: &str,
+    ) -> Result<Vec<UploadedPart>, StorageError> {
+        let mut parts = Vec::new();
+        let mut marker: Option<String> = None;
+        loop {
+            let output = self
+                .client
+                .list_parts()
+                .bucket(&self.bucket)
+                .key(key)
+                .upload_id(upload_id)
+                .set_part_number_marker(marker)
+                .send()
+                .await
+                .map_err(Self::upload_error("list_parts", key, upload_id))?;
+            parts.extend(output.parts().iter().filter_map(|part| {
+                Some(UploadedPart {
+                    part_number: part.part_number()?,
+                    size: part.size().unwrap_or(0),
+                    etag: part.e_tag()?.to_string(),
+                })
+            }));
+            marker = output.next_part_number_marker().map(str::to_string);
+            if output.is_truncated() != Some(true) || marker.is_none() {
+                return Ok(parts);
+            }
+        }
+    }
+
+    async fn complete_multipart_upload(
+        &self,
+        key: &str,
+        upload_id: &str,
+        parts: &[UploadedPart],
+    ) -> Result<(), StorageError> {
+        let parts = parts
+            .iter()
+            .map(|part| {
+                CompletedPart::builder()
+                    .part_number(part.part_number)
+                    .e_tag(&part.etag)
+                    .build()
+            })
+            .collect();
+        self.client
+            .complete_multipart_upload()
+            .bucket(&self.bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .multipart_upload(
+                CompletedMultipartUpload::builder()
+                    .set_parts(Some(parts))
+                    .build(),
+            )
+            .send()
+            .await
+            .map_err(Self::upload_error(
+                "complete_multipart_upload",
+                key,
+                upload_id,
+            ))?;
+        Ok(())
+    }
+
+    async fn abort_multipart_upload(&self, key: &str, upload_id: &str) -> Result<(), StorageError> {
+        self.client
+            .abort_multipart_upload()
+            .bucket(&self.bucket)
+            .key(key)
+            .upload_id(upload_id)
+            .send()
+            .await
+            .map_err(Self::upload_error("abort_multipart_upload", key, upload_id))?;
+        Ok(())
+    }
+
+    async fn list_multipart_uploads(&self) -> Result<Vec<MultipartUpload>, StorageError> {
+        let mut uploads = Vec::new();
+        let (mut key_marker, mut upload_id_marker) = (None, None);
+        loop {
+            let output = self
+                .client
+                .list_multipart_uploads()
+                .bucket(&self.bucket)
+                .set_key_marker(key_marker)
+                .set_upload_id_marker(upload_id_marker)
+                .send()
+                .await
+                .map_err(backend_error("list_multipart_uploads", ""))?;
+            uploads.extend(output.uploads().iter().filter_map(|upload| {
+                Some(MultipartUpload {
+                    key: upload.key()?.to_string(),
+                    upload_id: upload.upload_id()?.to_string(),
+                    initiated: upload
+                        .initiated()
+                        .and_then(|dt| SystemTime::try_from(*dt).ok())?,
+                })
+            }));
+            key_marker = output.next_key_marker().map(str::to_string);
+            upload_id_marker = output.next_upload_id_marker().map(str::to_string);
+            if output.is_truncated() != Some(true) {
+                return Ok(uploads);
+            }
+        }
+    }
+}
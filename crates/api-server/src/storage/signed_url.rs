@@ -0,0 +1,329 @@
+//! Presigned URLs for backends the server serves itself: HMAC-signed, expiring
+//! `/storage/<key>` URLs (object uploads and downloads, downloads of a version, and
+//! multipart part uploads), and
+//! the GET/`Range` handling that goes with them.
+
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use axum::http::{header, response, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use hmac::{Hmac, Mac};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use rand::RngCore;
+use sha2::Sha256;
+
+use super::Metadata;
+use crate::error::AppError;
+
+/// Characters to percent-encode in keys in URL paths: as for S3, unreserved characters and
+/// '/' stay as they are.
+const KEY_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'/')
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// Characters to percent-encode in query parameter names and values.
+const QUERY_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// Query parameters of a signed URL; metadata travels as `meta-<name>`.
+const EXPIRES_PARAM: &str = "expires";
+const SIGNATURE_PARAM: &str = "signature";
+const METADATA_PARAM_PREFIX: &str = "meta-";
+/// Query parameters of a part upload URL, named as in S3.
+const UPLOAD_ID_PARAM: &str = "uploadId";
+const PART_NUMBER_PARAM: &str = "partNumber";
+/// Query parameter of a download URL for one version, named as in S3.
+const VERSION_ID_PARAM: &str = "versionId";
+
+/// Method signed into part upload URLs, so that a part URL cannot store a whole object.
+const PUT_PART: &str = "PUT-PART";
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |age| age.as_secs())
+}
+
+/// Signs and checks URLs. The signature covers method, key, expiry and metadata, so a
+/// download URL cannot be used to upload and an upload's `content-hash` cannot be altered.
+pub(crate) struct UrlSigner {
+    /// Base URL clients reach this server at, without a trailing slash.
+    public_url: String,
+    /// Random per process, so URLs die with a restart.
+    secret: [u8; 32],
+}
+
+impl UrlSigner {
+    pub(crate) fn new(public_url: &str) -> Self {
+        let mut secret = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut secret);
+        Self {
+            public_url: public_url.trim_end_matches('/').to_string(),
+            secret,
+        }
+    }
+
+    pub(crate) fn mac(
+        &self,
+        method: &str,
+        key: &str,
+        expires: u64,
+        metadata: &Metadata,
+    ) -> Hmac<Sha256> {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC accepts any key length");
+        let expires = expires.to_string();
+        let fields = [method, key, expires.as_str()].into_iter().chain(
+            metadata
+                .iter()
+                .flat_map(|(name, value)| [name.as_str(), value.as_str()]),
+        );
+        // Length-prefixed, so no two different inputs sign the same bytes.
+        for field in fields {
+            mac.update(&(field.len() as u64).to_le_bytes());
+            mac.update(field.as_bytes());
+        }
+        mac
+    }
+
+    /// URL for `method` on `key`, valid for `expires_in`, carrying `metadata`.
+    pub(crate) fn sign(
+        &self,
+        method: &str,
+        key: &str,
+        metadata: &Metadata,
+        expires_in: Duration,
+    ) -> String {
+        let params = metadata
+            .iter()
+            .map(|(name, value)| (format!("{METADATA_PARAM_PREFIX}{name}"), value.as_str()));
+        self.signed_url(method, key, metadata, params, expires_in)
+    }
+
+    /// URL to PUT part `part_number` of multipart upload `upload_id` to.
+    pub(crate) fn sign_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        part_number: i32,
+        expires_in: Duration,
+    ) -> String {
+        let fields = part_fields(upload_id, part_number);
+        let params = fields
+            .iter()
+            .map(|(name, value)| (name.clone(), value.as_str()));
+        self.signed_url(PUT_PART, key, &fields, params, expires_in)
+    }
+
+    /// URL to GET version `version_id` of `key`.
+    pub(crate) fn sign_version(&self, key: &str, version_id: &str, expires_in: Duration) -> String {
+        let fields = Metadata::from([(VERSION_ID_PARAM.to_string(), version_id.to_string())]);
+        let params = fields
+            .iter()
+            .map(|(name, value)| (name.clone(), value.as_str()));
+        self.signed_url("GET", key, &fields, params, expires_in)
+    }
+
+    /// `{public_url}/storage/{key}?{params}&expires=..&signature=..`, signing `fields`.
+    fn signed_url<'a>(
+        &self,
+        method: &str,
+        key: &str,
+        fields: &Metadata,
+        params: impl Iterator<Item = (String, &'a str)>,
+        expires_in: Duration,
+    ) -> String {
+        let expires = unix_now() + expires_in.as_secs();
+        let signature = hex::encode(
+            self.mac(method, key, expires, fields)
+                .finalize()
+                .into_bytes(),
+        );
+        let mut url = format!(
+            "{}/storage/{}?{EXPIRES_PARAM}={expires}",
+            self.public_url,
+            utf8_percent_encode(key, KEY_ENCODE_SET)
+        );
+        for (name, value) in params {
+            url.push_str(&format!(
+                "&{}={}",
+                utf8_percent_encode(&name, QUERY_ENCODE_SET),
+                utf8_percent_encode(value, QUERY_ENCODE_SET)
+            ));
+        }
+        url.push_str(&format!("&{SIGNATURE_PARAM}={signature}"));
+        url
+    }
+
+    /// Check a request against its signed URL and return the metadata it carries.
+    pub(crate) fn verify(
+        &self,
+        method: &str,
+        key: &str,
+        query: &HashMap<String, String>,
+    ) -> Result<Metadata, AppError> {
+        let metadata: Metadata = query
+            .iter()
+            .filter_map(|(name, value)| {
+                let name = name.strip_prefix(METADATA_PARAM_PREFIX)?;
+                Some((name.to_string(), value.clone()))
+            })
+            .collect();
+        self.check(method, key, query, &metadata)?;
+        Ok(metadata)
+    }
+
+    /// Check a GET against its signed URL and return the version it is for, if any.
+    pub(crate) fn verify_download(
+        &self,
+        key: &str,
+        query: &HashMap<String, String>,
+    ) -> Result<Option<String>, AppError> {
+        let version_id = query.get(VERSION_ID_PARAM);
+        let fields = version_id
+            .map(|id| Metadata::from([(VERSION_ID_PARAM.to_string(), id.clone())]))
+            .unwrap_or_default();
+        self.check("GET", key, query, &fields)?;
+        Ok(version_id.cloned())
+    }
+
+    /// Check a part upload against its signed URL and return the upload ID and part number.
+    pub(crate) fn verify_part(
+        &self,
+        key: &str,
+        query: &HashMap<String, String>,
+    ) -> Result<(String, i32), AppError> {
+        let invalid = || AppError::Forbidden("invalid part upload URL".into());
+        let upload_id = query.get(UPLOAD_ID_PARAM).ok_or_else(invalid)?;
+        let part_number: i32 = query
+            .get(PART_NUMBER_PARAM)
+            .and_then(|value| value.parse().ok())
+            .ok_or_else(invalid)?;
+        self.check(PUT_PART, key, query, &part_fields(upload_id, part_number))?;
+        Ok((upload_id.clone(), part_number))
+    }
+
+    fn check(
+        &self,
+        method: &str,
+        key: &str,
+        query: &HashMap<String, String>,
+        fields: &Metadata,
+    ) -> Result<(), AppError> {
+        let invalid = || AppError::Forbidden("invalid or expired signature".into());
+        let expires: u64 = query
+            .get(EXPIRES_PARAM)
+            .and_then(|value| value.parse().ok())
+            .ok_or_else(invalid)?;
+        let signature = query
+            .get(SIGNATURE_PARAM)
+            .and_then(|value| hex::decode(value).ok())
+            .ok_or_else(invalid)?;
+
+        // `verify_slice` compares in constant time.
+        self.mac(method, key, expires, fields)
+            .verify_slice(&signature)
+            .map_err(|_| invalid())?;
+        if expires < unix_now() {
+            return Err(invalid());
+        }
+        Ok(())
+    }
+}
+
+/// Whether a PUT to `/storage/<key>` uploads a part rather than a whole object.
+pub(crate) fn is_part_upload(query: &HashMap<String, String>) -> bool {
+    query.contains_key(UPLOAD_ID_PARAM)
+}
+
+/// What a part upload URL signs besides method, key and expiry.
+fn part_fields(upload_id: &str, part_number: i32) -> Metadata {
+    Metadata::from([
+        (UPLOAD_ID_PARAM.to_string(), upload_id.to_string()),
+        (PART_NUMBER_PARAM.to_string(), part_number.to_string()),
+    ])
+}
+
+/// Upload IDs of the local and memory backends: 32 random hex characters.
+pub(crate) fn new_upload_id() -> String {
+    let mut id = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut id);
+    hex::encode(id)
+}
+
+/// ETag the local and memory backends give a part: hex SHA-256 of its data, quoted as in S3.
+pub(crate) fn part_etag(digest: impl AsRef<[u8]>) -> String {
+    format!("\"{}\"", hex::encode(digest))
+}
+
+/// A `Range: bytes=...` header as an inclusive byte range of a `size`-byte object.
+/// `None` for no or an unparseable header (serve everything); `Some(Err)` when it cannot
+/// be satisfied.
+fn parse_range(headers: &HeaderMap, size: u64) -> Option<Result<(u64, u64), ()>> {
+    let spec = headers
+        .get(header::RANGE)?
+        .to_str()
+        .ok()?
+        .strip_prefix("bytes=")?;
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix: u64 = suffix.parse().ok()?;
+            (size.saturating_sub(suffix), size.checked_sub(1)?)
+        }
+        (start, "") => (start.parse().ok()?, size.saturating_sub(1)),
+        (start, end) => (start.parse().ok()?, end.parse::<u64>().ok()?),
+    };
+    if start > end || start >= size {
+        return Some(Err(()));
+    }
+    Some(Ok((start, end.min(size - 1))))
+}
+
+/// A `Range` outside the object; answered with 416.
+pub(crate) struct RangeNotSatisfiable {
+    size: u64,
+}
+
+impl IntoResponse for RangeNotSatisfiable {
+    fn into_response(self) -> Response {
+        (
+            StatusCode::RANGE_NOT_SATISFIABLE,
+            [(header::CONTENT_RANGE, format!("bytes */{}", self.size))],
+        )
+            .into_response()
+    }
+}
+
+/// How to answer a GET of a `size`-byte object: the byte offset and length to send and a
+/// response with status and headers set, waiting for that body.
+pub(crate) fn read_response(
+    headers: &HeaderMap,
+    size: u64,
+) -> Result<(u64, u64, response::Builder), RangeNotSatisfiable> {
+    let (status, start, len) = match parse_range(headers, size) {
+        None => (StatusCode::OK, 0, size),
+        Some(Ok((start, end))) => (StatusCode::PARTIAL_CONTENT, start, end - start + 1),
+        Some(Err(())) => return Err(RangeNotSatisfiable { size }),
+    };
+
+    let mut response = Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/octet-stream")
+        .header(header::CONTENT_LENGTH, len)
+        .header(header::ACCEPT_RANGES, "bytes");
+    if status == StatusCode::PARTIAL_CONTENT {
+        response = response.header(
+            header::CONTENT_RANGE,
+            format!("bytes {start}-{}/{size}", start + len - 1),
+        );
+    }
+    Ok((start, len, response))
+}
@@ -0,0 +1,56 @@
+//! The whole server, in-process on a loopback port with [`MemoryBackend`] storage: what
+//! hermetic tests here and in the CLI run against instead of docker-compose and MinIO.
+
+use std::sync::Arc;
+
+use axum::Router;
+
+use crate::config::{AppConfig, StorageKind};
+use crate::routes::{router_with_auth, AppState};
+use crate::storage::MemoryBackend;
+
+pub struct InMemoryServer {
+    /// `http://127.0.0.1:<port>`, without a trailing slash.
+    pub base_url: String,
+    /// The objects, for seeding and inspecting them directly.
+    pub storage: Arc<MemoryBackend>,
+    /// The app being served, for in-process requests (e.g. through `axum-test`).
+    pub router: Router,
+}
+
+/// Serve the app with bearer token `api_key` on a free port until the runtime shuts down.
+pub async fn spawn_in_memory(api_key: &str) -> InMemoryServer {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("failed to bind a loopback port");
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    let config = AppConfig {
+        port: 0,
+        storage_backend: StorageKind::Memory,
+        s3_bucket: String::new(),
+        api_key: api_key.into(),
+        aws_region: String::new(),
+        s3_endpoint_url: None,
+        s3_force_path_style: false,
+        s3_public_endpoint_url: None,
+        local_storage_dir: None,
+        public_url: Some(base_url.clone()),
+        multipart_max_age_hours: 24,
+    };
+    let storage = Arc::new(MemoryBackend::from_config(&config));
+    let state = AppState {
+        storage: storage.clone(),
+        config,
+    };
+    let router = Router::new()
+        .merge(router_with_auth(state.clone()))
+        .with_state(state);
+
+    let app = router.clone();
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    InMemoryServer {
+        base_url,
+        storage,
+        router,
+    }
+}
@@ -1,18 +1,28 @@
+use std::sync::Arc;
+
 use axum::http::{HeaderName, HeaderValue};
 use axum::Router;
 use axum_test::TestServer;
-use serde_json::json;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde_json::{json, Value};
 
-use solidrop_api_server::config::AppConfig;
+use solidrop_api_server::config::{AppConfig, StorageKind};
 use solidrop_api_server::routes::{router_with_auth, AppState};
 use solidrop_api_server::s3_client::create_s3_client;
+use solidrop_api_server::storage::{LocalBackend, S3Backend, StorageBackend};
+use solidrop_api_server::testing::spawn_in_memory;
 
 const TEST_API_KEY: &str = "test-secret-key";
 
-/// Build a test-ready AppConfig pointing to MinIO.
+/// A well-formed keyed content fingerprint, as the presign endpoint requires.
+const TEST_FINGERPRINT: &str =
+    "hmac-sha256:00112233445566778899aabbccddeeff00112233445566778899aabbccddeeff";
+
+/// Build an AppConfig pointing to MinIO.
 fn test_config() -> AppConfig {
     AppConfig {
         port: 3000,
+        storage_backend: StorageKind::S3,
         s3_bucket: std::env::var("S3_BUCKET").unwrap_or_else(|_| "solidrop-dev".into()),
         api_key: TEST_API_KEY.into(),
         aws_region: "us-east-1".into(),
@@ -24,15 +34,24 @@ fn test_config() -> AppConfig {
             std::env::var("S3_PUBLIC_ENDPOINT_URL")
                 .unwrap_or_else(|_| "http://localhost:9000".into()),
         ),
+        local_storage_dir: None,
+        public_url: None,
+        multipart_max_age_hours: 24,
     }
 }
 
-/// Build the full app router with auth middleware.
+/// Build the full app router with auth middleware, served in-process on the in-memory
+/// backend so presigned URLs work without MinIO.
 async fn test_app() -> Router {
+    spawn_in_memory(TEST_API_KEY).await.router
+}
+
+/// Build the full app router with auth middleware, on MinIO.
+async fn minio_app() -> Router {
     let config = test_config();
     let s3 = create_s3_client(&config).await;
     let state = AppState {
-        s3,
+        storage: Arc::new(S3Backend::new(s3, &config)),
         config: config.clone(),
     };
     Router::new()
@@ -47,7 +66,7 @@ fn auth_header() -> (HeaderName, HeaderValue) {
     )
 }
 
-// ─── Non-S3 Tests (always run) ─────────────────────────────
+// ─── Route Tests (always run, in-memory backend) ───────────
 
 #[tokio::test]
 async fn test_health_no_auth() {
@@ -66,7 +85,7 @@ async fn test_401_without_token() {
 
     let resp = server
         .post("/api/v1/presign/upload")
-        .json(&json!({"path": "test.enc", "content_hash": "abc", "size_bytes": 100}))
+        .json(&json!({"path": "test.enc", "content_hash": TEST_FINGERPRINT, "size_bytes": 100}))
         .await;
     resp.assert_status_unauthorized();
 }
@@ -82,7 +101,7 @@ async fn test_401_with_wrong_token() {
             HeaderName::from_static("authorization"),
             HeaderValue::from_static("Bearer wrong-token"),
         )
-        .json(&json!({"path": "test.enc", "content_hash": "abc", "size_bytes": 100}))
+        .json(&json!({"path": "test.enc", "content_hash": TEST_FINGERPRINT, "size_bytes": 100}))
         .await;
     resp.assert_status_unauthorized();
 }
@@ -98,7 +117,7 @@ async fn test_auth_with_valid_token_passes() {
     let resp = server
         .post("/api/v1/presign/upload")
         .add_header(header_name.clone(), header_val.clone())
-        .json(&json!({"path": "test.enc", "content_hash": "abc", "size_bytes": 100}))
+        .json(&json!({"path": "test.enc", "content_hash": TEST_FINGERPRINT, "size_bytes": 100}))
         .await;
 
     // Should be anything except 401
@@ -109,6 +128,23 @@ async fn test_auth_with_valid_token_passes() {
     );
 }
 
+#[tokio::test]
+async fn test_presign_upload_rejects_plain_hash() {
+    let app = test_app().await;
+    let server = TestServer::new(app).unwrap();
+
+    let (header_name, header_val) = auth_header();
+    let plain = format!("sha256:{}", "ab".repeat(32));
+    let resp = server
+        .post("/api/v1/presign/upload")
+        .add_header(header_name, header_val)
+        .json(&json!({"path": "test.enc", "content_hash": plain, "size_bytes": 100}))
+        .await;
+    resp.assert_status_bad_request();
+    let body: serde_json::Value = resp.json();
+    assert_eq!(body["error"]["code"], "BAD_REQUEST");
+}
+
 #[tokio::test]
 async fn test_cache_report_no_overage() {
     let app = test_app().await;
@@ -179,6 +215,139 @@ async fn test_cache_report_empty_files() {
     assert_eq!(body["evict_candidates"], json!([]));
 }
 
+// ─── Local Backend (always run) ────────────────────────────
+
+/// Serve the app over HTTP with the local backend in `dir`; returns the base URL.
+async fn serve_local(dir: &std::path::Path) -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    let config = AppConfig {
+        storage_backend: StorageKind::Local,
+        local_storage_dir: Some(dir.to_path_buf()),
+        public_url: Some(base_url.clone()),
+        ..test_config()
+    };
+    let state = AppState {
+        storage: Arc::new(LocalBackend::from_config(&config).unwrap()),
+        config,
+    };
+    let app = Router::new()
+        .merge(router_with_auth(state.clone()))
+        .with_state(state);
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    base_url
+}
+
+/// Send an authenticated API request; returns the status and JSON body.
+async fn api_call(
+    request: reqwest::RequestBuilder,
+    body: Option<Value>,
+) -> (reqwest::StatusCode, Value) {
+    let mut request = request.bearer_auth(TEST_API_KEY);
+    if let Some(body) = body {
+        request = request
+            .header("content-type", "application/json")
+            .body(body.to_string());
+    }
+    let resp = request.send().await.unwrap();
+    let status = resp.status();
+    (
+        status,
+        serde_json::from_str(&resp.text().await.unwrap()).unwrap(),
+    )
+}
+
+/// `GET /api/v1/files/versions` for `path`.
+fn versions_request(
+    client: &reqwest::Client,
+    base_url: &str,
+    path: &str,
+) -> reqwest::RequestBuilder {
+    let path = utf8_percent_encode(path, NON_ALPHANUMERIC);
+    client.get(format!("{base_url}/api/v1/files/versions?path={path}"))
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_local_backend_roundtrip() {
+    let dir = tempfile::tempdir().unwrap();
+    let base_url = serve_local(dir.path()).await;
+    let client = reqwest::Client::new();
+    let api = |path: &str| format!("{base_url}{path}");
+
+    let (status, body) = api_call(
+        client.post(api("/api/v1/presign/upload")),
+        Some(json!({
+            "path": "active/2026-02/my drawing.png.enc",
+            "content_hash": TEST_FINGERPRINT,
+            "size_bytes": 7
+        })),
+    )
+    .await;
+    assert_eq!(status, 200);
+    let upload_url = body["upload_url"].as_str().unwrap();
+    assert!(upload_url.starts_with(&format!("{base_url}/storage/")));
+    // Signed URLs need no bearer token.
+    let resp = client.put(upload_url).body("payload").send().await.unwrap();
+    assert_eq!(resp.status(), 200);
+
+    let (status, body) = api_call(client.get(api("/api/v1/files?prefix=active/")), None).await;
+    assert_eq!(status, 200);
+    let files = body["files"].as_array().unwrap();
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0]["key"], "active/2026-02/my drawing.png.enc");
+    assert_eq!(files[0]["size"], 7);
+    assert_eq!(files[0]["content_hash"], TEST_FINGERPRINT);
+
+    // No versioning: the object is its only version.
+    let (status, body) = api_call(
+        versions_request(&client, &base_url, "active/2026-02/my drawing.png.enc"),
+        None,
+    )
+    .await;
+    assert_eq!(status, 200);
+    let versions = body["versions"].as_array().unwrap();
+    assert_eq!(versions.len(), 1);
+    assert_eq!(versions[0]["version_id"], "null");
+    assert_eq!(versions[0]["content_hash"], TEST_FINGERPRINT);
+
+    let (status, _) = api_call(
+        client.post(api("/api/v1/files/move")),
+        Some(json!({
+            "from": "active/2026-02/my drawing.png.enc",
+            "to": "archived/2026-02/my drawing.png.enc"
+        })),
+    )
+    .await;
+    assert_eq!(status, 200);
+
+    let (status, body) = api_call(
+        client.post(api("/api/v1/presign/download")),
+        Some(json!({"path": "archived/2026-02/my drawing.png.enc"})),
+    )
+    .await;
+    assert_eq!(status, 200);
+    let download_url = body["download_url"].as_str().unwrap();
+    let resp = client.get(download_url).send().await.unwrap();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.text().await.unwrap(), "payload");
+    let resp = client.get(format!("{download_url}0")).send().await.unwrap();
+    assert_eq!(resp.status(), 403);
+
+    let (status, _) = api_call(
+        client.delete(api("/api/v1/files/archived/2026-02/my drawing.png.enc")),
+        None,
+    )
+    .await;
+    assert_eq!(status, 200);
+    let (status, body) = api_call(
+        client.delete(api("/api/v1/files/archived/2026-02/my drawing.png.enc")),
+        None,
+    )
+    .await;
+    assert_eq!(status, 404);
+    assert_eq!(body["error"]["code"], "FILE_NOT_FOUND");
+}
+
 // ─── Error/edge-case tests (no S3 required) ────────────────
 
 #[tokio::test]
@@ -239,60 +408,9 @@ async fn test_cache_report_timezone_handling() {
     assert_eq!(candidates[0]["path"], "earlier-tz.enc");
 }
 
-// ─── S3 Integration Tests (require MinIO) ──────────────────
-
-#[tokio::test]
-#[ignore]
-async fn test_presign_upload_returns_url() {
-    let app = test_app().await;
-    let server = TestServer::new(app).unwrap();
-
-    let (header_name, header_val) = auth_header();
-    let resp = server
-        .post("/api/v1/presign/upload")
-        .add_header(header_name.clone(), header_val.clone())
-        .json(&json!({
-            "path": "test/upload.enc",
-            "content_hash": "abc123",
-            "size_bytes": 1024
-        }))
-        .await;
-
-    resp.assert_status_ok();
-    let body: serde_json::Value = resp.json();
-    let url = body["upload_url"].as_str().unwrap();
-    assert!(
-        url.contains("test/upload.enc"),
-        "URL should contain the key"
-    );
-    assert!(
-        url.contains("X-Amz-"),
-        "URL should have presigning parameters"
-    );
-}
-
-#[tokio::test]
-#[ignore]
-async fn test_presign_download_returns_url() {
-    let app = test_app().await;
-    let server = TestServer::new(app).unwrap();
-
-    let (header_name, header_val) = auth_header();
-    let resp = server
-        .post("/api/v1/presign/download")
-        .add_header(header_name.clone(), header_val.clone())
-        .json(&json!({"path": "test/download.enc"}))
-        .await;
-
-    resp.assert_status_ok();
-    let body: serde_json::Value = resp.json();
-    let url = body["download_url"].as_str().unwrap();
-    assert!(url.contains("test/download.enc"));
-    assert!(url.contains("X-Amz-"));
-}
+// ─── Storage Flows (always run, in-memory backend) ─────────
 
 #[tokio::test]
-#[ignore]
 async fn test_list_files_empty() {
     let app = test_app().await;
     let server = TestServer::new(app).unwrap();
@@ -311,7 +429,6 @@ async fn test_list_files_empty() {
 }
 
 #[tokio::test]
-#[ignore]
 async fn test_upload_then_list() {
     let app = test_app().await;
     let server = TestServer::new(app).unwrap();
@@ -323,7 +440,7 @@ async fn test_upload_then_list() {
         .add_header(header_name.clone(), header_val.clone())
         .json(&json!({
             "path": "integration-test/list-test.enc",
-            "content_hash": "testhash123",
+            "content_hash": TEST_FINGERPRINT,
             "size_bytes": 11
         }))
         .await;
@@ -337,7 +454,7 @@ async fn test_upload_then_list() {
     let client = reqwest::Client::new();
     let upload_resp = client
         .put(&upload_url)
-        .header("x-amz-meta-content-hash", "testhash123")
+        .header("x-amz-meta-content-hash", TEST_FINGERPRINT)
         .header("x-amz-meta-original-size", "11")
         .body("hello world")
         .send()
@@ -375,7 +492,6 @@ async fn test_upload_then_list() {
 }
 
 #[tokio::test]
-#[ignore]
 async fn test_delete_nonexistent_returns_404() {
     let app = test_app().await;
     let server = TestServer::new(app).unwrap();
@@ -390,7 +506,42 @@ async fn test_delete_nonexistent_returns_404() {
 }
 
 #[tokio::test]
-#[ignore]
+async fn test_delete_rejects_reserved_path() {
+    let server = spawn_in_memory(TEST_API_KEY).await;
+    let storage = server.storage.clone();
+    let server = TestServer::new(server.router).unwrap();
+    let (header_name, header_val) = auth_header();
+    let entry = ".solidrop/by-hash/ab";
+    storage.put(entry, "", Default::default());
+
+    server
+        .delete(&format!("/api/v1/files/{entry}"))
+        .add_header(header_name, header_val)
+        .await
+        .assert_status_bad_request();
+    assert!(storage.get(entry).is_some());
+}
+
+#[tokio::test]
+async fn test_move_rejects_reserved_source() {
+    let server = spawn_in_memory(TEST_API_KEY).await;
+    let storage = server.storage.clone();
+    let server = TestServer::new(server.router).unwrap();
+    let (header_name, header_val) = auth_header();
+    let entry = ".solidrop/by-hash/ab";
+    storage.put(entry, "", Default::default());
+
+    server
+        .post("/api/v1/files/move")
+        .add_header(header_name, header_val)
+        .json(&json!({"from": entry, "to": "active/stolen.enc"}))
+        .await
+        .assert_status_bad_request();
+    assert!(storage.get(entry).is_some());
+    assert!(storage.get("active/stolen.enc").is_none());
+}
+
+#[tokio::test]
 async fn test_move_file() {
     let app = test_app().await;
     let server = TestServer::new(app).unwrap();
@@ -402,7 +553,7 @@ async fn test_move_file() {
         .add_header(header_name.clone(), header_val.clone())
         .json(&json!({
             "path": "integration-test/move-source.enc",
-            "content_hash": "movehash",
+            "content_hash": TEST_FINGERPRINT,
             "size_bytes": 9
         }))
         .await;
@@ -415,7 +566,7 @@ async fn test_move_file() {
     let client = reqwest::Client::new();
     client
         .put(&upload_url)
-        .header("x-amz-meta-content-hash", "movehash")
+        .header("x-amz-meta-content-hash", TEST_FINGERPRINT)
         .header("x-amz-meta-original-size", "9")
         .body("move test")
         .send()
@@ -453,7 +604,6 @@ async fn test_move_file() {
 }
 
 #[tokio::test]
-#[ignore]
 async fn test_move_encoded_key() {
     let app = test_app().await;
     let server = TestServer::new(app).unwrap();
@@ -465,7 +615,7 @@ async fn test_move_encoded_key() {
         .add_header(header_name.clone(), header_val.clone())
         .json(&json!({
             "path": "integration-test/my drawing (1).enc",
-            "content_hash": "spacehash",
+            "content_hash": TEST_FINGERPRINT,
             "size_bytes": 4
         }))
         .await;
@@ -478,7 +628,7 @@ async fn test_move_encoded_key() {
     let client = reqwest::Client::new();
     client
         .put(&upload_url)
-        .header("x-amz-meta-content-hash", "spacehash")
+        .header("x-amz-meta-content-hash", TEST_FINGERPRINT)
         .header("x-amz-meta-original-size", "4")
         .body("test")
         .send()
@@ -499,12 +649,297 @@ async fn test_move_encoded_key() {
     let body: serde_json::Value = resp.json();
     assert_eq!(body["moved"], true);
 
-    // Cleanup
+    // Cleanup (the key is percent-encoded in the URL path)
+    let (header_name, header_val) = auth_header();
+    server
+        .delete("/api/v1/files/integration-test/my%20drawing%20(moved).enc")
+        .add_header(header_name.clone(), header_val.clone())
+        .await
+        .assert_status_ok();
+}
+
+#[tokio::test]
+async fn test_check_reports_duplicates() {
+    let server = spawn_in_memory(TEST_API_KEY).await;
+    let storage = server.storage.clone();
+    let server = TestServer::new(server.router).unwrap();
     let (header_name, header_val) = auth_header();
+    let check = |path: &str, content_hash: &str| {
+        server
+            .post("/api/v1/files/check")
+            .add_header(header_name.clone(), header_val.clone())
+            .json(&json!({"path": path, "content_hash": content_hash}))
+    };
+    let other_hash = format!("hmac-sha256:{}", "ff".repeat(32));
+
+    let resp = check("active/2026-02/a.clip.enc", TEST_FINGERPRINT).await;
+    resp.assert_status_ok();
+    resp.assert_json(&json!({"status": "needs_upload"}));
+
+    // Presigning indexes the fingerprint; the entry counts once the object is there.
     server
-        .delete("/api/v1/files/integration-test/my drawing (moved).enc")
+        .post("/api/v1/presign/upload")
+        .add_header(header_name.clone(), header_val.clone())
+        .json(&json!({
+            "path": "active/2026-01/a.clip.enc",
+            "content_hash": TEST_FINGERPRINT,
+            "size_bytes": 10
+        }))
+        .await
+        .assert_status_ok();
+    check("active/2026-02/a.clip.enc", TEST_FINGERPRINT)
+        .await
+        .assert_json(&json!({"status": "needs_upload"}));
+    storage.put(
+        "active/2026-01/a.clip.enc",
+        "ciphertext",
+        [("content-hash".to_string(), TEST_FINGERPRINT.to_string())].into(),
+    );
+    check("active/2026-01/a.clip.enc", TEST_FINGERPRINT)
+        .await
+        .assert_json(&json!({"status": "already_uploaded"}));
+    check("active/2026-02/a.clip.enc", TEST_FINGERPRINT)
+        .await
+        .assert_json(&json!({
+            "status": "exists_at_other_path",
+            "existing_path": "active/2026-01/a.clip.enc"
+        }));
+    // Same path, changed content: the upload replaces it.
+    check("active/2026-01/a.clip.enc", &other_hash)
+        .await
+        .assert_json(&json!({"status": "needs_upload"}));
+
+    // The index follows a move.
+    server
+        .post("/api/v1/files/move")
+        .add_header(header_name.clone(), header_val.clone())
+        .json(&json!({"from": "active/2026-01/a.clip.enc", "to": "archived/a.clip.enc"}))
+        .await
+        .assert_status_ok();
+    check("active/2026-02/a.clip.enc", TEST_FINGERPRINT)
+        .await
+        .assert_json(&json!({
+            "status": "exists_at_other_path",
+            "existing_path": "archived/a.clip.enc"
+        }));
+
+    // The index is neither listed nor writable by clients.
+    let resp = server
+        .get("/api/v1/files")
+        .add_header(header_name.clone(), header_val.clone())
+        .await;
+    let listed: Vec<_> = resp.json::<Value>()["files"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|file| file["key"].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(listed, ["archived/a.clip.enc"]);
+    server
+        .post("/api/v1/presign/upload")
+        .add_header(header_name.clone(), header_val.clone())
+        .json(&json!({
+            "path": ".solidrop/by-hash/x",
+            "content_hash": TEST_FINGERPRINT,
+            "size_bytes": 10
+        }))
+        .await
+        .assert_status_bad_request();
+
+    let plain = format!("sha256:{}", "ab".repeat(32));
+    check("active/2026-02/a.clip.enc", &plain)
+        .await
+        .assert_status_bad_request();
+}
+
+#[tokio::test]
+async fn test_multipart_upload_flow() {
+    let server = spawn_in_memory(TEST_API_KEY).await;
+    let client = reqwest::Client::new();
+    let api = |path: &str| format!("{}/api/v1/multipart/{path}", server.base_url);
+    let key = "active/2026-02/large.clip.enc";
+
+    let (status, body) = api_call(
+        client.post(api("initiate")),
+        Some(json!({"path": key, "content_hash": TEST_FINGERPRINT, "size_bytes": 11})),
+    )
+    .await;
+    assert_eq!(status, 200);
+    let upload_id = body["upload_id"].as_str().unwrap().to_string();
+
+    // Parts may arrive in any order.
+    for (part_number, data) in [(2, "world"), (1, "hello ")] {
+        let (status, body) = api_call(
+            client.post(api("presign-part")),
+            Some(json!({"path": key, "upload_id": upload_id, "part_number": part_number})),
+        )
+        .await;
+        assert_eq!(status, 200);
+        let url = body["upload_url"].as_str().unwrap();
+        let resp = client.put(url).body(data).send().await.unwrap();
+        assert_eq!(resp.status(), 200);
+        assert!(resp.headers().contains_key("etag"));
+    }
+    let (status, _) = api_call(
+        client.post(api("presign-part")),
+        Some(json!({"path": key, "upload_id": upload_id, "part_number": 0})),
+    )
+    .await;
+    assert_eq!(status, 400);
+
+    let upload = json!({"path": key, "upload_id": upload_id});
+    let (status, body) = api_call(client.post(api("list-parts")), Some(upload.clone())).await;
+    assert_eq!(status, 200);
+    assert_eq!(
+        body,
+        json!({"parts": [{"part_number": 1, "size": 6}, {"part_number": 2, "size": 5}]})
+    );
+
+    let (status, body) = api_call(
+        client.post(api("complete")),
+        Some(json!({"path": key, "upload_id": upload_id, "part_count": 3})),
+    )
+    .await;
+    assert_eq!(status, 400);
+    assert_eq!(body["error"]["message"], "part 3 has not been uploaded");
+    let (status, body) = api_call(
+        client.post(api("complete")),
+        Some(json!({"path": key, "upload_id": upload_id, "part_count": 2})),
+    )
+    .await;
+    assert_eq!(status, 200);
+    assert_eq!(body, json!({"completed": true}));
+    assert_eq!(server.storage.get(key).unwrap(), "hello world");
+    let head = server.storage.head(key).await.unwrap();
+    assert_eq!(head.metadata["content-hash"], TEST_FINGERPRINT);
+
+    // The upload is gone once completed, and an aborted one too.
+    let (status, _) = api_call(client.post(api("list-parts")), Some(upload)).await;
+    assert_eq!(status, 404);
+    let (_, body) = api_call(
+        client.post(api("initiate")),
+        Some(json!({"path": key, "content_hash": TEST_FINGERPRINT, "size_bytes": 11})),
+    )
+    .await;
+    let upload = json!({"path": key, "upload_id": body["upload_id"]});
+    let (status, body) = api_call(client.post(api("abort")), Some(upload.clone())).await;
+    assert_eq!(status, 200);
+    assert_eq!(body, json!({"aborted": true}));
+    let (status, _) = api_call(client.post(api("abort")), Some(upload)).await;
+    assert_eq!(status, 404);
+}
+
+#[tokio::test]
+async fn test_versions_and_restore() {
+    let server = spawn_in_memory(TEST_API_KEY).await;
+    let client = reqwest::Client::new();
+    let api = |path: &str| format!("{}{path}", server.base_url);
+    let key = "active/2026-02/a.clip.enc";
+    let other_hash = format!("hmac-sha256:{}", "ff".repeat(32));
+    let hash = |value: &str| [("content-hash".to_string(), value.to_string())].into();
+    let first = server.storage.put(key, "first", hash(TEST_FINGERPRINT));
+    server.storage.put(key, "second!", hash(&other_hash));
+    server.storage.delete(key).await.unwrap();
+    let versions = || async {
+        let request = versions_request(&client, &server.base_url, key);
+        let (status, body) = api_call(request, None).await;
+        assert_eq!(status, 200);
+        body["versions"].as_array().unwrap().clone()
+    };
+
+    let listed = versions().await;
+    assert_eq!(listed.len(), 3);
+    assert_eq!(listed[0]["is_delete_marker"], true);
+    assert_eq!(listed[0]["is_latest"], true);
+    assert_eq!(listed[0]["content_hash"], json!(null));
+    assert_eq!(listed[1]["content_hash"], other_hash);
+    assert_eq!(listed[1]["size"], 7);
+    assert_eq!(listed[2]["version_id"], first);
+    assert_eq!(listed[2]["content_hash"], TEST_FINGERPRINT);
+
+    let (status, body) = api_call(
+        client.post(api("/api/v1/presign/download")),
+        Some(json!({"path": key, "version_id": first})),
+    )
+    .await;
+    assert_eq!(status, 200);
+    let resp = client
+        .get(body["download_url"].as_str().unwrap())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.text().await.unwrap(), "first");
+
+    let restore = |version_id: &Value| {
+        api_call(
+            client.post(api("/api/v1/files/restore")),
+            Some(json!({"path": key, "version_id": version_id})),
+        )
+    };
+    assert_eq!(restore(&listed[0]["version_id"]).await.0, 400);
+    assert_eq!(restore(&json!("no-such-version")).await.0, 404);
+    let (status, body) = restore(&json!(first)).await;
+    assert_eq!(status, 200);
+    assert_eq!(body, json!({"restored": true}));
+    assert_eq!(server.storage.get(key).unwrap(), "first");
+    let listed = versions().await;
+    assert_eq!(listed.len(), 4);
+    assert_eq!(listed[0]["content_hash"], TEST_FINGERPRINT);
+
+    let request = versions_request(&client, &server.base_url, "active/missing.enc");
+    assert_eq!(api_call(request, None).await.0, 404);
+}
+
+// ─── S3-Specific Tests (require MinIO) ─────────────────────
+
+#[tokio::test]
+#[ignore]
+async fn test_presign_upload_returns_url() {
+    let app = minio_app().await;
+    let server = TestServer::new(app).unwrap();
+
+    let (header_name, header_val) = auth_header();
+    let resp = server
+        .post("/api/v1/presign/upload")
         .add_header(header_name.clone(), header_val.clone())
+        .json(&json!({
+            "path": "test/upload.enc",
+            "content_hash": TEST_FINGERPRINT,
+            "size_bytes": 1024
+        }))
         .await;
+
+    resp.assert_status_ok();
+    let body: serde_json::Value = resp.json();
+    let url = body["upload_url"].as_str().unwrap();
+    assert!(
+        url.contains("test/upload.enc"),
+        "URL should contain the key"
+    );
+    assert!(
+        url.contains("X-Amz-"),
+        "URL should have presigning parameters"
+    );
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_presign_download_returns_url() {
+    let app = minio_app().await;
+    let server = TestServer::new(app).unwrap();
+
+    let (header_name, header_val) = auth_header();
+    let resp = server
+        .post("/api/v1/presign/download")
+        .add_header(header_name.clone(), header_val.clone())
+        .json(&json!({"path": "test/download.enc"}))
+        .await;
+
+    resp.assert_status_ok();
+    let body: serde_json::Value = resp.json();
+    let url = body["download_url"].as_str().unwrap();
+    assert!(url.contains("test/download.enc"));
+    assert!(url.contains("X-Amz-"));
 }
 
 #[tokio::test]
@@ -512,12 +947,9 @@ async fn test_move_encoded_key() {
 async fn test_delete_s3_error_not_masked() {
     // This test verifies that S3 errors other than 404 are not mapped to NotFound.
     // We create a server with an invalid S3 endpoint to simulate connection failure.
-    use solidrop_api_server::config::AppConfig;
-    use solidrop_api_server::routes::{router_with_auth, AppState};
-    use solidrop_api_server::s3_client::create_s3_client;
-
     let config = AppConfig {
         port: 3000,
+        storage_backend: StorageKind::S3,
         s3_bucket: "solidrop-dev".into(),
         api_key: TEST_API_KEY.into(),
         aws_region: "us-east-1".into(),
@@ -525,10 +957,13 @@ async fn test_delete_s3_error_not_masked() {
         s3_endpoint_url: Some("http://localhost:1".into()),
         s3_force_path_style: true,
         s3_public_endpoint_url: Some("http://localhost:1".into()),
+        local_storage_dir: None,
+        public_url: None,
+        multipart_max_age_hours: 24,
     };
     let s3 = create_s3_client(&config).await;
     let state = AppState {
-        s3,
+        storage: Arc::new(S3Backend::new(s3, &config)),
         config: config.clone(),
     };
     let app = Router::new()
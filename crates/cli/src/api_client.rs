@@ -1,9 +1,18 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
 use anyhow::{bail, Context, Result};
 use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
 use reqwest::Client;
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use solidrop_crypto::hash::ContentFingerprint;
 
-use crate::config::CliConfig;
+use crate::config::{data_dir, Backend, CliConfig};
+use crate::direct_s3::DirectS3;
+use crate::upload_journal::UploadJournal;
 
 /// Characters that are safe in a URL path segment (not percent-encoded).
 /// We keep alphanumerics, `-`, `_`, `.`, and `~` unencoded per RFC 3986.
@@ -13,10 +22,32 @@ const PATH_SEGMENT_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
     .remove(b'.')
     .remove(b'~');
 
+/// Objects larger than this are uploaded to the API server in parts of this size, so a
+/// network failure costs one part rather than the whole file. S3 needs parts of at least
+/// 5 MiB, except the last.
+pub const MULTIPART_PART_SIZE: usize = 8 * 1024 * 1024;
+
+/// Attempts at sending the missing parts of an upload before giving up until the next run.
+const MULTIPART_ATTEMPTS: u32 = 3;
+/// Journaled uploads older than this are given up: the API server's default
+/// `MULTIPART_MAX_AGE_HOURS`, after which it aborts them itself.
+const JOURNAL_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Remote storage for the commands: the API server, or S3 directly (see [`Backend`]).
+///
+/// Object bytes always travel over plain HTTP via presigned URLs, except uploads in direct
+/// mode, which go through the SDK.
 pub struct ApiClient {
     client: Client,
-    base_url: String,
-    api_key: String,
+    backend: RemoteBackend,
+    /// Where multipart uploads are recorded so a later run can resume them; without one an
+    /// interrupted upload starts over.
+    journal: Option<UploadJournal>,
+}
+
+enum RemoteBackend {
+    Server { base_url: String, api_key: String },
+    Direct(DirectS3),
 }
 
 // --- Request/Response types matching the API server ---
@@ -34,8 +65,10 @@ struct PresignUploadResponse {
 }
 
 #[derive(Serialize)]
-struct PresignDownloadRequest {
-    path: String,
+struct PresignDownloadRequest<'a> {
+    path: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version_id: Option<&'a str>,
 }
 
 #[derive(Deserialize)]
@@ -57,6 +90,92 @@ struct ListResponse {
     pub next_token: Option<String>,
 }
 
+/// One version of a remote file, newest first in [`ApiClient::list_versions`].
+#[derive(Debug, Deserialize)]
+pub struct FileVersion {
+    pub version_id: String,
+    pub size: i64,
+    pub last_modified: Option<String>,
+    pub is_latest: bool,
+    /// The file was deleted here; there is no data.
+    pub is_delete_marker: bool,
+    pub content_hash: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct VersionsResponse {
+    versions: Vec<FileVersion>,
+}
+
+#[derive(Serialize)]
+struct RestoreRequest<'a> {
+    path: &'a str,
+    version_id: &'a str,
+}
+
+#[derive(Serialize)]
+struct CheckRequest {
+    path: String,
+    content_hash: String,
+}
+
+/// Whether an upload is needed, as `POST /files/check` reports it.
+#[derive(Debug, PartialEq, Eq, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum UploadCheck {
+    /// The path already holds this content.
+    AlreadyUploaded,
+    /// Another object already holds this content.
+    ExistsAtOtherPath {
+        existing_path: String,
+    },
+    NeedsUpload,
+}
+
+#[derive(Serialize)]
+struct InitiateMultipartRequest<'a> {
+    path: &'a str,
+    content_hash: String,
+    size_bytes: u64,
+}
+
+#[derive(Deserialize)]
+struct InitiateMultipartResponse {
+    upload_id: String,
+}
+
+#[derive(Serialize)]
+struct PresignPartRequest<'a> {
+    path: &'a str,
+    upload_id: &'a str,
+    part_number: i32,
+}
+
+#[derive(Serialize)]
+struct CompleteMultipartRequest<'a> {
+    path: &'a str,
+    upload_id: &'a str,
+    part_count: i32,
+}
+
+#[derive(Deserialize)]
+struct ListPartsResponse {
+    parts: Vec<PartEntry>,
+}
+
+#[derive(Deserialize)]
+struct PartEntry {
+    part_number: i32,
+    size: i64,
+}
+
+/// A multipart upload in progress on the API server: what it takes to resume it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MultipartUpload {
+    pub path: String,
+    pub upload_id: String,
+}
+
 #[derive(Serialize)]
 struct MoveRequest {
     from: String,
@@ -75,40 +194,381 @@ struct ApiErrorDetail {
 }
 
 impl ApiClient {
-    pub fn from_config(config: &CliConfig) -> Result<Self> {
-        let api_key = std::env::var(&config.server.api_key_env).with_context(|| {
-            format!(
-                "environment variable '{}' not set (required for API authentication)",
-                config.server.api_key_env
-            )
-        })?;
+    /// Client for the API server at `endpoint` (e.g. `http://localhost:3000/api/v1`).
+    pub fn new(endpoint: &str, api_key: &str) -> Self {
+        Self {
+            client: Client::new(),
+            backend: RemoteBackend::Server {
+                base_url: endpoint.trim_end_matches('/').to_string(),
+                api_key: api_key.to_string(),
+            },
+            journal: None,
+        }
+    }
+
+    /// Record multipart uploads in `dir`, and resume the ones recorded there.
+    pub fn with_upload_journal(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.journal = Some(UploadJournal::new(dir));
+        self
+    }
+
+    pub async fn from_config(config: &CliConfig) -> Result<Self> {
+        match config.server.backend {
+            Backend::Api => {
+                let api_key = std::env::var(&config.server.api_key_env).with_context(|| {
+                    format!(
+                        "environment variable '{}' not set (required for API authentication)",
+                        config.server.api_key_env
+                    )
+                })?;
+                Ok(Self::new(&config.server.endpoint, &api_key)
+                    .with_upload_journal(data_dir()?.join("uploads")))
+            }
+            Backend::S3 => {
+                let s3 = config
+                    .s3
+                    .as_ref()
+                    .context("server.backend is \"s3\" but the config has no [s3] section")?;
+                Ok(Self {
+                    client: Client::new(),
+                    backend: RemoteBackend::Direct(DirectS3::from_config(s3).await),
+                    journal: None,
+                })
+            }
+        }
+    }
+
+    /// Store an encrypted object at `path`, with the fingerprint as its `content-hash`
+    /// metadata. Through the API server, objects over [`MULTIPART_PART_SIZE`] go up in
+    /// parts, resuming after failed parts, and with a journal also after the process ends:
+    /// an upload to `path` of the same content that was cut off then is finished with the
+    /// ciphertext it was started with, and `data` is not sent.
+    pub async fn upload(
+        &self,
+        path: &str,
+        fingerprint: &ContentFingerprint,
+        data: &[u8],
+    ) -> Result<()> {
+        match &self.backend {
+            RemoteBackend::Server { .. } if data.len() > MULTIPART_PART_SIZE => {
+                self.upload_in_parts(path, fingerprint, data).await
+            }
+            RemoteBackend::Server { base_url, api_key } => {
+                let upload_url = self
+                    .presign_upload(base_url, api_key, path, fingerprint, data.len() as u64)
+                    .await?;
+                self.put_to_s3(&upload_url, data).await
+            }
+            RemoteBackend::Direct(s3) => s3.put(path, fingerprint, data).await,
+        }
+    }
+
+    async fn upload_in_parts(
+        &self,
+        path: &str,
+        fingerprint: &ContentFingerprint,
+        data: &[u8],
+    ) -> Result<()> {
+        let pending = match &self.journal {
+            Some(journal) => {
+                self.prune_journal(journal, path).await;
+                journal.find(path)?
+            }
+            None => None,
+        };
+        let (upload, data) = match pending {
+            Some(pending)
+                if pending.content_hash == fingerprint.as_str()
+                    && self.multipart_in_progress(&pending.upload).await? =>
+            {
+                tracing::info!("resuming the upload of {path} started earlier");
+                (pending.upload, Cow::Owned(pending.ciphertext))
+            }
+            // Other content, or an upload the server has since given up on (its parts are
+            // discarded with it): start again.
+            pending => {
+                if let Some(superseded) = pending {
+                    // The new entry replaces this one; nothing would ever resume it.
+                    if let Err(err) = self.abort_multipart(&superseded.upload).await {
+                        tracing::debug!("superseded upload of {path} not aborted: {err:#}");
+                    }
+                }
+                let upload = self
+                    .initiate_multipart(path, fingerprint, data.len() as u64)
+                    .await?;
+                if let Some(journal) = &self.journal {
+                    journal.record(&upload, fingerprint, data)?;
+                }
+                (upload, Cow::Borrowed(data))
+            }
+        };
+
+        let mut attempt = 1;
+        loop {
+            match self.resume_multipart(&upload, &data).await {
+                Ok(_) => break,
+                Err(err) if attempt < MULTIPART_ATTEMPTS => {
+                    tracing::warn!("upload of {path} interrupted, resuming: {err:#}");
+                    tokio::time::sleep(Duration::from_secs(attempt.into())).await;
+                    attempt += 1;
+                }
+                // The upload is left in progress: the parts sent so far are kept for the
+                // next run until the server aborts stale uploads.
+                Err(err) if self.journal.is_some() => {
+                    return Err(err.context(format!(
+                        "upload of {path} interrupted; upload the file again to resume it"
+                    )));
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        if let Some(journal) = &self.journal {
+            if let Err(err) = journal.remove(path) {
+                tracing::warn!("uploaded {path}, but could not clear its journal entry: {err:#}");
+            }
+        }
+        Ok(())
+    }
+
+    /// Forget journaled uploads, other than the one to `path`, that can no longer be
+    /// resumed: those `list-parts` no longer knows, and those older than
+    /// [`JOURNAL_MAX_AGE`], which are aborted first in case the server keeps uploads
+    /// longer. Each entry holds a copy of its ciphertext. Failures are only logged.
+    async fn prune_journal(&self, journal: &UploadJournal, path: &str) {
+        let entries = match journal.entries() {
+            Ok(entries) => entries,
+            Err(err) => {
+                tracing::warn!("upload journal not pruned: {err:#}");
+                return;
+            }
+        };
+        for entry in entries
+            .into_iter()
+            .filter(|entry| entry.upload.path != path)
+        {
+            let expired = entry
+                .recorded
+                .elapsed()
+                .is_ok_and(|age| age >= JOURNAL_MAX_AGE);
+            let resumable = if expired {
+                if let Err(err) = self.abort_multipart(&entry.upload).await {
+                    tracing::debug!(
+                        "expired upload of {} not aborted: {err:#}",
+                        entry.upload.path
+                    );
+                }
+                false
+            } else {
+                match self.multipart_in_progress(&entry.upload).await {
+                    Ok(in_progress) => in_progress,
+                    Err(err) => {
+                        tracing::warn!(
+                            "could not check the upload of {}: {err:#}",
+                            entry.upload.path
+                        );
+                        true
+                    }
+                }
+            };
+            if !resumable {
+                if let Err(err) = journal.remove(&entry.upload.path) {
+                    tracing::warn!("upload journal not pruned: {err:#}");
+                }
+            }
+        }
+    }
 
-        let client = Client::new();
-        let base_url = config.server.endpoint.trim_end_matches('/').to_string();
+    /// Remote path of an interrupted upload of the content with `fingerprint` into `dir`,
+    /// under whatever name it was started with. For names chosen at random per run
+    /// (`upload --opaque`), which would otherwise never resume.
+    pub fn interrupted_upload(
+        &self,
+        fingerprint: &ContentFingerprint,
+        dir: &str,
+    ) -> Result<Option<String>> {
+        match &self.journal {
+            Some(journal) => journal.find_content(fingerprint, dir),
+            None => Ok(None),
+        }
+    }
 
-        Ok(Self {
-            client,
-            base_url,
-            api_key,
+    /// Whether the server still has `upload` in progress; it aborts uploads left for
+    /// longer than `MULTIPART_MAX_AGE_HOURS`.
+    async fn multipart_in_progress(&self, upload: &MultipartUpload) -> Result<bool> {
+        let RemoteBackend::Server { base_url, api_key } = &self.backend else {
+            bail!("multipart uploads need the API server");
+        };
+        let resp = self
+            .client
+            .post(format!("{base_url}/multipart/list-parts"))
+            .bearer_auth(api_key)
+            .json(upload)
+            .send()
+            .await
+            .context("failed to list uploaded parts")?;
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(false);
+        }
+        Self::check_response(resp).await?;
+        Ok(true)
+    }
+
+    /// POST /multipart/initiate — start a multipart upload of `size_bytes` of encrypted
+    /// data to `path`. API server only.
+    pub async fn initiate_multipart(
+        &self,
+        path: &str,
+        fingerprint: &ContentFingerprint,
+        size_bytes: u64,
+    ) -> Result<MultipartUpload> {
+        let body = InitiateMultipartRequest {
+            path,
+            content_hash: fingerprint.to_string(),
+            size_bytes,
+        };
+        let parsed: InitiateMultipartResponse = self
+            .post_multipart("initiate", &body, "failed to start multipart upload")
+            .await?;
+        Ok(MultipartUpload {
+            path: path.to_string(),
+            upload_id: parsed.upload_id,
         })
     }
 
-    /// POST /presign/upload — returns a presigned S3 upload URL.
-    pub async fn presign_upload(
+    /// Send the parts of `data` the server does not have yet, in parts of
+    /// [`MULTIPART_PART_SIZE`], and complete the upload. `data` must be the bytes the upload
+    /// was started for. Returns how many parts were sent.
+    pub async fn resume_multipart(&self, upload: &MultipartUpload, data: &[u8]) -> Result<usize> {
+        let uploaded: HashMap<i32, i64> = self
+            .post_multipart::<_, ListPartsResponse>(
+                "list-parts",
+                upload,
+                "failed to list uploaded parts",
+            )
+            .await?
+            .parts
+            .into_iter()
+            .map(|part| (part.part_number, part.size))
+            .collect();
+
+        let mut sent = 0;
+        let mut part_count = 0;
+        for (part_number, part) in (1..).zip(data.chunks(MULTIPART_PART_SIZE)) {
+            part_count = part_number;
+            if uploaded.get(&part_number) == Some(&(part.len() as i64)) {
+                continue;
+            }
+            let body = PresignPartRequest {
+                path: &upload.path,
+                upload_id: &upload.upload_id,
+                part_number,
+            };
+            let url: PresignUploadResponse = self
+                .post_multipart("presign-part", &body, "failed to request part upload URL")
+                .await?;
+            self.put_to_s3(&url.upload_url, part)
+                .await
+                .with_context(|| format!("failed to upload part {part_number}"))?;
+            sent += 1;
+        }
+
+        let body = CompleteMultipartRequest {
+            path: &upload.path,
+            upload_id: &upload.upload_id,
+            part_count,
+        };
+        self.post_multipart::<_, serde_json::Value>(
+            "complete",
+            &body,
+            "failed to complete multipart upload",
+        )
+        .await?;
+        Ok(sent)
+    }
+
+    /// POST /multipart/abort — give up on an upload and discard its parts.
+    pub async fn abort_multipart(&self, upload: &MultipartUpload) -> Result<()> {
+        self.post_multipart::<_, serde_json::Value>(
+            "abort",
+            upload,
+            "failed to abort multipart upload",
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// POST /multipart/{action} with a JSON body.
+    async fn post_multipart<B: Serialize, R: DeserializeOwned>(
+        &self,
+        action: &str,
+        body: &B,
+        context: &'static str,
+    ) -> Result<R> {
+        let RemoteBackend::Server { base_url, api_key } = &self.backend else {
+            bail!("multipart uploads need the API server");
+        };
+        let resp = self
+            .client
+            .post(format!("{base_url}/multipart/{action}"))
+            .bearer_auth(api_key)
+            .json(body)
+            .send()
+            .await
+            .context(context)?;
+
+        let resp = Self::check_response(resp).await?;
+        resp.json()
+            .await
+            .with_context(|| format!("failed to parse multipart {action} response"))
+    }
+
+    /// POST /files/check — whether `path` or another object already holds the content
+    /// with this fingerprint.
+    pub async fn check_upload(
+        &self,
+        path: &str,
+        fingerprint: &ContentFingerprint,
+    ) -> Result<UploadCheck> {
+        let (base_url, api_key) = match &self.backend {
+            RemoteBackend::Server { base_url, api_key } => (base_url, api_key),
+            RemoteBackend::Direct(s3) => return s3.check_upload(path, fingerprint).await,
+        };
+        let body = CheckRequest {
+            path: path.to_string(),
+            content_hash: fingerprint.to_string(),
+        };
+        let resp = self
+            .client
+            .post(format!("{base_url}/files/check"))
+            .bearer_auth(api_key)
+            .json(&body)
+            .send()
+            .await
+            .context("failed to check for an existing upload")?;
+
+        let resp = Self::check_response(resp).await?;
+        resp.json().await.context("failed to parse check response")
+    }
+
+    /// POST /presign/upload — returns a presigned S3 upload URL. The fingerprint is stored
+    /// as the object's `content-hash` metadata.
+    async fn presign_upload(
         &self,
+        base_url: &str,
+        api_key: &str,
         path: &str,
-        content_hash: &str,
+        fingerprint: &ContentFingerprint,
         size_bytes: u64,
     ) -> Result<String> {
         let body = PresignUploadRequest {
             path: path.to_string(),
-            content_hash: content_hash.to_string(),
+            content_hash: fingerprint.to_string(),
             size_bytes,
         };
         let resp = self
             .client
-            .post(format!("{}/presign/upload", self.base_url))
-            .bearer_auth(&self.api_key)
+            .post(format!("{base_url}/presign/upload"))
+            .bearer_auth(api_key)
             .json(&body)
             .send()
             .await
@@ -122,15 +582,32 @@ impl ApiClient {
         Ok(parsed.upload_url)
     }
 
-    /// POST /presign/download — returns a presigned S3 download URL.
+    /// POST /presign/download — returns a presigned S3 download URL (presigned locally in
+    /// direct mode).
     pub async fn presign_download(&self, path: &str) -> Result<String> {
-        let body = PresignDownloadRequest {
-            path: path.to_string(),
+        self.presign_download_object(path, None).await
+    }
+
+    /// Like [`presign_download`](Self::presign_download), for one version from
+    /// [`list_versions`](Self::list_versions).
+    pub async fn presign_download_version(&self, path: &str, version_id: &str) -> Result<String> {
+        self.presign_download_object(path, Some(version_id)).await
+    }
+
+    async fn presign_download_object(
+        &self,
+        path: &str,
+        version_id: Option<&str>,
+    ) -> Result<String> {
+        let (base_url, api_key) = match &self.backend {
+            RemoteBackend::Server { base_url, api_key } => (base_url, api_key),
+            RemoteBackend::Direct(s3) => return s3.presign_download(path, version_id).await,
         };
+        let body = PresignDownloadRequest { path, version_id };
         let resp = self
             .client
-            .post(format!("{}/presign/download", self.base_url))
-            .bearer_auth(&self.api_key)
+            .post(format!("{base_url}/presign/download"))
+            .bearer_auth(api_key)
             .json(&body)
             .send()
             .await
@@ -151,10 +628,14 @@ impl ApiClient {
         limit: Option<i32>,
         next_token: Option<&str>,
     ) -> Result<(Vec<FileEntry>, Option<String>)> {
+        let (base_url, api_key) = match &self.backend {
+            RemoteBackend::Server { base_url, api_key } => (base_url, api_key),
+            RemoteBackend::Direct(s3) => return s3.list_files(prefix, limit, next_token).await,
+        };
         let mut req = self
             .client
-            .get(format!("{}/files", self.base_url))
-            .bearer_auth(&self.api_key);
+            .get(format!("{base_url}/files"))
+            .bearer_auth(api_key);
 
         if let Some(p) = prefix {
             req = req.query(&[("prefix", p)]);
@@ -174,11 +655,15 @@ impl ApiClient {
 
     /// DELETE /files/{path} — delete a remote file.
     pub async fn delete_file(&self, path: &str) -> Result<()> {
+        let (base_url, api_key) = match &self.backend {
+            RemoteBackend::Server { base_url, api_key } => (base_url, api_key),
+            RemoteBackend::Direct(s3) => return s3.delete_file(path).await,
+        };
         let encoded_path = encode_path_segments(path);
         let resp = self
             .client
-            .delete(format!("{}/files/{}", self.base_url, encoded_path))
-            .bearer_auth(&self.api_key)
+            .delete(format!("{base_url}/files/{encoded_path}"))
+            .bearer_auth(api_key)
             .send()
             .await
             .context("failed to delete file")?;
@@ -189,14 +674,18 @@ impl ApiClient {
 
     /// POST /files/move — move (rename) a remote file.
     pub async fn move_file(&self, from: &str, to: &str) -> Result<()> {
+        let (base_url, api_key) = match &self.backend {
+            RemoteBackend::Server { base_url, api_key } => (base_url, api_key),
+            RemoteBackend::Direct(s3) => return s3.move_file(from, to).await,
+        };
         let body = MoveRequest {
             from: from.to_string(),
             to: to.to_string(),
         };
         let resp = self
             .client
-            .post(format!("{}/files/move", self.base_url))
-            .bearer_auth(&self.api_key)
+            .post(format!("{base_url}/files/move"))
+            .bearer_auth(api_key)
             .json(&body)
             .send()
             .await
@@ -206,8 +695,73 @@ impl ApiClient {
         Ok(())
     }
 
+    /// POST /files/copy — copy a remote file within the bucket, without downloading it.
+    pub async fn copy_file(&self, from: &str, to: &str) -> Result<()> {
+        let (base_url, api_key) = match &self.backend {
+            RemoteBackend::Server { base_url, api_key } => (base_url, api_key),
+            RemoteBackend::Direct(s3) => return s3.copy_file(from, to).await,
+        };
+        let body = MoveRequest {
+            from: from.to_string(),
+            to: to.to_string(),
+        };
+        let resp = self
+            .client
+            .post(format!("{base_url}/files/copy"))
+            .bearer_auth(api_key)
+            .json(&body)
+            .send()
+            .await
+            .context("failed to copy file")?;
+
+        Self::check_response(resp).await?;
+        Ok(())
+    }
+
+    /// GET /files/versions — every version of a remote file, newest first.
+    pub async fn list_versions(&self, path: &str) -> Result<Vec<FileVersion>> {
+        let (base_url, api_key) = match &self.backend {
+            RemoteBackend::Server { base_url, api_key } => (base_url, api_key),
+            RemoteBackend::Direct(s3) => return s3.list_versions(path).await,
+        };
+        let resp = self
+            .client
+            .get(format!("{base_url}/files/versions"))
+            .bearer_auth(api_key)
+            .query(&[("path", path)])
+            .send()
+            .await
+            .context("failed to list versions")?;
+
+        let resp = Self::check_response(resp).await?;
+        let parsed: VersionsResponse = resp
+            .json()
+            .await
+            .context("failed to parse versions response")?;
+        Ok(parsed.versions)
+    }
+
+    /// POST /files/restore — make a copy of an earlier version the current one.
+    pub async fn restore_version(&self, path: &str, version_id: &str) -> Result<()> {
+        let (base_url, api_key) = match &self.backend {
+            RemoteBackend::Server { base_url, api_key } => (base_url, api_key),
+            RemoteBackend::Direct(s3) => return s3.restore_version(path, version_id).await,
+        };
+        let resp = self
+            .client
+            .post(format!("{base_url}/files/restore"))
+            .bearer_auth(api_key)
+            .json(&RestoreRequest { path, version_id })
+            .send()
+            .await
+            .context("failed to restore version")?;
+
+        Self::check_response(resp).await?;
+        Ok(())
+    }
+
     /// PUT encrypted bytes directly to S3 via presigned URL (no auth header needed).
-    pub async fn put_to_s3(&self, presigned_url: &str, data: &[u8]) -> Result<()> {
+    async fn put_to_s3(&self, presigned_url: &str, data: &[u8]) -> Result<()> {
         let resp = self
             .client
             .put(presigned_url)
@@ -247,6 +801,57 @@ impl ApiClient {
         Ok(bytes.to_vec())
     }
 
+    /// GET the first `len` bytes of an object via presigned URL. Returns fewer bytes if the
+    /// object is shorter.
+    pub async fn get_range_from_s3(&self, presigned_url: &str, len: u64) -> Result<Vec<u8>> {
+        self.get_range_with_size_from_s3(presigned_url, len)
+            .await
+            .map(|(bytes, _)| bytes)
+    }
+
+    /// Like [`get_range_from_s3`](Self::get_range_from_s3), also returning the size of the
+    /// whole object if the response states it (`Content-Range`, or a full 200 response).
+    pub async fn get_range_with_size_from_s3(
+        &self,
+        presigned_url: &str,
+        len: u64,
+    ) -> Result<(Vec<u8>, Option<u64>)> {
+        let resp = self
+            .client
+            .get(presigned_url)
+            .header("Range", format!("bytes=0-{}", len.saturating_sub(1)))
+            .send()
+            .await
+            .context("failed to download from S3")?;
+
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let body = resp.text().await.unwrap_or_default();
+            bail!("S3 download failed (HTTP {}): {}", status, body);
+        }
+
+        let ranged = resp.status() == reqwest::StatusCode::PARTIAL_CONTENT;
+        let total = resp
+            .headers()
+            .get(reqwest::header::CONTENT_RANGE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(content_range_total);
+
+        // A server that ignores the Range header answers 200 with the whole object.
+        let mut bytes = resp
+            .bytes()
+            .await
+            .context("failed to read S3 response body")?
+            .to_vec();
+        let total = if ranged {
+            total
+        } else {
+            Some(bytes.len() as u64)
+        };
+        bytes.truncate(len as usize);
+        Ok((bytes, total))
+    }
+
     /// Check HTTP response status; extract API error body if present.
     async fn check_response(resp: reqwest::Response) -> Result<reqwest::Response> {
         if resp.status().is_success() {
@@ -270,6 +875,11 @@ impl ApiClient {
     }
 }
 
+/// Complete length from a `Content-Range: bytes 0-49/1234` header; `None` if unknown (`*`).
+fn content_range_total(value: &str) -> Option<u64> {
+    value.rsplit_once('/')?.1.trim().parse().ok()
+}
+
 /// Percent-encode each segment of a `/`-separated path, preserving `/` as-is.
 ///
 /// For example, `active/2026-02/my file.enc` becomes `active/2026-02/my%20file.enc`.
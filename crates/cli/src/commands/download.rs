@@ -1,4 +1,7 @@
 use anyhow::{Context, Result};
+use solidrop_crypto::decrypt::DecryptedFile;
+use solidrop_crypto::keyring::Keyring;
+use solidrop_crypto::{CryptoError, FileMetadata};
 use std::path::Path;
 
 use crate::api_client::ApiClient;
@@ -7,21 +10,24 @@ use crate::config::CliConfig;
 pub async fn run(
     config: &CliConfig,
     api: &ApiClient,
-    key: &[u8; 32],
+    keyring: &Keyring,
     remote_path: &str,
+    version_id: Option<&str>,
 ) -> Result<()> {
-    let download_url = api.presign_download(remote_path).await?;
-    let encrypted_data = api.get_from_s3(&download_url).await?;
-
-    let plaintext =
-        solidrop_crypto::decrypt::decrypt(key, &encrypted_data).context("decryption failed")?;
+    let file = fetch_decrypted(api, keyring, remote_path, version_id).await?;
+    if !file.format.header_authenticated() {
+        eprintln!(
+            "Warning: {} uses format {}; re-upload it to migrate",
+            remote_path, file.format
+        );
+    }
 
     let basename = Path::new(remote_path)
         .file_name()
         .context("invalid remote path: no filename")?
         .to_str()
         .context("filename is not valid UTF-8")?;
-    let filename = basename.strip_suffix(".enc").unwrap_or(basename);
+    let filename = local_file_name(basename, &file.metadata);
 
     let output_dir = &config.storage.download_dir;
     std::fs::create_dir_all(output_dir).with_context(|| {
@@ -32,9 +38,84 @@ pub async fn run(
     })?;
 
     let output_path = output_dir.join(filename);
-    std::fs::write(&output_path, &plaintext)
+    std::fs::write(&output_path, &file.plaintext)
         .with_context(|| format!("failed to write file: {}", output_path.display()))?;
 
     println!("Downloaded: {} -> {}", remote_path, output_path.display());
     Ok(())
 }
+
+/// Local name for a decrypted file stored as `encrypted_name`.
+///
+/// Prefers the encrypted original name; only its last component, so a crafted name cannot
+/// write outside the target directory. Falls back to `encrypted_name` without `.enc`.
+pub fn local_file_name<'a>(encrypted_name: &'a str, metadata: &'a FileMetadata) -> &'a str {
+    metadata
+        .name
+        .as_deref()
+        .and_then(|name| Path::new(name).file_name())
+        .and_then(|name| name.to_str())
+        .unwrap_or(
+            encrypted_name
+                .strip_suffix(".enc")
+                .unwrap_or(encrypted_name),
+        )
+}
+
+/// Download attempts before a truncated object is reported as an error.
+const MAX_ATTEMPTS: u32 = 3;
+
+/// Download `remote_path` (the current version, or `version_id`) and decrypt it, along
+/// with its metadata block.
+///
+/// A truncated download is retried, since the object itself is usually intact. Other
+/// decryption errors are returned at once with a hint at the cause; the [`CryptoError`]
+/// stays reachable through `downcast_ref` so callers can react to its kind.
+pub async fn fetch_decrypted(
+    api: &ApiClient,
+    keyring: &Keyring,
+    remote_path: &str,
+    version_id: Option<&str>,
+) -> Result<DecryptedFile> {
+    let mut attempt = 1;
+    loop {
+        let download_url = match version_id {
+            Some(version_id) => {
+                api.presign_download_version(remote_path, version_id)
+                    .await?
+            }
+            None => api.presign_download(remote_path).await?,
+        };
+        let encrypted_data = api.get_from_s3(&download_url).await?;
+
+        let err = match solidrop_crypto::decrypt::decrypt_file(keyring, &encrypted_data) {
+            Ok(decrypted) => return Ok(decrypted),
+            Err(err) => err,
+        };
+        let hint = match &err {
+            CryptoError::Truncated { .. } if attempt < MAX_ATTEMPTS => {
+                eprintln!(
+                    "Warning: {remote_path}: {err}; retrying ({attempt}/{})",
+                    MAX_ATTEMPTS - 1
+                );
+                attempt += 1;
+                continue;
+            }
+            CryptoError::Truncated { .. } => "the download was incomplete every time",
+            err => decrypt_hint(err),
+        };
+        return Err(err).with_context(|| format!("{remote_path}: {hint}"));
+    }
+}
+
+/// What a decryption error most likely means, for error messages.
+pub fn decrypt_hint(err: &CryptoError) -> &'static str {
+    match err {
+        CryptoError::Truncated { .. } => "the file is incomplete",
+        CryptoError::WrongKey { .. } => "the file needs a different master key",
+        CryptoError::AuthenticationFailed(_) => "the file is corrupted or was tampered with",
+        CryptoError::UnsupportedVersion(_) => "the file was written by a newer SoliDrop",
+        CryptoError::HashMismatch { .. } => "the decrypted content does not match its checksum",
+        _ => "decryption failed",
+    }
+}
@@ -0,0 +1,193 @@
+use anyhow::{bail, Context, Result};
+use solidrop_crypto::header::EncryptedHeader;
+use solidrop_crypto::stream::TAG_SIZE;
+use solidrop_crypto::{format_key_id, Padding, V8_HEADER_SIZE};
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use crate::api_client::ApiClient;
+
+/// Outcome of one sanity check.
+#[derive(Debug, PartialEq, Eq)]
+enum Status {
+    Ok,
+    Warn,
+    Fail,
+}
+
+/// What the header of one file says, and what the checks found.
+struct Inspection {
+    fields: Vec<(&'static str, String)>,
+    checks: Vec<(Status, String)>,
+}
+
+/// Inspect a local encrypted file.
+pub fn run_local(path: &Path) -> Result<()> {
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let file_size = file
+        .metadata()
+        .with_context(|| format!("failed to read {}", path.display()))?
+        .len();
+    let mut prefix = Vec::with_capacity(V8_HEADER_SIZE);
+    file.take(V8_HEADER_SIZE as u64)
+        .read_to_end(&mut prefix)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+
+    print(&path.display().to_string(), &inspect(&prefix, file_size)?)
+}
+
+/// Inspect a remote file, fetching only its header bytes.
+pub async fn run_remote(api: &ApiClient, remote_path: &str) -> Result<()> {
+    let url = api.presign_download(remote_path).await?;
+    let (prefix, object_size) = api
+        .get_range_with_size_from_s3(&url, V8_HEADER_SIZE as u64)
+        .await?;
+    let object_size = object_size.context("storage did not report the object size")?;
+
+    print(remote_path, &inspect(&prefix, object_size)?)
+}
+
+fn print(name: &str, inspection: &Inspection) -> Result<()> {
+    println!("{name}");
+    for (label, value) in &inspection.fields {
+        println!("  {:<14} {}", format!("{label}:"), value);
+    }
+    println!("Checks:");
+    for (status, text) in &inspection.checks {
+        let status = match status {
+            Status::Ok => "ok",
+            Status::Warn => "WARN",
+            Status::Fail => "FAIL",
+        };
+        println!("  {status:<5} {text}");
+    }
+
+    let failed = inspection
+        .checks
+        .iter()
+        .filter(|(status, _)| *status == Status::Fail)
+        .count();
+    if failed > 0 {
+        bail!("{failed} check(s) failed");
+    }
+    Ok(())
+}
+
+/// Read the header from the first bytes of a file of `file_size` bytes and check that
+/// the two agree. Needs no key, so nothing here is authenticated.
+fn inspect(prefix: &[u8], file_size: u64) -> Result<Inspection> {
+    let header = EncryptedHeader::parse(prefix).context("failed to parse the file header")?;
+    let format = header.format();
+
+    let mut fields = vec![
+        ("format", format.to_string()),
+        ("size", format!("{file_size} bytes")),
+        (
+            "key ID",
+            format
+                .key_id
+                .as_ref()
+                .map_or_else(|| "none (before v4)".to_string(), format_key_id),
+        ),
+        ("salt", hex::encode(header.salt())),
+    ];
+    match &header {
+        EncryptedHeader::Legacy(legacy) => {
+            fields.push(("nonce", hex::encode(legacy.nonce)));
+            fields.push(("original size", format!("{} bytes", legacy.original_size)));
+        }
+        EncryptedHeader::Segmented(segmented) => {
+            fields.push(("nonce prefix", hex::encode(segmented.nonce_prefix)));
+            fields.push(("segment size", format!("{} bytes", segmented.segment_size)));
+            let metadata = match segmented.metadata_len {
+                0 => "none".to_string(),
+                len => format!("{len} bytes (encrypted)"),
+            };
+            fields.push(("metadata", metadata));
+        }
+    }
+
+    let mut checks = Vec::new();
+    match header.payload_len(file_size) {
+        Ok(payload) => {
+            let what = match &header {
+                EncryptedHeader::Legacy(_) => "plaintext".to_string(),
+                EncryptedHeader::Segmented(segmented) => {
+                    let stride = u64::from(segmented.segment_size) + TAG_SIZE as u64;
+                    let segments = (file_size - segmented.segments_offset()).div_ceil(stride);
+                    fields.push(("segments", segments.to_string()));
+                    "segment plaintext".to_string()
+                }
+            };
+            checks.push((
+                Status::Ok,
+                format!("size matches the header ({payload} bytes of {what})"),
+            ));
+            if format.padding != Padding::None {
+                if format.padding.padded_len(payload) == payload {
+                    checks.push((
+                        Status::Ok,
+                        format!("payload fills a {} bucket", format.padding),
+                    ));
+                } else {
+                    checks.push((
+                        Status::Fail,
+                        format!(
+                            "payload of {payload} bytes is not a {} bucket",
+                            format.padding
+                        ),
+                    ));
+                }
+            }
+        }
+        Err(err) => checks.push((
+            Status::Fail,
+            format!("size does not match the header: {err}"),
+        )),
+    }
+    if format.header_authenticated() {
+        checks.push((Status::Ok, "header is authenticated on decryption".into()));
+    } else {
+        checks.push((
+            Status::Warn,
+            "legacy format: header is not authenticated; re-upload to migrate".into(),
+        ));
+    }
+
+    Ok(Inspection { fields, checks })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solidrop_crypto::encrypt::{encrypt_with_options, EncryptOptions};
+    use solidrop_crypto::MasterKey;
+
+    #[test]
+    fn test_inspect_checks_size_against_header() {
+        let key = MasterKey::from_bytes([7u8; 32]);
+        let options = EncryptOptions {
+            padding: Padding::Padme,
+            ..Default::default()
+        };
+        let data = encrypt_with_options(&key, &[1u8; 100_000], &options).unwrap();
+
+        let inspection = inspect(&data[..V8_HEADER_SIZE], data.len() as u64).unwrap();
+        assert!(inspection
+            .checks
+            .iter()
+            .all(|(status, _)| *status == Status::Ok));
+        assert!(inspection
+            .fields
+            .contains(&("segment size", "65536 bytes".to_string())));
+
+        // A few bytes short still fits the segment layout, but no longer a padding bucket.
+        let cut = inspect(&data[..V8_HEADER_SIZE], data.len() as u64 - 10).unwrap();
+        assert_eq!(cut.checks[0].0, Status::Ok);
+        assert_eq!(cut.checks[1].0, Status::Fail);
+        let cut = inspect(&data[..V8_HEADER_SIZE], 60).unwrap();
+        assert_eq!(cut.checks[0].0, Status::Fail);
+        assert!(inspect(b"not an encrypted file", 21).is_err());
+    }
+}
@@ -0,0 +1,196 @@
+use anyhow::{bail, Context, Result};
+use solidrop_crypto::MasterKey;
+use std::io::BufRead;
+use std::time::{Duration, Instant};
+
+use solidrop_crypto::key_derivation::{calibrate, derive_master_key_with_params, generate_salt};
+
+use crate::config::CryptoConfig;
+use crate::master_key;
+
+fn ensure_keyfile_absent(path: &std::path::Path, force: bool) -> Result<()> {
+    if path.exists() && !force {
+        bail!(
+            "keyfile already exists at {} (use --force to overwrite it)",
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+/// Create the keyfile. An existing `SOLIDROP_MASTER_KEY` is wrapped as-is so files already
+/// uploaded with it stay readable; otherwise a new random master key is generated.
+pub fn init(config: &CryptoConfig, force: bool) -> Result<()> {
+    let path = config.keyfile_path()?;
+    ensure_keyfile_absent(&path, force)?;
+
+    let existing = master_key::master_key_from_env()?;
+    let password = master_key::read_new_password()?;
+    let params = config.key_params();
+
+    let keyfile = match &existing {
+        Some(key) => solidrop_crypto::keyfile::wrap_master_key(key, password.as_bytes(), &params),
+        None => {
+            solidrop_crypto::keyfile::create_keyfile(password.as_bytes(), &params).map(|(_, f)| f)
+        }
+    }
+    .context("failed to create keyfile")?;
+    master_key::write_keyfile(&path, &keyfile)?;
+
+    if existing.is_some() {
+        println!(
+            "Wrapped the key from SOLIDROP_MASTER_KEY into {}; the variable can now be unset",
+            path.display()
+        );
+    } else {
+        println!("Created new master key in {}", path.display());
+    }
+    Ok(())
+}
+
+/// Print the master key as a 24-word recovery phrase for a paper backup.
+pub fn export_phrase(config: &CryptoConfig) -> Result<()> {
+    let key = master_key::acquire_master_key(config)?;
+    let phrase = solidrop_crypto::recovery::master_key_to_phrase(&key);
+
+    println!("Recovery phrase (anyone with these words can decrypt all your files):");
+    println!();
+    let words: Vec<&str> = phrase.split(' ').collect();
+    for (row, chunk) in words.chunks(6).enumerate() {
+        let numbered: Vec<String> = chunk
+            .iter()
+            .enumerate()
+            .map(|(i, word)| format!("{:>2}. {:<8}", row * 6 + i + 1, word))
+            .collect();
+        println!("  {}", numbered.join("  ").trim_end());
+    }
+    println!();
+    println!("Write it down and keep it offline. Restore with `solidrop key import-phrase`.");
+    Ok(())
+}
+
+/// Restore the master key from a recovery phrase read from stdin and write a new keyfile.
+pub fn import_phrase(config: &CryptoConfig, force: bool) -> Result<()> {
+    let path = config.keyfile_path()?;
+    ensure_keyfile_absent(&path, force)?;
+
+    eprintln!("Enter the 24-word recovery phrase, then an empty line (or EOF):");
+    let phrase = read_stdin_block().context("failed to read recovery phrase")?;
+    // Numbered rows as printed by `export-phrase` ("1. word") are accepted too.
+    let words: Vec<&str> = phrase
+        .iter()
+        .flat_map(|line| line.split_whitespace())
+        .filter(|w| !w.trim_end_matches('.').chars().all(|c| c.is_ascii_digit()))
+        .collect();
+
+    let key = solidrop_crypto::recovery::master_key_from_phrase(&words.join(" "))
+        .context("recovery phrase rejected")?;
+    write_restored_key(config, &path, &key)
+}
+
+/// Split the master key into `shares` Shamir shares, any `threshold` of which restore it.
+pub fn split(config: &CryptoConfig, threshold: u8, shares: u8) -> Result<()> {
+    let key = master_key::acquire_master_key(config)?;
+    let shares = solidrop_crypto::shamir::split_master_key(&key, threshold, shares)
+        .context("failed to split master key")?;
+
+    println!(
+        "{} shares, any {} of which restore the master key (fingerprint {}).",
+        shares.len(),
+        threshold,
+        hex::encode(solidrop_crypto::key_derivation::key_fingerprint(&key))
+    );
+    println!("Store each share in a different place. Restore with `solidrop key combine`.");
+    println!();
+    for share in &shares {
+        println!("Share {}: {}", share.index, share.encode());
+    }
+    Ok(())
+}
+
+/// Restore the master key from Shamir shares read from stdin (one per line) and write a
+/// new keyfile.
+pub fn combine(config: &CryptoConfig, force: bool) -> Result<()> {
+    let path = config.keyfile_path()?;
+    ensure_keyfile_absent(&path, force)?;
+
+    eprintln!("Enter one share per line, then an empty line (or EOF):");
+    let lines = read_stdin_block().context("failed to read shares")?;
+    let shares = lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| {
+            // Accept lines copied from `key split` output ("Share 2: sdshare1:...").
+            let encoded = line.rsplit_once(' ').map_or(line.as_str(), |(_, s)| s);
+            solidrop_crypto::shamir::Share::decode(encoded)
+                .with_context(|| format!("share on line {} rejected", i + 1))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let key = solidrop_crypto::shamir::combine_shares(&shares)
+        .context("could not reconstruct the master key")?;
+    write_restored_key(config, &path, &key)
+}
+
+/// Read non-empty lines from stdin until an empty line (after some input) or EOF.
+fn read_stdin_block() -> Result<Vec<String>> {
+    let mut lines = Vec::new();
+    for line in std::io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            if lines.is_empty() {
+                continue;
+            }
+            break;
+        }
+        lines.push(line.trim().to_string());
+    }
+    Ok(lines)
+}
+
+fn write_restored_key(
+    config: &CryptoConfig,
+    path: &std::path::Path,
+    key: &MasterKey,
+) -> Result<()> {
+    let password = master_key::read_new_password()?;
+    let keyfile =
+        solidrop_crypto::keyfile::wrap_master_key(key, password.as_bytes(), &config.key_params())
+            .context("failed to create keyfile")?;
+    master_key::write_keyfile(path, &keyfile)?;
+
+    println!("Restored master key into {}", path.display());
+    Ok(())
+}
+
+/// Benchmark Argon2id locally and print parameters for a target unlock time, as a
+/// `[crypto.kdf]` snippet for the config file.
+pub fn calibrate_params(target_ms: u64, memory_mib: u32, parallelism: u32) -> Result<()> {
+    let target = Duration::from_millis(target_ms);
+    let m_cost = memory_mib
+        .checked_mul(1024)
+        .with_context(|| format!("--memory-mib {memory_mib} is too large"))?;
+    let params = calibrate(target, m_cost, parallelism).context("Argon2id calibration failed")?;
+
+    // Time the proposal once more, so the printed figure is a real unlock, not an estimate.
+    let start = Instant::now();
+    derive_master_key_with_params(b"calibration", &generate_salt(), &params)
+        .context("Argon2id calibration failed")?;
+    let measured = start.elapsed();
+
+    println!(
+        "Proposed Argon2id parameters ({} MiB, {} passes, {} lanes): {} ms on this machine",
+        params.m_cost / 1024,
+        params.t_cost,
+        params.p_cost,
+        measured.as_millis()
+    );
+    println!();
+    println!("[crypto.kdf]");
+    println!("m_cost_kib = {}", params.m_cost);
+    println!("t_cost = {}", params.t_cost);
+    println!("p_cost = {}", params.p_cost);
+    println!();
+    println!("Add this to config.toml, then run `solidrop passwd` to re-wrap the keyfile.");
+    Ok(())
+}
@@ -1,8 +1,16 @@
 use anyhow::Result;
+use solidrop_crypto::keyring::Keyring;
+use solidrop_crypto::{CryptoError, FileMetadata};
 
+use super::upload::is_opaque_key;
 use crate::api_client::ApiClient;
+use crate::config::CliConfig;
+use crate::master_key;
 
-fn format_size(bytes: i64) -> String {
+/// Bytes fetched to read a file's metadata block; enough for typical names.
+const METADATA_PREFIX_LEN: u64 = 4096;
+
+pub(crate) fn format_size(bytes: i64) -> String {
     if bytes < 1024 {
         format!("{} B", bytes)
     } else if bytes < 1024 * 1024 {
@@ -14,7 +22,33 @@ fn format_size(bytes: i64) -> String {
     }
 }
 
-pub async fn run(api: &ApiClient, prefix: Option<&str>) -> Result<()> {
+/// The encrypted metadata of `remote_path`, read from the first few KiB of the object.
+/// `None` for files without a metadata block.
+pub(crate) async fn remote_metadata(
+    api: &ApiClient,
+    keyring: &Keyring,
+    remote_path: &str,
+) -> Result<Option<FileMetadata>> {
+    let url = api.presign_download(remote_path).await?;
+    let mut len = METADATA_PREFIX_LEN;
+    loop {
+        let prefix = api.get_range_from_s3(&url, len).await?;
+        match solidrop_crypto::decrypt::read_metadata(keyring, &prefix) {
+            Ok(metadata) => return Ok(metadata),
+            // The block is larger than the first fetch; get exactly what it needs.
+            Err(CryptoError::Truncated { expected, .. })
+                if expected > len && prefix.len() as u64 == len =>
+            {
+                len = expected;
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+/// List remote files. Objects uploaded under opaque keys are shown with their original
+/// names, which needs the master key; it is only asked for if there are such objects.
+pub async fn run(config: &CliConfig, api: &ApiClient, prefix: Option<&str>) -> Result<()> {
     let mut all_files = Vec::new();
     let mut next_token: Option<String> = None;
 
@@ -29,10 +63,32 @@ pub async fn run(api: &ApiClient, prefix: Option<&str>) -> Result<()> {
         }
     }
 
+    let keyring = if all_files.iter().any(|file| is_opaque_key(&file.key)) {
+        let key = master_key::acquire_master_key(&config.crypto)?;
+        Some(master_key::keyring(&config.crypto, &key)?)
+    } else {
+        None
+    };
+
     for file in &all_files {
         let size = format_size(file.size);
         let modified = file.last_modified.as_deref().unwrap_or("\u{2014}");
-        println!("{:>10}  {}  {}", size, modified, file.key);
+        let name = match &keyring {
+            Some(keyring) if is_opaque_key(&file.key) => {
+                match remote_metadata(api, keyring, &file.key).await {
+                    Ok(metadata) => metadata.and_then(|m| m.name),
+                    Err(err) => {
+                        eprintln!("Warning: {}: could not read name: {err:#}", file.key);
+                        None
+                    }
+                }
+            }
+            _ => None,
+        };
+        match name {
+            Some(name) => println!("{:>10}  {}  {}  ({})", size, modified, file.key, name),
+            None => println!("{:>10}  {}  {}", size, modified, file.key),
+        }
     }
 
     println!("\n{} file(s)", all_files.len());
@@ -1,6 +1,13 @@
 pub mod delete;
 pub mod download;
+pub mod inspect;
+pub mod key;
 pub mod list;
 pub mod move_cmd;
+pub mod offline;
+pub mod passwd;
+pub mod restore;
+pub mod rotate;
 pub mod sync;
 pub mod upload;
+pub mod versions;
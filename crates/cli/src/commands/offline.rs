@@ -0,0 +1,393 @@
+//! `encrypt` and `decrypt`: local files in, local files out, without the API server.
+//!
+//! These are the recovery path when the server is unavailable: objects copied out of the
+//! bucket (S3 console, `aws s3 cp`) can be decrypted with nothing but the master key.
+
+use anyhow::{bail, Context, Result};
+use solidrop_crypto::encrypt::EncryptOptions;
+use solidrop_crypto::hash::{self, FINGERPRINT_PREFIX};
+use solidrop_crypto::keyring::Keyring;
+use solidrop_crypto::{CryptoError, MasterKey};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use super::download::{decrypt_hint, local_file_name};
+use super::upload::file_metadata;
+
+/// A file to process and the directory, relative to the output directory, to write it to.
+struct Input {
+    path: PathBuf,
+    relative_dir: PathBuf,
+}
+
+/// Expected content hashes from a checksum file: `<hash>  <file>` per line, the format
+/// `encrypt` prints. Hashes are keyed fingerprints (`hmac-sha256:`, as stored in the
+/// `content-hash` object metadata) or plain `sha256:` hashes from older uploads.
+#[derive(Debug, Default)]
+pub struct Manifest {
+    by_path: HashMap<String, String>,
+    /// By file name alone, for files moved since the list was made; `None` if ambiguous.
+    by_name: HashMap<String, Option<String>>,
+}
+
+impl Manifest {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read checksum file: {}", path.display()))?;
+        Self::parse(&content).with_context(|| format!("invalid checksum file: {}", path.display()))
+    }
+
+    fn parse(content: &str) -> Result<Self> {
+        let mut manifest = Self::default();
+        for (number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (hash, path) = line
+                .split_once(char::is_whitespace)
+                .with_context(|| format!("line {}: expected `<hash>  <file>`", number + 1))?;
+            // sha256sum marks binary mode with a `*` before the name.
+            let path = path.trim_start().trim_start_matches('*');
+
+            if let Some(name) = Path::new(path).file_name().and_then(|n| n.to_str()) {
+                manifest
+                    .by_name
+                    .entry(name.to_string())
+                    .and_modify(|existing| {
+                        if existing.as_deref() != Some(hash) {
+                            *existing = None;
+                        }
+                    })
+                    .or_insert_with(|| Some(hash.to_string()));
+            }
+            manifest.by_path.insert(path.to_string(), hash.to_string());
+        }
+        Ok(manifest)
+    }
+
+    /// The expected hash of `path`: by the path as given, else by its file name.
+    fn expected(&self, path: &Path) -> Option<&str> {
+        if let Some(hash) = path.to_str().and_then(|p| self.by_path.get(p)) {
+            return Some(hash);
+        }
+        let name = path.file_name()?.to_str()?;
+        self.by_name.get(name)?.as_deref()
+    }
+}
+
+/// Encrypt local files (directories recursively) into `output_dir` as `<name>.enc`,
+/// keeping the directory structure. Prints `<fingerprint>  <output>` per file, a checksum
+/// list `decrypt --hashes` accepts.
+pub fn encrypt(
+    key: &MasterKey,
+    options: &EncryptOptions,
+    paths: &[PathBuf],
+    output_dir: &Path,
+) -> Result<()> {
+    let inputs = collect_inputs(paths, |_| true)?;
+    for_each(&inputs, "encrypted", |input| {
+        encrypt_file(key, options, input, output_dir)
+    })
+}
+
+/// Decrypt local `.enc` files (directories are searched recursively) into `output_dir`,
+/// named after the original name in their metadata. Content hashes listed in `manifest`
+/// are verified before anything is written.
+pub fn decrypt(
+    keyring: &Keyring,
+    paths: &[PathBuf],
+    output_dir: &Path,
+    manifest: Option<&Manifest>,
+) -> Result<()> {
+    let inputs = collect_inputs(paths, |path| {
+        path.extension().is_some_and(|extension| extension == "enc")
+    })?;
+    for_each(&inputs, "decrypted", |input| {
+        decrypt_file(keyring, input, output_dir, manifest)
+    })
+}
+
+/// Run `process` on every input. A single file's error is returned as is; with several,
+/// failures are reported and the rest still run.
+fn for_each(
+    inputs: &[Input],
+    done: &str,
+    mut process: impl FnMut(&Input) -> Result<()>,
+) -> Result<()> {
+    if inputs.is_empty() {
+        bail!("no files to process");
+    }
+    let mut failed = 0;
+    for input in inputs {
+        match process(input) {
+            Ok(()) => {}
+            Err(err) if inputs.len() == 1 => return Err(err),
+            Err(err) => {
+                eprintln!("Error: {}: {err:#}", input.path.display());
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        bail!("{failed} of {} file(s) could not be {done}", inputs.len());
+    }
+    Ok(())
+}
+
+fn encrypt_file(
+    key: &MasterKey,
+    options: &EncryptOptions,
+    input: &Input,
+    output_dir: &Path,
+) -> Result<()> {
+    let filename = input
+        .path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .context("filename is not valid UTF-8")?;
+    let output = output_dir
+        .join(&input.relative_dir)
+        .join(format!("{filename}.enc"));
+
+    let reader = File::open(&input.path).context("failed to read file")?;
+    let options = EncryptOptions {
+        metadata: Some(file_metadata(&input.path, filename)),
+        ..options.clone()
+    };
+    let mut writer = BufWriter::new(create_new(&output)?);
+    let sealed = solidrop_crypto::stream::encrypt_and_fingerprint(
+        key,
+        &options,
+        BufReader::new(reader),
+        &mut writer,
+    )
+    .and_then(|sealed| writer.flush().map(|()| sealed));
+    let sealed = match sealed {
+        Ok(sealed) => sealed,
+        Err(err) => {
+            drop(writer);
+            let _ = std::fs::remove_file(&output);
+            return Err(err).context("failed to encrypt file");
+        }
+    };
+
+    println!("{}  {}", sealed.fingerprint, output.display());
+    Ok(())
+}
+
+fn decrypt_file(
+    keyring: &Keyring,
+    input: &Input,
+    output_dir: &Path,
+    manifest: Option<&Manifest>,
+) -> Result<()> {
+    let data = std::fs::read(&input.path).context("failed to read file")?;
+    let file = solidrop_crypto::decrypt::decrypt_file(keyring, &data).map_err(|err| {
+        let hint = decrypt_hint(&err);
+        anyhow::Error::new(err).context(hint)
+    })?;
+    if !file.format.header_authenticated() {
+        eprintln!(
+            "Warning: {} uses format {}",
+            input.path.display(),
+            file.format
+        );
+    }
+
+    let expected = manifest.and_then(|manifest| manifest.expected(&input.path));
+    if let Some(expected) = expected {
+        // A fingerprint is keyed with the key the file was encrypted under.
+        let key = file
+            .format
+            .key_id
+            .and_then(|key_id| keyring.get(&key_id))
+            .or(keyring.primary())
+            .expect("keyring is not empty");
+        verify(key, &file.plaintext, expected)?;
+    }
+
+    let encrypted_name = input
+        .path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .context("filename is not valid UTF-8")?;
+    let output = output_dir
+        .join(&input.relative_dir)
+        .join(local_file_name(encrypted_name, &file.metadata));
+    create_new(&output)?
+        .write_all(&file.plaintext)
+        .with_context(|| format!("failed to write file: {}", output.display()))?;
+
+    let verified = if expected.is_some() {
+        " (hash verified)"
+    } else {
+        ""
+    };
+    println!(
+        "Decrypted: {} -> {}{verified}",
+        input.path.display(),
+        output.display()
+    );
+    Ok(())
+}
+
+/// Check `plaintext` against a content hash in either of its stored forms.
+fn verify(key: &MasterKey, plaintext: &[u8], expected: &str) -> Result<()> {
+    let actual = if expected.starts_with(FINGERPRINT_PREFIX) {
+        hash::content_fingerprint(key, plaintext).to_string()
+    } else if expected.starts_with("sha256:") {
+        hash::sha256_hex(plaintext)
+    } else {
+        bail!("unsupported content hash: {expected}");
+    };
+    if actual != expected {
+        return Err(CryptoError::HashMismatch {
+            expected: expected.to_string(),
+            actual,
+        })
+        .context("the decrypted content does not match the checksum file");
+    }
+    Ok(())
+}
+
+/// Create `path` and its parent directories, refusing to overwrite an existing file.
+fn create_new(path: &Path) -> Result<File> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create directory: {}", parent.display()))?;
+    }
+    File::create_new(path).with_context(|| format!("failed to create {}", path.display()))
+}
+
+/// Expand `paths` into files. Files are taken as given; directories are walked
+/// recursively for files `wanted` accepts, keeping their layout below the directory.
+fn collect_inputs(paths: &[PathBuf], wanted: impl Fn(&Path) -> bool) -> Result<Vec<Input>> {
+    let mut inputs = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            walk(path, Path::new(""), &wanted, &mut inputs)?;
+        } else {
+            inputs.push(Input {
+                path: path.clone(),
+                relative_dir: PathBuf::new(),
+            });
+        }
+    }
+    Ok(inputs)
+}
+
+fn walk(
+    dir: &Path,
+    relative_dir: &Path,
+    wanted: &impl Fn(&Path) -> bool,
+    inputs: &mut Vec<Input>,
+) -> Result<()> {
+    let mut entries = std::fs::read_dir(dir)
+        .with_context(|| format!("failed to read directory: {}", dir.display()))?
+        .collect::<std::io::Result<Vec<_>>>()
+        .with_context(|| format!("failed to read directory: {}", dir.display()))?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let path = entry.path();
+        // Symlinked directories are not followed, so a link cycle cannot recurse forever.
+        if entry.file_type()?.is_dir() {
+            walk(&path, &relative_dir.join(entry.file_name()), wanted, inputs)?;
+        } else if path.is_file() && wanted(&path) {
+            inputs.push(Input {
+                path,
+                relative_dir: relative_dir.to_path_buf(),
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_manifest_lookup() {
+        let manifest = Manifest::parse(
+            "# comment\n\
+             hmac-sha256:aa  backup/2026-02/a.png.enc\n\
+             sha256:bb *b.psd.enc\n\
+             hmac-sha256:cc  one/same.enc\n\
+             hmac-sha256:dd  two/same.enc\n",
+        )
+        .unwrap();
+        assert_eq!(
+            manifest.expected(Path::new("backup/2026-02/a.png.enc")),
+            Some("hmac-sha256:aa")
+        );
+        assert_eq!(
+            manifest.expected(Path::new("elsewhere/a.png.enc")),
+            Some("hmac-sha256:aa")
+        );
+        assert_eq!(manifest.expected(Path::new("b.psd.enc")), Some("sha256:bb"));
+        assert_eq!(
+            manifest.expected(Path::new("two/same.enc")),
+            Some("hmac-sha256:dd")
+        );
+        assert_eq!(manifest.expected(Path::new("three/same.enc")), None);
+        assert!(Manifest::parse("no-separator\n").is_err());
+    }
+
+    #[test]
+    fn test_encrypt_then_decrypt_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source");
+        std::fs::create_dir_all(source.join("sub")).unwrap();
+        std::fs::write(source.join("a.png"), b"first").unwrap();
+        std::fs::write(source.join("sub/b.psd"), b"second").unwrap();
+        let key = MasterKey::from_bytes([7u8; 32]);
+
+        let encrypted = dir.path().join("encrypted");
+        encrypt(
+            &key,
+            &EncryptOptions::default(),
+            std::slice::from_ref(&source),
+            &encrypted,
+        )
+        .unwrap();
+        assert!(encrypted.join("sub/b.psd.enc").is_file());
+        // Existing outputs are never overwritten.
+        assert!(encrypt(&key, &EncryptOptions::default(), &[source], &encrypted).is_err());
+
+        let fingerprint = hash::content_fingerprint(&key, b"first");
+        let manifest = Manifest::parse(&format!("{fingerprint}  a.png.enc\n")).unwrap();
+        let restored = dir.path().join("restored");
+        let keyring = Keyring::from_key(&key);
+        decrypt(
+            &keyring,
+            std::slice::from_ref(&encrypted),
+            &restored,
+            Some(&manifest),
+        )
+        .unwrap();
+        assert_eq!(std::fs::read(restored.join("a.png")).unwrap(), b"first");
+        assert_eq!(
+            std::fs::read(restored.join("sub/b.psd")).unwrap(),
+            b"second"
+        );
+
+        let wrong = hash::content_fingerprint(&key, b"other");
+        let manifest = Manifest::parse(&format!("{wrong}  a.png.enc\n")).unwrap();
+        let err = decrypt(
+            &keyring,
+            &[encrypted.join("a.png.enc")],
+            &dir.path().join("again"),
+            Some(&manifest),
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<CryptoError>(),
+            Some(CryptoError::HashMismatch { .. })
+        ));
+        assert!(!dir.path().join("again/a.png").exists());
+    }
+}
@@ -0,0 +1,28 @@
+use anyhow::{Context, Result};
+
+use crate::config::CryptoConfig;
+use crate::master_key;
+
+/// Change the keyfile password. Only the keyfile is rewritten; remote objects are untouched
+/// because the master key itself does not change. The new keyfile uses the configured
+/// `[crypto.kdf]` parameters, so this is also how parameters are upgraded.
+pub fn run(config: &CryptoConfig) -> Result<()> {
+    let path = config.keyfile_path()?;
+    let keyfile = master_key::read_keyfile(&path)?;
+
+    let old_password = master_key::read_password("Current password: ")?;
+    let master_key = solidrop_crypto::keyfile::unwrap_master_key(&keyfile, old_password.as_bytes())
+        .with_context(|| format!("failed to unlock keyfile {}", path.display()))?;
+
+    let new_password = master_key::read_new_password()?;
+    let rewrapped = solidrop_crypto::keyfile::wrap_master_key(
+        &master_key,
+        new_password.as_bytes(),
+        &config.key_params(),
+    )
+    .context("failed to re-wrap master key")?;
+    master_key::write_keyfile(&path, &rewrapped)?;
+
+    println!("Password changed for {}", path.display());
+    Ok(())
+}
@@ -0,0 +1,64 @@
+use anyhow::{bail, Context, Result};
+use solidrop_crypto::header::EncryptedHeader;
+use solidrop_crypto::keyring::Keyring;
+use solidrop_crypto::{format_key_id, V8_HEADER_SIZE};
+
+use crate::api_client::ApiClient;
+
+/// Make `version_id` the current version of `remote_path`.
+///
+/// The version's header is read first: a version encrypted under a key that is not in
+/// `keyring` would leave the file unreadable once restored, so it is refused unless
+/// `force` is set.
+pub async fn run(
+    api: &ApiClient,
+    keyring: &Keyring,
+    remote_path: &str,
+    version_id: &str,
+    force: bool,
+) -> Result<()> {
+    if let Err(err) = check_readable(api, keyring, remote_path, version_id).await {
+        if !force {
+            return Err(err.context("use --force to restore it anyway"));
+        }
+        eprintln!("Warning: {err:#}");
+    }
+    api.restore_version(remote_path, version_id).await?;
+    println!("Restored: {} to version {}", remote_path, version_id);
+    Ok(())
+}
+
+/// Check that `version_id` is encrypted under a key in `keyring`, fetching only its header.
+/// Delete markers and unknown versions pass; the restore itself rejects them.
+async fn check_readable(
+    api: &ApiClient,
+    keyring: &Keyring,
+    remote_path: &str,
+    version_id: &str,
+) -> Result<()> {
+    let versions = api.list_versions(remote_path).await?;
+    if !versions
+        .iter()
+        .any(|version| version.version_id == version_id && !version.is_delete_marker)
+    {
+        return Ok(());
+    }
+
+    let url = api
+        .presign_download_version(remote_path, version_id)
+        .await?;
+    let prefix = api.get_range_from_s3(&url, V8_HEADER_SIZE as u64).await?;
+    let header = EncryptedHeader::parse(&prefix)
+        .with_context(|| format!("version {version_id} has no readable header"))?;
+    // Formats without a key ID cannot be checked before decryption.
+    if let Some(key_id) = header.format().key_id {
+        if keyring.get(&key_id).is_none() {
+            bail!(
+                "version {version_id} is encrypted under key {}, which is not in the keyring; \
+                 restored, {remote_path} could not be decrypted",
+                format_key_id(&key_id)
+            );
+        }
+    }
+    Ok(())
+}
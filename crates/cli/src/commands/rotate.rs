@@ -0,0 +1,436 @@
+use anyhow::{bail, Context, Result};
+use solidrop_crypto::MasterKey;
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use solidrop_crypto::encrypt::EncryptOptions;
+use solidrop_crypto::format_key_id;
+use solidrop_crypto::hash::{
+    content_fingerprint, verify_fingerprint, verify_hash, ContentFingerprint,
+};
+use solidrop_crypto::key_derivation::key_fingerprint;
+use solidrop_crypto::keyring::Keyring;
+use solidrop_crypto::FileMetadata;
+
+use crate::api_client::ApiClient;
+use crate::config::CryptoConfig;
+use crate::master_key;
+
+const JOURNAL_HEADER: &str = "solidrop-rotate-v1";
+
+/// Progress of a rotation: a header line with the old and new key IDs, then one line per
+/// object already re-encrypted (a JSON string, so any key is one line). Each line is synced
+/// before the next object starts; an object uploaded but not yet recorded is recognised by
+/// its key ID on resume.
+struct Journal {
+    file: File,
+    old_id: [u8; 8],
+    new_id: [u8; 8],
+    done: HashSet<String>,
+}
+
+impl Journal {
+    fn create(path: &Path, old_id: [u8; 8], new_id: [u8; 8]) -> Result<Self> {
+        let mut file = File::create(path)
+            .with_context(|| format!("failed to create journal: {}", path.display()))?;
+        writeln!(
+            file,
+            "{JOURNAL_HEADER} {} {}",
+            format_key_id(&old_id),
+            format_key_id(&new_id)
+        )?;
+        file.sync_all()?;
+        Ok(Self {
+            file,
+            old_id,
+            new_id,
+            done: HashSet::new(),
+        })
+    }
+
+    /// Open an existing journal, or `None` if no rotation is in progress.
+    fn open(path: &Path) -> Result<Option<Self>> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("failed to read journal: {}", path.display()))
+            }
+        };
+
+        let mut lines = BufReader::new(file).lines();
+        let header = lines.next().transpose()?.unwrap_or_default();
+        let ids: Vec<&str> = header.split(' ').collect();
+        let (old_id, new_id) = match ids.as_slice() {
+            [JOURNAL_HEADER, old, new] => (parse_key_id(old)?, parse_key_id(new)?),
+            _ => bail!("unrecognised rotation journal: {}", path.display()),
+        };
+
+        let mut done = HashSet::new();
+        for line in lines {
+            let line = line?;
+            // A torn last line from a crash mid-write is ignored; that object is redone.
+            if let Ok(key) = serde_json::from_str::<String>(&line) {
+                done.insert(key);
+            }
+        }
+
+        let file = OpenOptions::new()
+            .append(true)
+            .open(path)
+            .with_context(|| format!("failed to open journal: {}", path.display()))?;
+        Ok(Some(Self {
+            file,
+            old_id,
+            new_id,
+            done,
+        }))
+    }
+
+    fn record(&mut self, key: &str) -> Result<()> {
+        writeln!(self.file, "{}", serde_json::to_string(key)?)?;
+        self.file.sync_data()?;
+        self.done.insert(key.to_string());
+        Ok(())
+    }
+}
+
+fn parse_key_id(hex_id: &str) -> Result<[u8; 8]> {
+    hex::decode(hex_id)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .with_context(|| format!("invalid key ID in rotation journal: {hex_id}"))
+}
+
+/// `path` with `suffix` appended to the file name (`master.key` -> `master.key.new`).
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// An object re-encrypted under the new key, ready to upload.
+struct Reencrypted {
+    ciphertext: Vec<u8>,
+    /// Fingerprint under the new key; the old one is meaningless once the key is gone.
+    fingerprint: ContentFingerprint,
+}
+
+/// Whether `plaintext` matches an object's `content-hash` metadata: a keyed fingerprint
+/// under any key in `keyring`, or a plain SHA-256 written by clients before fingerprints.
+fn content_matches(keyring: &Keyring, plaintext: &[u8], expected: &str) -> bool {
+    if expected.starts_with("sha256:") {
+        return verify_hash(plaintext, expected);
+    }
+    keyring
+        .keys()
+        .any(|key| verify_fingerprint(key, plaintext, expected))
+}
+
+/// Decrypt `encrypted` with any key in `keyring` and encrypt it again under `new_key`.
+///
+/// The plaintext must match `expected_hash` (the object's `content-hash` metadata, if any),
+/// and the new ciphertext is decrypted once more and checked against it, so nothing is
+/// uploaded that would not read back. Compression, padding and the metadata block are kept. Returns `None` if
+/// the object already uses `new_key`.
+fn reencrypt(
+    keyring: &Keyring,
+    new_key: &MasterKey,
+    encrypted: &[u8],
+    expected_hash: Option<&str>,
+) -> Result<Option<Reencrypted>> {
+    let decrypted =
+        solidrop_crypto::decrypt::decrypt_file(keyring, encrypted).context("decryption failed")?;
+    let (plaintext, format) = (decrypted.plaintext, decrypted.format);
+    if format.key_id == Some(key_fingerprint(new_key)) {
+        return Ok(None);
+    }
+
+    if let Some(expected) = expected_hash {
+        if !content_matches(keyring, &plaintext, expected) {
+            bail!("content hash mismatch: plaintext does not match metadata {expected}");
+        }
+    }
+    let fingerprint = content_fingerprint(new_key, &plaintext);
+
+    let options = EncryptOptions {
+        compression: format.compression,
+        padding: format.padding,
+        metadata: (decrypted.metadata != FileMetadata::default()).then_some(decrypted.metadata),
+    };
+    let ciphertext = solidrop_crypto::encrypt::encrypt_with_options(new_key, &plaintext, &options)
+        .context("encryption failed")?;
+    let check = solidrop_crypto::decrypt::decrypt(new_key, &ciphertext)
+        .context("re-encrypted object does not decrypt")?;
+    if !verify_fingerprint(new_key, &check, fingerprint.as_str()) {
+        bail!("re-encrypted object does not match the original content");
+    }
+
+    Ok(Some(Reencrypted {
+        ciphertext,
+        fingerprint,
+    }))
+}
+
+/// Move every remote object to a new master key.
+///
+/// A new key is generated and written to `<keyfile>.new`; objects are then downloaded,
+/// re-encrypted and uploaded in place one at a time, with progress recorded in
+/// `<keyfile>.rotate`. Running the command again after an interruption resumes where it
+/// stopped. Once every object is done, the old keyfile is kept as `<keyfile>.old` and the
+/// new one takes its place. The old key is added to `<keyfile>.retired`, since older
+/// versions of the objects are still encrypted under it.
+pub async fn run(config: &CryptoConfig, api: &ApiClient) -> Result<()> {
+    let keyfile_path = config.keyfile_path()?;
+    let pending_path = with_suffix(&keyfile_path, ".new");
+    let journal_path = with_suffix(&keyfile_path, ".rotate");
+    let retired_path = master_key::retired_keys_path(config)?;
+
+    let current_key = master_key::acquire_master_key(config)?;
+    let current_id = key_fingerprint(&current_key);
+
+    let (mut journal, new_key) = match Journal::open(&journal_path)? {
+        Some(journal) if journal.new_id == current_id => {
+            // Interrupted after the keyfiles were swapped; only the journal is left.
+            std::fs::remove_file(&journal_path)?;
+            println!(
+                "Rotation already complete (key {})",
+                format_key_id(&current_id)
+            );
+            return Ok(());
+        }
+        Some(journal) => {
+            if journal.old_id != current_id {
+                bail!(
+                    "rotation journal {} is for key {}, but the current key is {}",
+                    journal_path.display(),
+                    format_key_id(&journal.old_id),
+                    format_key_id(&current_id)
+                );
+            }
+            if !pending_path.exists() {
+                bail!(
+                    "rotation journal {} exists but the new keyfile {} is missing",
+                    journal_path.display(),
+                    pending_path.display()
+                );
+            }
+            let keyfile = master_key::read_keyfile(&pending_path)?;
+            let password = master_key::read_pending_password("New keyfile password: ")?;
+            let new_key =
+                solidrop_crypto::keyfile::unwrap_master_key(&keyfile, password.as_bytes())
+                    .with_context(|| format!("failed to unlock {}", pending_path.display()))?;
+            if key_fingerprint(&new_key) != journal.new_id {
+                bail!(
+                    "{} does not hold the key named in the journal",
+                    pending_path.display()
+                );
+            }
+            println!(
+                "Resuming rotation to key {} ({} object(s) already done)",
+                format_key_id(&journal.new_id),
+                journal.done.len()
+            );
+            (journal, new_key)
+        }
+        None => {
+            let new_key = solidrop_crypto::keyfile::generate_master_key();
+            let password = master_key::read_new_password()?;
+            let keyfile = solidrop_crypto::keyfile::wrap_master_key(
+                &new_key,
+                password.as_bytes(),
+                &config.key_params(),
+            )
+            .context("failed to create keyfile")?;
+            // The new key must be on disk before anything is encrypted with it.
+            master_key::write_keyfile(&pending_path, &keyfile)?;
+            let journal = Journal::create(&journal_path, current_id, key_fingerprint(&new_key))?;
+            println!(
+                "Rotating from key {} to new key {} (saved in {})",
+                format_key_id(&current_id),
+                format_key_id(&journal.new_id),
+                pending_path.display()
+            );
+            (journal, new_key)
+        }
+    };
+
+    let mut keyring = Keyring::from_key(&new_key);
+    keyring.add(&current_key);
+    // Sealed under the current key, or already under the new one if an earlier run
+    // stopped between writing them and swapping the keyfiles.
+    let retired = master_key::read_retired_keys(&retired_path, &keyring)?;
+    for key in &retired {
+        keyring.add(key);
+    }
+
+    let mut rotated = 0u64;
+    let mut skipped = 0u64;
+    let mut next_token: Option<String> = None;
+    loop {
+        let (files, token) = api.list_files(None, None, next_token.as_deref()).await?;
+
+        for file in &files {
+            if journal.done.contains(&file.key) {
+                skipped += 1;
+                continue;
+            }
+
+            let download_url = api.presign_download(&file.key).await?;
+            let encrypted_data = api.get_from_s3(&download_url).await?;
+            let reencrypted = reencrypt(
+                &keyring,
+                &new_key,
+                &encrypted_data,
+                file.content_hash.as_deref(),
+            )
+            .with_context(|| format!("failed to rotate {} (nothing was replaced)", file.key))?;
+
+            match reencrypted {
+                Some(object) => {
+                    api.upload(&file.key, &object.fingerprint, &object.ciphertext)
+                        .await?;
+                    println!("Rotated: {}", file.key);
+                    rotated += 1;
+                }
+                None => skipped += 1,
+            }
+            journal.record(&file.key)?;
+        }
+
+        next_token = token;
+        if next_token.is_none() {
+            break;
+        }
+    }
+
+    // Only current object versions were re-encrypted; older ones still need the keys they
+    // were written with, so the current key joins the retired ones, sealed under the new key.
+    let new_id = key_fingerprint(&new_key);
+    let mut retiring: Vec<&MasterKey> = Vec::new();
+    for key in retired.iter().chain([&current_key]) {
+        let id = key_fingerprint(key);
+        if id != new_id && retiring.iter().all(|kept| key_fingerprint(kept) != id) {
+            retiring.push(key);
+        }
+    }
+    master_key::write_retired_keys(&retired_path, &new_key, &retiring)?;
+
+    // Keep the old keyfile until the user has checked the result; swap in the new one
+    // atomically, then drop the journal.
+    if keyfile_path.exists() {
+        std::fs::copy(&keyfile_path, with_suffix(&keyfile_path, ".old"))
+            .context("failed to keep the old keyfile")?;
+    }
+    std::fs::rename(&pending_path, &keyfile_path)
+        .with_context(|| format!("failed to install new keyfile {}", keyfile_path.display()))?;
+    std::fs::remove_file(&journal_path)?;
+
+    println!(
+        "Rotation complete: {} re-encrypted, {} already done",
+        rotated, skipped
+    );
+    println!(
+        "The old keyfile is kept as {}.old; delete it once you have checked your files.",
+        keyfile_path.display()
+    );
+    println!(
+        "The old key is kept in {}, so earlier versions of your files stay readable.",
+        retired_path.display()
+    );
+    println!("Recovery phrases and shares of the old key no longer apply; create new ones.");
+    if master_key::master_key_from_env()?.is_some() {
+        println!("SOLIDROP_MASTER_KEY still holds the old key; unset it to use the new keyfile.");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_journal_resumes_recorded_objects() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("master.key.rotate");
+        assert!(Journal::open(&path).unwrap().is_none());
+
+        let mut journal = Journal::create(&path, [1; 8], [2; 8]).unwrap();
+        journal.record("active/2026-02/a.png.enc").unwrap();
+        journal.record("transfer/line\nbreak.enc").unwrap();
+        drop(journal);
+
+        let journal = Journal::open(&path).unwrap().unwrap();
+        assert_eq!((journal.old_id, journal.new_id), ([1; 8], [2; 8]));
+        assert_eq!(journal.done.len(), 2);
+        assert!(journal.done.contains("transfer/line\nbreak.enc"));
+    }
+
+    #[test]
+    fn test_reencrypt_moves_object_to_new_key() {
+        let (old_key, new_key) = (
+            MasterKey::from_bytes([1u8; 32]),
+            MasterKey::from_bytes([2u8; 32]),
+        );
+        let mut keyring = Keyring::from_key(&new_key);
+        keyring.add(&old_key);
+
+        let original = solidrop_crypto::encrypt::encrypt(&old_key, b"artwork").unwrap();
+        let old_fingerprint = content_fingerprint(&old_key, b"artwork");
+        let object = reencrypt(
+            &keyring,
+            &new_key,
+            &original,
+            Some(old_fingerprint.as_str()),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(
+            object.fingerprint,
+            content_fingerprint(&new_key, b"artwork")
+        );
+        assert_eq!(
+            solidrop_crypto::decrypt::decrypt(&new_key, &object.ciphertext).unwrap(),
+            b"artwork"
+        );
+
+        // Already rotated (uploaded, but not yet recorded when interrupted): left alone.
+        assert!(reencrypt(&keyring, &new_key, &object.ciphertext, None)
+            .unwrap()
+            .is_none());
+
+        // Objects uploaded before fingerprints carry a plain SHA-256.
+        let legacy_hash = solidrop_crypto::hash::sha256_hex(b"artwork");
+        assert!(reencrypt(&keyring, &new_key, &original, Some(&legacy_hash))
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn test_reencrypt_rejects_hash_mismatch() {
+        let key = MasterKey::from_bytes([1u8; 32]);
+        let keyring = Keyring::from_key(&key);
+        let original = solidrop_crypto::encrypt::encrypt(&key, b"artwork").unwrap();
+        let wrong = content_fingerprint(&key, b"other").to_string();
+        let result = reencrypt(
+            &keyring,
+            &MasterKey::from_bytes([2u8; 32]),
+            &original,
+            Some(&wrong),
+        );
+        let err = result.err().expect("hash mismatch must be rejected");
+        assert!(err.to_string().contains("hash mismatch"), "error: {err}");
+    }
+
+    #[test]
+    fn test_with_suffix_appends_to_file_name() {
+        assert_eq!(
+            with_suffix(Path::new("/cfg/master.key"), ".new"),
+            PathBuf::from("/cfg/master.key.new")
+        );
+    }
+}
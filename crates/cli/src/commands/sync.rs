@@ -1,11 +1,17 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+use solidrop_crypto::keyring::Keyring;
+use solidrop_crypto::{CryptoError, FileMetadata};
 
+use super::download::{self, local_file_name};
+use super::list::remote_metadata;
+use super::upload::is_opaque_key;
 use crate::api_client::ApiClient;
 use crate::config::CliConfig;
 
-pub async fn run(config: &CliConfig, api: &ApiClient, key: &[u8; 32]) -> Result<()> {
+pub async fn run(config: &CliConfig, api: &ApiClient, keyring: &Keyring) -> Result<()> {
     let mut downloaded = 0u64;
     let mut skipped = 0u64;
+    let mut failed = 0u64;
     let mut next_token: Option<String> = None;
 
     loop {
@@ -14,29 +20,58 @@ pub async fn run(config: &CliConfig, api: &ApiClient, key: &[u8; 32]) -> Result<
             .await?;
 
         for file in &files {
-            // Preserve directory structure: strip "transfer/" prefix, then strip ".enc" suffix.
-            // e.g. "transfer/2026-02-11/reference.png.enc" -> "2026-02-11/reference.png"
+            // Preserve directory structure below "transfer/" and name the file after the
+            // original name in its metadata, e.g.
+            // "transfer/2026-02-11/reference.png.enc" -> "2026-02-11/reference.png".
             let relative = file.key.strip_prefix("transfer/").unwrap_or(&file.key);
-            let relative = relative.strip_suffix(".enc").unwrap_or(relative);
+            let (subdir, encrypted_name) = relative.rsplit_once('/').unwrap_or(("", relative));
+            let local_dir = config.storage.download_dir.join(subdir);
 
-            let local_path = config.storage.download_dir.join(relative);
-            if local_path.exists() {
+            // An opaque key says nothing about the name; read it from the metadata block
+            // (a ranged GET) so that files already synced are skipped without a download.
+            let metadata = if is_opaque_key(&file.key) {
+                match remote_metadata(api, keyring, &file.key).await {
+                    Ok(metadata) => metadata.unwrap_or_default(),
+                    Err(err) if affects_one_file(&err) => {
+                        eprintln!("Error: {}: {err:#}", file.key);
+                        failed += 1;
+                        continue;
+                    }
+                    Err(err) => return Err(err),
+                }
+            } else {
+                FileMetadata::default()
+            };
+            if local_dir
+                .join(local_file_name(encrypted_name, &metadata))
+                .exists()
+            {
                 skipped += 1;
                 continue;
             }
 
-            let download_url = api.presign_download(&file.key).await?;
-            let encrypted_data = api.get_from_s3(&download_url).await?;
-            let plaintext = solidrop_crypto::decrypt::decrypt(key, &encrypted_data)
-                .context("decryption failed")?;
+            let decrypted = match download::fetch_decrypted(api, keyring, &file.key, None).await {
+                Ok(decrypted) => decrypted,
+                Err(err) if affects_one_file(&err) => {
+                    eprintln!("Error: {err:#}");
+                    failed += 1;
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+            if !decrypted.format.header_authenticated() {
+                eprintln!(
+                    "Warning: {} uses format {}; re-upload it to migrate",
+                    file.key, decrypted.format
+                );
+            }
 
             // Create parent directories (e.g. download_dir/2026-02-11/)
-            if let Some(parent) = local_path.parent() {
-                std::fs::create_dir_all(parent)
-                    .with_context(|| format!("failed to create directory: {}", parent.display()))?;
-            }
+            std::fs::create_dir_all(&local_dir)
+                .with_context(|| format!("failed to create directory: {}", local_dir.display()))?;
 
-            std::fs::write(&local_path, &plaintext)
+            let local_path = local_dir.join(local_file_name(encrypted_name, &decrypted.metadata));
+            std::fs::write(&local_path, &decrypted.plaintext)
                 .with_context(|| format!("failed to write file: {}", local_path.display()))?;
 
             downloaded += 1;
@@ -52,5 +87,15 @@ pub async fn run(config: &CliConfig, api: &ApiClient, key: &[u8; 32]) -> Result<
         "Sync complete: {} downloaded, {} skipped",
         downloaded, skipped
     );
+    if failed > 0 {
+        bail!("{failed} file(s) could not be decrypted");
+    }
     Ok(())
 }
+
+/// Whether a decryption error concerns only the object at hand. A damaged object only
+/// affects itself; a wrong key affects every file.
+fn affects_one_file(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<CryptoError>()
+        .is_some_and(|e| !matches!(e, CryptoError::WrongKey { .. }))
+}
@@ -1,10 +1,162 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use chrono::Utc;
+use rand::RngCore;
+use solidrop_crypto::encrypt::EncryptOptions;
+use solidrop_crypto::hash::ContentFingerprint;
+use solidrop_crypto::{FileMetadata, MasterKey};
+use std::fs::File;
+use std::io::BufReader;
 use std::path::Path;
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+use tokio::task::JoinHandle;
 
-use crate::api_client::ApiClient;
+use crate::api_client::{ApiClient, UploadCheck};
 
-pub async fn run(api: &ApiClient, key: &[u8; 32], file_path: &str) -> Result<()> {
+/// Random bytes in an opaque object name (hex-encoded, so 32 characters).
+const OPAQUE_NAME_BYTES: usize = 16;
+
+/// A file encrypted and ready to upload.
+pub struct Prepared {
+    pub remote_path: String,
+    pub fingerprint: ContentFingerprint,
+    pub ciphertext: Vec<u8>,
+}
+
+/// Encrypt and upload `file_paths`.
+///
+/// The next file is encrypted on a blocking thread while the current one uploads, so the
+/// uplink stays busy; at most two encrypted files are held in memory. With several files,
+/// a failure is reported and the rest still upload.
+///
+/// Unless `force` is set, a file whose content is already stored (at its remote path or
+/// elsewhere) is not transferred again.
+pub async fn run(
+    api: &ApiClient,
+    key: &Arc<MasterKey>,
+    options: &EncryptOptions,
+    file_paths: &[String],
+    opaque: bool,
+    force: bool,
+) -> Result<()> {
+    let remote_dir = Arc::new(format!("active/{}", Utc::now().format("%Y-%m")));
+    let spawn = |file_path: &String| -> JoinHandle<Result<Prepared>> {
+        let (key, options, file_path) = (Arc::clone(key), options.clone(), file_path.clone());
+        let remote_dir = Arc::clone(&remote_dir);
+        tokio::task::spawn_blocking(move || {
+            prepare(&key, &options, &file_path, &remote_dir, opaque)
+        })
+    };
+
+    let mut failed = 0;
+    let mut next = file_paths.first().map(spawn);
+    for (i, file_path) in file_paths.iter().enumerate() {
+        let prepared = next.take().expect("spawned for every file").await?;
+        next = file_paths.get(i + 1).map(spawn);
+
+        let result = match prepared {
+            Ok(mut prepared) => upload(api, file_path, &mut prepared, force).await,
+            Err(err) => Err(err),
+        };
+        match result {
+            Ok(()) => {}
+            Err(err) if file_paths.len() == 1 => return Err(err),
+            Err(err) => {
+                eprintln!("Error: {file_path}: {err:#}");
+                failed += 1;
+            }
+        }
+    }
+
+    if failed > 0 {
+        bail!(
+            "{failed} of {} file(s) could not be uploaded",
+            file_paths.len()
+        );
+    }
+    Ok(())
+}
+
+async fn upload(
+    api: &ApiClient,
+    file_path: &str,
+    prepared: &mut Prepared,
+    force: bool,
+) -> Result<()> {
+    let check = if force {
+        send(api, prepared).await?;
+        UploadCheck::NeedsUpload
+    } else {
+        upload_unless_stored(api, prepared).await?
+    };
+
+    match check {
+        UploadCheck::AlreadyUploaded => {
+            println!("Unchanged: {} -> {}", file_path, prepared.remote_path);
+        }
+        UploadCheck::ExistsAtOtherPath { existing_path } => {
+            println!(
+                "Copied: {} -> {} (already stored as {})",
+                file_path, prepared.remote_path, existing_path
+            );
+        }
+        UploadCheck::NeedsUpload => println!(
+            "Uploaded: {} -> {} ({} bytes)",
+            file_path,
+            prepared.remote_path,
+            prepared.ciphertext.len()
+        ),
+    }
+    Ok(())
+}
+
+/// Put `prepared` at its remote path unless it is already there (`POST /files/check`):
+/// content stored at another path is copied within the bucket rather than uploaded again,
+/// anything else is uploaded. Returns what the check found; either way, the content is at
+/// `prepared.remote_path` afterwards.
+pub async fn upload_unless_stored(api: &ApiClient, prepared: &mut Prepared) -> Result<UploadCheck> {
+    let check = api
+        .check_upload(&prepared.remote_path, &prepared.fingerprint)
+        .await?;
+    match &check {
+        UploadCheck::AlreadyUploaded => {}
+        UploadCheck::ExistsAtOtherPath { existing_path } => {
+            api.copy_file(existing_path, &prepared.remote_path).await?
+        }
+        UploadCheck::NeedsUpload => send(api, prepared).await?,
+    }
+    Ok(check)
+}
+
+async fn send(api: &ApiClient, prepared: &mut Prepared) -> Result<()> {
+    // An opaque name is drawn anew on every run; an interrupted upload of the same content
+    // resumes under the name it was started with.
+    if is_opaque_key(&prepared.remote_path) {
+        if let Some((dir, _)) = prepared.remote_path.rsplit_once('/') {
+            let interrupted = api
+                .interrupted_upload(&prepared.fingerprint, dir)?
+                .filter(|path| is_opaque_key(path));
+            if let Some(path) = interrupted {
+                prepared.remote_path = path;
+            }
+        }
+    }
+    api.upload(
+        &prepared.remote_path,
+        &prepared.fingerprint,
+        &prepared.ciphertext,
+    )
+    .await
+}
+
+/// Read, fingerprint and encrypt a file in one pass, for upload into `remote_dir`.
+pub fn prepare(
+    key: &MasterKey,
+    options: &EncryptOptions,
+    file_path: &str,
+    remote_dir: &str,
+    opaque: bool,
+) -> Result<Prepared> {
     let path = Path::new(file_path);
     let filename = path
         .file_name()
@@ -12,26 +164,132 @@ pub async fn run(api: &ApiClient, key: &[u8; 32], file_path: &str) -> Result<()>
         .to_str()
         .context("filename is not valid UTF-8")?;
 
-    let plaintext =
-        std::fs::read(path).with_context(|| format!("failed to read file: {}", file_path))?;
+    let file = File::open(path).with_context(|| format!("failed to read file: {}", file_path))?;
+    let options = EncryptOptions {
+        metadata: Some(file_metadata(path, filename)),
+        ..options.clone()
+    };
 
-    let content_hash = solidrop_crypto::hash::sha256_hex(&plaintext);
-    let ciphertext =
-        solidrop_crypto::encrypt::encrypt(key, &plaintext).context("encryption failed")?;
+    let capacity = file.metadata().map(|m| m.len() as usize).unwrap_or(0);
+    let mut ciphertext = Vec::with_capacity(capacity);
+    let sealed = solidrop_crypto::stream::encrypt_and_fingerprint(
+        key,
+        &options,
+        BufReader::new(file),
+        &mut ciphertext,
+    )
+    .with_context(|| format!("failed to encrypt file: {}", file_path))?;
 
-    let now = Utc::now();
-    let remote_path = format!("active/{}/{}.enc", now.format("%Y-%m"), filename);
+    let object_name = if opaque {
+        opaque_name()
+    } else {
+        filename.to_string()
+    };
+    Ok(Prepared {
+        remote_path: format!("{}/{}.enc", remote_dir.trim_end_matches('/'), object_name),
+        fingerprint: sealed.fingerprint,
+        ciphertext,
+    })
+}
 
-    let upload_url = api
-        .presign_upload(&remote_path, &content_hash, ciphertext.len() as u64)
-        .await?;
-    api.put_to_s3(&upload_url, &ciphertext).await?;
-
-    println!(
-        "Uploaded: {} -> {} ({} bytes)",
-        file_path,
-        remote_path,
-        ciphertext.len()
-    );
-    Ok(())
+/// Metadata stored encrypted inside the uploaded file.
+pub fn file_metadata(path: &Path, filename: &str) -> FileMetadata {
+    let modified = std::fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|age| age.as_secs() as i64);
+
+    FileMetadata {
+        name: Some(filename.to_string()),
+        modified,
+        mime_type: Some(mime_type(filename).to_string()),
+        source_app: Some(concat!("solidrop-cli/", env!("CARGO_PKG_VERSION")).to_string()),
+        unknown: Vec::new(),
+    }
+}
+
+/// MIME type guessed from the file extension, for the formats SoliDrop usually carries.
+fn mime_type(filename: &str) -> &'static str {
+    let extension = Path::new(filename)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("psd") => "image/vnd.adobe.photoshop",
+        Some("pdf") => "application/pdf",
+        Some("zip") => "application/zip",
+        Some("txt") => "text/plain",
+        Some("mp4") => "video/mp4",
+        _ => "application/octet-stream",
+    }
+}
+
+/// A random object name that reveals nothing about the file.
+fn opaque_name() -> String {
+    let mut bytes = [0u8; OPAQUE_NAME_BYTES];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Whether `remote_path` was uploaded under an opaque name (see `upload --opaque`).
+pub fn is_opaque_key(remote_path: &str) -> bool {
+    let basename = remote_path.rsplit('/').next().unwrap_or(remote_path);
+    basename.strip_suffix(".enc").is_some_and(|name| {
+        name.len() == OPAQUE_NAME_BYTES * 2
+            && name.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mime_type() {
+        assert_eq!(mime_type("reference.PNG"), "image/png");
+        assert_eq!(mime_type("illustration.clip"), "application/octet-stream");
+        assert_eq!(mime_type("noextension"), "application/octet-stream");
+    }
+
+    #[test]
+    fn test_prepare_encrypts_with_metadata() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("reference.png");
+        std::fs::write(&path, b"not really a png").unwrap();
+        let key = MasterKey::from_bytes([7u8; 32]);
+
+        let prepared = prepare(
+            &key,
+            &EncryptOptions::default(),
+            path.to_str().unwrap(),
+            "transfer/",
+            true,
+        )
+        .unwrap();
+        assert!(prepared.remote_path.starts_with("transfer/"));
+        assert!(is_opaque_key(&prepared.remote_path));
+        assert_eq!(
+            prepared.fingerprint,
+            solidrop_crypto::hash::content_fingerprint(&key, b"not really a png")
+        );
+
+        let keyring = solidrop_crypto::keyring::Keyring::from_key(&key);
+        let file = solidrop_crypto::decrypt::decrypt_file(&keyring, &prepared.ciphertext).unwrap();
+        assert_eq!(file.plaintext, b"not really a png");
+        assert_eq!(file.metadata.name.as_deref(), Some("reference.png"));
+        assert_eq!(file.metadata.mime_type.as_deref(), Some("image/png"));
+    }
+
+    #[test]
+    fn test_opaque_names() {
+        let key = format!("active/2026-02/{}.enc", opaque_name());
+        assert!(is_opaque_key(&key));
+        assert!(!is_opaque_key("active/2026-02/reference.png.enc"));
+        assert!(!is_opaque_key(&format!("active/{}", opaque_name())));
+    }
 }
@@ -0,0 +1,43 @@
+use anyhow::Result;
+
+use super::list::format_size;
+use crate::api_client::ApiClient;
+
+/// Characters of a content hash shown: enough to tell versions apart.
+const HASH_PREFIX_LEN: usize = 12;
+
+/// List the versions of a remote file, newest first.
+pub async fn run(api: &ApiClient, remote_path: &str) -> Result<()> {
+    let versions = api.list_versions(remote_path).await?;
+
+    for version in &versions {
+        let modified = version.last_modified.as_deref().unwrap_or("\u{2014}");
+        let note = if version.is_delete_marker {
+            "  (deleted)"
+        } else if version.is_latest {
+            "  (current)"
+        } else {
+            ""
+        };
+        let size = if version.is_delete_marker {
+            "\u{2014}".to_string()
+        } else {
+            format_size(version.size)
+        };
+        let hash = version
+            .content_hash
+            .as_deref()
+            .and_then(|hash| hash.split_once(':'))
+            .map_or("\u{2014}", |(_, hex)| {
+                hex.get(..HASH_PREFIX_LEN).unwrap_or(hex)
+            });
+        println!(
+            "{:>10}  {}  {:<12}  {}{}",
+            size, modified, hash, version.version_id, note
+        );
+    }
+
+    println!("\n{} version(s)", versions.len());
+
+    Ok(())
+}
@@ -1,5 +1,8 @@
 use anyhow::{Context, Result};
 use serde::Deserialize;
+use solidrop_crypto::encrypt::EncryptOptions;
+use solidrop_crypto::key_derivation::KeyParams;
+use solidrop_crypto::{Compression, Padding};
 use std::path::PathBuf;
 
 #[derive(Debug, Deserialize)]
@@ -7,30 +10,158 @@ pub struct CliConfig {
     pub server: ServerConfig,
     pub storage: StorageConfig,
     pub crypto: CryptoConfig,
+    /// Direct S3 access, used when `server.backend` is `s3`.
+    #[serde(default)]
+    pub s3: Option<S3Config>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct ServerConfig {
     pub endpoint: String,
     pub api_key_env: String,
+    /// Where remote commands go: the API server, or S3 directly when it is down.
+    #[serde(default)]
+    pub backend: Backend,
+}
+
+/// Remote backend, as written in the config file.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    /// Presigned URLs from the SoliDrop API server.
+    #[default]
+    Api,
+    /// The bucket in `[s3]`, with local AWS credentials (disaster recovery).
+    S3,
+}
+
+/// The bucket and how to reach it without the API server.
+#[derive(Debug, Deserialize)]
+pub struct S3Config {
+    pub bucket: String,
+    #[serde(default = "default_region")]
+    pub region: String,
+    /// Custom endpoint for MinIO or another S3-compatible store (e.g. "http://localhost:9000").
+    #[serde(default)]
+    pub endpoint_url: Option<String>,
+    /// Path-style addressing (required for MinIO).
+    #[serde(default)]
+    pub force_path_style: bool,
+    /// Named profile in `~/.aws`; the default credential chain otherwise.
+    #[serde(default)]
+    pub profile: Option<String>,
+}
+
+/// Same default region as the API server.
+fn default_region() -> String {
+    "ap-northeast-1".into()
 }
 
 #[derive(Debug, Deserialize)]
 pub struct StorageConfig {
     pub download_dir: PathBuf,
+    /// Upload under random object keys instead of the file name (same as `upload --opaque`).
+    #[serde(default)]
+    pub opaque_keys: bool,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct CryptoConfig {
+    /// OS credential store lookup. Not read yet: the keychain integration is still planned.
+    #[allow(dead_code)]
     pub keychain_service: String,
+    #[allow(dead_code)]
     pub keychain_account: String,
+    /// Password-wrapped master key. Defaults to `master.key` next to `config.toml`.
+    #[serde(default)]
+    pub keyfile: Option<PathBuf>,
+    /// Argon2id parameters for newly written keyfiles (`key init`, `passwd`).
+    #[serde(default)]
+    pub kdf: Option<KdfConfig>,
+    /// zstd-compress uploads before encrypting them, unless a sample shows no gain.
+    #[serde(default)]
+    pub compress: bool,
+    /// Pad uploads so their size only reveals a size bucket.
+    #[serde(default)]
+    pub padding: PaddingMode,
+}
+
+/// Length-hiding padding policy, as written in the config file.
+#[derive(Debug, Default, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PaddingMode {
+    #[default]
+    None,
+    /// Padmé buckets, at most ~12% larger.
+    Padme,
+    /// Power-of-two buckets, up to twice as large.
+    Pow2,
+}
+
+/// Argon2id parameters, as proposed by `solidrop key calibrate`.
+#[derive(Debug, Deserialize)]
+pub struct KdfConfig {
+    pub m_cost_kib: u32,
+    pub t_cost: u32,
+    pub p_cost: u32,
+}
+
+impl CryptoConfig {
+    /// Path of the keyfile: the configured one, or the default in the config directory.
+    pub fn keyfile_path(&self) -> Result<PathBuf> {
+        match &self.keyfile {
+            Some(path) => Ok(path.clone()),
+            None => Ok(config_dir()?.join("master.key")),
+        }
+    }
+
+    /// Parameters for wrapping a keyfile: `[crypto.kdf]` if present, else the crate defaults.
+    pub fn key_params(&self) -> KeyParams {
+        match &self.kdf {
+            Some(kdf) => KeyParams {
+                m_cost: kdf.m_cost_kib,
+                t_cost: kdf.t_cost,
+                p_cost: kdf.p_cost,
+            },
+            None => KeyParams::default(),
+        }
+    }
+
+    /// Options for encrypting uploads.
+    pub fn encrypt_options(&self) -> EncryptOptions {
+        EncryptOptions {
+            compression: if self.compress {
+                Compression::Zstd
+            } else {
+                Compression::None
+            },
+            padding: match self.padding {
+                PaddingMode::None => Padding::None,
+                PaddingMode::Padme => Padding::Padme,
+                PaddingMode::Pow2 => Padding::PowerOfTwo,
+            },
+            ..Default::default()
+        }
+    }
+}
+
+/// Platform-specific SoliDrop config directory.
+pub fn config_dir() -> Result<PathBuf> {
+    let dirs = directories::ProjectDirs::from("dev", "nafell", "solidrop")
+        .context("could not determine config directory")?;
+    Ok(dirs.config_dir().to_path_buf())
+}
+
+/// Platform-specific directory for SoliDrop's local state (uploads in progress).
+pub fn data_dir() -> Result<PathBuf> {
+    let dirs = directories::ProjectDirs::from("dev", "nafell", "solidrop")
+        .context("could not determine data directory")?;
+    Ok(dirs.data_local_dir().to_path_buf())
 }
 
 impl CliConfig {
     pub fn load() -> Result<Self> {
-        let config_dir = directories::ProjectDirs::from("dev", "nafell", "solidrop")
-            .context("could not determine config directory")?;
-        let config_path = config_dir.config_dir().join("config.toml");
+        let config_path = config_dir()?.join("config.toml");
         let content = std::fs::read_to_string(&config_path)
             .with_context(|| format!("could not read config file at {}", config_path.display()))?;
         let config: CliConfig = toml::from_str(&content)
@@ -0,0 +1,605 @@
+//! Disaster-recovery backend: S3 itself, with local AWS credentials.
+//!
+//! Does what the API server does for each route, against the same bucket and object
+//! metadata, so `list`, `download`, `sync` and `upload` keep working while the server is
+//! unreachable. Selected with `backend = "s3"` in `[server]`; see `[s3]` in the config.
+
+use anyhow::{anyhow, bail, Result};
+use aws_sdk_s3::error::{DisplayErrorContext, SdkError};
+use aws_sdk_s3::presigning::PresigningConfig;
+use aws_sdk_s3::primitives::{ByteStream, DateTimeFormat};
+use aws_sdk_s3::Client;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use solidrop_crypto::hash::{ContentFingerprint, FINGERPRINT_PREFIX};
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::api_client::{FileEntry, FileVersion, UploadCheck};
+use crate::config::S3Config;
+
+/// Characters to percent-encode in `copy_source` keys and index entries, as the server
+/// does: unreserved characters and `/` stay as they are.
+const S3_KEY_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'/')
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// Lifetime of presigned download URLs (same as the server's).
+const PRESIGN_EXPIRY: Duration = Duration::from_secs(3600);
+
+/// Keys the server keeps for itself, hidden from listings.
+const RESERVED_PREFIX: &str = ".solidrop/";
+
+/// The server's content index: an empty object per fingerprint, whose `path` metadata
+/// (percent-encoded) names an object stored with it.
+const INDEX_PREFIX: &str = ".solidrop/by-hash/";
+
+fn index_key(content_hash: &str) -> Option<String> {
+    let fingerprint = ContentFingerprint::parse(content_hash)?;
+    let hex = fingerprint.as_str().strip_prefix(FINGERPRINT_PREFIX)?;
+    Some(format!("{INDEX_PREFIX}{hex}"))
+}
+
+pub struct DirectS3 {
+    client: Client,
+    bucket: String,
+}
+
+/// Turn an SDK error into one that names the operation and the full cause chain.
+fn s3_error<E>(operation: &'static str) -> impl FnOnce(SdkError<E>) -> anyhow::Error
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    move |err| anyhow!("S3 {operation} failed: {}", DisplayErrorContext(err))
+}
+
+fn is_not_found<E>(err: &SdkError<E>) -> bool {
+    matches!(err, SdkError::ServiceError(e) if e.raw().status().as_u16() == 404)
+}
+
+impl DirectS3 {
+    /// Client for `config.bucket`, with credentials from the usual AWS sources
+    /// (environment, `~/.aws`, optionally the named profile).
+    pub async fn from_config(config: &S3Config) -> Self {
+        let mut loader = aws_config::defaults(aws_config::BehaviorVersion::latest())
+            .region(aws_sdk_s3::config::Region::new(config.region.clone()));
+        if let Some(profile) = &config.profile {
+            loader = loader.profile_name(profile);
+        }
+        let aws_config = loader.load().await;
+
+        let mut s3_config = aws_sdk_s3::config::Builder::from(&aws_config);
+        if let Some(endpoint_url) = &config.endpoint_url {
+            s3_config = s3_config.endpoint_url(endpoint_url);
+        }
+        if config.force_path_style {
+            s3_config = s3_config.force_path_style(true);
+        }
+
+        Self {
+            client: Client::from_conf(s3_config.build()),
+            bucket: config.bucket.clone(),
+        }
+    }
+
+    /// Presigned GET URL of the current version, or of `version_id`, so downloads go
+    /// through the same HTTP path as with the server.
+    pub async fn presign_download(&self, path: &str, version_id: Option<&str>) -> Result<String> {
+        let presigning_config = PresigningConfig::expires_in(PRESIGN_EXPIRY)?;
+        let presigned = self
+            .client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(path)
+            .set_version_id(version_id.map(str::to_string))
+            .presigned(presigning_config)
+            .await
+            .map_err(s3_error("presign"))?;
+        Ok(presigned.uri().to_string())
+    }
+
+    /// PUT an encrypted object with the metadata the server's presigned upload sets.
+    pub async fn put(
+        &self,
+        path: &str,
+        fingerprint: &ContentFingerprint,
+        data: &[u8],
+    ) -> Result<()> {
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(path)
+            .content_type("application/octet-stream")
+            .metadata("content-hash", fingerprint.as_str())
+            .metadata("original-size", data.len().to_string())
+            .body(ByteStream::from(data.to_vec()))
+            .send()
+            .await
+            .map_err(s3_error("put_object"))?;
+        self.index(fingerprint.as_str(), path).await;
+        Ok(())
+    }
+
+    /// User metadata of `key`, or `None` if there is no such object.
+    async fn head_metadata(&self, key: &str) -> Result<Option<HashMap<String, String>>> {
+        match self
+            .client
+            .head_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .await
+        {
+            Ok(head) => Ok(Some(head.metadata().cloned().unwrap_or_default())),
+            Err(err) if is_not_found(&err) => Ok(None),
+            Err(err) => Err(s3_error("head_object")(err)),
+        }
+    }
+
+    /// Point the index entry for `content_hash` at `path`, as the server does when it
+    /// presigns an upload. Best-effort: the object is stored either way.
+    async fn index(&self, content_hash: &str, path: &str) {
+        let Some(key) = index_key(content_hash) else {
+            return;
+        };
+        if let Err(err) = self
+            .client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(&key)
+            .metadata(
+                "path",
+                utf8_percent_encode(path, S3_KEY_ENCODE_SET).to_string(),
+            )
+            .body(ByteStream::from_static(b""))
+            .send()
+            .await
+        {
+            tracing::warn!(
+                path,
+                "content index entry not written: {}",
+                DisplayErrorContext(err)
+            );
+        }
+    }
+
+    /// Like [`index`](Self::index), with the fingerprint `path` is stored with.
+    async fn index_object(&self, path: &str) {
+        match self.head_metadata(path).await {
+            Ok(Some(metadata)) => {
+                if let Some(content_hash) = metadata.get("content-hash") {
+                    self.index(content_hash, path).await;
+                }
+            }
+            Ok(None) => {}
+            Err(err) => tracing::warn!(path, "content index entry not written: {err:#}"),
+        }
+    }
+
+    /// One page of objects, with `content-hash` metadata read per object like `GET /files`.
+    pub async fn list_files(
+        &self,
+        prefix: Option<&str>,
+        limit: Option<i32>,
+        next_token: Option<&str>,
+    ) -> Result<(Vec<FileEntry>, Option<String>)> {
+        let output = self
+            .client
+            .list_objects_v2()
+            .bucket(&self.bucket)
+            .max_keys(limit.unwrap_or(100).clamp(1, 100))
+            .set_prefix(prefix.map(str::to_string))
+            .set_continuation_token(next_token.map(str::to_string))
+            .send()
+            .await
+            .map_err(s3_error("list_objects_v2"))?;
+
+        let mut files = Vec::new();
+        for object in output.contents() {
+            let Some(key) = object.key().filter(|key| !key.starts_with(RESERVED_PREFIX)) else {
+                continue;
+            };
+            let content_hash = match self
+                .client
+                .head_object()
+                .bucket(&self.bucket)
+                .key(key)
+                .send()
+                .await
+            {
+                Ok(head) => head.metadata().and_then(|m| m.get("content-hash").cloned()),
+                Err(_) => None,
+            };
+            files.push(FileEntry {
+                key: key.to_string(),
+                size: object.size().unwrap_or(0),
+                last_modified: object
+                    .last_modified()
+                    .and_then(|time| time.fmt(DateTimeFormat::DateTime).ok()),
+                content_hash,
+            });
+        }
+
+        let next_token = output.next_continuation_token().map(str::to_string);
+        Ok((files, next_token))
+    }
+
+    /// Like `POST /files/check`: the path itself, then the content index, trusted only if
+    /// the object it names still has this fingerprint. At most three HEADs.
+    pub async fn check_upload(
+        &self,
+        path: &str,
+        fingerprint: &ContentFingerprint,
+    ) -> Result<UploadCheck> {
+        let has_fingerprint = |metadata: &HashMap<String, String>| {
+            metadata.get("content-hash").map(String::as_str) == Some(fingerprint.as_str())
+        };
+        if self
+            .head_metadata(path)
+            .await?
+            .is_some_and(|m| has_fingerprint(&m))
+        {
+            return Ok(UploadCheck::AlreadyUploaded);
+        }
+
+        let Some(key) = index_key(fingerprint.as_str()) else {
+            return Ok(UploadCheck::NeedsUpload);
+        };
+        let existing_path = self
+            .head_metadata(&key)
+            .await?
+            .and_then(|entry| entry.get("path").cloned())
+            .and_then(|path| Some(percent_decode_str(&path).decode_utf8().ok()?.into_owned()))
+            .filter(|existing_path| existing_path != path);
+        if let Some(existing_path) = existing_path {
+            if self
+                .head_metadata(&existing_path)
+                .await?
+                .is_some_and(|m| has_fingerprint(&m))
+            {
+                return Ok(UploadCheck::ExistsAtOtherPath { existing_path });
+            }
+        }
+        Ok(UploadCheck::NeedsUpload)
+    }
+
+    pub async fn delete_file(&self, path: &str) -> Result<()> {
+        if let Err(err) = self
+            .client
+            .head_object()
+            .bucket(&self.bucket)
+            .key(path)
+            .send()
+            .await
+        {
+            if is_not_found(&err) {
+                bail!("file not found: {path}");
+            }
+            return Err(s3_error("head_object")(err));
+        }
+        self.client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(path)
+            .send()
+            .await
+            .map_err(s3_error("delete_object"))?;
+        Ok(())
+    }
+
+    /// Like `GET /files/versions`: versions and delete markers merged newest first, with
+    /// `content-hash` read per version.
+    pub async fn list_versions(&self, path: &str) -> Result<Vec<FileVersion>> {
+        let mut versions = Vec::new();
+        let (mut key_marker, mut version_id_marker) = (None, None);
+        loop {
+            let output = self
+                .client
+                .list_object_versions()
+                .bucket(&self.bucket)
+                .prefix(path)
+                .set_key_marker(key_marker)
+                .set_version_id_marker(version_id_marker)
+                .send()
+                .await
+                .map_err(s3_error("list_object_versions"))?;
+            for version in output.versions().iter().filter(|v| v.key() == Some(path)) {
+                let version_id = version.version_id().unwrap_or("null").to_string();
+                let content_hash = match self
+                    .client
+                    .head_object()
+                    .bucket(&self.bucket)
+                    .key(path)
+                    .version_id(&version_id)
+                    .send()
+                    .await
+                {
+                    Ok(head) => head.metadata().and_then(|m| m.get("content-hash").cloned()),
+                    Err(_) => None,
+                };
+                versions.push((
+                    version.last_modified().copied(),
+                    FileVersion {
+                        version_id,
+                        size: version.size().unwrap_or(0),
+                        last_modified: None,
+                        is_latest: version.is_latest() == Some(true),
+                        is_delete_marker: false,
+                        content_hash,
+                    },
+                ));
+            }
+            for marker in output
+                .delete_markers()
+                .iter()
+                .filter(|m| m.key() == Some(path))
+            {
+                versions.push((
+                    marker.last_modified().copied(),
+                    FileVersion {
+                        version_id: marker.version_id().unwrap_or("null").to_string(),
+                        size: 0,
+                        last_modified: None,
+                        is_latest: marker.is_latest() == Some(true),
+                        is_delete_marker: true,
+                        content_hash: None,
+                    },
+                ));
+            }
+            key_marker = output.next_key_marker().map(str::to_string);
+            version_id_marker = output.next_version_id_marker().map(str::to_string);
+            if output.is_truncated() != Some(true) {
+                break;
+            }
+        }
+
+        versions.sort_by_key(|(time, version)| {
+            Reverse((
+                time.map(|time| (time.secs(), time.subsec_nanos())),
+                version.is_latest,
+            ))
+        });
+        Ok(versions
+            .into_iter()
+            .map(|(time, mut version)| {
+                version.last_modified =
+                    time.and_then(|time| time.fmt(DateTimeFormat::DateTime).ok());
+                version
+            })
+            .collect())
+    }
+
+    /// Like `POST /files/restore`: copy the version over the current one.
+    pub async fn restore_version(&self, path: &str, version_id: &str) -> Result<()> {
+        let versions = self.list_versions(path).await?;
+        let Some(version) = versions.iter().find(|v| v.version_id == version_id) else {
+            bail!("version not found: {version_id}");
+        };
+        if version.is_delete_marker {
+            bail!("a delete marker cannot be restored; restore the version before it");
+        }
+        if version.is_latest {
+            return Ok(());
+        }
+        let encoded_path = utf8_percent_encode(path, S3_KEY_ENCODE_SET);
+        let encoded_version = utf8_percent_encode(version_id, S3_KEY_ENCODE_SET);
+        self.client
+            .copy_object()
+            .bucket(&self.bucket)
+            .copy_source(format!(
+                "{}/{encoded_path}?versionId={encoded_version}",
+                self.bucket
+            ))
+            .key(path)
+            .send()
+            .await
+            .map_err(s3_error("copy_object"))?;
+        self.index_object(path).await;
+        Ok(())
+    }
+
+    /// Copy, then delete the original (S3 has no rename).
+    pub async fn move_file(&self, from: &str, to: &str) -> Result<()> {
+        self.copy_file(from, to).await?;
+        self.client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(from)
+            .send()
+            .await
+            .map_err(|err| {
+                anyhow!(
+                    "{to} was copied but {from} could not be deleted, so it now exists at both: {}",
+                    DisplayErrorContext(err)
+                )
+            })?;
+        self.index_object(to).await;
+        Ok(())
+    }
+
+    /// Like `POST /files/copy`: a server-side copy; the source stays.
+    pub async fn copy_file(&self, from: &str, to: &str) -> Result<()> {
+        let encoded_from = utf8_percent_encode(from, S3_KEY_ENCODE_SET);
+        self.client
+            .copy_object()
+            .bucket(&self.bucket)
+            .copy_source(format!("{}/{encoded_from}", self.bucket))
+            .key(to)
+            .send()
+            .await
+            .map_err(s3_error("copy_object"))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_sdk_s3::config::{BehaviorVersion, Credentials, Region, RequestChecksumCalculation};
+    use axum::body::Bytes;
+    use axum::extract::{Path, State};
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::response::{IntoResponse, Response};
+    use axum::routing::get;
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Mutex};
+
+    /// Objects of the stand-in bucket: data and `x-amz-meta-*` headers.
+    type Objects = Arc<Mutex<BTreeMap<String, (Bytes, Vec<(String, String)>)>>>;
+
+    /// Just enough of S3's path-style API for the calls above: PUT, GET and HEAD of an
+    /// object, and one unpaginated ListObjectsV2 page.
+    async fn stand_in_s3() -> DirectS3 {
+        async fn put(
+            State(objects): State<Objects>,
+            Path((_, key)): Path<(String, String)>,
+            headers: HeaderMap,
+            data: Bytes,
+        ) -> StatusCode {
+            let metadata = headers
+                .iter()
+                .filter(|(name, _)| name.as_str().starts_with("x-amz-meta-"))
+                .map(|(name, value)| (name.to_string(), value.to_str().unwrap().to_string()))
+                .collect();
+            objects.lock().unwrap().insert(key, (data, metadata));
+            StatusCode::OK
+        }
+
+        async fn get_object(
+            State(objects): State<Objects>,
+            Path((_, key)): Path<(String, String)>,
+        ) -> Response {
+            match objects.lock().unwrap().get(&key) {
+                Some((data, metadata)) => {
+                    let mut headers = HeaderMap::new();
+                    for (name, value) in metadata {
+                        headers.insert(
+                            axum::http::HeaderName::try_from(name.as_str()).unwrap(),
+                            value.parse().unwrap(),
+                        );
+                    }
+                    (headers, data.clone()).into_response()
+                }
+                None => StatusCode::NOT_FOUND.into_response(),
+            }
+        }
+
+        async fn list(State(objects): State<Objects>) -> String {
+            let contents: String = objects
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(key, (data, _))| {
+                    format!(
+                        "<Contents><Key>{key}</Key><Size>{}</Size></Contents>",
+                        data.len()
+                    )
+                })
+                .collect();
+            format!(
+                "<ListBucketResult><IsTruncated>false</IsTruncated>{contents}</ListBucketResult>"
+            )
+        }
+
+        let objects = Objects::default();
+        let app = axum::Router::new()
+            .route("/:bucket/", get(list))
+            .route("/:bucket/*key", get(get_object).put(put))
+            .with_state(objects);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let config = aws_sdk_s3::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("us-east-1"))
+            .credentials_provider(Credentials::new("test", "test", None, None, "test"))
+            .endpoint_url(endpoint)
+            .force_path_style(true)
+            // Plain bodies rather than aws-chunked ones with checksum trailers.
+            .request_checksum_calculation(RequestChecksumCalculation::WhenRequired)
+            .build();
+        DirectS3 {
+            client: Client::from_conf(config),
+            bucket: "solidrop-test".into(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_check_upload_uses_content_index() {
+        let s3 = stand_in_s3().await;
+        let fingerprint = |byte: &str| {
+            ContentFingerprint::parse(&format!("hmac-sha256:{}", byte.repeat(32))).unwrap()
+        };
+        let (stored, other) = (fingerprint("ab"), fingerprint("cd"));
+        let path = "transfer/a b.bin.enc";
+
+        s3.put(path, &stored, b"ciphertext").await.unwrap();
+        assert_eq!(
+            s3.check_upload(path, &stored).await.unwrap(),
+            UploadCheck::AlreadyUploaded
+        );
+        assert_eq!(
+            s3.check_upload("active/a.bin.enc", &stored).await.unwrap(),
+            UploadCheck::ExistsAtOtherPath {
+                existing_path: path.into()
+            }
+        );
+        assert_eq!(
+            s3.check_upload("active/a.bin.enc", &other).await.unwrap(),
+            UploadCheck::NeedsUpload
+        );
+
+        // The index is not listed.
+        let (files, _) = s3.list_files(None, None, None).await.unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].key, path);
+        assert_eq!(files[0].content_hash.as_deref(), Some(stored.as_str()));
+
+        // An entry whose object now holds other content is not trusted.
+        s3.put(path, &other, b"other").await.unwrap();
+        s3.index(stored.as_str(), path).await;
+        assert_eq!(
+            s3.check_upload("active/a.bin.enc", &stored).await.unwrap(),
+            UploadCheck::NeedsUpload
+        );
+    }
+
+    /// Requires the docker-compose MinIO (`docker compose up -d minio minio-init`) and
+    /// `AWS_ACCESS_KEY_ID`/`AWS_SECRET_ACCESS_KEY` set to `minioadmin`.
+    #[tokio::test]
+    #[ignore]
+    async fn test_roundtrip_against_minio() {
+        let s3 = DirectS3::from_config(&S3Config {
+            bucket: "solidrop-dev".into(),
+            region: "ap-northeast-1".into(),
+            endpoint_url: Some("http://localhost:9000".into()),
+            force_path_style: true,
+            profile: None,
+        })
+        .await;
+        let fingerprint = ContentFingerprint::parse(&format!("hmac-sha256:{}", "ab".repeat(32)))
+            .expect("valid fingerprint");
+        let path = "transfer/direct-s3-test/a.bin.enc";
+
+        s3.put(path, &fingerprint, b"ciphertext").await.unwrap();
+        let (files, _) = s3
+            .list_files(Some("transfer/direct-s3-test/"), None, None)
+            .await
+            .unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].content_hash.as_deref(), Some(fingerprint.as_str()));
+
+        let url = s3.presign_download(path, None).await.unwrap();
+        let body = reqwest::get(url).await.unwrap().bytes().await.unwrap();
+        assert_eq!(&body[..], b"ciphertext");
+
+        let moved = "transfer/direct-s3-test/b.bin.enc";
+        s3.move_file(path, moved).await.unwrap();
+        assert!(s3.delete_file(path).await.is_err());
+        s3.delete_file(moved).await.unwrap();
+    }
+}
@@ -0,0 +1,9 @@
+//! The `solidrop` CLI as a library, so other front ends (the Python bindings) reuse its
+//! configuration, API client and commands.
+
+pub mod api_client;
+pub mod commands;
+pub mod config;
+pub mod direct_s3;
+pub mod master_key;
+pub mod upload_journal;
@@ -1,9 +1,9 @@
 use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+use std::sync::Arc;
 
-mod api_client;
-mod commands;
-mod config;
-mod master_key;
+use solidrop_cli::api_client::ApiClient;
+use solidrop_cli::{commands, config, master_key};
 
 #[derive(Parser)]
 #[command(
@@ -18,15 +18,25 @@ struct Cli {
 
 #[derive(Subcommand)]
 enum Commands {
-    /// Upload a file to the cloud
+    /// Upload files to the cloud
     Upload {
-        /// Path to the file to upload
-        file_path: String,
+        /// Paths of the files to upload
+        #[arg(required = true)]
+        file_paths: Vec<String>,
+        /// Store under a random object key; the name is only kept in the encrypted metadata
+        #[arg(long)]
+        opaque: bool,
+        /// Upload even if the same content is already stored
+        #[arg(long)]
+        force: bool,
     },
     /// Download a file from the cloud
     Download {
         /// Remote path of the file to download
         remote_path: String,
+        /// Download this version instead of the current one, as shown by `versions`
+        #[arg(long)]
+        version: Option<String>,
     },
     /// List files in the cloud
     List {
@@ -48,6 +58,105 @@ enum Commands {
         /// New remote path
         to: String,
     },
+    /// List the versions of a file in the cloud, newest first
+    Versions {
+        /// Remote path of the file
+        remote_path: String,
+    },
+    /// Make an earlier version of a file the current one
+    Restore {
+        /// Remote path of the file
+        remote_path: String,
+        /// Version ID to restore, as shown by `versions`
+        #[arg(long)]
+        version: String,
+        /// Restore even if the version is encrypted under a key not in the keyring
+        #[arg(long)]
+        force: bool,
+    },
+    /// Encrypt local files or directories without the server
+    Encrypt {
+        /// Files, or directories to encrypt recursively
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        /// Directory to write the `.enc` files to
+        #[arg(short, long, default_value = ".")]
+        output: PathBuf,
+    },
+    /// Decrypt local `.enc` files or directories without the server
+    Decrypt {
+        /// Encrypted files, or directories to search for `.enc` files
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+        /// Directory to write the decrypted files to
+        #[arg(short, long, default_value = ".")]
+        output: PathBuf,
+        /// Checksum file with `<content hash>  <file>` lines, as printed by `encrypt`
+        #[arg(long)]
+        hashes: Option<PathBuf>,
+    },
+    /// Show the header of an encrypted file and check it against the file size (no key needed)
+    Inspect {
+        /// Local `.enc` file, or a remote path with --remote
+        path: String,
+        /// Inspect a remote file, fetching only its header
+        #[arg(long)]
+        remote: bool,
+    },
+    /// Manage the password-protected master key
+    Key {
+        #[command(subcommand)]
+        command: KeyCommands,
+    },
+    /// Change the keyfile password (remote files are not re-encrypted)
+    Passwd,
+}
+
+#[derive(Subcommand)]
+enum KeyCommands {
+    /// Create the keyfile (wraps SOLIDROP_MASTER_KEY if set, else a new random key)
+    Init {
+        /// Overwrite an existing keyfile
+        #[arg(long)]
+        force: bool,
+    },
+    /// Print the master key as a 24-word recovery phrase
+    ExportPhrase,
+    /// Restore the master key from a recovery phrase (read from stdin) into a new keyfile
+    ImportPhrase {
+        /// Overwrite an existing keyfile
+        #[arg(long)]
+        force: bool,
+    },
+    /// Split the master key into Shamir shares
+    Split {
+        /// Number of shares needed to restore the key
+        #[arg(long)]
+        threshold: u8,
+        /// Total number of shares to create
+        #[arg(long)]
+        shares: u8,
+    },
+    /// Restore the master key from Shamir shares (read from stdin) into a new keyfile
+    Combine {
+        /// Overwrite an existing keyfile
+        #[arg(long)]
+        force: bool,
+    },
+    /// Benchmark Argon2id and propose parameters for a target unlock time
+    Calibrate {
+        /// Target unlock time in milliseconds
+        #[arg(long, default_value_t = 1000)]
+        target_ms: u64,
+        /// Starting memory cost in MiB (lowered if a single pass is already too slow)
+        #[arg(long, default_value_t = 64)]
+        memory_mib: u32,
+        /// Degree of parallelism (lanes)
+        #[arg(long, default_value_t = 1)]
+        parallelism: u32,
+    },
+    /// Re-encrypt every remote file under a new master key (resumable)
+    Rotate,
 }
 
 #[tokio::main]
@@ -56,29 +165,106 @@ async fn main() -> anyhow::Result<()> {
 
     let cli = Cli::parse();
     let config = config::CliConfig::load()?;
-    let api = api_client::ApiClient::from_config(&config)?;
+    // Key management works offline, so the API key is only required by remote commands.
+    let api = || ApiClient::from_config(&config);
 
     match cli.command {
-        Commands::Upload { file_path } => {
-            let key = master_key::acquire_master_key(&config.crypto)?;
-            commands::upload::run(&api, &key, &file_path).await?;
+        Commands::Upload {
+            file_paths,
+            opaque,
+            force,
+        } => {
+            let key = Arc::new(master_key::acquire_master_key(&config.crypto)?);
+            let options = config.crypto.encrypt_options();
+            let opaque = opaque || config.storage.opaque_keys;
+            commands::upload::run(&api().await?, &key, &options, &file_paths, opaque, force)
+                .await?;
         }
-        Commands::Download { remote_path } => {
+        Commands::Download {
+            remote_path,
+            version,
+        } => {
             let key = master_key::acquire_master_key(&config.crypto)?;
-            commands::download::run(&config, &api, &key, &remote_path).await?;
+            let keyring = master_key::keyring(&config.crypto, &key)?;
+            commands::download::run(
+                &config,
+                &api().await?,
+                &keyring,
+                &remote_path,
+                version.as_deref(),
+            )
+            .await?;
         }
         Commands::List { prefix } => {
-            commands::list::run(&api, prefix.as_deref()).await?;
+            commands::list::run(&config, &api().await?, prefix.as_deref()).await?;
         }
         Commands::Sync => {
             let key = master_key::acquire_master_key(&config.crypto)?;
-            commands::sync::run(&config, &api, &key).await?;
+            let keyring = master_key::keyring(&config.crypto, &key)?;
+            commands::sync::run(&config, &api().await?, &keyring).await?;
         }
         Commands::Delete { remote_path } => {
-            commands::delete::run(&api, &remote_path).await?;
+            commands::delete::run(&api().await?, &remote_path).await?;
         }
         Commands::Move { from, to } => {
-            commands::move_cmd::run(&api, &from, &to).await?;
+            commands::move_cmd::run(&api().await?, &from, &to).await?;
+        }
+        Commands::Versions { remote_path } => {
+            commands::versions::run(&api().await?, &remote_path).await?;
+        }
+        Commands::Restore {
+            remote_path,
+            version,
+            force,
+        } => {
+            let key = master_key::acquire_master_key(&config.crypto)?;
+            let keyring = master_key::keyring(&config.crypto, &key)?;
+            commands::restore::run(&api().await?, &keyring, &remote_path, &version, force).await?;
+        }
+        Commands::Encrypt { paths, output } => {
+            let key = master_key::acquire_master_key(&config.crypto)?;
+            let options = config.crypto.encrypt_options();
+            commands::offline::encrypt(&key, &options, &paths, &output)?;
+        }
+        Commands::Decrypt {
+            paths,
+            output,
+            hashes,
+        } => {
+            let manifest = hashes
+                .as_deref()
+                .map(commands::offline::Manifest::load)
+                .transpose()?;
+            let key = master_key::acquire_master_key(&config.crypto)?;
+            let keyring = master_key::keyring(&config.crypto, &key)?;
+            commands::offline::decrypt(&keyring, &paths, &output, manifest.as_ref())?;
+        }
+        Commands::Inspect { path, remote } => {
+            if remote {
+                commands::inspect::run_remote(&api().await?, &path).await?;
+            } else {
+                commands::inspect::run_local(std::path::Path::new(&path))?;
+            }
+        }
+        Commands::Key { command } => match command {
+            KeyCommands::Init { force } => commands::key::init(&config.crypto, force)?,
+            KeyCommands::ExportPhrase => commands::key::export_phrase(&config.crypto)?,
+            KeyCommands::ImportPhrase { force } => {
+                commands::key::import_phrase(&config.crypto, force)?
+            }
+            KeyCommands::Split { threshold, shares } => {
+                commands::key::split(&config.crypto, threshold, shares)?
+            }
+            KeyCommands::Combine { force } => commands::key::combine(&config.crypto, force)?,
+            KeyCommands::Calibrate {
+                target_ms,
+                memory_mib,
+                parallelism,
+            } => commands::key::calibrate_params(target_ms, memory_mib, parallelism)?,
+            KeyCommands::Rotate => commands::rotate::run(&config.crypto, &api().await?).await?,
+        },
+        Commands::Passwd => {
+            commands::passwd::run(&config.crypto)?;
         }
     }
 
@@ -1,44 +1,208 @@
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+use solidrop_crypto::keyring::Keyring;
+use solidrop_crypto::MasterKey;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use zeroize::Zeroizing;
 
 use crate::config::CryptoConfig;
 
 const MASTER_KEY_ENV: &str = "SOLIDROP_MASTER_KEY";
+/// Keyfile password for non-interactive use (scripts, tests).
+const PASSWORD_ENV: &str = "SOLIDROP_PASSWORD";
+/// New keyfile password for non-interactive `key init` / `passwd` / `key rotate`.
+const NEW_PASSWORD_ENV: &str = "SOLIDROP_NEW_PASSWORD";
 
-/// Acquire the 32-byte master key from the environment.
+/// Acquire the 32-byte master key.
 ///
-/// MVP implementation reads from the `SOLIDROP_MASTER_KEY` environment variable
-/// (hex-encoded, 64 characters = 32 bytes). Future versions will support OS keychain.
-pub fn acquire_master_key(_config: &CryptoConfig) -> Result<[u8; 32]> {
-    let hex_key = std::env::var(MASTER_KEY_ENV).with_context(|| {
-        format!(
-            "environment variable '{MASTER_KEY_ENV}' is not set.\n\
+/// Sources, in order:
+/// 1. The `SOLIDROP_MASTER_KEY` environment variable (hex-encoded, 64 characters = 32 bytes).
+/// 2. The keyfile (`crypto.keyfile`, default `master.key` in the config directory), unlocked
+///    with the password from `SOLIDROP_PASSWORD` or an interactive prompt.
+///
+/// Future versions will support OS keychain.
+pub fn acquire_master_key(config: &CryptoConfig) -> Result<MasterKey> {
+    if let Some(key) = master_key_from_env()? {
+        return Ok(key);
+    }
+
+    let keyfile_path = config.keyfile_path()?;
+    if !keyfile_path.exists() {
+        bail!(
+            "environment variable '{MASTER_KEY_ENV}' is not set and no keyfile was found at {}.\n\
              \n\
-             Generate a key with:\n  \
-             openssl rand -hex 32\n\
+             Create a password-protected keyfile with:\n  \
+             solidrop key init\n\
              \n\
-             Then export it:\n  \
-             export {MASTER_KEY_ENV}=<your-64-char-hex-key>"
-        )
-    })?;
+             or export a raw key:\n  \
+             export {MASTER_KEY_ENV}=$(openssl rand -hex 32)",
+            keyfile_path.display()
+        );
+    }
+
+    let keyfile = read_keyfile(&keyfile_path)?;
+    let password = read_password("Keyfile password: ")?;
+    solidrop_crypto::keyfile::unwrap_master_key(&keyfile, password.as_bytes())
+        .with_context(|| format!("failed to unlock keyfile {}", keyfile_path.display()))
+}
+
+/// The keyring for reading remote objects: `key` and the keys retired by earlier
+/// `key rotate` runs, which older object versions are still encrypted under.
+///
+/// If the retired keys cannot be read (for instance because `key` is not the key they are
+/// sealed under), a warning is logged and only `key` is used.
+pub fn keyring(config: &CryptoConfig, key: &MasterKey) -> Result<Keyring> {
+    let mut keyring = Keyring::from_key(key);
+    match read_retired_keys(&retired_keys_path(config)?, &keyring) {
+        Ok(retired) => {
+            for retired_key in &retired {
+                keyring.add(retired_key);
+            }
+        }
+        Err(err) => tracing::warn!("retired keys not loaded: {err:#}"),
+    }
+    Ok(keyring)
+}
+
+/// `<keyfile>.retired`: the keys `key rotate` replaced, encrypted under the current key.
+pub fn retired_keys_path(config: &CryptoConfig) -> Result<PathBuf> {
+    let mut path = config.keyfile_path()?.into_os_string();
+    path.push(".retired");
+    Ok(PathBuf::from(path))
+}
+
+/// Read the retired keys at `path`, decrypting them with any key in `keyring`. A missing
+/// file means no key has been retired.
+pub fn read_retired_keys(path: &Path, keyring: &Keyring) -> Result<Vec<MasterKey>> {
+    let sealed = match std::fs::read(path) {
+        Ok(sealed) => sealed,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err).with_context(|| format!("failed to read {}", path.display())),
+    };
+    let file = solidrop_crypto::decrypt::decrypt_file(keyring, &sealed)
+        .with_context(|| format!("failed to decrypt {}", path.display()))?;
+    let plaintext = Zeroizing::new(file.plaintext);
+    if plaintext.len() % 32 != 0 {
+        bail!("{} is corrupt", path.display());
+    }
+    Ok(plaintext
+        .chunks_exact(32)
+        .map(|chunk| MasterKey::from_bytes(chunk.try_into().expect("32-byte chunk")))
+        .collect())
+}
 
-    parse_master_key_hex(&hex_key)
+/// Write `keys` to `path`, encrypted under `sealing_key`, in the manner of [`write_keyfile`].
+pub fn write_retired_keys(path: &Path, sealing_key: &MasterKey, keys: &[&MasterKey]) -> Result<()> {
+    let plaintext = Zeroizing::new(
+        keys.iter()
+            .flat_map(|key| key.as_bytes().iter().copied())
+            .collect::<Vec<u8>>(),
+    );
+    let sealed = solidrop_crypto::encrypt::encrypt(sealing_key, &plaintext)
+        .context("failed to encrypt retired keys")?;
+    write_keyfile(path, &sealed)
+}
+
+/// The master key from `SOLIDROP_MASTER_KEY`, if the variable is set.
+pub fn master_key_from_env() -> Result<Option<MasterKey>> {
+    match std::env::var(MASTER_KEY_ENV) {
+        Ok(hex_key) => parse_master_key_hex(&Zeroizing::new(hex_key)).map(Some),
+        Err(_) => Ok(None),
+    }
 }
 
 /// Parse a hex-encoded master key string into a 32-byte array.
-fn parse_master_key_hex(hex_key: &str) -> Result<[u8; 32]> {
-    let bytes = hex::decode(hex_key).with_context(|| {
+fn parse_master_key_hex(hex_key: &str) -> Result<MasterKey> {
+    let bytes = Zeroizing::new(hex::decode(hex_key).with_context(|| {
         format!("{MASTER_KEY_ENV} is not valid hex (expected 64 hex characters)")
-    })?;
+    })?);
 
-    let key: [u8; 32] = bytes.try_into().map_err(|v: Vec<u8>| {
+    let key: [u8; 32] = bytes.as_slice().try_into().map_err(|_| {
         anyhow::anyhow!(
             "{MASTER_KEY_ENV} must be exactly 32 bytes (64 hex chars), got {} bytes ({} hex chars)",
-            v.len(),
-            v.len() * 2
+            bytes.len(),
+            bytes.len() * 2
         )
     })?;
 
-    Ok(key)
+    Ok(MasterKey::from_bytes(key))
+}
+
+/// Read the current keyfile password from `SOLIDROP_PASSWORD` or the terminal.
+pub fn read_password(prompt: &str) -> Result<Zeroizing<String>> {
+    if let Ok(password) = std::env::var(PASSWORD_ENV) {
+        return Ok(Zeroizing::new(password));
+    }
+    prompt_password(prompt)
+}
+
+fn prompt_password(prompt: &str) -> Result<Zeroizing<String>> {
+    rpassword::prompt_password(prompt)
+        .map(Zeroizing::new)
+        .context("failed to read password")
+}
+
+/// Read a new keyfile password from `SOLIDROP_NEW_PASSWORD`, or prompt twice for it.
+pub fn read_new_password() -> Result<Zeroizing<String>> {
+    let password = match std::env::var(NEW_PASSWORD_ENV) {
+        Ok(password) => Zeroizing::new(password),
+        Err(_) => {
+            let password = prompt_password("New password: ")?;
+            let confirm = prompt_password("Repeat new password: ")?;
+            if password != confirm {
+                bail!("passwords do not match");
+            }
+            password
+        }
+    };
+
+    if password.is_empty() {
+        bail!("password must not be empty");
+    }
+    Ok(password)
+}
+
+/// Read the password of a keyfile written earlier with a new password (the pending keyfile
+/// of an interrupted `key rotate`): `SOLIDROP_NEW_PASSWORD` or a single prompt.
+pub fn read_pending_password(prompt: &str) -> Result<Zeroizing<String>> {
+    if let Ok(password) = std::env::var(NEW_PASSWORD_ENV) {
+        return Ok(Zeroizing::new(password));
+    }
+    prompt_password(prompt)
+}
+
+pub fn read_keyfile(path: &Path) -> Result<Vec<u8>> {
+    std::fs::read(path).with_context(|| format!("failed to read keyfile: {}", path.display()))
+}
+
+/// Write a keyfile atomically (temp file + rename), readable only by the owner on Unix.
+pub fn write_keyfile(path: &Path, keyfile: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create directory: {}", parent.display()))?;
+    }
+
+    let tmp_path = path.with_extension("tmp");
+    // Left over from an interrupted write; `create_new` below refuses to reuse it.
+    match std::fs::remove_file(&tmp_path) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => {
+            return Err(err).with_context(|| format!("failed to remove {}", tmp_path.display()))
+        }
+    }
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    // Owner-only from the moment it exists, never readable under the default umask.
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options
+        .open(&tmp_path)
+        .and_then(|mut file| file.write_all(keyfile).and_then(|()| file.sync_all()))
+        .with_context(|| format!("failed to write keyfile: {}", tmp_path.display()))?;
+
+    std::fs::rename(&tmp_path, path)
+        .with_context(|| format!("failed to write keyfile: {}", path.display()))
 }
 
 #[cfg(test)]
@@ -46,10 +210,14 @@ mod tests {
     use super::*;
     use crate::config::CryptoConfig;
 
-    fn dummy_config() -> CryptoConfig {
+    fn dummy_config(keyfile: &Path) -> CryptoConfig {
         CryptoConfig {
             keychain_service: "test".into(),
             keychain_account: "test".into(),
+            keyfile: Some(keyfile.to_path_buf()),
+            kdf: None,
+            compress: false,
+            padding: Default::default(),
         }
     }
 
@@ -58,18 +226,20 @@ mod tests {
         let key_hex = "aa".repeat(32); // 64 hex chars = 32 bytes
         let result = parse_master_key_hex(&key_hex);
         assert!(result.is_ok());
-        assert_eq!(result.unwrap(), [0xaa; 32]);
+        assert_eq!(result.unwrap().as_bytes(), &[0xaa; 32]);
     }
 
     #[test]
-    fn test_missing_env_var() {
+    fn test_missing_env_var_and_keyfile() {
         // This is the only test that touches the environment, but it only removes
         // the var (idempotent) so parallel risk is minimal.
         std::env::remove_var(MASTER_KEY_ENV);
-        let result = acquire_master_key(&dummy_config());
+        let dir = tempfile::tempdir().unwrap();
+        let result = acquire_master_key(&dummy_config(&dir.path().join("missing.key")));
         assert!(result.is_err());
         let err = result.unwrap_err().to_string();
         assert!(err.contains("not set"), "error: {err}");
+        assert!(err.contains("key init"), "error: {err}");
     }
 
     #[test]
@@ -87,4 +257,51 @@ mod tests {
         let err = result.unwrap_err().to_string();
         assert!(err.contains("32 bytes"), "error: {err}");
     }
+
+    #[test]
+    fn test_retired_keys_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = dummy_config(&dir.path().join("master.key"));
+        let path = retired_keys_path(&config).unwrap();
+        assert_eq!(path, dir.path().join("master.key.retired"));
+
+        let current = MasterKey::from_bytes([1; 32]);
+        assert!(read_retired_keys(&path, &Keyring::from_key(&current))
+            .unwrap()
+            .is_empty());
+
+        let retired = [
+            MasterKey::from_bytes([2; 32]),
+            MasterKey::from_bytes([3; 32]),
+        ];
+        write_retired_keys(&path, &current, &[&retired[0], &retired[1]]).unwrap();
+        let read = read_retired_keys(&path, &Keyring::from_key(&current)).unwrap();
+        assert_eq!(read.len(), 2);
+        assert_eq!(read[1].as_bytes(), &[3; 32]);
+
+        // Sealed under another key: usable, with the current key only.
+        let other = MasterKey::from_bytes([4; 32]);
+        assert!(read_retired_keys(&path, &Keyring::from_key(&other)).is_err());
+        assert_eq!(keyring(&config, &other).unwrap().len(), 1);
+        assert_eq!(keyring(&config, &current).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_write_keyfile_replaces_atomically() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join("master.key");
+        write_keyfile(&path, b"first").unwrap();
+        // A temp file left by an interrupted write is replaced, not appended to.
+        std::fs::write(path.with_extension("tmp"), b"stale data").unwrap();
+        write_keyfile(&path, b"second").unwrap();
+        assert_eq!(read_keyfile(&path).unwrap(), b"second");
+        assert!(!path.with_extension("tmp").exists());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
 }
@@ -0,0 +1,253 @@
+//! Multipart uploads in progress, kept on disk so that one cut off by a network failure or
+//! a crash resumes on the next run. Encrypting the file again would give other bytes (a new
+//! nonce), which the parts already sent would not match, so the journal keeps the
+//! ciphertext the upload was started with.
+//!
+//! Each upload is two files named after a hash of its remote path: `<id>.enc` holds the
+//! ciphertext and `<id>.json` the path, upload ID and fingerprint. Both are written before
+//! the first part is sent and removed once the upload completes, is superseded, or can no
+//! longer be resumed (see `ApiClient`).
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use solidrop_crypto::hash::{sha256_hex, ContentFingerprint};
+
+use crate::api_client::MultipartUpload;
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    path: String,
+    upload_id: String,
+    content_hash: String,
+}
+
+/// An entry listed by [`UploadJournal::entries`].
+pub struct JournalEntry {
+    pub upload: MultipartUpload,
+    /// When the entry was written, i.e. when the upload was started.
+    pub recorded: SystemTime,
+}
+
+/// An upload found in the journal.
+pub struct PendingUpload {
+    pub upload: MultipartUpload,
+    /// Fingerprint of the plaintext, as sent when the upload was started.
+    pub content_hash: String,
+    pub ciphertext: Vec<u8>,
+}
+
+pub struct UploadJournal {
+    dir: PathBuf,
+}
+
+impl UploadJournal {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn file(&self, remote_path: &str, extension: &str) -> PathBuf {
+        let hash = sha256_hex(remote_path.as_bytes());
+        let id = &hash["sha256:".len()..][..32];
+        self.dir.join(format!("{id}.{extension}"))
+    }
+
+    /// Record `upload` of `ciphertext`, before its first part is sent. Replaces an earlier
+    /// upload to the same path.
+    pub fn record(
+        &self,
+        upload: &MultipartUpload,
+        fingerprint: &ContentFingerprint,
+        ciphertext: &[u8],
+    ) -> Result<()> {
+        std::fs::create_dir_all(&self.dir)
+            .with_context(|| format!("failed to create directory: {}", self.dir.display()))?;
+        let entry = Entry {
+            path: upload.path.clone(),
+            upload_id: upload.upload_id.clone(),
+            content_hash: fingerprint.to_string(),
+        };
+        // The entry goes last, so it is only ever found with its ciphertext complete.
+        write_file(&self.file(&upload.path, "enc"), ciphertext)?;
+        write_file(
+            &self.file(&upload.path, "json"),
+            &serde_json::to_vec(&entry).expect("strings serialize"),
+        )
+    }
+
+    /// The journaled upload to `remote_path`, if there is one.
+    pub fn find(&self, remote_path: &str) -> Result<Option<PendingUpload>> {
+        let Some(entry) = read_entry(&self.file(remote_path, "json"))? else {
+            return Ok(None);
+        };
+        if entry.path != remote_path {
+            return Ok(None);
+        }
+        let enc = self.file(remote_path, "enc");
+        let ciphertext = match std::fs::read(&enc) {
+            Ok(ciphertext) => ciphertext,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(err).with_context(|| format!("failed to read {}", enc.display()))
+            }
+        };
+        Ok(Some(PendingUpload {
+            upload: MultipartUpload {
+                path: entry.path,
+                upload_id: entry.upload_id,
+            },
+            content_hash: entry.content_hash,
+            ciphertext,
+        }))
+    }
+
+    /// Remote path of a journaled upload of the content with `fingerprint` into directory
+    /// `dir` (no trailing `/`), whatever its name.
+    pub fn find_content(
+        &self,
+        fingerprint: &ContentFingerprint,
+        dir: &str,
+    ) -> Result<Option<String>> {
+        Ok(self
+            .read_entries()?
+            .into_iter()
+            .map(|(entry, _)| entry)
+            .find(|entry| {
+                entry.content_hash == fingerprint.as_str()
+                    && entry.path.rsplit_once('/').map(|(parent, _)| parent) == Some(dir)
+            })
+            .map(|entry| entry.path))
+    }
+
+    /// Every journaled upload.
+    pub fn entries(&self) -> Result<Vec<JournalEntry>> {
+        Ok(self
+            .read_entries()?
+            .into_iter()
+            .map(|(entry, recorded)| JournalEntry {
+                upload: MultipartUpload {
+                    path: entry.path,
+                    upload_id: entry.upload_id,
+                },
+                recorded,
+            })
+            .collect())
+    }
+
+    fn read_entries(&self) -> Result<Vec<(Entry, SystemTime)>> {
+        let files = match std::fs::read_dir(&self.dir) {
+            Ok(files) => files,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => {
+                return Err(err).with_context(|| format!("failed to read {}", self.dir.display()))
+            }
+        };
+        let mut entries = Vec::new();
+        for file in files {
+            let file = file?;
+            let path = file.path();
+            if path.extension().is_none_or(|extension| extension != "json") {
+                continue;
+            }
+            let Some(entry) = read_entry(&path)? else {
+                continue;
+            };
+            let recorded = file
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            entries.push((entry, recorded));
+        }
+        Ok(entries)
+    }
+
+    /// Forget the upload to `remote_path`.
+    pub fn remove(&self, remote_path: &str) -> Result<()> {
+        for extension in ["json", "enc"] {
+            let path = self.file(remote_path, extension);
+            match std::fs::remove_file(&path) {
+                Ok(()) => {}
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => {
+                    return Err(err).with_context(|| format!("failed to remove {}", path.display()))
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn read_entry(path: &Path) -> Result<Option<Entry>> {
+    match std::fs::read(path) {
+        Ok(data) => serde_json::from_slice(&data)
+            .map(Some)
+            .with_context(|| format!("corrupt upload journal entry: {}", path.display())),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err).with_context(|| format!("failed to read {}", path.display())),
+    }
+}
+
+/// Write `data` to a temporary file beside `path`, then rename it into place.
+fn write_file(path: &Path, data: &[u8]) -> Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    std::fs::write(&tmp_path, data)
+        .and_then(|()| std::fs::rename(&tmp_path, path))
+        .with_context(|| format!("failed to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_find_remove() {
+        let dir = tempfile::tempdir().unwrap();
+        let journal = UploadJournal::new(dir.path().join("uploads"));
+        let fingerprint = ContentFingerprint::parse(&format!("hmac-sha256:{}", "ab".repeat(32)))
+            .expect("valid fingerprint");
+        let upload = MultipartUpload {
+            path: "active/2026-10/0123.enc".into(),
+            upload_id: "upload-1".into(),
+        };
+
+        assert!(journal.find(&upload.path).unwrap().is_none());
+        journal
+            .record(&upload, &fingerprint, b"ciphertext")
+            .unwrap();
+
+        let pending = journal.find(&upload.path).unwrap().unwrap();
+        assert_eq!(pending.upload, upload);
+        assert_eq!(pending.content_hash, fingerprint.as_str());
+        assert_eq!(pending.ciphertext, b"ciphertext");
+        assert_eq!(
+            journal
+                .find_content(&fingerprint, "active/2026-10")
+                .unwrap(),
+            Some(upload.path.clone())
+        );
+        assert_eq!(
+            journal
+                .find_content(&fingerprint, "active/2026-11")
+                .unwrap(),
+            None
+        );
+
+        let entries = journal.entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].upload, upload);
+        assert!(entries[0].recorded.elapsed().unwrap() < std::time::Duration::from_secs(60));
+
+        journal.remove(&upload.path).unwrap();
+        assert!(journal.find(&upload.path).unwrap().is_none());
+        assert!(journal.entries().unwrap().is_empty());
+        assert_eq!(
+            journal
+                .find_content(&fingerprint, "active/2026-10")
+                .unwrap(),
+            None
+        );
+    }
+}
@@ -1,38 +1,52 @@
 //! API contract tests for the solidrop CLI.
 //!
-//! These tests verify the API server contract using reqwest directly (not through the CLI binary).
-//! They require docker-compose (MinIO + API server) to be running:
-//!   docker compose up -d
-//!   cargo test -p solidrop-cli -- --ignored
-//!   docker compose down
-//!
-//! TODO: Add true CLI E2E tests using `assert_cmd` that invoke the `solidrop` binary.
-
-/// Test API endpoint (API server running via docker-compose).
-const API_ENDPOINT: &str = "http://localhost:3000/api/v1";
-/// Test API key matching docker-compose default.
+//! Each test runs the API server in-process on its in-memory backend
+//! (`solidrop_api_server::testing`), so no docker-compose stack is needed. The contract
+//! tests use reqwest directly; the end-to-end test drives the CLI commands through
+//! `ApiClient`.
+
+use std::sync::Arc;
+
+use solidrop_api_server::testing::{spawn_in_memory, InMemoryServer};
+use solidrop_cli::api_client::{ApiClient, MULTIPART_PART_SIZE};
+use solidrop_cli::commands;
+use solidrop_cli::config::CliConfig;
+use solidrop_cli::upload_journal::UploadJournal;
+use solidrop_crypto::encrypt::EncryptOptions;
+use solidrop_crypto::keyring::Keyring;
+use solidrop_crypto::MasterKey;
+
+/// Test API key for the in-process server.
 const API_KEY: &str = "dev-api-key";
 
-/// Build a reqwest-based API client pointing at the local docker-compose stack.
-fn api_client() -> reqwest::Client {
-    reqwest::Client::new()
+/// A running server and a plain HTTP client for it.
+struct TestApi {
+    client: reqwest::Client,
+    /// `http://127.0.0.1:<port>/api/v1`
+    endpoint: String,
+    server: InMemoryServer,
+}
+
+async fn api_client() -> TestApi {
+    let server = spawn_in_memory(API_KEY).await;
+    TestApi {
+        client: reqwest::Client::new(),
+        endpoint: format!("{}/api/v1", server.base_url),
+        server,
+    }
 }
 
 /// Generate a fixed 32-byte master key for tests.
-fn test_master_key() -> [u8; 32] {
-    [0x42u8; 32]
+fn test_master_key() -> MasterKey {
+    MasterKey::from_bytes([0x42u8; 32])
 }
 
 // --- Helper functions wrapping the API (mirror api_client.rs logic) ---
 
-async fn presign_upload(
-    client: &reqwest::Client,
-    path: &str,
-    content_hash: &str,
-    size_bytes: u64,
-) -> String {
-    let resp = client
-        .post(format!("{API_ENDPOINT}/presign/upload"))
+async fn presign_upload(api: &TestApi, path: &str, content_hash: &str, size_bytes: u64) -> String {
+    let resp = api
+        .client
+        .post(format!("{}/presign/upload", api.endpoint))
         .bearer_auth(API_KEY)
         .json(&serde_json::json!({
             "path": path,
@@ -51,9 +65,10 @@ async fn presign_upload(
     body["upload_url"].as_str().unwrap().to_string()
 }
 
-async fn presign_download(client: &reqwest::Client, path: &str) -> String {
-    let resp = client
-        .post(format!("{API_ENDPOINT}/presign/download"))
+async fn presign_download(api: &TestApi, path: &str) -> String {
+    let resp = api
+        .client
+        .post(format!("{}/presign/download", api.endpoint))
         .bearer_auth(API_KEY)
         .json(&serde_json::json!({"path": path}))
         .send()
@@ -68,9 +83,9 @@ async fn presign_download(client: &reqwest::Client, path: &str) -> String {
     body["download_url"].as_str().unwrap().to_string()
 }
 
-async fn list_files(client: &reqwest::Client, prefix: Option<&str>) -> Vec<serde_json::Value> {
-    let url = format!("{API_ENDPOINT}/files");
-    let mut req = client.get(&url).bearer_auth(API_KEY);
+async fn list_files(api: &TestApi, prefix: Option<&str>) -> Vec<serde_json::Value> {
+    let url = format!("{}/files", api.endpoint);
+    let mut req = api.client.get(&url).bearer_auth(API_KEY);
     if let Some(p) = prefix {
         req = req.query(&[("prefix", p)]);
     }
@@ -84,7 +99,7 @@ async fn list_files(client: &reqwest::Client, prefix: Option<&str>) -> Vec<serde
     body["files"].as_array().unwrap().clone()
 }
 
-async fn delete_file(client: &reqwest::Client, path: &str) {
+async fn delete_file(api: &TestApi, path: &str) {
     let encoded_path: String = path
         .split('/')
         .map(|seg| {
@@ -93,8 +108,9 @@ async fn delete_file(client: &reqwest::Client, path: &str) {
         })
         .collect::<Vec<_>>()
         .join("/");
-    let resp = client
-        .delete(format!("{API_ENDPOINT}/files/{encoded_path}"))
+    let resp = api
+        .client
+        .delete(format!("{}/files/{encoded_path}", api.endpoint))
         .bearer_auth(API_KEY)
         .send()
         .await
@@ -106,9 +122,10 @@ async fn delete_file(client: &reqwest::Client, path: &str) {
     );
 }
 
-async fn move_file(client: &reqwest::Client, from: &str, to: &str) {
-    let resp = client
-        .post(format!("{API_ENDPOINT}/files/move"))
+async fn move_file(api: &TestApi, from: &str, to: &str) {
+    let resp = api
+        .client
+        .post(format!("{}/files/move", api.endpoint))
         .bearer_auth(API_KEY)
         .json(&serde_json::json!({"from": from, "to": to}))
         .send()
@@ -123,19 +140,25 @@ async fn move_file(client: &reqwest::Client, from: &str, to: &str) {
 
 /// Upload encrypted data to a given remote path. Returns the ciphertext bytes.
 async fn upload_encrypted(
-    client: &reqwest::Client,
+    api: &TestApi,
     remote_path: &str,
     plaintext: &[u8],
-    master_key: &[u8; 32],
+    master_key: &MasterKey,
 ) -> Vec<u8> {
-    let content_hash = solidrop_crypto::hash::sha256_hex(plaintext);
+    let content_hash = solidrop_crypto::hash::content_fingerprint(master_key, plaintext);
     let ciphertext =
         solidrop_crypto::encrypt::encrypt(master_key, plaintext).expect("encryption failed");
 
-    let upload_url =
-        presign_upload(client, remote_path, &content_hash, ciphertext.len() as u64).await;
+    let upload_url = presign_upload(
+        api,
+        remote_path,
+        content_hash.as_str(),
+        ciphertext.len() as u64,
+    )
+    .await;
 
-    let resp = client
+    let resp = api
+        .client
         .put(&upload_url)
         .header("Content-Type", "application/octet-stream")
         .body(ciphertext.clone())
@@ -151,29 +174,28 @@ async fn upload_encrypted(
     ciphertext
 }
 
-// --- Integration tests (require docker-compose) ---
+// --- Contract tests ---
 
 #[tokio::test]
-#[ignore]
 async fn test_upload_and_list_roundtrip() {
-    let client = api_client();
+    let api = api_client().await;
     let key = test_master_key();
     let plaintext = b"integration test data for upload+list";
     let remote_path = "test/integration/upload-list-test.clip.enc";
 
     // Upload
-    upload_encrypted(&client, remote_path, plaintext, &key).await;
+    upload_encrypted(&api, remote_path, plaintext, &key).await;
 
     // Verify it appears in list
-    let files = list_files(&client, Some("test/integration/")).await;
+    let files = list_files(&api, Some("test/integration/")).await;
     let found = files.iter().any(|f| f["key"].as_str() == Some(remote_path));
     assert!(found, "uploaded file not found in list: {remote_path}");
 
     // Cleanup
-    delete_file(&client, remote_path).await;
+    delete_file(&api, remote_path).await;
 
     // Verify it's gone
-    let files_after = list_files(&client, Some("test/integration/")).await;
+    let files_after = list_files(&api, Some("test/integration/")).await;
     let still_there = files_after
         .iter()
         .any(|f| f["key"].as_str() == Some(remote_path));
@@ -181,19 +203,19 @@ async fn test_upload_and_list_roundtrip() {
 }
 
 #[tokio::test]
-#[ignore]
 async fn test_upload_and_download_roundtrip() {
-    let client = api_client();
+    let api = api_client().await;
     let key = test_master_key();
     let plaintext = b"roundtrip test: upload then download and verify plaintext match";
     let remote_path = "test/integration/roundtrip-test.clip.enc";
 
     // Upload
-    upload_encrypted(&client, remote_path, plaintext, &key).await;
+    upload_encrypted(&api, remote_path, plaintext, &key).await;
 
     // Download
-    let download_url = presign_download(&client, remote_path).await;
-    let encrypted_data = client
+    let download_url = presign_download(&api, remote_path).await;
+    let encrypted_data = api
+        .client
         .get(&download_url)
         .send()
         .await
@@ -211,56 +233,55 @@ async fn test_upload_and_download_roundtrip() {
     );
 
     // Cleanup
-    delete_file(&client, remote_path).await;
+    delete_file(&api, remote_path).await;
 }
 
 #[tokio::test]
-#[ignore]
 async fn test_delete() {
-    let client = api_client();
+    let api = api_client().await;
     let key = test_master_key();
     let plaintext = b"delete test data";
     let remote_path = "test/integration/delete-test.dat.enc";
 
     // Upload
-    upload_encrypted(&client, remote_path, plaintext, &key).await;
+    upload_encrypted(&api, remote_path, plaintext, &key).await;
 
     // Verify exists
-    let files = list_files(&client, Some("test/integration/delete-")).await;
+    let files = list_files(&api, Some("test/integration/delete-")).await;
     assert!(files.iter().any(|f| f["key"].as_str() == Some(remote_path)));
 
     // Delete
-    delete_file(&client, remote_path).await;
+    delete_file(&api, remote_path).await;
 
     // Verify gone
-    let files = list_files(&client, Some("test/integration/delete-")).await;
+    let files = list_files(&api, Some("test/integration/delete-")).await;
     assert!(!files.iter().any(|f| f["key"].as_str() == Some(remote_path)));
 }
 
 #[tokio::test]
-#[ignore]
 async fn test_move() {
-    let client = api_client();
+    let api = api_client().await;
     let key = test_master_key();
     let plaintext = b"move test data";
     let src = "test/integration/move-src.dat.enc";
     let dst = "test/integration/move-dst.dat.enc";
 
     // Upload to source
-    upload_encrypted(&client, src, plaintext, &key).await;
+    upload_encrypted(&api, src, plaintext, &key).await;
 
     // Move
-    move_file(&client, src, dst).await;
+    move_file(&api, src, dst).await;
 
     // Verify source is gone and destination exists
-    let files = list_files(&client, Some("test/integration/move-")).await;
+    let files = list_files(&api, Some("test/integration/move-")).await;
     let keys: Vec<&str> = files.iter().filter_map(|f| f["key"].as_str()).collect();
     assert!(!keys.contains(&src), "source should be gone after move");
     assert!(keys.contains(&dst), "destination should exist after move");
 
     // Verify data integrity: download from new location and decrypt
-    let download_url = presign_download(&client, dst).await;
-    let encrypted = client
+    let download_url = presign_download(&api, dst).await;
+    let encrypted = api
+        .client
         .get(&download_url)
         .send()
         .await
@@ -272,5 +293,393 @@ async fn test_move() {
     assert_eq!(decrypted, plaintext);
 
     // Cleanup
-    delete_file(&client, dst).await;
+    delete_file(&api, dst).await;
+}
+
+// --- CLI end-to-end ---
+
+/// Config with downloads going to `download_dir`; only the fields the commands read matter.
+fn cli_config(endpoint: &str, download_dir: &std::path::Path) -> CliConfig {
+    toml::from_str(&format!(
+        r#"
+        [server]
+        endpoint = "{endpoint}"
+        api_key_env = "UNUSED"
+
+        [storage]
+        download_dir = {download_dir:?}
+
+        [crypto]
+        keychain_service = "solidrop-test"
+        keychain_account = "test"
+        "#
+    ))
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_cli_upload_sync_move_delete() {
+    let api = api_client().await;
+    let cli = ApiClient::new(&api.endpoint, API_KEY);
+    let key = Arc::new(test_master_key());
+    let workdir = tempfile::tempdir().unwrap();
+    let downloads = workdir.path().join("downloads");
+    let config = cli_config(&api.endpoint, &downloads);
+
+    let source = workdir.path().join("sketch.clip");
+    let plaintext = b"end-to-end test through the CLI commands";
+    std::fs::write(&source, plaintext).unwrap();
+    commands::upload::run(
+        &cli,
+        &key,
+        &EncryptOptions::default(),
+        &[source.to_str().unwrap().to_string()],
+        false,
+        false,
+    )
+    .await
+    .unwrap();
+
+    let (files, _) = cli.list_files(Some("active/"), None, None).await.unwrap();
+    assert_eq!(files.len(), 1);
+    let active = files[0].key.clone();
+    assert!(
+        active.ends_with("/sketch.clip.enc"),
+        "unexpected key {active}"
+    );
+    let fingerprint = solidrop_crypto::hash::content_fingerprint(&key, plaintext);
+    assert_eq!(files[0].content_hash.as_deref(), Some(fingerprint.as_str()));
+
+    // Into transfer/, where `sync` picks it up.
+    let transfer = "transfer/2026-02-11/sketch.clip.enc";
+    commands::move_cmd::run(&cli, &active, transfer)
+        .await
+        .unwrap();
+    commands::sync::run(&config, &cli, &Keyring::from_key(&key))
+        .await
+        .unwrap();
+    let synced = std::fs::read(downloads.join("2026-02-11/sketch.clip")).unwrap();
+    assert_eq!(synced, plaintext);
+
+    commands::delete::run(&cli, transfer).await.unwrap();
+    let (files, _) = cli.list_files(None, None, None).await.unwrap();
+    assert!(files.is_empty(), "left behind: {files:?}");
+    // The fake keeps versions like a versioned bucket: the delete only added a marker.
+    let versions = api.server.storage.versions(transfer);
+    assert_eq!(versions.len(), 2);
+    assert!(versions[0].is_delete_marker);
+}
+
+#[tokio::test]
+async fn test_cli_sync_names_opaque_uploads() {
+    let api = api_client().await;
+    let cli = ApiClient::new(&api.endpoint, API_KEY);
+    let key = Arc::new(test_master_key());
+    let workdir = tempfile::tempdir().unwrap();
+    let downloads = workdir.path().join("downloads");
+    let config = cli_config(&api.endpoint, &downloads);
+
+    let source = workdir.path().join("reference.png");
+    std::fs::write(&source, b"uploaded under a random name").unwrap();
+    commands::upload::run(
+        &cli,
+        &key,
+        &EncryptOptions::default(),
+        &[source.to_str().unwrap().to_string()],
+        true,
+        false,
+    )
+    .await
+    .unwrap();
+    let (files, _) = cli.list_files(Some("active/"), None, None).await.unwrap();
+    let opaque_name = files[0].key.rsplit('/').next().unwrap().to_string();
+    let transfer = format!("transfer/2026-02-11/{opaque_name}");
+    commands::move_cmd::run(&cli, &files[0].key, &transfer)
+        .await
+        .unwrap();
+
+    commands::sync::run(&config, &cli, &Keyring::from_key(&key))
+        .await
+        .unwrap();
+    let synced = downloads.join("2026-02-11/reference.png");
+    assert_eq!(
+        std::fs::read(&synced).unwrap(),
+        b"uploaded under a random name"
+    );
+    assert!(!downloads
+        .join("2026-02-11")
+        .join(opaque_name.trim_end_matches(".enc"))
+        .exists());
+
+    // Already synced under its original name: skipped, not written again.
+    std::fs::write(&synced, b"edited locally").unwrap();
+    commands::sync::run(&config, &cli, &Keyring::from_key(&key))
+        .await
+        .unwrap();
+    assert_eq!(std::fs::read(&synced).unwrap(), b"edited locally");
+}
+
+#[tokio::test]
+async fn test_cli_upload_skips_stored_content() {
+    let api = api_client().await;
+    let cli = ApiClient::new(&api.endpoint, API_KEY);
+    let key = Arc::new(test_master_key());
+    let workdir = tempfile::tempdir().unwrap();
+    let upload = |name: &str, force: bool| {
+        let path = workdir.path().join(name);
+        std::fs::write(&path, b"the same drawing").unwrap();
+        let (cli, key) = (&cli, &key);
+        async move {
+            commands::upload::run(
+                cli,
+                key,
+                &EncryptOptions::default(),
+                &[path.to_str().unwrap().to_string()],
+                false,
+                force,
+            )
+            .await
+            .unwrap()
+        }
+    };
+    let stored = |prefix: &'static str| {
+        let cli = &cli;
+        async move {
+            let (files, _) = cli.list_files(Some(prefix), None, None).await.unwrap();
+            files.into_iter().map(|f| f.key).collect::<Vec<_>>()
+        }
+    };
+
+    upload("a.clip", false).await;
+    let keys = stored("active/").await;
+    assert_eq!(keys.len(), 1);
+    let versions = || api.server.storage.versions(&keys[0]).len();
+    assert_eq!(versions(), 1);
+
+    // Same path and content: nothing is written.
+    upload("a.clip", false).await;
+    assert_eq!(versions(), 1);
+    // Same content under another name: copied within the bucket, not uploaded.
+    upload("b.clip", false).await;
+    let keys = stored("active/").await;
+    assert_eq!(keys.len(), 2);
+    assert_eq!(
+        api.server.storage.get(&keys[0]),
+        api.server.storage.get(&keys[1])
+    );
+
+    // --force uploads regardless: a fresh encryption, other bytes.
+    upload("b.clip", true).await;
+    assert_eq!(stored("active/").await, keys);
+    assert_ne!(
+        api.server.storage.get(&keys[0]),
+        api.server.storage.get(&keys[1])
+    );
+}
+
+#[tokio::test]
+async fn test_multipart_upload_resumes() {
+    let api = api_client().await;
+    let cli = ApiClient::new(&api.endpoint, API_KEY);
+    let fingerprint = solidrop_crypto::hash::content_fingerprint(&test_master_key(), b"large");
+    // Three parts, the last one short.
+    let data: Vec<u8> = (0..2 * MULTIPART_PART_SIZE + 1000)
+        .map(|i| (i % 251) as u8)
+        .collect();
+
+    cli.upload("active/2026-02/large.clip.enc", &fingerprint, &data)
+        .await
+        .unwrap();
+    let stored = api.server.storage.get("active/2026-02/large.clip.enc");
+    assert_eq!(stored.unwrap(), data);
+
+    // An upload interrupted after its first part: resuming sends only the other two.
+    let upload = cli
+        .initiate_multipart(
+            "active/2026-02/resumed.clip.enc",
+            &fingerprint,
+            data.len() as u64,
+        )
+        .await
+        .unwrap();
+    let resp = api
+        .client
+        .post(format!("{}/multipart/presign-part", api.endpoint))
+        .bearer_auth(API_KEY)
+        .json(&serde_json::json!({
+            "path": upload.path,
+            "upload_id": upload.upload_id,
+            "part_number": 1,
+        }))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = resp.json().await.unwrap();
+    let resp = api
+        .client
+        .put(body["upload_url"].as_str().unwrap())
+        .body(data[..MULTIPART_PART_SIZE].to_vec())
+        .send()
+        .await
+        .unwrap();
+    assert!(resp.status().is_success());
+
+    assert_eq!(cli.resume_multipart(&upload, &data).await.unwrap(), 2);
+    let stored = api.server.storage.get("active/2026-02/resumed.clip.enc");
+    assert_eq!(stored.unwrap(), data);
+    let listed = cli
+        .list_files(Some("active/2026-02/resumed"), None, None)
+        .await;
+    assert_eq!(
+        listed.unwrap().0[0].content_hash.as_deref(),
+        Some(fingerprint.as_str())
+    );
+
+    // Nothing left to resume or abort once completed.
+    assert!(cli.abort_multipart(&upload).await.is_err());
+}
+
+#[tokio::test]
+async fn test_multipart_upload_resumes_from_journal() {
+    let api = api_client().await;
+    let dir = tempfile::tempdir().unwrap();
+    let journal = UploadJournal::new(dir.path());
+    let cli = ApiClient::new(&api.endpoint, API_KEY).with_upload_journal(dir.path());
+    let fingerprint = solidrop_crypto::hash::content_fingerprint(&test_master_key(), b"large");
+    let data = vec![1u8; MULTIPART_PART_SIZE + 1000];
+    // What encrypting the same file again on the next run gives.
+    let reencrypted = vec![2u8; MULTIPART_PART_SIZE + 1000];
+    let path = "active/2026-10/0123456789abcdef0123456789abcdef.enc";
+
+    // A run that started the upload and was killed before finishing it.
+    let upload = cli
+        .initiate_multipart(path, &fingerprint, data.len() as u64)
+        .await
+        .unwrap();
+    journal.record(&upload, &fingerprint, &data).unwrap();
+    assert_eq!(
+        cli.interrupted_upload(&fingerprint, "active/2026-10")
+            .unwrap()
+            .as_deref(),
+        Some(path)
+    );
+
+    // The next run finishes it with the ciphertext it was started with.
+    cli.upload(path, &fingerprint, &reencrypted).await.unwrap();
+    assert_eq!(api.server.storage.get(path).unwrap(), data);
+    assert!(journal.find(path).unwrap().is_none());
+
+    // An upload the server has given up on starts over with the new ciphertext.
+    let upload = cli
+        .initiate_multipart(path, &fingerprint, data.len() as u64)
+        .await
+        .unwrap();
+    journal.record(&upload, &fingerprint, &data).unwrap();
+    cli.abort_multipart(&upload).await.unwrap();
+    cli.upload(path, &fingerprint, &reencrypted).await.unwrap();
+    assert_eq!(api.server.storage.get(path).unwrap(), reencrypted);
+    assert!(journal.find(path).unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_upload_journal_drops_uploads_that_cannot_resume() {
+    use solidrop_api_server::storage::StorageBackend;
+
+    let api = api_client().await;
+    let dir = tempfile::tempdir().unwrap();
+    let journal = UploadJournal::new(dir.path());
+    let cli = ApiClient::new(&api.endpoint, API_KEY).with_upload_journal(dir.path());
+    let fingerprint =
+        |content: &[u8]| solidrop_crypto::hash::content_fingerprint(&test_master_key(), content);
+    let data = vec![1u8; MULTIPART_PART_SIZE + 1000];
+    let in_progress = || async {
+        let uploads = api.server.storage.list_multipart_uploads().await.unwrap();
+        uploads.into_iter().map(|u| u.upload_id).collect::<Vec<_>>()
+    };
+
+    // Interrupted uploads: one still in progress, one the server has since swept.
+    let live = cli
+        .initiate_multipart("active/live.enc", &fingerprint(b"live"), data.len() as u64)
+        .await
+        .unwrap();
+    journal.record(&live, &fingerprint(b"live"), &data).unwrap();
+    let swept = solidrop_cli::api_client::MultipartUpload {
+        path: "active/swept.enc".into(),
+        upload_id: "swept".into(),
+    };
+    journal
+        .record(&swept, &fingerprint(b"swept"), &data)
+        .unwrap();
+    // And one to the path about to be uploaded, of content since changed.
+    let path = "active/a.enc";
+    let superseded = cli
+        .initiate_multipart(path, &fingerprint(b"old"), data.len() as u64)
+        .await
+        .unwrap();
+    journal
+        .record(&superseded, &fingerprint(b"old"), &data)
+        .unwrap();
+
+    cli.upload(path, &fingerprint(b"new"), &data).await.unwrap();
+    assert_eq!(in_progress().await, vec![live.upload_id.clone()]);
+    assert!(journal.find(&swept.path).unwrap().is_none());
+    assert!(journal.find(path).unwrap().is_none());
+    assert!(journal.find(&live.path).unwrap().is_some());
+}
+
+#[tokio::test]
+async fn test_cli_versions_and_restore() {
+    let api = api_client().await;
+    let cli = ApiClient::new(&api.endpoint, API_KEY);
+    let path = "active/2026-02/a.clip.enc";
+    let hash = |value: &str| [("content-hash".to_string(), value.to_string())].into();
+    let first_hash = format!("hmac-sha256:{}", "11".repeat(32));
+    // The first version predates a key rotation.
+    let (old_key, key) = (MasterKey::from_bytes([1; 32]), test_master_key());
+    let first_data = solidrop_crypto::encrypt::encrypt(&old_key, b"first").unwrap();
+    let first = api
+        .server
+        .storage
+        .put(path, first_data.clone(), hash(&first_hash));
+    api.server.storage.put(
+        path,
+        solidrop_crypto::encrypt::encrypt(&key, b"second").unwrap(),
+        hash(&format!("hmac-sha256:{}", "22".repeat(32))),
+    );
+
+    let versions = cli.list_versions(path).await.unwrap();
+    assert_eq!(versions.len(), 2);
+    assert!(versions[0].is_latest);
+    assert_eq!(versions[1].version_id, first);
+    assert_eq!(
+        versions[1].content_hash.as_deref(),
+        Some(first_hash.as_str())
+    );
+    commands::versions::run(&cli, path).await.unwrap();
+
+    // Without the old key, the restore would leave the file unreadable.
+    let current_only = Keyring::from_key(&key);
+    let err = commands::restore::run(&cli, &current_only, path, &first, false)
+        .await
+        .unwrap_err();
+    assert!(format!("{err:#}").contains("--force"), "error: {err:#}");
+    assert_eq!(cli.list_versions(path).await.unwrap().len(), 2);
+
+    let mut keyring = Keyring::from_key(&key);
+    keyring.add(&old_key);
+    let old = commands::download::fetch_decrypted(&cli, &keyring, path, Some(&first))
+        .await
+        .unwrap();
+    assert_eq!(old.plaintext, b"first");
+
+    commands::restore::run(&cli, &keyring, path, &first, false)
+        .await
+        .unwrap();
+    assert_eq!(api.server.storage.get(path).unwrap(), first_data);
+    assert_eq!(cli.list_versions(path).await.unwrap().len(), 3);
+    assert!(
+        commands::restore::run(&cli, &keyring, path, "no-such-version", false)
+            .await
+            .is_err()
+    );
 }
@@ -0,0 +1,20 @@
+//! Generates `solidrop_crypto.h` in `OUT_DIR` from the exported items in `src/`. The copy
+//! checked in under `include/` is kept equal to it by `tests/header.rs`.
+
+use std::path::{Path, PathBuf};
+
+fn main() {
+    let crate_dir = env!("CARGO_MANIFEST_DIR");
+    let out_dir = PathBuf::from(std::env::var_os("OUT_DIR").expect("set by cargo"));
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+
+    let config = cbindgen::Config::from_file(Path::new(crate_dir).join("cbindgen.toml"))
+        .expect("cbindgen.toml is valid");
+    cbindgen::Builder::new()
+        .with_crate(crate_dir)
+        .with_config(config)
+        .generate()
+        .expect("failed to generate the C header")
+        .write_to_file(out_dir.join("solidrop_crypto.h"));
+}
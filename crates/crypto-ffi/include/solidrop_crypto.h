@@ -0,0 +1,195 @@
+#ifndef SOLIDROP_CRYPTO_H
+#define SOLIDROP_CRYPTO_H
+
+/* Generated by cbindgen from crates/crypto-ffi/src. Do not edit. */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+// Size of a master key.
+#define SOLIDROP_KEY_SIZE 32
+
+// Size of an Argon2id salt.
+#define SOLIDROP_SALT_SIZE 16
+
+// Array size for a content fingerprint: "hmac-sha256:" + 64 hex digits + NUL.
+#define SOLIDROP_FINGERPRINT_SIZE ((12 + 64) + 1)
+
+// Array size for a SHA-256 hash: "sha256:" + 64 hex digits + NUL.
+#define SOLIDROP_SHA256_SIZE ((7 + 64) + 1)
+
+// Values of `SolidropEncryptOptions::padding`: length-hiding padding, as in
+// `solidrop_crypto::Padding`.
+#define SOLIDROP_PADDING_NONE 0
+
+#define SOLIDROP_PADDING_PADME 1
+
+#define SOLIDROP_PADDING_POWER_OF_TWO 2
+
+// Result of every `solidrop_*` call. On anything but `SOLIDROP_STATUS_OK`,
+// `solidrop_last_error` describes what went wrong.
+typedef enum SolidropStatus {
+  SOLIDROP_STATUS_OK = 0,
+  // A required pointer was null, or a path was not valid UTF-8.
+  SOLIDROP_STATUS_INVALID_ARGUMENT = 1,
+  // Reading or writing a file failed.
+  SOLIDROP_STATUS_IO = 2,
+  // The input ended before the format says it should.
+  SOLIDROP_STATUS_TRUNCATED = 3,
+  // An authentication tag did not verify: corrupted, tampered with, or the wrong key.
+  SOLIDROP_STATUS_AUTHENTICATION_FAILED = 4,
+  // The file names a different key ID than the key passed in.
+  SOLIDROP_STATUS_WRONG_KEY = 5,
+  // Not a SoliDrop file, or one this library cannot read.
+  SOLIDROP_STATUS_INVALID_FORMAT = 6,
+  // Argon2id failed, the parameters were rejected, or the keyfile did not unlock.
+  SOLIDROP_STATUS_KEY_DERIVATION_FAILED = 7,
+  // Any other error from the crypto library.
+  SOLIDROP_STATUS_FAILED = 8,
+  // The content decrypted, but does not match the hash recorded for it.
+  SOLIDROP_STATUS_HASH_MISMATCH = 9,
+  // The library panicked; this is a bug.
+  SOLIDROP_STATUS_PANIC = 99,
+} SolidropStatus;
+
+// Options for `solidrop_encrypt` and `solidrop_encrypt_file`. A null options pointer means
+// no compression, no padding and no metadata beyond what the file path provides.
+//
+// The integer fields are plain integers rather than `bool` and an enum so that any value C
+// puts there is defined; values other than the documented ones are `INVALID_ARGUMENT`.
+typedef struct SolidropEncryptOptions {
+  // 1 to zstd-compress the plaintext if a sample of it shrinks, 0 not to.
+  uint32_t compress;
+  // One of the `SOLIDROP_PADDING_*` values.
+  uint32_t padding;
+  // Original file name for the metadata block, or null. `solidrop_encrypt_file`
+  // defaults to the input's file name.
+  const char *name;
+  // Name of the application writing the file, or null.
+  const char *source_app;
+} SolidropEncryptOptions;
+
+// Bytes allocated by the library. Release with `solidrop_buffer_free`.
+typedef struct SolidropBuffer {
+  uint8_t *data;
+  size_t len;
+} SolidropBuffer;
+
+// Argon2id cost parameters, as in `solidrop_crypto::key_derivation::KeyParams`.
+typedef struct SolidropKdfParams {
+  // Memory cost in KiB.
+  uint32_t m_cost;
+  uint32_t t_cost;
+  uint32_t p_cost;
+} SolidropKdfParams;
+
+// Encrypt `plaintext` into a complete SoliDrop file, written to `*out`.
+//
+// # Safety
+//
+// `key` must point to `SOLIDROP_KEY_SIZE` bytes and `plaintext` to `plaintext_len` bytes
+// (or be null with a length of 0). `options` is null or points to valid options whose
+// strings are NUL-terminated. `out` must be valid for writes.
+enum SolidropStatus solidrop_encrypt(const uint8_t *key,
+                                     const uint8_t *plaintext,
+                                     size_t plaintext_len,
+                                     const struct SolidropEncryptOptions *options,
+                                     struct SolidropBuffer *out);
+
+// Decrypt a complete SoliDrop file of any format version, writing the plaintext to `*out`.
+//
+// # Safety
+//
+// `key` must point to `SOLIDROP_KEY_SIZE` bytes and `data` to `data_len` bytes. `out` must
+// be valid for writes.
+enum SolidropStatus solidrop_decrypt(const uint8_t *key,
+                                     const uint8_t *data,
+                                     size_t data_len,
+                                     struct SolidropBuffer *out);
+
+// Encrypt the file at `input_path` into `output_path`, streaming, and optionally write the
+// plaintext's content fingerprint (as sent with uploads) to `fingerprint_out`.
+//
+// The metadata block records the input's file name (unless `options->name` is set) and
+// modification time. On failure `output_path` is removed.
+//
+// # Safety
+//
+// `key` must point to `SOLIDROP_KEY_SIZE` bytes; the paths must be NUL-terminated UTF-8.
+// `options` is null or points to valid options. `fingerprint_out` is null or points to
+// `SOLIDROP_FINGERPRINT_SIZE` writable bytes.
+enum SolidropStatus solidrop_encrypt_file(const uint8_t *key,
+                                          const char *input_path,
+                                          const char *output_path,
+                                          const struct SolidropEncryptOptions *options,
+                                          char *fingerprint_out);
+
+// Decrypt the SoliDrop file at `input_path` into `output_path`, streaming. Plaintext is
+// written as each segment authenticates; on any failure `output_path` is removed.
+//
+// # Safety
+//
+// `key` must point to `SOLIDROP_KEY_SIZE` bytes; the paths must be NUL-terminated UTF-8.
+enum SolidropStatus solidrop_decrypt_file(const uint8_t *key,
+                                          const char *input_path,
+                                          const char *output_path);
+
+// Derive a key from a password with Argon2id. `params` may be null for the library
+// defaults.
+//
+// # Safety
+//
+// `password` must point to `password_len` bytes, `salt` to `SOLIDROP_SALT_SIZE` bytes and
+// `key_out` to `SOLIDROP_KEY_SIZE` writable bytes.
+enum SolidropStatus solidrop_derive_key(const uint8_t *password,
+                                        size_t password_len,
+                                        const uint8_t *salt,
+                                        const struct SolidropKdfParams *params,
+                                        uint8_t *key_out);
+
+// Unlock a keyfile (as written by `solidrop key init`) with its password. Any failure,
+// including a wrong password, is `SOLIDROP_STATUS_KEY_DERIVATION_FAILED`.
+//
+// # Safety
+//
+// `keyfile` must point to `keyfile_len` bytes, `password` to `password_len` bytes and
+// `key_out` to `SOLIDROP_KEY_SIZE` writable bytes.
+enum SolidropStatus solidrop_unwrap_keyfile(const uint8_t *keyfile,
+                                            size_t keyfile_len,
+                                            const uint8_t *password,
+                                            size_t password_len,
+                                            uint8_t *key_out);
+
+// Plain SHA-256 of `data` as "sha256:<hex>", for local checks.
+//
+// # Safety
+//
+// `data` must point to `data_len` bytes and `out` to `SOLIDROP_SHA256_SIZE` writable bytes.
+enum SolidropStatus solidrop_sha256(const uint8_t *data, size_t data_len, char *out);
+
+// Keyed content fingerprint of `data` as "hmac-sha256:<hex>", as sent with uploads.
+//
+// # Safety
+//
+// `key` must point to `SOLIDROP_KEY_SIZE` bytes, `data` to `data_len` bytes and `out` to
+// `SOLIDROP_FINGERPRINT_SIZE` writable bytes.
+enum SolidropStatus solidrop_content_fingerprint(const uint8_t *key,
+                                                 const uint8_t *data,
+                                                 size_t data_len,
+                                                 char *out);
+
+// Release a buffer returned by this library. Freeing an empty (null) buffer is a no-op.
+//
+// # Safety
+//
+// `buffer` must come from this library and must not be used or freed again afterwards.
+void solidrop_buffer_free(struct SolidropBuffer buffer);
+
+// Message describing the last failed call on this thread, or null after a successful one.
+//
+// The string is owned by the library and stays valid until the next `solidrop_*` call on
+// the same thread.
+const char *solidrop_last_error(void);
+
+#endif  /* SOLIDROP_CRYPTO_H */
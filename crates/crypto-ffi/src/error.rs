@@ -0,0 +1,126 @@
+use std::cell::RefCell;
+use std::ffi::{c_char, CString};
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+
+use solidrop_crypto::CryptoError;
+
+/// Result of every `solidrop_*` call. On anything but `SOLIDROP_STATUS_OK`,
+/// `solidrop_last_error` describes what went wrong.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SolidropStatus {
+    Ok = 0,
+    /// A required pointer was null, or a path was not valid UTF-8.
+    InvalidArgument = 1,
+    /// Reading or writing a file failed.
+    Io = 2,
+    /// The input ended before the format says it should.
+    Truncated = 3,
+    /// An authentication tag did not verify: corrupted, tampered with, or the wrong key.
+    AuthenticationFailed = 4,
+    /// The file names a different key ID than the key passed in.
+    WrongKey = 5,
+    /// Not a SoliDrop file, or one this library cannot read.
+    InvalidFormat = 6,
+    /// Argon2id failed, the parameters were rejected, or the keyfile did not unlock.
+    KeyDerivationFailed = 7,
+    /// Any other error from the crypto library.
+    Failed = 8,
+    /// The content decrypted, but does not match the hash recorded for it.
+    HashMismatch = 9,
+    /// The library panicked; this is a bug.
+    Panic = 99,
+}
+
+/// An error on its way across the C boundary.
+pub(crate) struct Failure {
+    status: SolidropStatus,
+    message: String,
+}
+
+impl Failure {
+    pub(crate) fn invalid_argument(message: impl Into<String>) -> Self {
+        Self {
+            status: SolidropStatus::InvalidArgument,
+            message: message.into(),
+        }
+    }
+
+    pub(crate) fn with_status(self, status: SolidropStatus) -> Self {
+        Self { status, ..self }
+    }
+}
+
+impl From<CryptoError> for Failure {
+    fn from(err: CryptoError) -> Self {
+        let status = match &err {
+            CryptoError::Truncated { .. } => SolidropStatus::Truncated,
+            CryptoError::AuthenticationFailed(_) => SolidropStatus::AuthenticationFailed,
+            CryptoError::WrongKey { .. } => SolidropStatus::WrongKey,
+            CryptoError::HashMismatch { .. } => SolidropStatus::HashMismatch,
+            CryptoError::InvalidHeader(_)
+            | CryptoError::UnsupportedVersion(_)
+            | CryptoError::SizeMismatch { .. }
+            | CryptoError::DecompressionFailed(_)
+            | CryptoError::InvalidMetadata(_) => SolidropStatus::InvalidFormat,
+            CryptoError::KeyDerivationFailed(_) | CryptoError::InvalidKeyfile(_) => {
+                SolidropStatus::KeyDerivationFailed
+            }
+            _ => SolidropStatus::Failed,
+        };
+        Self {
+            status,
+            message: err.to_string(),
+        }
+    }
+}
+
+impl From<io::Error> for Failure {
+    /// The streaming adapters report crypto errors as `io::Error`s wrapping a
+    /// [`CryptoError`]; those keep their own status.
+    fn from(err: io::Error) -> Self {
+        if err.get_ref().is_some_and(|inner| inner.is::<CryptoError>()) {
+            let inner = err.into_inner().expect("checked above");
+            return (*inner.downcast::<CryptoError>().expect("checked above")).into();
+        }
+        Self {
+            status: SolidropStatus::Io,
+            message: err.to_string(),
+        }
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Run the body of an exported function: record its error for `solidrop_last_error` and
+/// keep panics from unwinding into the caller.
+pub(crate) fn run(body: impl FnOnce() -> Result<(), Failure>) -> SolidropStatus {
+    let (status, message) = match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(Ok(())) => (SolidropStatus::Ok, None),
+        Ok(Err(failure)) => (failure.status, Some(failure.message)),
+        Err(_) => (
+            SolidropStatus::Panic,
+            Some("panic in solidrop-crypto".into()),
+        ),
+    };
+    let message = message
+        .map(|message| CString::new(message.replace('\0', " ")).expect("NUL bytes were replaced"));
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+    status
+}
+
+/// Message describing the last failed call on this thread, or null after a successful one.
+///
+/// The string is owned by the library and stays valid until the next `solidrop_*` call on
+/// the same thread.
+#[no_mangle]
+pub extern "C" fn solidrop_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(std::ptr::null(), |s| s.as_ptr())
+    })
+}
@@ -0,0 +1,638 @@
+//! C ABI for `solidrop-crypto`.
+//!
+//! Every function returns a [`SolidropStatus`]; results are written through out-pointers.
+//! Keys are passed as pointers to `SOLIDROP_KEY_SIZE` bytes and never kept past the call.
+//! Byte results are [`SolidropBuffer`]s owned by the library and released with
+//! [`solidrop_buffer_free`]; string results are written NUL-terminated into caller-provided
+//! arrays of the documented size. The header `include/solidrop_crypto.h` is generated by
+//! `build.rs` (into `OUT_DIR`) and checked in.
+
+use std::ffi::{c_char, CStr};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use solidrop_crypto::decrypt::decrypt;
+use solidrop_crypto::encrypt::{encrypt_with_options, EncryptOptions};
+use solidrop_crypto::hash::{content_fingerprint, sha256_hex};
+use solidrop_crypto::key_derivation::{derive_master_key_with_params, KeyParams};
+use solidrop_crypto::keyfile::unwrap_master_key;
+use solidrop_crypto::stream::{encrypt_and_fingerprint, DecryptReader};
+use solidrop_crypto::{Compression, FileMetadata, MasterKey, Padding};
+
+mod error;
+
+use error::{run, Failure};
+pub use error::{solidrop_last_error, SolidropStatus};
+
+/// Size of a master key.
+pub const SOLIDROP_KEY_SIZE: usize = 32;
+/// Size of an Argon2id salt.
+pub const SOLIDROP_SALT_SIZE: usize = 16;
+/// Array size for a content fingerprint: "hmac-sha256:" + 64 hex digits + NUL.
+pub const SOLIDROP_FINGERPRINT_SIZE: usize = 12 + 64 + 1;
+/// Array size for a SHA-256 hash: "sha256:" + 64 hex digits + NUL.
+pub const SOLIDROP_SHA256_SIZE: usize = 7 + 64 + 1;
+
+/// Bytes allocated by the library. Release with `solidrop_buffer_free`.
+#[repr(C)]
+pub struct SolidropBuffer {
+    pub data: *mut u8,
+    pub len: usize,
+}
+
+impl SolidropBuffer {
+    fn from_vec(data: Vec<u8>) -> Self {
+        let len = data.len();
+        let data = Box::into_raw(data.into_boxed_slice()) as *mut u8;
+        Self { data, len }
+    }
+}
+
+/// Values of `SolidropEncryptOptions::padding`: length-hiding padding, as in
+/// `solidrop_crypto::Padding`.
+pub const SOLIDROP_PADDING_NONE: u32 = 0;
+pub const SOLIDROP_PADDING_PADME: u32 = 1;
+pub const SOLIDROP_PADDING_POWER_OF_TWO: u32 = 2;
+
+/// Options for `solidrop_encrypt` and `solidrop_encrypt_file`. A null options pointer means
+/// no compression, no padding and no metadata beyond what the file path provides.
+///
+/// The integer fields are plain integers rather than `bool` and an enum so that any value C
+/// puts there is defined; values other than the documented ones are `INVALID_ARGUMENT`.
+#[repr(C)]
+pub struct SolidropEncryptOptions {
+    /// 1 to zstd-compress the plaintext if a sample of it shrinks, 0 not to.
+    pub compress: u32,
+    /// One of the `SOLIDROP_PADDING_*` values.
+    pub padding: u32,
+    /// Original file name for the metadata block, or null. `solidrop_encrypt_file`
+    /// defaults to the input's file name.
+    pub name: *const c_char,
+    /// Name of the application writing the file, or null.
+    pub source_app: *const c_char,
+}
+
+/// Argon2id cost parameters, as in `solidrop_crypto::key_derivation::KeyParams`.
+#[repr(C)]
+pub struct SolidropKdfParams {
+    /// Memory cost in KiB.
+    pub m_cost: u32,
+    pub t_cost: u32,
+    pub p_cost: u32,
+}
+
+/// `len` bytes at `ptr`; null is accepted for an empty slice.
+unsafe fn bytes<'a>(ptr: *const u8, len: usize, what: &str) -> Result<&'a [u8], Failure> {
+    if ptr.is_null() {
+        return match len {
+            0 => Ok(&[]),
+            _ => Err(Failure::invalid_argument(format!("{what} is null"))),
+        };
+    }
+    Ok(std::slice::from_raw_parts(ptr, len))
+}
+
+unsafe fn key(ptr: *const u8) -> Result<MasterKey, Failure> {
+    if ptr.is_null() {
+        return Err(Failure::invalid_argument("key is null"));
+    }
+    Ok(MasterKey::from_bytes(
+        *(ptr as *const [u8; SOLIDROP_KEY_SIZE]),
+    ))
+}
+
+unsafe fn string<'a>(ptr: *const c_char, what: &str) -> Result<&'a str, Failure> {
+    if ptr.is_null() {
+        return Err(Failure::invalid_argument(format!("{what} is null")));
+    }
+    CStr::from_ptr(ptr)
+        .to_str()
+        .map_err(|_| Failure::invalid_argument(format!("{what} is not valid UTF-8")))
+}
+
+unsafe fn optional_string(ptr: *const c_char, what: &str) -> Result<Option<String>, Failure> {
+    match ptr.is_null() {
+        true => Ok(None),
+        false => string(ptr, what).map(|s| Some(s.to_string())),
+    }
+}
+
+fn out_ptr<T>(ptr: *mut T, what: &str) -> Result<*mut T, Failure> {
+    match ptr.is_null() {
+        true => Err(Failure::invalid_argument(format!("{what} is null"))),
+        false => Ok(ptr),
+    }
+}
+
+/// Copy `value` and a terminating NUL into `out`, which holds at least `value.len() + 1`.
+unsafe fn write_str(out: *mut c_char, value: &str) {
+    std::ptr::copy_nonoverlapping(value.as_ptr(), out as *mut u8, value.len());
+    *out.add(value.len()) = 0;
+}
+
+unsafe fn encrypt_options(
+    options: *const SolidropEncryptOptions,
+    default_name: Option<String>,
+) -> Result<EncryptOptions, Failure> {
+    let Some(options) = options.as_ref() else {
+        return Ok(EncryptOptions {
+            metadata: default_name.map(|name| FileMetadata {
+                name: Some(name),
+                ..Default::default()
+            }),
+            ..Default::default()
+        });
+    };
+    let name = optional_string(options.name, "options.name")?.or(default_name);
+    let source_app = optional_string(options.source_app, "options.source_app")?;
+    let metadata = (name.is_some() || source_app.is_some()).then(|| FileMetadata {
+        name,
+        source_app,
+        ..Default::default()
+    });
+    let compression = match options.compress {
+        0 => Compression::None,
+        1 => Compression::Zstd,
+        other => {
+            return Err(Failure::invalid_argument(format!(
+                "options.compress must be 0 or 1, not {other}"
+            )))
+        }
+    };
+    let padding = match options.padding {
+        SOLIDROP_PADDING_NONE => Padding::None,
+        SOLIDROP_PADDING_PADME => Padding::Padme,
+        SOLIDROP_PADDING_POWER_OF_TWO => Padding::PowerOfTwo,
+        other => {
+            return Err(Failure::invalid_argument(format!(
+                "options.padding is not a SOLIDROP_PADDING_* value: {other}"
+            )))
+        }
+    };
+    Ok(EncryptOptions {
+        compression,
+        metadata,
+        padding,
+    })
+}
+
+/// Run `write` against a new file at `path`, removing the file again if it fails.
+fn write_file(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> Result<(), Failure>,
+) -> Result<(), Failure> {
+    let mut output = BufWriter::new(File::create(path)?);
+    let result = write(&mut output).and_then(|()| Ok(output.flush()?));
+    if result.is_err() {
+        drop(output);
+        let _ = fs::remove_file(path);
+    }
+    result
+}
+
+/// Encrypt `plaintext` into a complete SoliDrop file, written to `*out`.
+///
+/// # Safety
+///
+/// `key` must point to `SOLIDROP_KEY_SIZE` bytes and `plaintext` to `plaintext_len` bytes
+/// (or be null with a length of 0). `options` is null or points to valid options whose
+/// strings are NUL-terminated. `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn solidrop_encrypt(
+    key: *const u8,
+    plaintext: *const u8,
+    plaintext_len: usize,
+    options: *const SolidropEncryptOptions,
+    out: *mut SolidropBuffer,
+) -> SolidropStatus {
+    run(|| {
+        let master_key = self::key(key)?;
+        let plaintext = bytes(plaintext, plaintext_len, "plaintext")?;
+        let options = encrypt_options(options, None)?;
+        let out = out_ptr(out, "out")?;
+        let encrypted = encrypt_with_options(&master_key, plaintext, &options)?;
+        *out = SolidropBuffer::from_vec(encrypted);
+        Ok(())
+    })
+}
+
+/// Decrypt a complete SoliDrop file of any format version, writing the plaintext to `*out`.
+///
+/// # Safety
+///
+/// `key` must point to `SOLIDROP_KEY_SIZE` bytes and `data` to `data_len` bytes. `out` must
+/// be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn solidrop_decrypt(
+    key: *const u8,
+    data: *const u8,
+    data_len: usize,
+    out: *mut SolidropBuffer,
+) -> SolidropStatus {
+    run(|| {
+        let master_key = self::key(key)?;
+        let data = bytes(data, data_len, "data")?;
+        let out = out_ptr(out, "out")?;
+        let plaintext = decrypt(&master_key, data)?;
+        *out = SolidropBuffer::from_vec(plaintext);
+        Ok(())
+    })
+}
+
+/// Encrypt the file at `input_path` into `output_path`, streaming, and optionally write the
+/// plaintext's content fingerprint (as sent with uploads) to `fingerprint_out`.
+///
+/// The metadata block records the input's file name (unless `options->name` is set) and
+/// modification time. On failure `output_path` is removed.
+///
+/// # Safety
+///
+/// `key` must point to `SOLIDROP_KEY_SIZE` bytes; the paths must be NUL-terminated UTF-8.
+/// `options` is null or points to valid options. `fingerprint_out` is null or points to
+/// `SOLIDROP_FINGERPRINT_SIZE` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn solidrop_encrypt_file(
+    key: *const u8,
+    input_path: *const c_char,
+    output_path: *const c_char,
+    options: *const SolidropEncryptOptions,
+    fingerprint_out: *mut c_char,
+) -> SolidropStatus {
+    run(|| {
+        let master_key = self::key(key)?;
+        let input_path = Path::new(string(input_path, "input_path")?);
+        let output_path = Path::new(string(output_path, "output_path")?);
+        let file_name = input_path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map(str::to_string);
+        let mut options = encrypt_options(options, file_name)?;
+
+        let input = File::open(input_path)?;
+        if let Some(metadata) = &mut options.metadata {
+            metadata.modified = input
+                .metadata()
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|age| age.as_secs() as i64);
+        }
+        write_file(output_path, |output| {
+            let sealed = encrypt_and_fingerprint(&master_key, &options, input, output)?;
+            if !fingerprint_out.is_null() {
+                write_str(fingerprint_out, sealed.fingerprint.as_str());
+            }
+            Ok(())
+        })
+    })
+}
+
+/// Decrypt the SoliDrop file at `input_path` into `output_path`, streaming. Plaintext is
+/// written as each segment authenticates; on any failure `output_path` is removed.
+///
+/// # Safety
+///
+/// `key` must point to `SOLIDROP_KEY_SIZE` bytes; the paths must be NUL-terminated UTF-8.
+#[no_mangle]
+pub unsafe extern "C" fn solidrop_decrypt_file(
+    key: *const u8,
+    input_path: *const c_char,
+    output_path: *const c_char,
+) -> SolidropStatus {
+    run(|| {
+        let master_key = self::key(key)?;
+        let input_path = Path::new(string(input_path, "input_path")?);
+        let output_path = Path::new(string(output_path, "output_path")?);
+
+        let mut reader = DecryptReader::new(&master_key, BufReader::new(File::open(input_path)?));
+        write_file(output_path, |output| {
+            io::copy(&mut reader, output)?;
+            Ok(())
+        })
+    })
+}
+
+/// Derive a key from a password with Argon2id. `params` may be null for the library
+/// defaults.
+///
+/// # Safety
+///
+/// `password` must point to `password_len` bytes, `salt` to `SOLIDROP_SALT_SIZE` bytes and
+/// `key_out` to `SOLIDROP_KEY_SIZE` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn solidrop_derive_key(
+    password: *const u8,
+    password_len: usize,
+    salt: *const u8,
+    params: *const SolidropKdfParams,
+    key_out: *mut u8,
+) -> SolidropStatus {
+    run(|| {
+        let password = bytes(password, password_len, "password")?;
+        if salt.is_null() {
+            return Err(Failure::invalid_argument("salt is null"));
+        }
+        let salt = &*(salt as *const [u8; SOLIDROP_SALT_SIZE]);
+        let params = params
+            .as_ref()
+            .map_or_else(KeyParams::default, |p| KeyParams {
+                m_cost: p.m_cost,
+                t_cost: p.t_cost,
+                p_cost: p.p_cost,
+            });
+        let key_out = out_ptr(key_out, "key_out")?;
+        let master_key = derive_master_key_with_params(password, salt, &params)?;
+        std::ptr::copy_nonoverlapping(master_key.as_bytes().as_ptr(), key_out, SOLIDROP_KEY_SIZE);
+        Ok(())
+    })
+}
+
+/// Unlock a keyfile (as written by `solidrop key init`) with its password. Any failure,
+/// including a wrong password, is `SOLIDROP_STATUS_KEY_DERIVATION_FAILED`.
+///
+/// # Safety
+///
+/// `keyfile` must point to `keyfile_len` bytes, `password` to `password_len` bytes and
+/// `key_out` to `SOLIDROP_KEY_SIZE` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn solidrop_unwrap_keyfile(
+    keyfile: *const u8,
+    keyfile_len: usize,
+    password: *const u8,
+    password_len: usize,
+    key_out: *mut u8,
+) -> SolidropStatus {
+    run(|| {
+        let keyfile = bytes(keyfile, keyfile_len, "keyfile")?;
+        let password = bytes(password, password_len, "password")?;
+        let key_out = out_ptr(key_out, "key_out")?;
+        let master_key = unwrap_master_key(keyfile, password)
+            .map_err(|err| Failure::from(err).with_status(SolidropStatus::KeyDerivationFailed))?;
+        std::ptr::copy_nonoverlapping(master_key.as_bytes().as_ptr(), key_out, SOLIDROP_KEY_SIZE);
+        Ok(())
+    })
+}
+
+/// Plain SHA-256 of `data` as "sha256:<hex>", for local checks.
+///
+/// # Safety
+///
+/// `data` must point to `data_len` bytes and `out` to `SOLIDROP_SHA256_SIZE` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn solidrop_sha256(
+    data: *const u8,
+    data_len: usize,
+    out: *mut c_char,
+) -> SolidropStatus {
+    run(|| {
+        let data = bytes(data, data_len, "data")?;
+        let out = out_ptr(out, "out")?;
+        write_str(out, &sha256_hex(data));
+        Ok(())
+    })
+}
+
+/// Keyed content fingerprint of `data` as "hmac-sha256:<hex>", as sent with uploads.
+///
+/// # Safety
+///
+/// `key` must point to `SOLIDROP_KEY_SIZE` bytes, `data` to `data_len` bytes and `out` to
+/// `SOLIDROP_FINGERPRINT_SIZE` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn solidrop_content_fingerprint(
+    key: *const u8,
+    data: *const u8,
+    data_len: usize,
+    out: *mut c_char,
+) -> SolidropStatus {
+    run(|| {
+        let master_key = self::key(key)?;
+        let data = bytes(data, data_len, "data")?;
+        let out = out_ptr(out, "out")?;
+        write_str(out, content_fingerprint(&master_key, data).as_str());
+        Ok(())
+    })
+}
+
+/// Release a buffer returned by this library. Freeing an empty (null) buffer is a no-op.
+///
+/// # Safety
+///
+/// `buffer` must come from this library and must not be used or freed again afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn solidrop_buffer_free(buffer: SolidropBuffer) {
+    if !buffer.data.is_null() {
+        drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(
+            buffer.data,
+            buffer.len,
+        )));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+
+    const KEY: [u8; SOLIDROP_KEY_SIZE] = [7u8; SOLIDROP_KEY_SIZE];
+
+    fn empty() -> SolidropBuffer {
+        SolidropBuffer {
+            data: std::ptr::null_mut(),
+            len: 0,
+        }
+    }
+
+    fn last_error() -> String {
+        unsafe { CStr::from_ptr(solidrop_last_error()) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn test_buffer_roundtrip_and_errors() {
+        let plaintext = vec![42u8; 200_000];
+        let options = SolidropEncryptOptions {
+            compress: 1,
+            padding: SOLIDROP_PADDING_PADME,
+            name: c"sketch.png".as_ptr(),
+            source_app: std::ptr::null(),
+        };
+        let mut encrypted = empty();
+        let status = unsafe {
+            solidrop_encrypt(
+                KEY.as_ptr(),
+                plaintext.as_ptr(),
+                plaintext.len(),
+                &options,
+                &mut encrypted,
+            )
+        };
+        assert_eq!(status, SolidropStatus::Ok);
+        assert!(solidrop_last_error().is_null());
+
+        let mut decrypted = empty();
+        let status = unsafe {
+            solidrop_decrypt(KEY.as_ptr(), encrypted.data, encrypted.len, &mut decrypted)
+        };
+        assert_eq!(status, SolidropStatus::Ok);
+        assert_eq!(
+            unsafe { std::slice::from_raw_parts(decrypted.data, decrypted.len) },
+            &plaintext[..]
+        );
+        unsafe { solidrop_buffer_free(decrypted) };
+
+        let wrong_key = [8u8; SOLIDROP_KEY_SIZE];
+        let mut out = empty();
+        let status = unsafe {
+            solidrop_decrypt(wrong_key.as_ptr(), encrypted.data, encrypted.len, &mut out)
+        };
+        assert_eq!(status, SolidropStatus::WrongKey);
+        assert!(last_error().starts_with("wrong key"));
+
+        let status = unsafe { solidrop_decrypt(KEY.as_ptr(), encrypted.data, 20, &mut out) };
+        assert_eq!(status, SolidropStatus::Truncated);
+        let status =
+            unsafe { solidrop_decrypt(std::ptr::null(), encrypted.data, encrypted.len, &mut out) };
+        assert_eq!(status, SolidropStatus::InvalidArgument);
+        assert_eq!(last_error(), "key is null");
+
+        // Whatever C stores in the integer options is rejected, not undefined behavior.
+        for (compress, padding) in [(2, SOLIDROP_PADDING_NONE), (0, 3), (u32::MAX, u32::MAX)] {
+            let invalid = SolidropEncryptOptions {
+                compress,
+                padding,
+                ..options
+            };
+            let status = unsafe {
+                solidrop_encrypt(
+                    KEY.as_ptr(),
+                    plaintext.as_ptr(),
+                    plaintext.len(),
+                    &invalid,
+                    &mut out,
+                )
+            };
+            assert_eq!(status, SolidropStatus::InvalidArgument);
+            assert!(last_error().starts_with("options."));
+        }
+        unsafe { solidrop_buffer_free(encrypted) };
+        unsafe { solidrop_buffer_free(empty()) };
+    }
+
+    #[test]
+    fn test_file_roundtrip_removes_output_on_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let input = dir.join("input.bin");
+        let encrypted = dir.join("input.bin.enc");
+        let output = dir.join("output.bin");
+        let plaintext: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+        fs::write(&input, &plaintext).unwrap();
+        let path = |p: &Path| CString::new(p.to_str().unwrap()).unwrap();
+
+        let mut fingerprint = [0 as c_char; SOLIDROP_FINGERPRINT_SIZE];
+        let status = unsafe {
+            solidrop_encrypt_file(
+                KEY.as_ptr(),
+                path(&input).as_ptr(),
+                path(&encrypted).as_ptr(),
+                std::ptr::null(),
+                fingerprint.as_mut_ptr(),
+            )
+        };
+        assert_eq!(status, SolidropStatus::Ok);
+        let fingerprint = unsafe { CStr::from_ptr(fingerprint.as_ptr()) };
+        let master_key = MasterKey::from_bytes(KEY);
+        assert_eq!(
+            fingerprint.to_str().unwrap(),
+            content_fingerprint(&master_key, &plaintext).as_str()
+        );
+
+        let status = unsafe {
+            solidrop_decrypt_file(
+                KEY.as_ptr(),
+                path(&encrypted).as_ptr(),
+                path(&output).as_ptr(),
+            )
+        };
+        assert_eq!(status, SolidropStatus::Ok);
+        assert_eq!(fs::read(&output).unwrap(), plaintext);
+
+        let mut corrupted = fs::read(&encrypted).unwrap();
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 1;
+        fs::write(&encrypted, &corrupted).unwrap();
+        fs::remove_file(&output).unwrap();
+        let status = unsafe {
+            solidrop_decrypt_file(
+                KEY.as_ptr(),
+                path(&encrypted).as_ptr(),
+                path(&output).as_ptr(),
+            )
+        };
+        assert_eq!(status, SolidropStatus::AuthenticationFailed);
+        assert!(!output.exists());
+
+        let status = unsafe {
+            solidrop_decrypt_file(
+                KEY.as_ptr(),
+                path(&dir.join("missing.enc")).as_ptr(),
+                path(&output).as_ptr(),
+            )
+        };
+        assert_eq!(status, SolidropStatus::Io);
+    }
+
+    #[test]
+    fn test_key_derivation_and_hashes() {
+        let params = SolidropKdfParams {
+            m_cost: 64,
+            t_cost: 1,
+            p_cost: 1,
+        };
+        let salt = [1u8; SOLIDROP_SALT_SIZE];
+        let mut derived = [0u8; SOLIDROP_KEY_SIZE];
+        let status = unsafe {
+            solidrop_derive_key(
+                b"pw".as_ptr(),
+                2,
+                salt.as_ptr(),
+                &params,
+                derived.as_mut_ptr(),
+            )
+        };
+        assert_eq!(status, SolidropStatus::Ok);
+        let expected = derive_master_key_with_params(
+            b"pw",
+            &salt,
+            &KeyParams {
+                m_cost: 64,
+                t_cost: 1,
+                p_cost: 1,
+            },
+        )
+        .unwrap();
+        assert_eq!(&derived, expected.as_bytes());
+
+        let bad = SolidropKdfParams {
+            m_cost: 1,
+            t_cost: 0,
+            p_cost: 0,
+        };
+        let status = unsafe {
+            solidrop_derive_key(b"pw".as_ptr(), 2, salt.as_ptr(), &bad, derived.as_mut_ptr())
+        };
+        assert_eq!(status, SolidropStatus::KeyDerivationFailed);
+
+        let mut hash = [0 as c_char; SOLIDROP_SHA256_SIZE];
+        let status = unsafe { solidrop_sha256(b"abc".as_ptr(), 3, hash.as_mut_ptr()) };
+        assert_eq!(status, SolidropStatus::Ok);
+        assert_eq!(
+            unsafe { CStr::from_ptr(hash.as_ptr()) }.to_str().unwrap(),
+            sha256_hex(b"abc")
+        );
+    }
+}
@@ -0,0 +1,40 @@
+//! Compiles `roundtrip.c` against the header generated by `build.rs` and the static library with the
+//! system C compiler, then runs it.
+
+#![cfg(target_os = "linux")]
+
+use std::path::PathBuf;
+use std::process::Command;
+
+#[test]
+fn test_roundtrip_through_c() {
+    let crate_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    // Integration tests live in target/<profile>/deps; the library is built next to it.
+    let target_dir = std::env::current_exe()
+        .unwrap()
+        .parent()
+        .and_then(|deps| deps.parent())
+        .unwrap()
+        .to_path_buf();
+    let scratch = tempfile::tempdir().unwrap();
+    let binary = scratch.path().join("roundtrip");
+
+    let status = Command::new(std::env::var("CC").unwrap_or_else(|_| "cc".into()))
+        .arg(crate_dir.join("tests/roundtrip.c"))
+        .arg("-I")
+        .arg(env!("OUT_DIR"))
+        .arg(target_dir.join("libsolidrop_crypto_ffi.a"))
+        .args(["-lpthread", "-ldl", "-lm", "-o"])
+        .arg(&binary)
+        .status()
+        .expect("failed to run the C compiler");
+    assert!(status.success(), "compiling roundtrip.c failed");
+
+    let output = Command::new(&binary).arg(scratch.path()).output().unwrap();
+    assert!(
+        output.status.success(),
+        "roundtrip.c failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(output.stdout, b"ok\n");
+}
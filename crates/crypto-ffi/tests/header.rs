@@ -0,0 +1,19 @@
+//! The header under `include/` is checked in for app builds; it must match the one
+//! `build.rs` generates from the current sources.
+
+use std::path::Path;
+
+#[test]
+fn test_checked_in_header_is_current() {
+    let generated = Path::new(env!("OUT_DIR")).join("solidrop_crypto.h");
+    let checked_in = Path::new(env!("CARGO_MANIFEST_DIR")).join("include/solidrop_crypto.h");
+    let generated_text = std::fs::read_to_string(&generated).unwrap();
+    let checked_in_text = std::fs::read_to_string(&checked_in).unwrap();
+    assert!(
+        generated_text == checked_in_text,
+        "{} is out of date; update it with:\n  cp {} {}",
+        checked_in.display(),
+        generated.display(),
+        checked_in.display()
+    );
+}
@@ -0,0 +1,93 @@
+/* Round-trips data through the generated header and the static library. The first
+ * argument is a scratch directory for the file-based calls. */
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "solidrop_crypto.h"
+
+#define CHECK(call, expected)                                                   \
+  do {                                                                          \
+    SolidropStatus status_ = (call);                                            \
+    if (status_ != (expected)) {                                                \
+      const char *error_ = solidrop_last_error();                               \
+      fprintf(stderr, "%s:%d: %s returned %d: %s\n", __FILE__, __LINE__, #call, \
+              status_, error_ ? error_ : "(no error)");                         \
+      return 1;                                                                 \
+    }                                                                           \
+  } while (0)
+
+static int write_file(const char *path, const uint8_t *data, size_t len) {
+  FILE *file = fopen(path, "wb");
+  if (!file || fwrite(data, 1, len, file) != len) return 1;
+  return fclose(file);
+}
+
+static long read_file(const char *path, uint8_t *data, size_t capacity) {
+  FILE *file = fopen(path, "rb");
+  if (!file) return -1;
+  long len = (long)fread(data, 1, capacity, file);
+  fclose(file);
+  return len;
+}
+
+int main(int argc, char **argv) {
+  if (argc != 2) return 2;
+
+  uint8_t key[SOLIDROP_KEY_SIZE];
+  uint8_t salt[SOLIDROP_SALT_SIZE] = {0};
+  SolidropKdfParams params = {.m_cost = 64, .t_cost = 1, .p_cost = 1};
+  CHECK(solidrop_derive_key((const uint8_t *)"password", 8, salt, &params, key),
+        SOLIDROP_STATUS_OK);
+
+  static uint8_t plaintext[200000];
+  for (size_t i = 0; i < sizeof plaintext; i++) plaintext[i] = (uint8_t)(i % 251);
+
+  SolidropEncryptOptions options = {
+      .compress = 0, .padding = SOLIDROP_PADDING_PADME, .name = "a.bin", .source_app = "c-test"};
+  SolidropBuffer encrypted = {0};
+  CHECK(solidrop_encrypt(key, plaintext, sizeof plaintext, &options, &encrypted),
+        SOLIDROP_STATUS_OK);
+  if (memcmp(encrypted.data, "SOLIDROP", 8) != 0) return 1;
+
+  SolidropBuffer decrypted = {0};
+  CHECK(solidrop_decrypt(key, encrypted.data, encrypted.len, &decrypted), SOLIDROP_STATUS_OK);
+  if (decrypted.len != sizeof plaintext || memcmp(decrypted.data, plaintext, decrypted.len) != 0)
+    return 1;
+  solidrop_buffer_free(decrypted);
+
+  encrypted.data[encrypted.len - 1] ^= 1;
+  CHECK(solidrop_decrypt(key, encrypted.data, encrypted.len, &decrypted),
+        SOLIDROP_STATUS_AUTHENTICATION_FAILED);
+  if (solidrop_last_error() == NULL) return 1;
+  solidrop_buffer_free(encrypted);
+
+  char input[4096], sealed[4096], output[4096];
+  snprintf(input, sizeof input, "%s/input.bin", argv[1]);
+  snprintf(sealed, sizeof sealed, "%s/input.bin.enc", argv[1]);
+  snprintf(output, sizeof output, "%s/output.bin", argv[1]);
+  if (write_file(input, plaintext, sizeof plaintext) != 0) return 1;
+
+  char fingerprint[SOLIDROP_FINGERPRINT_SIZE];
+  CHECK(solidrop_encrypt_file(key, input, sealed, NULL, fingerprint), SOLIDROP_STATUS_OK);
+  CHECK(solidrop_decrypt_file(key, sealed, output), SOLIDROP_STATUS_OK);
+  static uint8_t roundtrip[sizeof plaintext + 1];
+  if (read_file(output, roundtrip, sizeof roundtrip) != (long)sizeof plaintext ||
+      memcmp(roundtrip, plaintext, sizeof plaintext) != 0)
+    return 1;
+
+  char expected[SOLIDROP_FINGERPRINT_SIZE];
+  CHECK(solidrop_content_fingerprint(key, plaintext, sizeof plaintext, expected),
+        SOLIDROP_STATUS_OK);
+  if (strcmp(fingerprint, expected) != 0) return 1;
+
+  char hash[SOLIDROP_SHA256_SIZE];
+  CHECK(solidrop_sha256((const uint8_t *)"abc", 3, hash), SOLIDROP_STATUS_OK);
+  if (strcmp(hash, "sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad") != 0)
+    return 1;
+
+  CHECK(solidrop_decrypt(NULL, NULL, 0, &decrypted), SOLIDROP_STATUS_INVALID_ARGUMENT);
+  printf("ok\n");
+  return 0;
+}
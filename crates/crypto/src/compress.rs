@@ -0,0 +1,195 @@
+//! Optional compression of the plaintext before encryption (v5 and later).
+//!
+//! The algorithm is recorded in the header, so decryption undoes it transparently. Since
+//! the header and every segment are authenticated, the decompressor only ever sees data
+//! produced by a holder of the master key.
+
+use std::io::Write;
+
+use crate::CryptoError;
+
+/// zstd level used for file contents.
+pub const ZSTD_LEVEL: i32 = 3;
+
+/// Bytes taken from each of the start, middle and end of the input by [`worth_compressing`].
+const SAMPLE_WINDOW: usize = 32 * 1024;
+
+/// Compression is only used when the sample shrinks by at least this many percent.
+const MIN_SAVING_PERCENT: usize = 5;
+
+/// Compression algorithm applied to the plaintext, as recorded in the header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    #[default]
+    None,
+    Zstd,
+}
+
+impl Compression {
+    /// Header byte for this algorithm.
+    pub(crate) fn id(self) -> u8 {
+        match self {
+            Compression::None => 0,
+            Compression::Zstd => 1,
+        }
+    }
+
+    pub(crate) fn from_id(id: u8) -> Result<Self, CryptoError> {
+        match id {
+            0 => Ok(Compression::None),
+            1 => Ok(Compression::Zstd),
+            other => Err(CryptoError::InvalidHeader(format!(
+                "unknown compression algorithm: {other}"
+            ))),
+        }
+    }
+}
+
+impl std::fmt::Display for Compression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Compression::None => "none",
+            Compression::Zstd => "zstd",
+        })
+    }
+}
+
+/// Whether compressing `data` is likely to pay off.
+///
+/// Compresses up to three windows (start, middle, end) at a fast level and reports whether
+/// they shrink by at least 5%. Already-compressed formats such as PNG or JPEG fail this
+/// check, so they are stored as-is instead of paying CPU time for a slightly larger file.
+pub fn worth_compressing(data: &[u8]) -> bool {
+    let sample: Vec<u8> = if data.len() <= 3 * SAMPLE_WINDOW {
+        data.to_vec()
+    } else {
+        let middle = (data.len() - SAMPLE_WINDOW) / 2;
+        [
+            &data[..SAMPLE_WINDOW],
+            &data[middle..middle + SAMPLE_WINDOW],
+            &data[data.len() - SAMPLE_WINDOW..],
+        ]
+        .concat()
+    };
+    if sample.is_empty() {
+        return false;
+    }
+
+    match zstd::bulk::compress(&sample, 1) {
+        Ok(compressed) => compressed.len() * 100 <= sample.len() * (100 - MIN_SAVING_PERCENT),
+        Err(_) => false,
+    }
+}
+
+/// Incremental compressor feeding the encryptor.
+pub(crate) struct Compressor(zstd::stream::write::Encoder<'static, Vec<u8>>);
+
+impl Compressor {
+    /// A compressor for `compression`, or `None` when the plaintext is stored as-is.
+    pub(crate) fn new(compression: Compression) -> Result<Option<Self>, CryptoError> {
+        match compression {
+            Compression::None => Ok(None),
+            Compression::Zstd => zstd::stream::write::Encoder::new(Vec::new(), ZSTD_LEVEL)
+                .map(|encoder| Some(Self(encoder)))
+                .map_err(|e| CryptoError::EncryptionFailed(format!("compression: {e}"))),
+        }
+    }
+
+    /// Compress `data`, appending whatever compressed output is ready to `out`.
+    pub(crate) fn update(&mut self, data: &[u8], out: &mut Vec<u8>) -> Result<(), CryptoError> {
+        self.0
+            .write_all(data)
+            .map_err(|e| CryptoError::EncryptionFailed(format!("compression: {e}")))?;
+        out.append(self.0.get_mut());
+        Ok(())
+    }
+
+    /// End the compressed stream, appending the remaining output to `out`.
+    pub(crate) fn finish(self, out: &mut Vec<u8>) -> Result<(), CryptoError> {
+        let mut rest = self
+            .0
+            .finish()
+            .map_err(|e| CryptoError::EncryptionFailed(format!("compression: {e}")))?;
+        out.append(&mut rest);
+        Ok(())
+    }
+}
+
+/// Incremental decompressor fed with authenticated segment plaintext.
+pub(crate) struct Decompressor(zstd::stream::write::Decoder<'static, Vec<u8>>);
+
+impl Decompressor {
+    pub(crate) fn new(compression: Compression) -> Result<Option<Self>, CryptoError> {
+        match compression {
+            Compression::None => Ok(None),
+            Compression::Zstd => zstd::stream::write::Decoder::new(Vec::new())
+                .map(|decoder| Some(Self(decoder)))
+                .map_err(|e| CryptoError::DecompressionFailed(e.to_string())),
+        }
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8], out: &mut Vec<u8>) -> Result<(), CryptoError> {
+        self.0
+            .write_all(data)
+            .map_err(|e| CryptoError::DecompressionFailed(e.to_string()))?;
+        out.append(self.0.get_mut());
+        Ok(())
+    }
+
+    pub(crate) fn finish(mut self, out: &mut Vec<u8>) -> Result<(), CryptoError> {
+        self.0
+            .flush()
+            .map_err(|e| CryptoError::DecompressionFailed(e.to_string()))?;
+        out.append(self.0.get_mut());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::RngCore;
+
+    #[test]
+    fn test_worth_compressing() {
+        let text = b"layer 1: brush=soft round, opacity=0.8\n".repeat(10_000);
+        assert!(worth_compressing(&text));
+
+        let mut noise = vec![0u8; 200_000];
+        rand::thread_rng().fill_bytes(&mut noise);
+        assert!(!worth_compressing(&noise));
+        assert!(!worth_compressing(b""));
+    }
+
+    #[test]
+    fn test_compressor_roundtrip_in_pieces() {
+        let data = b"0123456789abcdef".repeat(20_000);
+        let mut compressor = Compressor::new(Compression::Zstd).unwrap().unwrap();
+        let mut compressed = Vec::new();
+        for piece in data.chunks(1000) {
+            compressor.update(piece, &mut compressed).unwrap();
+        }
+        compressor.finish(&mut compressed).unwrap();
+        assert!(compressed.len() < data.len() / 10);
+
+        let mut decompressor = Decompressor::new(Compression::Zstd).unwrap().unwrap();
+        let mut out = Vec::new();
+        for piece in compressed.chunks(7) {
+            decompressor.update(piece, &mut out).unwrap();
+        }
+        decompressor.finish(&mut out).unwrap();
+        assert_eq!(out, data);
+    }
+
+    #[test]
+    fn test_unknown_algorithm_rejected() {
+        assert_eq!(
+            Compression::from_id(Compression::Zstd.id()).unwrap(),
+            Compression::Zstd
+        );
+        assert!(matches!(
+            Compression::from_id(7),
+            Err(CryptoError::InvalidHeader(_))
+        ));
+    }
+}
@@ -3,83 +3,222 @@ use aes_gcm::{
     Aes256Gcm, Nonce,
 };
 
+use crate::header::LegacyHeader;
 use crate::key_derivation::derive_file_key;
-use crate::{CryptoError, FORMAT_VERSION, HEADER_SIZE, MAGIC_BYTES};
+use crate::keyring::Keyring;
+use crate::metadata::FileMetadata;
+use crate::stream::{Decryptor, TAG_SIZE};
+use crate::{
+    CryptoError, FormatInfo, MasterKey, FORMAT_VERSION_V1, MAGIC_BYTES, V1_HEADER_SIZE,
+    V8_HEADER_SIZE,
+};
 
 struct ParsedHeader<'a> {
-    salt: [u8; 16],
-    nonce: [u8; 12],
-    original_size: u64,
+    fields: LegacyHeader,
+    header_len: usize,
     ciphertext: &'a [u8],
 }
 
-fn parse_header(data: &[u8]) -> Result<ParsedHeader<'_>, CryptoError> {
-    if data.len() < HEADER_SIZE {
-        return Err(CryptoError::InvalidHeader("file too short".into()));
+/// Parse a v1 header whose salt starts at `9 + shift`.
+fn parse_header(data: &[u8], shift: usize) -> Result<ParsedHeader<'_>, CryptoError> {
+    if data.len() < V1_HEADER_SIZE + shift {
+        return Err(CryptoError::Truncated {
+            expected: (V1_HEADER_SIZE + shift) as u64,
+            actual: data.len() as u64,
+        });
     }
 
     if &data[..8] != MAGIC_BYTES.as_slice() {
         return Err(CryptoError::InvalidHeader("invalid magic bytes".into()));
     }
 
-    if data[8] != FORMAT_VERSION {
-        return Err(CryptoError::InvalidHeader(format!(
-            "unsupported version: {}",
-            data[8]
-        )));
+    if data[8] != FORMAT_VERSION_V1 {
+        return Err(CryptoError::UnsupportedVersion(data[8]));
     }
 
-    let mut salt = [0u8; 16];
-    salt.copy_from_slice(&data[9..25]);
-
-    let mut nonce = [0u8; 12];
-    nonce.copy_from_slice(&data[25..37]);
-
-    let original_size = u64::from_le_bytes(data[37..45].try_into().unwrap());
+    let data = &data[shift..];
 
     Ok(ParsedHeader {
-        salt,
-        nonce,
-        original_size,
-        ciphertext: &data[HEADER_SIZE..],
+        fields: LegacyHeader::from_fields(&data[9..V1_HEADER_SIZE]),
+        header_len: V1_HEADER_SIZE + shift,
+        ciphertext: &data[V1_HEADER_SIZE..],
     })
 }
 
-/// Decrypt an SoliDrop encrypted file using the master key.
-///
-/// Returns the original plaintext data after verifying the AES-256-GCM authentication tag.
-pub fn decrypt(master_key: &[u8; 32], encrypted_data: &[u8]) -> Result<Vec<u8>, CryptoError> {
-    let header = parse_header(encrypted_data)?;
+fn open_v1(master_key: &MasterKey, header: &ParsedHeader<'_>) -> Result<Vec<u8>, CryptoError> {
+    let original_size = header.fields.original_size;
+    let expected_len = original_size.saturating_add(TAG_SIZE as u64);
+    if (header.ciphertext.len() as u64) < expected_len {
+        let header_len = header.header_len as u64;
+        return Err(CryptoError::Truncated {
+            expected: header_len.saturating_add(expected_len),
+            actual: header_len + header.ciphertext.len() as u64,
+        });
+    }
 
-    let file_key = derive_file_key(master_key, &header.salt)?;
-    let nonce = Nonce::from_slice(&header.nonce);
+    let file_key = derive_file_key(master_key, &header.fields.salt)?;
+    let nonce = Nonce::from_slice(&header.fields.nonce);
 
-    let cipher = Aes256Gcm::new_from_slice(&file_key)
+    let cipher = Aes256Gcm::new_from_slice(file_key.as_bytes())
         .map_err(|e| CryptoError::DecryptionFailed(e.to_string()))?;
 
     let plaintext = cipher
         .decrypt(nonce, header.ciphertext)
-        .map_err(|e| CryptoError::DecryptionFailed(e.to_string()))?;
+        .map_err(|_| CryptoError::AuthenticationFailed("legacy v1 payload".into()))?;
 
-    if plaintext.len() as u64 != header.original_size {
-        return Err(CryptoError::DecryptionFailed(format!(
-            "size mismatch: header says {} bytes, got {}",
-            header.original_size,
-            plaintext.len()
-        )));
+    if plaintext.len() as u64 != original_size {
+        return Err(CryptoError::SizeMismatch {
+            expected: original_size,
+            actual: plaintext.len() as u64,
+        });
     }
 
     Ok(plaintext)
 }
 
+/// Decrypt a complete legacy v1 file (one AES-256-GCM message over the whole payload).
+///
+/// Files written by 0.1.0 carry a 46-byte header: `MAGIC_BYTES` used to include the
+/// `\x01` version byte and `encrypt` appended the version again, shifting every later
+/// field by one. If the standard layout fails to authenticate and the byte after the
+/// version is also `1`, the shifted layout is tried before giving up.
+pub(crate) fn decrypt_v1(master_key: &MasterKey, data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    let err = match open_v1(master_key, &parse_header(data, 0)?) {
+        Ok(plaintext) => return Ok(plaintext),
+        Err(e) => e,
+    };
+
+    if data.get(MAGIC_BYTES.len() + 1) == Some(&FORMAT_VERSION_V1) {
+        match parse_header(data, 1).and_then(|h| open_v1(master_key, &h)) {
+            Ok(plaintext) => return Ok(plaintext),
+            // The standard layout read its size field from the wrong offset, so a
+            // truncation it reports is meaningless for a shifted file.
+            Err(shifted) if matches!(err, CryptoError::Truncated { .. }) => return Err(shifted),
+            Err(_) => {}
+        }
+    }
+
+    Err(err)
+}
+
+/// Decrypt an SoliDrop encrypted file using the master key.
+///
+/// Accepts the segmented v2 through v8 formats (v8, with the plaintext SHA-256, is what is
+/// written now) and legacy v1 files. Returns the original plaintext data after verifying every
+/// AES-256-GCM authentication tag and, for v8, the SHA-256. A v4 or later file encrypted under a different master key fails with
+/// `WrongKey`.
+pub fn decrypt(master_key: &MasterKey, encrypted_data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    decrypt_with_info(master_key, encrypted_data).map(|(plaintext, _)| plaintext)
+}
+
+/// Like [`decrypt`], but also reports which format the file was written in, so callers can
+/// flag legacy files whose header is not authenticated.
+pub fn decrypt_with_info(
+    master_key: &MasterKey,
+    encrypted_data: &[u8],
+) -> Result<(Vec<u8>, FormatInfo), CryptoError> {
+    decrypt_with_keyring(&Keyring::from_key(master_key), encrypted_data)
+}
+
+/// Decrypt with whichever key in `keyring` the file was encrypted with (see [`Keyring`]).
+pub fn decrypt_with_keyring(
+    keyring: &Keyring,
+    encrypted_data: &[u8],
+) -> Result<(Vec<u8>, FormatInfo), CryptoError> {
+    decrypt_file(keyring, encrypted_data).map(|file| (file.plaintext, file.format))
+}
+
+/// Everything a decrypted file carries.
+#[derive(Debug)]
+pub struct DecryptedFile {
+    pub plaintext: Vec<u8>,
+    pub format: FormatInfo,
+    /// The file's encrypted metadata block; empty for files without one (before v6).
+    pub metadata: FileMetadata,
+}
+
+/// Like [`decrypt_with_keyring`], also returning the file's metadata block.
+pub fn decrypt_file(
+    keyring: &Keyring,
+    encrypted_data: &[u8],
+) -> Result<DecryptedFile, CryptoError> {
+    let mut decryptor = Decryptor::with_keyring(keyring.clone());
+    let mut plaintext = Vec::with_capacity(encrypted_data.len());
+
+    decryptor.update(encrypted_data, &mut plaintext)?;
+    let format = decryptor.format();
+    let metadata = decryptor.metadata().cloned().unwrap_or_default();
+    decryptor.finish(&mut plaintext)?;
+
+    Ok(DecryptedFile {
+        plaintext,
+        format: format.expect("finish fails before the header is complete"),
+        metadata,
+    })
+}
+
+/// Read only the metadata block from the first bytes of a file, e.g. a ranged download.
+///
+/// Returns `Ok(None)` for files without a metadata block. If `prefix` is too short, fails
+/// with [`CryptoError::Truncated`] whose `expected` is the number of bytes to fetch instead.
+/// The content is not decrypted.
+pub fn read_metadata(
+    keyring: &Keyring,
+    prefix: &[u8],
+) -> Result<Option<FileMetadata>, CryptoError> {
+    let mut decryptor = Decryptor::with_keyring(keyring.clone());
+    let mut sink = Vec::new();
+
+    // Headers are at most V8_HEADER_SIZE bytes; for older formats the excess is at most a
+    // few bytes of the first segment, which stay buffered.
+    let header_part = prefix.len().min(V8_HEADER_SIZE);
+    decryptor.update(&prefix[..header_part], &mut sink)?;
+    if decryptor.format().is_none() {
+        // Let `finish` report how many header bytes are missing.
+        return decryptor.finish(&mut sink).map(|()| None);
+    }
+    let Some(end) = decryptor.metadata_end() else {
+        return Ok(None);
+    };
+    if (prefix.len() as u64) < end {
+        return Err(CryptoError::Truncated {
+            expected: end,
+            actual: prefix.len() as u64,
+        });
+    }
+    decryptor.update(&prefix[header_part..end as usize], &mut sink)?;
+    Ok(decryptor.metadata().cloned())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::encrypt::encrypt;
+    use crate::encrypt::{encrypt, encrypt_with_options, EncryptOptions};
+    use crate::key_derivation::generate_salt;
+
+    /// Build a v1 file the way the original `encrypt` was specified (45-byte header).
+    fn encrypt_v1(master_key: &MasterKey, plaintext: &[u8], magic: &[u8]) -> Vec<u8> {
+        let salt = generate_salt();
+        let nonce = [7u8; 12];
+        let file_key = derive_file_key(master_key, &salt).unwrap();
+        let cipher = Aes256Gcm::new_from_slice(file_key.as_bytes()).unwrap();
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce), plaintext)
+            .unwrap();
+
+        let mut out = Vec::new();
+        out.extend_from_slice(magic);
+        out.push(FORMAT_VERSION_V1);
+        out.extend_from_slice(&salt);
+        out.extend_from_slice(&nonce);
+        out.extend_from_slice(&(plaintext.len() as u64).to_le_bytes());
+        out.extend_from_slice(&ciphertext);
+        out
+    }
 
     #[test]
     fn test_roundtrip() {
-        let master_key = [42u8; 32];
+        let master_key = MasterKey::from_bytes([42u8; 32]);
         let plaintext = b"hello world, this is a test of SoliDrop encryption";
         let encrypted = encrypt(&master_key, plaintext).unwrap();
         let decrypted = decrypt(&master_key, &encrypted).unwrap();
@@ -88,22 +227,146 @@ mod tests {
 
     #[test]
     fn test_wrong_key_fails() {
-        let master_key = [42u8; 32];
-        let wrong_key = [99u8; 32];
+        let master_key = MasterKey::from_bytes([42u8; 32]);
+        let wrong_key = MasterKey::from_bytes([99u8; 32]);
         let plaintext = b"secret data";
         let encrypted = encrypt(&master_key, plaintext).unwrap();
-        assert!(decrypt(&wrong_key, &encrypted).is_err());
+        assert!(matches!(
+            decrypt(&wrong_key, &encrypted),
+            Err(CryptoError::WrongKey { .. })
+        ));
     }
 
     #[test]
     fn test_truncated_data_fails() {
-        assert!(decrypt(&[0u8; 32], &[0u8; 10]).is_err());
+        assert!(decrypt(&MasterKey::from_bytes([0u8; 32]), &[0u8; 10]).is_err());
+        let encrypted = encrypt(&MasterKey::from_bytes([1u8; 32]), b"data").unwrap();
+        assert!(matches!(
+            decrypt(&MasterKey::from_bytes([1u8; 32]), &encrypted[..30]),
+            Err(CryptoError::Truncated {
+                expected: 50,
+                actual: 30
+            })
+        ));
+    }
+
+    #[test]
+    fn test_legacy_v1_errors_are_specific() {
+        let master_key = MasterKey::from_bytes([42u8; 32]);
+        let encrypted = encrypt_v1(&master_key, b"twelve bytes", MAGIC_BYTES);
+        assert!(matches!(
+            decrypt(&master_key, &encrypted[..encrypted.len() - 5]),
+            Err(CryptoError::Truncated { .. })
+        ));
+
+        let mut corrupted = encrypted.clone();
+        *corrupted.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            decrypt(&master_key, &corrupted),
+            Err(CryptoError::AuthenticationFailed(_))
+        ));
+    }
+
+    #[test]
+    fn test_metadata_roundtrip_and_prefix_read() {
+        let master_key = MasterKey::from_bytes([42u8; 32]);
+        let metadata = FileMetadata {
+            name: Some("reference.png".into()),
+            modified: Some(1_770_000_000),
+            mime_type: Some("image/png".into()),
+            source_app: None,
+            unknown: Vec::new(),
+        };
+        let options = EncryptOptions {
+            metadata: Some(metadata.clone()),
+            ..Default::default()
+        };
+        let encrypted = encrypt_with_options(&master_key, &[1u8; 100_000], &options).unwrap();
+        let keyring = Keyring::from_key(&master_key);
+
+        let file = decrypt_file(&keyring, &encrypted).unwrap();
+        assert_eq!(file.plaintext, [1u8; 100_000]);
+        assert_eq!(file.metadata, metadata);
+
+        // The name is not visible in the ciphertext.
+        assert!(!encrypted.windows(13).any(|w| w == b"reference.png"));
+
+        let end = match read_metadata(&keyring, &encrypted[..V8_HEADER_SIZE + 4]) {
+            Err(CryptoError::Truncated { expected, .. }) => expected as usize,
+            other => panic!("expected Truncated, got {other:?}"),
+        };
+        assert_eq!(
+            read_metadata(&keyring, &encrypted[..end]).unwrap(),
+            Some(metadata)
+        );
+        assert!(read_metadata(&keyring, &encrypted[..10]).is_err());
+
+        let plain = encrypt(&master_key, b"no metadata").unwrap();
+        assert_eq!(read_metadata(&keyring, &plain).unwrap(), None);
+        assert_eq!(
+            decrypt_file(&keyring, &plain).unwrap().metadata,
+            FileMetadata::default()
+        );
+    }
+
+    #[test]
+    fn test_tampered_metadata_fails() {
+        let master_key = MasterKey::from_bytes([42u8; 32]);
+        let options = EncryptOptions {
+            metadata: Some(FileMetadata {
+                name: Some("a.png".into()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let mut encrypted = encrypt_with_options(&master_key, b"data", &options).unwrap();
+        encrypted[V8_HEADER_SIZE + 1] ^= 1;
+        assert!(matches!(
+            decrypt(&master_key, &encrypted),
+            Err(CryptoError::AuthenticationFailed(_))
+        ));
+        // Cut inside the metadata block.
+        assert!(matches!(
+            decrypt(&master_key, &encrypted[..V8_HEADER_SIZE + 3]),
+            Err(CryptoError::Truncated { .. })
+        ));
     }
 
     #[test]
     fn test_invalid_magic_fails() {
         let mut data = vec![0u8; 100];
         data[..8].copy_from_slice(b"INVALID\x00");
-        assert!(decrypt(&[0u8; 32], &data).is_err());
+        assert!(decrypt(&MasterKey::from_bytes([0u8; 32]), &data).is_err());
+    }
+
+    #[test]
+    fn test_decrypts_legacy_v1() {
+        let master_key = MasterKey::from_bytes([42u8; 32]);
+        let plaintext = b"written before the segmented format existed";
+        let encrypted = encrypt_v1(&master_key, plaintext, MAGIC_BYTES);
+        let (decrypted, format) = decrypt_with_info(&master_key, &encrypted).unwrap();
+        assert_eq!(decrypted, plaintext);
+        assert_eq!(format.to_string(), "v1 (legacy, header unauthenticated)");
+    }
+
+    #[test]
+    fn test_decrypts_legacy_v1_with_shifted_header() {
+        let master_key = MasterKey::from_bytes([42u8; 32]);
+        let plaintext = b"uploaded by the 0.1.0 CLI";
+        let encrypted = encrypt_v1(&master_key, plaintext, b"SOLIDROP\x01");
+        assert_eq!(decrypt(&master_key, &encrypted).unwrap(), plaintext);
+    }
+
+    #[test]
+    fn test_keyring_decrypts_legacy_v1_with_older_key() {
+        let (old, new) = (
+            MasterKey::from_bytes([1u8; 32]),
+            MasterKey::from_bytes([2u8; 32]),
+        );
+        let mut keyring = Keyring::from_key(&new);
+        keyring.add(&old);
+        let encrypted = encrypt_v1(&old, b"before rotation", MAGIC_BYTES);
+        let (plaintext, _) = decrypt_with_keyring(&keyring, &encrypted).unwrap();
+        assert_eq!(plaintext, b"before rotation");
     }
 }
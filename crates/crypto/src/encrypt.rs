@@ -1,43 +1,64 @@
-use aes_gcm::{
-    aead::{Aead, KeyInit},
-    Aes256Gcm, Nonce,
+use crate::compress::{worth_compressing, Compression};
+use crate::metadata::FileMetadata;
+use crate::padding::Padding;
+use crate::stream::{
+    encrypted_size, metadata_block_size, padded_payload_len, Encryptor, DEFAULT_SEGMENT_SIZE,
 };
-use rand::RngCore;
+use crate::{CryptoError, MasterKey};
 
-use crate::key_derivation::{derive_file_key, generate_salt};
-use crate::{CryptoError, FORMAT_VERSION, HEADER_SIZE, MAGIC_BYTES};
+/// Options for [`encrypt_with_options`] and [`Encryptor::with_options`]. The default
+/// matches [`encrypt`].
+#[derive(Debug, Clone, Default)]
+pub struct EncryptOptions {
+    /// Compress the plaintext with this algorithm before encrypting it. `encrypt_with_options`
+    /// skips it (and records `None`) when a sample of the input does not shrink; see
+    /// [`worth_compressing`](crate::compress::worth_compressing).
+    pub compression: Compression,
+    /// Encrypted metadata block to store ahead of the content (v6).
+    pub metadata: Option<FileMetadata>,
+    /// Pad the (compressed) plaintext so the ciphertext length only reveals a size bucket (v7).
+    pub padding: Padding,
+}
 
-/// Encrypt plaintext data using AES-256-GCM with a derived per-file key.
+/// Encrypt plaintext data into the segmented SoliDrop format with a derived per-file key.
 ///
-/// Returns the full encrypted file including header (magic bytes, version, salt, nonce,
-/// original size) followed by the ciphertext + authentication tag.
-pub fn encrypt(master_key: &[u8; 32], plaintext: &[u8]) -> Result<Vec<u8>, CryptoError> {
-    let salt = generate_salt();
-    let file_key = derive_file_key(master_key, &salt)?;
-
-    let mut nonce_bytes = [0u8; 12];
-    rand::thread_rng().fill_bytes(&mut nonce_bytes);
-    let nonce = Nonce::from_slice(&nonce_bytes);
-
-    let cipher = Aes256Gcm::new_from_slice(&file_key)
-        .map_err(|e| CryptoError::EncryptionFailed(e.to_string()))?;
-
-    let ciphertext = cipher
-        .encrypt(nonce, plaintext)
-        .map_err(|e| CryptoError::EncryptionFailed(e.to_string()))?;
+/// Returns the full encrypted file: the v8 header (magic bytes, version, key ID, compression,
+/// padding, salt, nonce prefix, segment size, metadata length) followed by the AES-256-GCM segments,
+/// each authenticated together with the header, the last one ending with the SHA-256 of the
+/// plaintext. For large inputs prefer the streaming
+/// adapters in [`crate::stream`], which produce identical output without buffering the file.
+pub fn encrypt(master_key: &MasterKey, plaintext: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    encrypt_with_options(master_key, plaintext, &EncryptOptions::default())
+}
 
-    let original_size = plaintext.len() as u64;
-    let mut output = Vec::with_capacity(HEADER_SIZE + ciphertext.len());
+/// Like [`encrypt`], with optional compression and padding before encryption and an optional
+/// encrypted metadata block. `decrypt` undoes compression and padding transparently; the metadata is returned
+/// by [`decrypt_file`](crate::decrypt::decrypt_file).
+pub fn encrypt_with_options(
+    master_key: &MasterKey,
+    plaintext: &[u8],
+    options: &EncryptOptions,
+) -> Result<Vec<u8>, CryptoError> {
+    let compression = match options.compression {
+        Compression::None => Compression::None,
+        algorithm if worth_compressing(plaintext) => algorithm,
+        _ => Compression::None,
+    };
+    let options = EncryptOptions {
+        compression,
+        ..options.clone()
+    };
 
-    // Header
-    output.extend_from_slice(MAGIC_BYTES);
-    output.push(FORMAT_VERSION);
-    output.extend_from_slice(&salt);
-    output.extend_from_slice(&nonce_bytes);
-    output.extend_from_slice(&original_size.to_le_bytes());
+    let mut encryptor = Encryptor::with_options(master_key, &options)?;
+    let mut output = Vec::with_capacity(
+        (encrypted_size(
+            padded_payload_len(plaintext.len() as u64, options.padding),
+            DEFAULT_SEGMENT_SIZE,
+        ) + metadata_block_size(options.metadata.as_ref())?) as usize,
+    );
 
-    // Ciphertext + auth tag
-    output.extend_from_slice(&ciphertext);
+    encryptor.update(plaintext, &mut output)?;
+    encryptor.finish(&mut output)?;
 
     Ok(output)
 }
@@ -45,26 +66,99 @@ pub fn encrypt(master_key: &[u8; 32], plaintext: &[u8]) -> Result<Vec<u8>, Crypt
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::HEADER_SIZE;
+    use crate::decrypt::decrypt_with_info;
+    use crate::key_derivation::key_fingerprint;
+    use crate::{FORMAT_VERSION, MAGIC_BYTES, V8_HEADER_SIZE};
 
     #[test]
     fn test_encrypt_produces_valid_header() {
-        let master_key = [42u8; 32];
+        let master_key = MasterKey::from_bytes([42u8; 32]);
         let plaintext = b"hello world";
         let encrypted = encrypt(&master_key, plaintext).unwrap();
 
-        assert!(encrypted.len() > HEADER_SIZE);
+        assert!(encrypted.len() > V8_HEADER_SIZE);
         assert_eq!(&encrypted[..8], MAGIC_BYTES.as_slice());
         assert_eq!(encrypted[8], FORMAT_VERSION);
+        assert_eq!(encrypted[9..17], key_fingerprint(&master_key));
     }
 
     #[test]
     fn test_encrypt_different_each_time() {
-        let master_key = [42u8; 32];
+        let master_key = MasterKey::from_bytes([42u8; 32]);
         let plaintext = b"hello world";
         let enc1 = encrypt(&master_key, plaintext).unwrap();
         let enc2 = encrypt(&master_key, plaintext).unwrap();
         // Different salt + nonce should produce different ciphertext
         assert_ne!(enc1, enc2);
     }
+
+    #[test]
+    fn test_encrypted_size_matches_output() {
+        let master_key = MasterKey::from_bytes([42u8; 32]);
+        for len in [0usize, 1, 65_535, 65_536, 65_537, 200_000] {
+            let encrypted = encrypt(&master_key, &vec![7u8; len]).unwrap();
+            assert_eq!(
+                encrypted.len() as u64,
+                encrypted_size(len as u64, DEFAULT_SEGMENT_SIZE),
+                "len = {len}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_compression_applied_only_when_it_helps() {
+        let master_key = MasterKey::from_bytes([42u8; 32]);
+        let options = EncryptOptions {
+            compression: Compression::Zstd,
+            ..Default::default()
+        };
+
+        let tiff_like = [0u8, 0, 0, 255].repeat(100_000);
+        let encrypted = encrypt_with_options(&master_key, &tiff_like, &options).unwrap();
+        assert!(encrypted.len() < tiff_like.len() / 10);
+        let (decrypted, format) = decrypt_with_info(&master_key, &encrypted).unwrap();
+        assert_eq!(decrypted, tiff_like);
+        assert_eq!(format.compression, Compression::Zstd);
+        assert_eq!(format.to_string(), "v8, zstd");
+
+        // Already-compressed data looks random to zstd.
+        let mut png_like = vec![0u8; 100_000];
+        rand::RngCore::fill_bytes(&mut rand::thread_rng(), &mut png_like);
+        let encrypted = encrypt_with_options(&master_key, &png_like, &options).unwrap();
+        assert_eq!(
+            encrypted.len() as u64,
+            encrypted_size(png_like.len() as u64, DEFAULT_SEGMENT_SIZE)
+        );
+        let (decrypted, format) = decrypt_with_info(&master_key, &encrypted).unwrap();
+        assert_eq!(decrypted, png_like);
+        assert_eq!(format.compression, Compression::None);
+    }
+
+    #[test]
+    fn test_padding_hides_length() {
+        let master_key = MasterKey::from_bytes([42u8; 32]);
+        let options = EncryptOptions {
+            padding: Padding::Padme,
+            metadata: Some(FileMetadata {
+                name: Some("reference.png".into()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        let long_name = EncryptOptions {
+            metadata: Some(FileMetadata {
+                name: Some("reference-photo-from-the-museum.png".into()),
+                ..Default::default()
+            }),
+            ..options.clone()
+        };
+
+        let a = encrypt_with_options(&master_key, &[1u8; 1_000_000], &options).unwrap();
+        let b = encrypt_with_options(&master_key, &[2u8; 1_010_000], &long_name).unwrap();
+        assert_eq!(a.len(), b.len());
+
+        let (decrypted, format) = decrypt_with_info(&master_key, &b).unwrap();
+        assert_eq!(decrypted, [2u8; 1_010_000]);
+        assert_eq!(format.to_string(), "v8, padded (padme)");
+    }
 }
@@ -8,12 +8,48 @@ pub enum CryptoError {
     #[error("decryption failed: {0}")]
     DecryptionFailed(String),
 
+    /// The input ended early: `actual` bytes were read, at least `expected` were needed.
+    #[error("truncated: got {actual} bytes, expected at least {expected}")]
+    Truncated { expected: u64, actual: u64 },
+
+    /// An AES-GCM tag did not verify: the data is corrupted or was tampered with. For files
+    /// without a key ID (before v4) this is also how a wrong key shows up.
+    #[error("authentication failed ({0}): data is corrupted or was encrypted with another key")]
+    AuthenticationFailed(String),
+
+    /// The decrypted size differs from the size recorded in the header (v1).
+    #[error("size mismatch: header says {expected} bytes, got {actual}")]
+    SizeMismatch { expected: u64, actual: u64 },
+
+    /// The authenticated plaintext did not decompress with the algorithm named in the header.
+    #[error("decompression failed: {0}")]
+    DecompressionFailed(String),
+
+    /// The decrypted metadata block is malformed, or metadata to be written does not fit.
+    #[error("invalid file metadata: {0}")]
+    InvalidMetadata(String),
+
+    #[error("unsupported format version: {0}")]
+    UnsupportedVersion(u8),
+
     #[error("key derivation failed: {0}")]
     KeyDerivationFailed(String),
 
     #[error("invalid file header: {0}")]
     InvalidHeader(String),
 
+    #[error("wrong key: file was encrypted with key {key_id}")]
+    WrongKey { key_id: String },
+
+    #[error("invalid keyfile: {0}")]
+    InvalidKeyfile(String),
+
+    #[error("invalid recovery phrase: {0}")]
+    InvalidPhrase(String),
+
+    #[error("invalid key share: {0}")]
+    InvalidShare(String),
+
     #[error("hash mismatch: expected {expected}, got {actual}")]
     HashMismatch { expected: String, actual: String },
 }
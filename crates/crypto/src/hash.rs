@@ -1,5 +1,18 @@
+//! Content hashes.
+//!
+//! [`content_fingerprint`] is what leaves the client (S3 metadata, dedup checks): an HMAC
+//! under a key derived from the master key, so it cannot be matched against hashes of known
+//! files. [`sha256_hex`] is the plain hash, for checks that stay on the client.
+
+use hmac::{Hmac, Mac};
 use sha2::{Digest, Sha256};
 
+use crate::key_derivation::derive_content_key;
+use crate::MasterKey;
+
+/// Prefix of a keyed content fingerprint.
+pub const FINGERPRINT_PREFIX: &str = "hmac-sha256:";
+
 /// Compute SHA-256 hash of the given data, returned as a hex string prefixed with "sha256:".
 pub fn sha256_hex(data: &[u8]) -> String {
     let mut hasher = Sha256::new();
@@ -13,7 +26,69 @@ pub fn verify_hash(data: &[u8], expected: &str) -> bool {
     sha256_hex(data) == expected
 }
 
-fn hex_encode(bytes: &[u8]) -> String {
+/// Keyed fingerprint of plaintext content, in its wire form "hmac-sha256:<64 hex chars>".
+///
+/// HMAC-SHA256 under a key derived from the master key (HKDF info
+/// "solidrop-content-fingerprint"). Equal content under the same master key gives equal
+/// fingerprints, so they work for dedup; without the key they reveal nothing about the
+/// content.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ContentFingerprint(String);
+
+impl ContentFingerprint {
+    /// Validate a fingerprint received from elsewhere (API request, S3 metadata). Returns
+    /// `None` for anything else, including plain "sha256:" hashes.
+    pub fn parse(value: &str) -> Option<Self> {
+        let hex = value.strip_prefix(FINGERPRINT_PREFIX)?;
+        let valid = hex.len() == 64 && hex.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'));
+        valid.then(|| Self(value.to_string()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for ContentFingerprint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Compute the [`ContentFingerprint`] of `data` under `master_key`.
+pub fn content_fingerprint(master_key: &MasterKey, data: &[u8]) -> ContentFingerprint {
+    let mut fingerprinter = Fingerprinter::new(master_key);
+    fingerprinter.update(data);
+    fingerprinter.finish()
+}
+
+/// Incremental [`content_fingerprint`], for content that arrives in pieces.
+pub struct Fingerprinter(Hmac<Sha256>);
+
+impl Fingerprinter {
+    pub fn new(master_key: &MasterKey) -> Self {
+        let key = derive_content_key(master_key);
+        Self(Hmac::<Sha256>::new_from_slice(key.as_ref()).expect("HMAC accepts any key length"))
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
+
+    pub fn finish(self) -> ContentFingerprint {
+        ContentFingerprint(format!(
+            "{FINGERPRINT_PREFIX}{}",
+            hex_encode(&self.0.finalize().into_bytes())
+        ))
+    }
+}
+
+/// Verify that data matches a fingerprint string produced by [`content_fingerprint`].
+pub fn verify_fingerprint(master_key: &MasterKey, data: &[u8], expected: &str) -> bool {
+    content_fingerprint(master_key, data).as_str() == expected
+}
+
+pub(crate) fn hex_encode(bytes: &[u8]) -> String {
     bytes.iter().map(|b| format!("{b:02x}")).collect()
 }
 
@@ -35,4 +110,37 @@ mod tests {
         assert!(verify_hash(data, &hash));
         assert!(!verify_hash(b"world", &hash));
     }
+
+    #[test]
+    fn test_content_fingerprint_is_keyed() {
+        let key = MasterKey::from_bytes([42u8; 32]);
+        let fingerprint = content_fingerprint(&key, b"hello");
+        assert_eq!(
+            ContentFingerprint::parse(fingerprint.as_str()),
+            Some(fingerprint.clone())
+        );
+        assert!(verify_fingerprint(&key, b"hello", fingerprint.as_str()));
+        assert!(!verify_fingerprint(&key, b"world", fingerprint.as_str()));
+
+        let mut fingerprinter = Fingerprinter::new(&key);
+        fingerprinter.update(b"hel");
+        fingerprinter.update(b"lo");
+        assert_eq!(fingerprinter.finish(), fingerprint);
+
+        // Another key gives an unrelated value, and neither matches the public hash.
+        let other = content_fingerprint(&MasterKey::from_bytes([43u8; 32]), b"hello");
+        assert_ne!(fingerprint, other);
+        assert_ne!(
+            fingerprint.as_str()[FINGERPRINT_PREFIX.len()..],
+            sha256_hex(b"hello")[7..]
+        );
+    }
+
+    #[test]
+    fn test_fingerprint_parse_rejects_other_formats() {
+        assert!(ContentFingerprint::parse(&sha256_hex(b"hello")).is_none());
+        assert!(ContentFingerprint::parse("hmac-sha256:abc").is_none());
+        assert!(ContentFingerprint::parse(&format!("hmac-sha256:{}", "A".repeat(64))).is_none());
+        assert!(ContentFingerprint::parse(&format!("hmac-sha256:{}", "0f".repeat(32))).is_some());
+    }
 }
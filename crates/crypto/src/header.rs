@@ -0,0 +1,461 @@
+//! The file header, readable without any key.
+//!
+//! [`EncryptedHeader`] is everything an encrypted object reveals to whoever holds it: the
+//! format version, key ID, compression and padding policy, salt, nonce and the sizes the
+//! header declares. Nothing here is verified until the file is decrypted; from v3 on, a
+//! changed header byte fails decryption (see [`FormatInfo::header_authenticated`]).
+
+use crate::metadata::MAX_METADATA_SIZE;
+use crate::stream::{DIGEST_RECORD_SIZE, MAX_SEGMENT_SIZE, TAG_SIZE};
+use crate::{
+    Compression, CryptoError, FormatInfo, Padding, FORMAT_VERSION_V1, FORMAT_VERSION_V2,
+    FORMAT_VERSION_V3, FORMAT_VERSION_V4, FORMAT_VERSION_V5, FORMAT_VERSION_V6, FORMAT_VERSION_V7,
+    FORMAT_VERSION_V8, MAGIC_BYTES, V1_HEADER_SIZE, V2_HEADER_SIZE, V4_HEADER_SIZE, V5_HEADER_SIZE,
+    V6_HEADER_SIZE, V7_HEADER_SIZE, V8_HEADER_SIZE,
+};
+
+/// Random per-file part of the segment nonces in segmented headers.
+pub const NONCE_PREFIX_SIZE: usize = 7;
+
+/// Header size of a format version, or `None` if the version is unknown.
+pub fn header_size(version: u8) -> Option<usize> {
+    match version {
+        FORMAT_VERSION_V1 => Some(V1_HEADER_SIZE),
+        FORMAT_VERSION_V2 | FORMAT_VERSION_V3 => Some(V2_HEADER_SIZE),
+        FORMAT_VERSION_V4 => Some(V4_HEADER_SIZE),
+        FORMAT_VERSION_V5 => Some(V5_HEADER_SIZE),
+        FORMAT_VERSION_V6 => Some(V6_HEADER_SIZE),
+        FORMAT_VERSION_V7 => Some(V7_HEADER_SIZE),
+        FORMAT_VERSION_V8 => Some(V8_HEADER_SIZE),
+        _ => None,
+    }
+}
+
+/// Header of an encrypted file, in any supported format version.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EncryptedHeader {
+    /// v1: one AES-256-GCM message over the whole file.
+    Legacy(LegacyHeader),
+    /// v2 to v8: STREAM segments (see [`crate::stream`]).
+    Segmented(SegmentedHeader),
+}
+
+/// Fields of a v1 header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LegacyHeader {
+    pub salt: [u8; 16],
+    pub nonce: [u8; 12],
+    /// Plaintext size the header claims; checked after decryption.
+    pub original_size: u64,
+}
+
+/// Fields of a v2 to v8 header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SegmentedHeader {
+    pub version: u8,
+    /// Present from v4 on.
+    pub key_id: Option<[u8; 8]>,
+    /// Recorded from v5 on; always `None` before.
+    pub compression: Compression,
+    /// Recorded from v7 on; always `None` before.
+    pub padding: Padding,
+    pub salt: [u8; 16],
+    pub nonce_prefix: [u8; NONCE_PREFIX_SIZE],
+    pub segment_size: u32,
+    /// Encrypted metadata block length, tag included (v6 on; 0 means no block).
+    pub metadata_len: u32,
+}
+
+impl EncryptedHeader {
+    /// Parse the header at the start of `data`; any bytes after it are ignored.
+    ///
+    /// If `data` is too short, fails with [`CryptoError::Truncated`] whose `expected` is
+    /// the number of bytes to read instead. [`V8_HEADER_SIZE`] bytes always suffice.
+    pub fn parse(data: &[u8]) -> Result<Self, CryptoError> {
+        let version_at = MAGIC_BYTES.len();
+        if data.len() <= version_at {
+            return Err(CryptoError::Truncated {
+                expected: version_at as u64 + 1,
+                actual: data.len() as u64,
+            });
+        }
+        if &data[..version_at] != MAGIC_BYTES.as_slice() {
+            return Err(CryptoError::InvalidHeader("invalid magic bytes".into()));
+        }
+
+        let version = data[version_at];
+        let size = header_size(version).ok_or(CryptoError::UnsupportedVersion(version))?;
+        if data.len() < size {
+            return Err(CryptoError::Truncated {
+                expected: size as u64,
+                actual: data.len() as u64,
+            });
+        }
+
+        let fields = &data[version_at + 1..size];
+        if version == FORMAT_VERSION_V1 {
+            Ok(Self::Legacy(LegacyHeader::from_fields(fields)))
+        } else {
+            SegmentedHeader::from_fields(version, fields).map(Self::Segmented)
+        }
+    }
+
+    /// The header as it is written at the start of a file.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Self::Legacy(header) => header.to_bytes(),
+            Self::Segmented(header) => header.to_bytes(),
+        }
+    }
+
+    pub fn version(&self) -> u8 {
+        match self {
+            Self::Legacy(_) => FORMAT_VERSION_V1,
+            Self::Segmented(header) => header.version,
+        }
+    }
+
+    /// Size of the header in bytes.
+    pub fn header_len(&self) -> usize {
+        header_size(self.version()).expect("parsed header has a known version")
+    }
+
+    pub fn salt(&self) -> &[u8; 16] {
+        match self {
+            Self::Legacy(header) => &header.salt,
+            Self::Segmented(header) => &header.salt,
+        }
+    }
+
+    /// The same details [`Decryptor::format`](crate::stream::Decryptor::format) reports
+    /// after decryption has started.
+    pub fn format(&self) -> FormatInfo {
+        match self {
+            Self::Legacy(_) => FormatInfo {
+                version: FORMAT_VERSION_V1,
+                key_id: None,
+                compression: Compression::None,
+                padding: Padding::None,
+            },
+            Self::Segmented(header) => FormatInfo {
+                version: header.version,
+                key_id: header.key_id,
+                compression: header.compression,
+                padding: header.padding,
+            },
+        }
+    }
+
+    /// Payload bytes a file of `file_size` bytes carries under this header: the segment
+    /// plaintext for segmented files (see [`SegmentedHeader::payload_len`]), the claimed
+    /// original size for v1.
+    ///
+    /// Fails with [`CryptoError::Truncated`] if the file is too short to be complete, and
+    /// for v1 with [`CryptoError::SizeMismatch`] if it is longer than the header claims.
+    pub fn payload_len(&self, file_size: u64) -> Result<u64, CryptoError> {
+        match self {
+            Self::Legacy(header) => header.payload_len(file_size),
+            Self::Segmented(header) => header.payload_len(file_size),
+        }
+    }
+}
+
+impl LegacyHeader {
+    /// Parse the fields after magic and version: salt, nonce and original size.
+    pub(crate) fn from_fields(fields: &[u8]) -> Self {
+        Self {
+            salt: fields[..16].try_into().unwrap(),
+            nonce: fields[16..28].try_into().unwrap(),
+            original_size: u64::from_le_bytes(fields[28..36].try_into().unwrap()),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(V1_HEADER_SIZE);
+        out.extend_from_slice(MAGIC_BYTES);
+        out.push(FORMAT_VERSION_V1);
+        out.extend_from_slice(&self.salt);
+        out.extend_from_slice(&self.nonce);
+        out.extend_from_slice(&self.original_size.to_le_bytes());
+        out
+    }
+
+    fn payload_len(&self, file_size: u64) -> Result<u64, CryptoError> {
+        let overhead = (V1_HEADER_SIZE + TAG_SIZE) as u64;
+        let expected = overhead.saturating_add(self.original_size);
+        if file_size < expected {
+            return Err(CryptoError::Truncated {
+                expected,
+                actual: file_size,
+            });
+        }
+        if file_size > expected {
+            return Err(CryptoError::SizeMismatch {
+                expected: self.original_size,
+                actual: file_size - overhead,
+            });
+        }
+        Ok(self.original_size)
+    }
+}
+
+impl SegmentedHeader {
+    /// Parse the fields after magic and version; `fields` holds exactly the rest of a
+    /// `version` header.
+    fn from_fields(version: u8, fields: &[u8]) -> Result<Self, CryptoError> {
+        let (key_id, rest) = if version >= FORMAT_VERSION_V4 {
+            (Some(fields[..8].try_into().unwrap()), &fields[8..])
+        } else {
+            (None, fields)
+        };
+        let (compression, rest) = if version >= FORMAT_VERSION_V5 {
+            (Compression::from_id(rest[0])?, &rest[1..])
+        } else {
+            (Compression::None, rest)
+        };
+        let (padding, rest) = if version >= FORMAT_VERSION_V7 {
+            (Padding::from_id(rest[0])?, &rest[1..])
+        } else {
+            (Padding::None, rest)
+        };
+
+        let salt: [u8; 16] = rest[..16].try_into().unwrap();
+        let nonce_prefix: [u8; NONCE_PREFIX_SIZE] = rest[16..23].try_into().unwrap();
+
+        let segment_size = u32::from_le_bytes(rest[23..27].try_into().unwrap());
+        if segment_size == 0 || segment_size > MAX_SEGMENT_SIZE {
+            return Err(CryptoError::InvalidHeader(format!(
+                "invalid segment size: {segment_size}"
+            )));
+        }
+
+        let metadata_len = match rest.get(27..31) {
+            Some(bytes) => u32::from_le_bytes(bytes.try_into().unwrap()),
+            None => 0,
+        };
+        if metadata_len > MAX_METADATA_SIZE || (1..TAG_SIZE as u32).contains(&metadata_len) {
+            return Err(CryptoError::InvalidHeader(format!(
+                "invalid metadata size: {metadata_len}"
+            )));
+        }
+
+        Ok(Self {
+            version,
+            key_id,
+            compression,
+            padding,
+            salt,
+            nonce_prefix,
+            segment_size,
+            metadata_len,
+        })
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(V8_HEADER_SIZE);
+        out.extend_from_slice(MAGIC_BYTES);
+        out.push(self.version);
+        if let Some(key_id) = &self.key_id {
+            out.extend_from_slice(key_id);
+        }
+        if self.version >= FORMAT_VERSION_V5 {
+            out.push(self.compression.id());
+        }
+        if self.version >= FORMAT_VERSION_V7 {
+            out.push(self.padding.id());
+        }
+        out.extend_from_slice(&self.salt);
+        out.extend_from_slice(&self.nonce_prefix);
+        out.extend_from_slice(&self.segment_size.to_le_bytes());
+        if self.version >= FORMAT_VERSION_V6 {
+            out.extend_from_slice(&self.metadata_len.to_le_bytes());
+        }
+        out
+    }
+
+    /// File offset of the first segment: header plus metadata block.
+    pub fn segments_offset(&self) -> u64 {
+        let header_len = header_size(self.version).expect("segmented version") as u64;
+        header_len + u64::from(self.metadata_len)
+    }
+
+    /// Segment plaintext bytes in a file of `file_size` bytes: the payload after
+    /// compression, plus padding and its trailer if the file is padded. From v8 on, the
+    /// SHA-256 record at the end of the final segment is not counted.
+    ///
+    /// Fails with [`CryptoError::Truncated`] if the file ends before the metadata block,
+    /// inside a segment's tag or, from v8 on, inside the SHA-256 record. A file cut exactly at a segment boundary looks complete
+    /// here; only decryption notices.
+    pub fn payload_len(&self, file_size: u64) -> Result<u64, CryptoError> {
+        let offset = self.segments_offset();
+        let tag = TAG_SIZE as u64;
+        if file_size < offset + tag {
+            return Err(CryptoError::Truncated {
+                expected: offset + tag,
+                actual: file_size,
+            });
+        }
+        let sealed = file_size - offset;
+        let stride = u64::from(self.segment_size) + tag;
+        let segments = sealed.div_ceil(stride);
+        let last = sealed - (segments - 1) * stride;
+        if last < tag {
+            return Err(CryptoError::Truncated {
+                expected: file_size - last + tag,
+                actual: file_size,
+            });
+        }
+        let plaintext = sealed - segments * tag;
+        if self.version < FORMAT_VERSION_V8 {
+            return Ok(plaintext);
+        }
+        let record = DIGEST_RECORD_SIZE as u64;
+        plaintext
+            .checked_sub(record)
+            .ok_or_else(|| CryptoError::Truncated {
+                expected: file_size + record - plaintext,
+                actual: file_size,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encrypt::{encrypt, encrypt_with_options, EncryptOptions};
+    use crate::stream::{encrypted_size, padded_payload_len, DEFAULT_SEGMENT_SIZE};
+    use crate::{FileMetadata, MasterKey};
+
+    fn test_key() -> MasterKey {
+        MasterKey::from_bytes([7u8; 32])
+    }
+
+    #[test]
+    fn test_parse_roundtrip_and_payload_len() {
+        let options = EncryptOptions {
+            metadata: Some(FileMetadata {
+                name: Some("sketch.clip".into()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        for len in [0usize, 1, 65_536, 200_000] {
+            let data = encrypt_with_options(&test_key(), &vec![1u8; len], &options).unwrap();
+            let header = EncryptedHeader::parse(&data).unwrap();
+            assert_eq!(header.to_bytes(), data[..V8_HEADER_SIZE]);
+            assert_eq!(header.header_len(), V8_HEADER_SIZE);
+            assert_eq!(header.payload_len(data.len() as u64).unwrap(), len as u64);
+
+            let EncryptedHeader::Segmented(segmented) = &header else {
+                panic!("v8 file parsed as legacy");
+            };
+            assert_eq!(segmented.segment_size, DEFAULT_SEGMENT_SIZE);
+            assert_eq!(
+                encrypted_size(len as u64, DEFAULT_SEGMENT_SIZE)
+                    + u64::from(segmented.metadata_len),
+                data.len() as u64
+            );
+        }
+
+        let data = encrypt(&test_key(), b"abc").unwrap();
+        let format = EncryptedHeader::parse(&data).unwrap().format();
+        assert_eq!(format.version, crate::FORMAT_VERSION);
+        assert_eq!(
+            format.key_id,
+            Some(crate::key_derivation::key_fingerprint(&test_key()))
+        );
+    }
+
+    #[test]
+    fn test_padded_payload_len_is_a_bucket() {
+        let options = EncryptOptions {
+            padding: Padding::Padme,
+            ..Default::default()
+        };
+        let data = encrypt_with_options(&test_key(), &[5u8; 1000], &options).unwrap();
+        let header = EncryptedHeader::parse(&data).unwrap();
+        let payload = header.payload_len(data.len() as u64).unwrap();
+        assert_eq!(payload, padded_payload_len(1000, Padding::Padme));
+        assert_eq!(Padding::Padme.padded_len(payload), payload);
+    }
+
+    #[test]
+    fn test_legacy_header_roundtrip() {
+        let header = LegacyHeader {
+            salt: [1u8; 16],
+            nonce: [2u8; 12],
+            original_size: 10,
+        };
+        let mut data = header.to_bytes();
+        assert_eq!(data.len(), V1_HEADER_SIZE);
+        data.extend_from_slice(&[0u8; 26]);
+        let parsed = EncryptedHeader::parse(&data).unwrap();
+        assert_eq!(parsed, EncryptedHeader::Legacy(header));
+        assert_eq!(parsed.payload_len(data.len() as u64).unwrap(), 10);
+        assert!(matches!(
+            parsed.payload_len(data.len() as u64 - 1),
+            Err(CryptoError::Truncated { expected: 71, .. })
+        ));
+        assert!(matches!(
+            parsed.payload_len(data.len() as u64 + 1),
+            Err(CryptoError::SizeMismatch {
+                expected: 10,
+                actual: 11
+            })
+        ));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let data = encrypt(&test_key(), b"hello").unwrap();
+        assert!(matches!(
+            EncryptedHeader::parse(&data[..4]),
+            Err(CryptoError::Truncated {
+                expected: 9,
+                actual: 4
+            })
+        ));
+        assert!(matches!(
+            EncryptedHeader::parse(&data[..20]),
+            Err(CryptoError::Truncated {
+                expected: 50,
+                actual: 20
+            })
+        ));
+
+        let mut bad = data.clone();
+        bad[0] = b'X';
+        assert!(matches!(
+            EncryptedHeader::parse(&bad),
+            Err(CryptoError::InvalidHeader(_))
+        ));
+        bad = data.clone();
+        bad[8] = 99;
+        assert!(matches!(
+            EncryptedHeader::parse(&bad),
+            Err(CryptoError::UnsupportedVersion(99))
+        ));
+
+        // A cut inside the last tag is visible from the size alone.
+        let header = EncryptedHeader::parse(&data).unwrap();
+        assert!(matches!(
+            header.payload_len(data.len() as u64 - 12),
+            Err(CryptoError::Truncated { .. })
+        ));
+        assert!(matches!(
+            header.payload_len(V8_HEADER_SIZE as u64),
+            Err(CryptoError::Truncated {
+                expected: 66,
+                actual: 50
+            })
+        ));
+        // So is one that keeps the tag but loses the SHA-256 record.
+        assert!(matches!(
+            header.payload_len(V8_HEADER_SIZE as u64 + 16 + 2),
+            Err(CryptoError::Truncated {
+                expected: 101,
+                actual: 68
+            })
+        ));
+    }
+}
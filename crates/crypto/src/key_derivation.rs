@@ -1,31 +1,227 @@
-use argon2::Argon2;
+use std::time::{Duration, Instant};
+
+use argon2::{Algorithm, Argon2, Params, Version};
 use hkdf::Hkdf;
 use rand::RngCore;
 use sha2::Sha256;
+use zeroize::Zeroizing;
+
+use crate::{CryptoError, FileKey, MasterKey};
+
+/// Version of the serialized [`KeyParams`] record.
+pub const KEY_PARAMS_VERSION: u8 = 1;
+/// Serialized record: version(1) + m_cost(4) + t_cost(4) + p_cost(4), integers little-endian.
+pub const KEY_PARAMS_SIZE: usize = 1 + 4 + 4 + 4;
+
+/// Upper bound on `m_cost` accepted from a stored record (4 GiB), so a corrupted or hostile
+/// record cannot make the deriving process allocate arbitrary amounts of memory.
+const MAX_M_COST_KIB: u32 = 4 * 1024 * 1024;
+/// Upper bound on `t_cost` accepted from a stored record, for the same reason: unlocking
+/// must finish. Twice what calibration ever proposes.
+const MAX_T_COST: u32 = 2 * MAX_CALIBRATED_T_COST;
+/// Upper bound on `p_cost` accepted from a stored record; each lane is a thread's worth of
+/// work run one after another here.
+const MAX_P_COST: u32 = 64;
+/// Calibration never proposes less memory than the argon2 crate default (19 MiB).
+const MIN_CALIBRATED_M_COST_KIB: u32 = Params::DEFAULT_M_COST;
+/// Calibration never proposes more passes than this: beyond it the timings are noise, not a
+/// fast machine, and a wrapped keyfile could take hours to unlock.
+const MAX_CALIBRATED_T_COST: u32 = 64;
+
+/// Argon2id cost parameters. Stored alongside the salt so a key can be re-derived with the
+/// exact parameters it was created with, on any device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyParams {
+    /// Memory cost in KiB.
+    pub m_cost: u32,
+    /// Number of passes.
+    pub t_cost: u32,
+    /// Degree of parallelism.
+    pub p_cost: u32,
+}
+
+impl Default for KeyParams {
+    /// The parameters `Argon2::default()` uses (19 MiB, 2 passes, 1 lane); keys derived
+    /// before parameters were recorded used these.
+    fn default() -> Self {
+        Self {
+            m_cost: Params::DEFAULT_M_COST,
+            t_cost: Params::DEFAULT_T_COST,
+            p_cost: Params::DEFAULT_P_COST,
+        }
+    }
+}
 
-use crate::CryptoError;
+impl KeyParams {
+    pub fn to_bytes(&self) -> [u8; KEY_PARAMS_SIZE] {
+        let mut out = [0u8; KEY_PARAMS_SIZE];
+        out[0] = KEY_PARAMS_VERSION;
+        out[1..5].copy_from_slice(&self.m_cost.to_le_bytes());
+        out[5..9].copy_from_slice(&self.t_cost.to_le_bytes());
+        out[9..13].copy_from_slice(&self.p_cost.to_le_bytes());
+        out
+    }
 
-/// Derive a 256-bit master key from a password using Argon2id.
-pub fn derive_master_key(password: &[u8], salt: &[u8; 16]) -> Result<[u8; 32], CryptoError> {
-    let mut master_key = [0u8; 32];
-    Argon2::default()
-        .hash_password_into(password, salt, &mut master_key)
+    pub fn from_bytes(data: &[u8; KEY_PARAMS_SIZE]) -> Result<Self, CryptoError> {
+        if data[0] != KEY_PARAMS_VERSION {
+            return Err(CryptoError::KeyDerivationFailed(format!(
+                "unsupported key params version: {}",
+                data[0]
+            )));
+        }
+        let params = Self {
+            m_cost: u32::from_le_bytes(data[1..5].try_into().unwrap()),
+            t_cost: u32::from_le_bytes(data[5..9].try_into().unwrap()),
+            p_cost: u32::from_le_bytes(data[9..13].try_into().unwrap()),
+        };
+        params.argon2()?;
+        Ok(params)
+    }
+
+    fn argon2(&self) -> Result<Argon2<'static>, CryptoError> {
+        if self.m_cost > MAX_M_COST_KIB {
+            return Err(CryptoError::KeyDerivationFailed(format!(
+                "memory cost {} KiB exceeds the {} KiB limit",
+                self.m_cost, MAX_M_COST_KIB
+            )));
+        }
+        if self.t_cost > MAX_T_COST {
+            return Err(CryptoError::KeyDerivationFailed(format!(
+                "{} passes exceed the limit of {}",
+                self.t_cost, MAX_T_COST
+            )));
+        }
+        if self.p_cost > MAX_P_COST {
+            return Err(CryptoError::KeyDerivationFailed(format!(
+                "{} lanes exceed the limit of {}",
+                self.p_cost, MAX_P_COST
+            )));
+        }
+        let params = Params::new(self.m_cost, self.t_cost, self.p_cost, Some(32))
+            .map_err(|e| CryptoError::KeyDerivationFailed(e.to_string()))?;
+        Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+    }
+}
+
+/// Derive a 256-bit master key from a password using Argon2id with the default parameters.
+pub fn derive_master_key(password: &[u8], salt: &[u8; 16]) -> Result<MasterKey, CryptoError> {
+    derive_master_key_with_params(password, salt, &KeyParams::default())
+}
+
+/// Derive a 256-bit key from a password using Argon2id with explicit parameters.
+pub fn derive_master_key_with_params(
+    password: &[u8],
+    salt: &[u8; 16],
+    params: &KeyParams,
+) -> Result<MasterKey, CryptoError> {
+    let mut master_key = MasterKey::from_bytes([0u8; 32]);
+    params
+        .argon2()?
+        .hash_password_into(password, salt, master_key.as_mut_bytes())
         .map_err(|e| CryptoError::KeyDerivationFailed(e.to_string()))?;
     Ok(master_key)
 }
 
+/// Benchmark Argon2id on this machine and propose parameters that take about `target`.
+///
+/// Memory is the main cost; starting from `m_cost` (KiB) it is halved, down to 19 MiB, while a
+/// single pass alone exceeds the target. The pass count is then scaled to fill the target,
+/// using the difference between a 1-pass and a 2-pass run so the fixed per-derivation cost
+/// (allocating and filling memory) is not counted once per pass. At most 64 passes are proposed.
+pub fn calibrate(target: Duration, m_cost: u32, p_cost: u32) -> Result<KeyParams, CryptoError> {
+    let salt = generate_salt();
+    let mut params = KeyParams {
+        m_cost,
+        t_cost: 1,
+        p_cost,
+    };
+    let time = |params: &KeyParams| -> Result<Duration, CryptoError> {
+        let start = Instant::now();
+        derive_master_key_with_params(b"calibration", &salt, params)?;
+        Ok(start.elapsed())
+    };
+
+    let one_pass = loop {
+        let one_pass = time(&params)?;
+        if one_pass > target && params.m_cost / 2 >= MIN_CALIBRATED_M_COST_KIB {
+            params.m_cost /= 2;
+            continue;
+        }
+        break one_pass;
+    };
+    if one_pass >= target {
+        return Ok(params);
+    }
+
+    let two_passes = time(&KeyParams {
+        t_cost: 2,
+        ..params
+    })?;
+    params.t_cost = calibrated_passes(target, one_pass, two_passes);
+    Ok(params)
+}
+
+/// Pass count filling `target`, from the times of a 1-pass and a 2-pass run.
+fn calibrated_passes(target: Duration, one_pass: Duration, two_passes: Duration) -> u32 {
+    // A second pass that cost (next to) nothing is timing noise; count the whole 1-pass run
+    // as one pass instead, which errs towards fewer passes.
+    let (per_pass, fixed) = match two_passes.saturating_sub(one_pass) {
+        per_pass if per_pass > one_pass / 10 => (per_pass, one_pass.saturating_sub(per_pass)),
+        _ => (one_pass, Duration::ZERO),
+    };
+    if per_pass.is_zero() {
+        return 1;
+    }
+    let passes = target.saturating_sub(fixed).as_secs_f64() / per_pass.as_secs_f64();
+    (passes.round() as u32).clamp(1, MAX_CALIBRATED_T_COST)
+}
+
 /// Derive a per-file encryption key from the master key using HKDF-SHA256.
 pub fn derive_file_key(
-    master_key: &[u8; 32],
+    master_key: &MasterKey,
     file_salt: &[u8; 16],
-) -> Result<[u8; 32], CryptoError> {
-    let hkdf = Hkdf::<Sha256>::new(Some(file_salt), master_key);
-    let mut file_key = [0u8; 32];
-    hkdf.expand(b"solidrop-file-encryption", &mut file_key)
+) -> Result<FileKey, CryptoError> {
+    let hkdf = Hkdf::<Sha256>::new(Some(file_salt), master_key.as_bytes());
+    let mut file_key = FileKey::from_bytes([0u8; 32]);
+    hkdf.expand(b"solidrop-file-encryption", file_key.as_mut_bytes())
         .map_err(|e| CryptoError::KeyDerivationFailed(e.to_string()))?;
     Ok(file_key)
 }
 
+/// Derive the key sealing a file's metadata block (v6): HKDF-SHA256 with the file salt and
+/// info "solidrop-file-metadata", so it differs from the file's content key.
+pub fn derive_metadata_key(
+    master_key: &MasterKey,
+    file_salt: &[u8; 16],
+) -> Result<FileKey, CryptoError> {
+    let hkdf = Hkdf::<Sha256>::new(Some(file_salt), master_key.as_bytes());
+    let mut metadata_key = FileKey::from_bytes([0u8; 32]);
+    hkdf.expand(b"solidrop-file-metadata", metadata_key.as_mut_bytes())
+        .map_err(|e| CryptoError::KeyDerivationFailed(e.to_string()))?;
+    Ok(metadata_key)
+}
+
+/// Short public identifier of a master key: HKDF-SHA256(master key, info
+/// "solidrop-key-fingerprint"), truncated to 8 bytes. Safe to store next to ciphertext or
+/// shares; it reveals nothing useful about the key but tells keys apart.
+pub fn key_fingerprint(master_key: &MasterKey) -> [u8; 8] {
+    let hkdf = Hkdf::<Sha256>::new(None, master_key.as_bytes());
+    let mut fingerprint = [0u8; 8];
+    hkdf.expand(b"solidrop-key-fingerprint", &mut fingerprint)
+        .expect("8 bytes is a valid HKDF-SHA256 output length");
+    fingerprint
+}
+
+/// HMAC key for content fingerprints: HKDF-SHA256(master key, info
+/// "solidrop-content-fingerprint"). Separate from file keys and the key fingerprint.
+pub(crate) fn derive_content_key(master_key: &MasterKey) -> Zeroizing<[u8; 32]> {
+    let hkdf = Hkdf::<Sha256>::new(None, master_key.as_bytes());
+    let mut key = Zeroizing::new([0u8; 32]);
+    hkdf.expand(b"solidrop-content-fingerprint", key.as_mut())
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    key
+}
+
 /// Generate a random 16-byte salt.
 pub fn generate_salt() -> [u8; 16] {
     let mut salt = [0u8; 16];
@@ -43,7 +239,7 @@ mod tests {
         let salt = [1u8; 16];
         let key1 = derive_master_key(password, &salt).unwrap();
         let key2 = derive_master_key(password, &salt).unwrap();
-        assert_eq!(key1, key2);
+        assert_eq!(key1.as_bytes(), key2.as_bytes());
     }
 
     #[test]
@@ -53,16 +249,114 @@ mod tests {
         let salt2 = [2u8; 16];
         let key1 = derive_master_key(password, &salt1).unwrap();
         let key2 = derive_master_key(password, &salt2).unwrap();
-        assert_ne!(key1, key2);
+        assert_ne!(key1.as_bytes(), key2.as_bytes());
     }
 
     #[test]
     fn test_derive_file_key() {
-        let master_key = [42u8; 32];
+        let master_key = MasterKey::from_bytes([42u8; 32]);
         let salt = [1u8; 16];
         let file_key = derive_file_key(&master_key, &salt).unwrap();
-        assert_eq!(file_key.len(), 32);
-        assert_ne!(file_key, master_key);
+        assert_ne!(file_key.as_bytes(), master_key.as_bytes());
+    }
+
+    #[test]
+    fn test_key_params_roundtrip_and_default_compat() {
+        let params = KeyParams {
+            m_cost: 1024,
+            t_cost: 3,
+            p_cost: 2,
+        };
+        assert_eq!(KeyParams::from_bytes(&params.to_bytes()).unwrap(), params);
+
+        let salt = [1u8; 16];
+        assert_eq!(
+            derive_master_key(b"pw", &salt).unwrap().as_bytes(),
+            derive_master_key_with_params(b"pw", &salt, &KeyParams::default())
+                .unwrap()
+                .as_bytes()
+        );
+        assert_ne!(
+            derive_master_key(b"pw", &salt).unwrap().as_bytes(),
+            derive_master_key_with_params(b"pw", &salt, &params)
+                .unwrap()
+                .as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_key_params_rejects_bad_records() {
+        let mut record = KeyParams::default().to_bytes();
+        record[0] = 9;
+        assert!(KeyParams::from_bytes(&record).is_err());
+
+        let huge = KeyParams {
+            m_cost: u32::MAX,
+            ..KeyParams::default()
+        };
+        assert!(KeyParams::from_bytes(&huge.to_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_key_params_rejects_unbounded_passes_and_lanes() {
+        for params in [
+            KeyParams {
+                t_cost: u32::MAX,
+                ..KeyParams::default()
+            },
+            KeyParams {
+                p_cost: MAX_P_COST + 1,
+                ..KeyParams::default()
+            },
+        ] {
+            let err = KeyParams::from_bytes(&params.to_bytes()).unwrap_err();
+            assert!(err.to_string().contains("limit"), "error: {err}");
+            assert!(derive_master_key_with_params(b"pw", &[0; 16], &params).is_err());
+        }
+        let most = KeyParams {
+            t_cost: MAX_T_COST,
+            ..KeyParams::default()
+        };
+        assert_eq!(KeyParams::from_bytes(&most.to_bytes()).unwrap(), most);
+    }
+
+    #[test]
+    fn test_calibrate_zero_target_gives_minimum_passes() {
+        let params = calibrate(Duration::ZERO, 1024, 1).unwrap();
+        assert_eq!(params.t_cost, 1);
+        assert_eq!(params.m_cost, 1024);
+    }
+
+    #[test]
+    fn test_calibrated_passes_with_degenerate_timings() {
+        let ms = Duration::from_millis;
+        // 10 ms fixed cost + 20 ms per pass.
+        assert_eq!(calibrated_passes(ms(1000), ms(30), ms(50)), 50);
+        // The second pass took no longer, or less: not a million passes.
+        assert_eq!(calibrated_passes(ms(1000), ms(100), ms(100)), 10);
+        assert_eq!(calibrated_passes(ms(1000), ms(100), ms(90)), 10);
+        assert_eq!(
+            calibrated_passes(ms(1000), ms(10), ms(10)),
+            MAX_CALIBRATED_T_COST
+        );
+        assert_eq!(
+            calibrated_passes(ms(1000), Duration::ZERO, Duration::ZERO),
+            1
+        );
+    }
+
+    #[test]
+    fn test_key_fingerprint() {
+        let master_key = MasterKey::from_bytes([42u8; 32]);
+        assert_eq!(key_fingerprint(&master_key), key_fingerprint(&master_key));
+        assert_ne!(
+            key_fingerprint(&master_key),
+            key_fingerprint(&MasterKey::from_bytes([43u8; 32]))
+        );
+        assert_ne!(
+            key_fingerprint(&master_key),
+            derive_file_key(&master_key, &[0u8; 16]).unwrap().as_bytes()[..8]
+        );
     }
 
     #[test]
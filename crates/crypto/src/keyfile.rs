@@ -0,0 +1,269 @@
+//! Password-wrapped master key ("keyfile").
+//!
+//! The master key that every per-file key is derived from is random, not password-derived.
+//! It is stored wrapped (AES-256-GCM) under a key-encryption key (KEK) derived from the
+//! password with Argon2id. Changing the password only re-wraps these few bytes; objects
+//! already in storage keep decrypting with the same master key.
+//!
+//! From keyfile v2 on, the Argon2id parameters are stored in the header (and authenticated
+//! with it), so a keyfile created on one device unlocks on any other.
+
+use aes_gcm::{
+    aead::{Aead, KeyInit, Payload},
+    Aes256Gcm, Nonce,
+};
+use rand::RngCore;
+use zeroize::Zeroizing;
+
+use crate::key_derivation::{
+    derive_master_key_with_params, generate_salt, KeyParams, KEY_PARAMS_SIZE,
+};
+use crate::{CryptoError, MasterKey};
+
+/// Keyfile magic bytes. The keyfile version byte follows immediately.
+pub const KEYFILE_MAGIC: &[u8; 8] = b"SDKEYFIL";
+/// Legacy keyfile without a parameters record; always uses `KeyParams::default()`.
+pub const KEYFILE_VERSION_V1: u8 = 1;
+/// Keyfile with the Argon2id parameters record after the version byte.
+pub const KEYFILE_VERSION_V2: u8 = 2;
+/// Keyfile format version written by this crate.
+pub const KEYFILE_VERSION: u8 = KEYFILE_VERSION_V2;
+
+/// Wrapped 32-byte key + 16-byte tag
+const WRAPPED_KEY_SIZE: usize = 32 + 16;
+/// v1: magic(8) + version(1) + kdf_salt(16) + nonce(12) = 37 bytes
+const V1_HEADER_SIZE: usize = 8 + 1 + 16 + 12;
+/// v2: magic(8) + version(1) + key_params(13) + kdf_salt(16) + nonce(12) = 50 bytes
+const V2_HEADER_SIZE: usize = 8 + 1 + KEY_PARAMS_SIZE + 16 + 12;
+/// Size of a keyfile written by this crate (98 bytes).
+pub const KEYFILE_SIZE: usize = V2_HEADER_SIZE + WRAPPED_KEY_SIZE;
+
+/// Fields of a parsed keyfile.
+struct ParsedKeyfile<'a> {
+    params: KeyParams,
+    kdf_salt: [u8; 16],
+    nonce: &'a [u8],
+    header: &'a [u8],
+    wrapped: &'a [u8],
+}
+
+fn parse_keyfile(keyfile: &[u8]) -> Result<ParsedKeyfile<'_>, CryptoError> {
+    if keyfile.len() < 9 || &keyfile[..8] != KEYFILE_MAGIC.as_slice() {
+        return Err(CryptoError::InvalidKeyfile("invalid magic bytes".into()));
+    }
+
+    let (header_size, params) = match keyfile[8] {
+        KEYFILE_VERSION_V1 => (V1_HEADER_SIZE, None),
+        KEYFILE_VERSION_V2 => (V2_HEADER_SIZE, Some(9..9 + KEY_PARAMS_SIZE)),
+        version => {
+            return Err(CryptoError::InvalidKeyfile(format!(
+                "unsupported version: {version}"
+            )))
+        }
+    };
+    if keyfile.len() != header_size + WRAPPED_KEY_SIZE {
+        return Err(CryptoError::InvalidKeyfile(format!(
+            "expected {} bytes, got {}",
+            header_size + WRAPPED_KEY_SIZE,
+            keyfile.len()
+        )));
+    }
+
+    let params = match params {
+        Some(range) => KeyParams::from_bytes(keyfile[range].try_into().unwrap())?,
+        None => KeyParams::default(),
+    };
+    let (header, wrapped) = keyfile.split_at(header_size);
+    let salt_start = header_size - 12 - 16;
+
+    Ok(ParsedKeyfile {
+        params,
+        kdf_salt: header[salt_start..salt_start + 16].try_into().unwrap(),
+        nonce: &header[salt_start + 16..],
+        header,
+        wrapped,
+    })
+}
+
+/// Generate a random 256-bit master key.
+pub fn generate_master_key() -> MasterKey {
+    let mut key = MasterKey::from_bytes([0u8; 32]);
+    rand::thread_rng().fill_bytes(key.as_mut_bytes());
+    key
+}
+
+/// Create a new random master key and its keyfile protected by `password`.
+pub fn create_keyfile(
+    password: &[u8],
+    params: &KeyParams,
+) -> Result<(MasterKey, Vec<u8>), CryptoError> {
+    let master_key = generate_master_key();
+    let keyfile = wrap_master_key(&master_key, password, params)?;
+    Ok((master_key, keyfile))
+}
+
+/// Wrap an existing master key under `password`, e.g. one previously kept in an
+/// environment variable.
+pub fn wrap_master_key(
+    master_key: &MasterKey,
+    password: &[u8],
+    params: &KeyParams,
+) -> Result<Vec<u8>, CryptoError> {
+    let kdf_salt = generate_salt();
+    let mut nonce = [0u8; 12];
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let mut keyfile = Vec::with_capacity(KEYFILE_SIZE);
+    keyfile.extend_from_slice(KEYFILE_MAGIC);
+    keyfile.push(KEYFILE_VERSION);
+    keyfile.extend_from_slice(&params.to_bytes());
+    keyfile.extend_from_slice(&kdf_salt);
+    keyfile.extend_from_slice(&nonce);
+
+    let kek = derive_master_key_with_params(password, &kdf_salt, params)?;
+    let cipher = Aes256Gcm::new_from_slice(kek.as_bytes())
+        .map_err(|e| CryptoError::EncryptionFailed(e.to_string()))?;
+    let wrapped = cipher
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: master_key.as_bytes(),
+                aad: &keyfile,
+            },
+        )
+        .map_err(|e| CryptoError::EncryptionFailed(e.to_string()))?;
+
+    keyfile.extend_from_slice(&wrapped);
+    Ok(keyfile)
+}
+
+/// Argon2id parameters a keyfile was created with (the defaults for v1 keyfiles).
+pub fn keyfile_params(keyfile: &[u8]) -> Result<KeyParams, CryptoError> {
+    parse_keyfile(keyfile).map(|parsed| parsed.params)
+}
+
+/// Recover the master key from a keyfile (v1 or v2).
+///
+/// A wrong password and a corrupted keyfile are indistinguishable; both fail with
+/// `DecryptionFailed`.
+pub fn unwrap_master_key(keyfile: &[u8], password: &[u8]) -> Result<MasterKey, CryptoError> {
+    let parsed = parse_keyfile(keyfile)?;
+
+    let kek = derive_master_key_with_params(password, &parsed.kdf_salt, &parsed.params)?;
+    let cipher = Aes256Gcm::new_from_slice(kek.as_bytes())
+        .map_err(|e| CryptoError::DecryptionFailed(e.to_string()))?;
+    let master_key = Zeroizing::new(
+        cipher
+            .decrypt(
+                Nonce::from_slice(parsed.nonce),
+                Payload {
+                    msg: parsed.wrapped,
+                    aad: parsed.header,
+                },
+            )
+            .map_err(|_| {
+                CryptoError::DecryptionFailed("wrong password or corrupted keyfile".into())
+            })?,
+    );
+
+    Ok(MasterKey::from_bytes(
+        master_key
+            .as_slice()
+            .try_into()
+            .expect("wrapped key is 32 bytes"),
+    ))
+}
+
+/// Re-wrap the master key in `keyfile` under a new password and/or new parameters. The
+/// master key itself (and therefore every encrypted object) is unchanged.
+pub fn rewrap_keyfile(
+    keyfile: &[u8],
+    old_password: &[u8],
+    new_password: &[u8],
+    params: &KeyParams,
+) -> Result<Vec<u8>, CryptoError> {
+    let master_key = unwrap_master_key(keyfile, old_password)?;
+    wrap_master_key(&master_key, new_password, params)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Cheap parameters so tests don't spend their time in Argon2.
+    const FAST: KeyParams = KeyParams {
+        m_cost: 64,
+        t_cost: 1,
+        p_cost: 1,
+    };
+
+    #[test]
+    fn test_create_and_unwrap() {
+        let (master_key, keyfile) = create_keyfile(b"correct horse", &FAST).unwrap();
+        assert_eq!(keyfile.len(), KEYFILE_SIZE);
+        assert_eq!(&keyfile[..8], KEYFILE_MAGIC);
+        assert_eq!(
+            unwrap_master_key(&keyfile, b"correct horse")
+                .unwrap()
+                .as_bytes(),
+            master_key.as_bytes()
+        );
+        assert!(unwrap_master_key(&keyfile, b"wrong").is_err());
+    }
+
+    #[test]
+    fn test_rewrap_keeps_master_key() {
+        let (master_key, keyfile) = create_keyfile(b"old", &FAST).unwrap();
+        let params = KeyParams { t_cost: 2, ..FAST };
+        let rewrapped = rewrap_keyfile(&keyfile, b"old", b"new", &params).unwrap();
+        assert_eq!(
+            unwrap_master_key(&rewrapped, b"new").unwrap().as_bytes(),
+            master_key.as_bytes()
+        );
+        assert!(unwrap_master_key(&rewrapped, b"old").is_err());
+        assert_eq!(keyfile_params(&rewrapped).unwrap(), params);
+    }
+
+    #[test]
+    fn test_unwraps_v1_keyfile_with_default_params() {
+        // v1 layout: no params record, Argon2::default() parameters.
+        let master_key = MasterKey::from_bytes([4u8; 32]);
+        let v2 = wrap_master_key(&master_key, b"pw", &KeyParams::default()).unwrap();
+        let mut v1 = v2[..8].to_vec();
+        v1.push(KEYFILE_VERSION_V1);
+        v1.extend_from_slice(&v2[9 + KEY_PARAMS_SIZE..V2_HEADER_SIZE]);
+
+        let kek = derive_master_key_with_params(
+            b"pw",
+            v1[9..25].try_into().unwrap(),
+            &KeyParams::default(),
+        )
+        .unwrap();
+        let cipher = Aes256Gcm::new_from_slice(kek.as_bytes()).unwrap();
+        let wrapped = cipher
+            .encrypt(
+                Nonce::from_slice(&v1[25..37]),
+                Payload {
+                    msg: master_key.as_bytes(),
+                    aad: &v1,
+                },
+            )
+            .unwrap();
+        v1.extend_from_slice(&wrapped);
+
+        assert_eq!(keyfile_params(&v1).unwrap(), KeyParams::default());
+        assert_eq!(
+            unwrap_master_key(&v1, b"pw").unwrap().as_bytes(),
+            master_key.as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_tampered_header_rejected() {
+        let keyfile = wrap_master_key(&MasterKey::from_bytes([9u8; 32]), b"pw", &FAST).unwrap();
+        let mut tampered = keyfile.clone();
+        tampered[10] ^= 1; // m_cost: still valid params, but covered by the AAD
+        assert!(unwrap_master_key(&tampered, b"pw").is_err());
+        assert!(unwrap_master_key(&keyfile[..40], b"pw").is_err());
+    }
+}
@@ -0,0 +1,103 @@
+//! A set of master keys, indexed by key fingerprint.
+//!
+//! Files written in format v4 and later carry the fingerprint of the key they were encrypted
+//! with, so a keyring picks the right key directly and reports `WrongKey` when it has none.
+//! Older files carry no key ID; for those every key is tried in insertion order.
+
+use std::sync::Arc;
+
+use crate::key_derivation::key_fingerprint;
+use crate::MasterKey;
+
+/// Master keys known to the caller. The first key added is the primary key, used for
+/// encrypting new files; the rest are kept to read older ones (e.g. during rotation).
+///
+/// Cloning a keyring shares its keys rather than copying them; they are wiped when the last
+/// clone is dropped.
+#[derive(Clone, Default)]
+pub struct Keyring {
+    keys: Vec<([u8; 8], Arc<MasterKey>)>,
+}
+
+impl std::fmt::Debug for Keyring {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.keys.iter().map(|(id, _)| crate::format_key_id(id)))
+            .finish()
+    }
+}
+
+impl Keyring {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A keyring holding only `master_key`.
+    pub fn from_key(master_key: &MasterKey) -> Self {
+        let mut keyring = Self::new();
+        keyring.add(master_key);
+        keyring
+    }
+
+    /// Add a key and return its ID. Adding a key that is already present is a no-op.
+    pub fn add(&mut self, master_key: &MasterKey) -> [u8; 8] {
+        let key_id = key_fingerprint(master_key);
+        if self.get(&key_id).is_none() {
+            self.keys.push((key_id, Arc::new(master_key.duplicate())));
+        }
+        key_id
+    }
+
+    /// The key with fingerprint `key_id`, if present.
+    pub fn get(&self, key_id: &[u8; 8]) -> Option<&MasterKey> {
+        self.keys
+            .iter()
+            .find(|(id, _)| id == key_id)
+            .map(|(_, key)| key.as_ref())
+    }
+
+    /// The key new files are encrypted with: the first one added.
+    pub fn primary(&self) -> Option<&MasterKey> {
+        self.keys.first().map(|(_, key)| key.as_ref())
+    }
+
+    /// All keys in insertion order (primary first).
+    pub fn keys(&self) -> impl Iterator<Item = &MasterKey> {
+        self.keys.iter().map(|(_, key)| key.as_ref())
+    }
+
+    pub fn key_ids(&self) -> impl Iterator<Item = &[u8; 8]> {
+        self.keys.iter().map(|(id, _)| id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_by_fingerprint() {
+        let (first, second) = (
+            MasterKey::from_bytes([1u8; 32]),
+            MasterKey::from_bytes([2u8; 32]),
+        );
+        let mut keyring = Keyring::new();
+        let old = keyring.add(&first);
+        let new = keyring.add(&second);
+        assert_eq!(keyring.add(&first), old);
+        assert_eq!(keyring.len(), 2);
+
+        assert_eq!(keyring.get(&new).unwrap().as_bytes(), &[2u8; 32]);
+        assert!(keyring.get(&[0u8; 8]).is_none());
+        assert_eq!(keyring.primary().unwrap().as_bytes(), &[1u8; 32]);
+        assert!(!format!("{keyring:?}").contains("1, 1"));
+    }
+}
@@ -0,0 +1,98 @@
+//! Secret key types.
+//!
+//! [`MasterKey`] and [`FileKey`] wrap the raw 32 bytes so that they are wiped from memory
+//! when dropped, never show up in `Debug` output, and are not copied by accident: neither
+//! type implements `Clone` or `Copy`. Code that needs the bytes borrows them with
+//! `as_bytes()`.
+
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+/// The 256-bit master key every file key is derived from.
+pub struct MasterKey([u8; 32]);
+
+impl MasterKey {
+    /// Wrap raw key bytes, e.g. decoded from `SOLIDROP_MASTER_KEY`. The caller should wipe
+    /// its own copy if it keeps one.
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    /// Output buffer for derivation functions writing the key in place.
+    pub(crate) fn as_mut_bytes(&mut self) -> &mut [u8; 32] {
+        &mut self.0
+    }
+
+    /// An explicit copy, for the few places inside the crate that must own a key they
+    /// were lent (the keyring).
+    pub(crate) fn duplicate(&self) -> Self {
+        Self(self.0)
+    }
+}
+
+impl From<[u8; 32]> for MasterKey {
+    fn from(bytes: [u8; 32]) -> Self {
+        Self::from_bytes(bytes)
+    }
+}
+
+impl Drop for MasterKey {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl ZeroizeOnDrop for MasterKey {}
+
+impl std::fmt::Debug for MasterKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("MasterKey(<redacted>)")
+    }
+}
+
+/// A per-file AES-256-GCM key, derived from a [`MasterKey`] and the file's salt.
+pub struct FileKey([u8; 32]);
+
+impl FileKey {
+    pub(crate) fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    pub(crate) fn as_mut_bytes(&mut self) -> &mut [u8; 32] {
+        &mut self.0
+    }
+}
+
+impl Drop for FileKey {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl ZeroizeOnDrop for FileKey {}
+
+impl std::fmt::Debug for FileKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("FileKey(<redacted>)")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_debug_is_redacted() {
+        let key = MasterKey::from_bytes([0xab; 32]);
+        let debug = format!("{key:?}");
+        assert_eq!(debug, "MasterKey(<redacted>)");
+        assert!(!format!("{:?}", FileKey::from_bytes([0xab; 32])).contains("171"));
+    }
+}
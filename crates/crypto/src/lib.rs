@@ -1,14 +1,109 @@
+pub mod compress;
 pub mod decrypt;
 pub mod encrypt;
 pub mod hash;
+pub mod header;
 pub mod key_derivation;
+pub mod keyfile;
+pub mod keyring;
+pub mod metadata;
+pub mod padding;
+pub mod recovery;
+pub mod shamir;
+pub mod stream;
 
 mod error;
+mod keys;
+pub use compress::Compression;
 pub use error::CryptoError;
+pub use header::EncryptedHeader;
+pub use keys::{FileKey, MasterKey};
+pub use metadata::FileMetadata;
+pub use padding::Padding;
 
-/// SoliDrop encrypted file magic bytes and version.
-pub const MAGIC_BYTES: &[u8; 9] = b"SOLIDROP\x01";
-pub const FORMAT_VERSION: u8 = 1;
+/// SoliDrop encrypted file magic bytes. The format version byte follows immediately.
+pub const MAGIC_BYTES: &[u8; 8] = b"SOLIDROP";
 
-/// Header size: magic(8) + version(1) + salt(16) + nonce(12) + original_size(8) = 45 bytes
-pub const HEADER_SIZE: usize = 8 + 1 + 16 + 12 + 8;
+/// Legacy format: a single AES-256-GCM message over the whole file.
+pub const FORMAT_VERSION_V1: u8 = 1;
+/// Segmented format: STREAM-style AES-256-GCM segments (see `stream`).
+pub const FORMAT_VERSION_V2: u8 = 2;
+/// Segmented format with the full header authenticated as associated data.
+pub const FORMAT_VERSION_V3: u8 = 3;
+/// v3 plus the fingerprint of the master key (key ID) in the header.
+pub const FORMAT_VERSION_V4: u8 = 4;
+/// v4 plus the compression algorithm applied before encryption.
+pub const FORMAT_VERSION_V5: u8 = 5;
+/// v5 plus an encrypted metadata block (original name, mtime, ...) before the segments.
+pub const FORMAT_VERSION_V6: u8 = 6;
+/// v6 plus the length-hiding padding policy applied before encryption.
+pub const FORMAT_VERSION_V7: u8 = 7;
+/// v7 plus the SHA-256 of the plaintext, sealed in a record at the end of the final segment.
+pub const FORMAT_VERSION_V8: u8 = 8;
+/// Format version written by `encrypt` and the streaming adapters.
+pub const FORMAT_VERSION: u8 = FORMAT_VERSION_V8;
+
+/// v1 header size: magic(8) + version(1) + salt(16) + nonce(12) + original_size(8) = 45 bytes
+pub const V1_HEADER_SIZE: usize = 8 + 1 + 16 + 12 + 8;
+
+/// v2/v3 header size: magic(8) + version(1) + salt(16) + nonce_prefix(7) + segment_size(4) = 36 bytes
+pub const V2_HEADER_SIZE: usize = 8 + 1 + 16 + 7 + 4;
+
+/// v4 header size: magic(8) + version(1) + key_id(8) + salt(16) + nonce_prefix(7) + segment_size(4) = 44 bytes
+pub const V4_HEADER_SIZE: usize = 8 + 1 + 8 + 16 + 7 + 4;
+
+/// v5 header size: magic(8) + version(1) + key_id(8) + compression(1) + salt(16) + nonce_prefix(7) + segment_size(4) = 45 bytes
+pub const V5_HEADER_SIZE: usize = 8 + 1 + 8 + 1 + 16 + 7 + 4;
+
+/// v6 header size: the v5 fields + metadata_len(4) = 49 bytes
+pub const V6_HEADER_SIZE: usize = V5_HEADER_SIZE + 4;
+
+/// v7 header size: the v6 fields + padding(1), which follows compression = 50 bytes
+pub const V7_HEADER_SIZE: usize = V6_HEADER_SIZE + 1;
+
+/// v8 header size: the same fields as v7 = 50 bytes. Also the largest header size.
+pub const V8_HEADER_SIZE: usize = V7_HEADER_SIZE;
+
+/// Format details of an encrypted file, reported alongside its plaintext.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FormatInfo {
+    pub version: u8,
+    /// Fingerprint of the master key the file was encrypted with (v4 and later).
+    pub key_id: Option<[u8; 8]>,
+    /// Compression applied before encryption (v5 and later; `None` before).
+    pub compression: Compression,
+    /// Length-hiding padding (v7 and later; `None` before).
+    pub padding: Padding,
+}
+
+impl FormatInfo {
+    /// Whether every header byte is bound to the authentication tags (v3 and later).
+    ///
+    /// For v1/v2 files, tampering with the salt or nonce still fails decryption because the
+    /// key or nonce changes, but other header fields are not authenticated.
+    pub fn header_authenticated(&self) -> bool {
+        self.version >= FORMAT_VERSION_V3
+    }
+}
+
+impl std::fmt::Display for FormatInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.header_authenticated() {
+            write!(f, "v{}", self.version)?;
+            if self.compression != Compression::None {
+                write!(f, ", {}", self.compression)?;
+            }
+            if self.padding != Padding::None {
+                write!(f, ", padded ({})", self.padding)?;
+            }
+            Ok(())
+        } else {
+            write!(f, "v{} (legacy, header unauthenticated)", self.version)
+        }
+    }
+}
+
+/// Hex form of a key ID / fingerprint, as shown to users.
+pub fn format_key_id(key_id: &[u8; 8]) -> String {
+    hash::hex_encode(key_id)
+}
@@ -0,0 +1,183 @@
+//! Encrypted per-file metadata (v6 and later).
+//!
+//! The metadata block sits between the header and the first segment, sealed with its own
+//! key so that it can be read from the first few KiB of a file (e.g. to list original file
+//! names) without decrypting the content. Its plaintext is a sequence of TLV records:
+//! `tag(u8) || length(u16 LE) || value`. Readers skip tags they do not know, so new fields
+//! can be added without a format bump. Tag 0 is filler used to pad the block (see
+//! [`crate::padding`]) and is dropped when reading.
+
+use crate::CryptoError;
+
+/// Largest metadata block (ciphertext, including its tag) a header may declare (64 KiB).
+pub const MAX_METADATA_SIZE: u32 = 64 * 1024;
+
+const TAG_PADDING: u8 = 0;
+const TAG_NAME: u8 = 1;
+const TAG_MODIFIED: u8 = 2;
+const TAG_MIME_TYPE: u8 = 3;
+const TAG_SOURCE_APP: u8 = 4;
+
+/// Descriptive fields stored encrypted inside a SoliDrop file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileMetadata {
+    /// Original file name, without directories.
+    pub name: Option<String>,
+    /// Last modification time of the original file, in seconds since the Unix epoch.
+    pub modified: Option<i64>,
+    /// MIME type, e.g. `image/png`.
+    pub mime_type: Option<String>,
+    /// Application that produced or uploaded the file.
+    pub source_app: Option<String>,
+    /// Records with tags this version does not know, kept so they survive re-encryption.
+    pub unknown: Vec<(u8, Vec<u8>)>,
+}
+
+impl FileMetadata {
+    /// Encode as TLV records. Fails if a value is longer than a record can hold.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, CryptoError> {
+        let mut out = Vec::new();
+        let mut put = |tag: u8, value: &[u8]| {
+            let len = u16::try_from(value.len()).map_err(|_| {
+                CryptoError::InvalidMetadata(format!("record {tag} is {} bytes", value.len()))
+            })?;
+            out.push(tag);
+            out.extend_from_slice(&len.to_le_bytes());
+            out.extend_from_slice(value);
+            Ok::<_, CryptoError>(())
+        };
+
+        if let Some(name) = &self.name {
+            put(TAG_NAME, name.as_bytes())?;
+        }
+        if let Some(modified) = self.modified {
+            put(TAG_MODIFIED, &modified.to_le_bytes())?;
+        }
+        if let Some(mime_type) = &self.mime_type {
+            put(TAG_MIME_TYPE, mime_type.as_bytes())?;
+        }
+        if let Some(source_app) = &self.source_app {
+            put(TAG_SOURCE_APP, source_app.as_bytes())?;
+        }
+        for (tag, value) in &self.unknown {
+            put(*tag, value)?;
+        }
+        Ok(out)
+    }
+
+    /// Decode TLV records produced by [`to_bytes`](Self::to_bytes).
+    pub fn from_bytes(mut data: &[u8]) -> Result<Self, CryptoError> {
+        let mut metadata = Self::default();
+        while !data.is_empty() {
+            if data.len() < 3 {
+                return Err(CryptoError::InvalidMetadata(
+                    "truncated record header".into(),
+                ));
+            }
+            let tag = data[0];
+            let len = u16::from_le_bytes([data[1], data[2]]) as usize;
+            let value = data
+                .get(3..3 + len)
+                .ok_or_else(|| CryptoError::InvalidMetadata(format!("record {tag} truncated")))?;
+            data = &data[3 + len..];
+
+            match tag {
+                TAG_NAME => metadata.name = Some(utf8(tag, value)?),
+                TAG_MODIFIED => {
+                    let bytes: [u8; 8] = value.try_into().map_err(|_| {
+                        CryptoError::InvalidMetadata("modification time is not 8 bytes".into())
+                    })?;
+                    metadata.modified = Some(i64::from_le_bytes(bytes));
+                }
+                TAG_MIME_TYPE => metadata.mime_type = Some(utf8(tag, value)?),
+                TAG_SOURCE_APP => metadata.source_app = Some(utf8(tag, value)?),
+                TAG_PADDING => {}
+                _ => metadata.unknown.push((tag, value.to_vec())),
+            }
+        }
+        Ok(metadata)
+    }
+}
+
+/// Append a filler record so that `records` ends on a multiple of `block` bytes.
+pub(crate) fn pad_records(records: &mut Vec<u8>, block: usize) {
+    let mut target = records.len().next_multiple_of(block);
+    // A filler record needs at least its 3-byte record header.
+    if target - records.len() < 3 && target != records.len() {
+        target += block;
+    }
+    if target == records.len() {
+        return;
+    }
+    let filler = target - records.len() - 3;
+    records.push(TAG_PADDING);
+    records.extend_from_slice(&(filler as u16).to_le_bytes());
+    records.resize(target, 0);
+}
+
+fn utf8(tag: u8, value: &[u8]) -> Result<String, CryptoError> {
+    String::from_utf8(value.to_vec())
+        .map_err(|_| CryptoError::InvalidMetadata(format!("record {tag} is not UTF-8")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip_keeps_unknown_records() {
+        let metadata = FileMetadata {
+            name: Some("illustration-01.clip".into()),
+            modified: Some(1_770_000_000),
+            mime_type: Some("application/octet-stream".into()),
+            source_app: Some("solidrop-cli/0.1.0".into()),
+            unknown: vec![(200, b"future field".to_vec())],
+        };
+        let bytes = metadata.to_bytes().unwrap();
+        assert_eq!(FileMetadata::from_bytes(&bytes).unwrap(), metadata);
+        assert_eq!(
+            FileMetadata::from_bytes(&[]).unwrap(),
+            FileMetadata::default()
+        );
+    }
+
+    #[test]
+    fn test_padded_records_decode_unchanged() {
+        let metadata = FileMetadata {
+            name: Some("a".repeat(251)),
+            ..Default::default()
+        };
+        let mut bytes = metadata.to_bytes().unwrap();
+        assert_eq!(bytes.len(), 254);
+        // Two bytes short of the block: too few for a filler record, so one more block.
+        pad_records(&mut bytes, 256);
+        assert_eq!(bytes.len(), 512);
+        assert_eq!(FileMetadata::from_bytes(&bytes).unwrap(), metadata);
+
+        let mut short = Vec::new();
+        pad_records(&mut short, 256);
+        assert!(short.is_empty());
+    }
+
+    #[test]
+    fn test_malformed_records_rejected() {
+        let bytes = FileMetadata {
+            name: Some("a.png".into()),
+            ..Default::default()
+        }
+        .to_bytes()
+        .unwrap();
+        assert!(FileMetadata::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(FileMetadata::from_bytes(&[TAG_MODIFIED, 1, 0, 0]).is_err());
+        assert!(FileMetadata::from_bytes(&[TAG_NAME, 1, 0, 0xff]).is_err());
+
+        let too_long = FileMetadata {
+            name: Some("x".repeat(70_000)),
+            ..Default::default()
+        };
+        assert!(matches!(
+            too_long.to_bytes(),
+            Err(CryptoError::InvalidMetadata(_))
+        ));
+    }
+}
@@ -0,0 +1,193 @@
+//! Optional length-hiding padding of the segment plaintext (v7 and later).
+//!
+//! The ciphertext length otherwise reveals the exact plaintext length, which is enough to
+//! recognise well-known files. With padding, zero bytes are appended after the (possibly
+//! compressed) plaintext up to a bucket boundary, followed by an 8-byte trailer holding the
+//! number of padding bytes. Both sit inside the last segments, so the real length is only
+//! known to someone who can decrypt them.
+
+use crate::CryptoError;
+
+/// Size of the trailer recording the padding length (u64 little-endian).
+pub(crate) const TRAILER_SIZE: usize = 8;
+
+/// Padding policy for the segment plaintext, as recorded in the header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Padding {
+    #[default]
+    None,
+    /// Padmé: at most ~12% overhead, leaking O(log log n) bits of the length.
+    Padme,
+    /// Next power of two: up to 100% overhead, leaking O(log log n) bits with far fewer
+    /// buckets than Padmé.
+    PowerOfTwo,
+}
+
+impl Padding {
+    /// Header byte for this policy.
+    pub(crate) fn id(self) -> u8 {
+        match self {
+            Padding::None => 0,
+            Padding::Padme => 1,
+            Padding::PowerOfTwo => 2,
+        }
+    }
+
+    pub(crate) fn from_id(id: u8) -> Result<Self, CryptoError> {
+        match id {
+            0 => Ok(Padding::None),
+            1 => Ok(Padding::Padme),
+            2 => Ok(Padding::PowerOfTwo),
+            other => Err(CryptoError::InvalidHeader(format!(
+                "unknown padding policy: {other}"
+            ))),
+        }
+    }
+
+    /// Length `len` bytes are padded to (trailer included in `len`).
+    pub fn padded_len(self, len: u64) -> u64 {
+        match self {
+            Padding::None => len,
+            Padding::Padme => padme(len),
+            Padding::PowerOfTwo => len.checked_next_power_of_two().unwrap_or(len),
+        }
+    }
+}
+
+impl std::fmt::Display for Padding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Padding::None => "none",
+            Padding::Padme => "padme",
+            Padding::PowerOfTwo => "pow2",
+        })
+    }
+}
+
+/// Padmé (Nikitin et al., "Reducing Metadata Leakage from Encrypted Files and Communication
+/// with PURBs", 2019): keep the top `floor(log2(E)) + 1` bits of the length, where
+/// `E = floor(log2(len))`, and round the rest up.
+fn padme(len: u64) -> u64 {
+    if len < 2 {
+        return len;
+    }
+    let exponent = 63 - u64::from(len.leading_zeros());
+    let mantissa_bits = 64 - u64::from(exponent.leading_zeros());
+    let mask = (1u64 << (exponent - mantissa_bits)) - 1;
+    len.checked_add(mask).map_or(len, |padded| padded & !mask)
+}
+
+/// Padding bytes to append after `payload_len` bytes so that payload, padding and trailer
+/// fill a whole bucket.
+pub(crate) fn padding_len(padding: Padding, payload_len: u64) -> u64 {
+    let unpadded = payload_len + TRAILER_SIZE as u64;
+    padding.padded_len(unpadded) - unpadded
+}
+
+/// Strips padding from authenticated segment plaintext as it arrives.
+///
+/// Padding is all zeros, so a trailing run of zero bytes is held back as a count rather
+/// than buffered; it is released once a non-zero byte shows it was data, or at the end,
+/// minus the padding length from the trailer. Memory use stays at the trailer size.
+#[derive(Default)]
+pub(crate) struct Unpadder {
+    /// Zero bytes seen but not yet released.
+    zeros: u64,
+    /// Last bytes seen, which may be the trailer.
+    tail: Vec<u8>,
+}
+
+impl Unpadder {
+    pub(crate) fn update(&mut self, data: &[u8], out: &mut Vec<u8>) {
+        self.tail.extend_from_slice(data);
+        if self.tail.len() > TRAILER_SIZE {
+            let release = self.tail.len() - TRAILER_SIZE;
+            match self.tail[..release].iter().rposition(|&b| b != 0) {
+                Some(last_data) => {
+                    out.resize(out.len() + self.zeros as usize, 0);
+                    out.extend_from_slice(&self.tail[..=last_data]);
+                    self.zeros = (release - last_data - 1) as u64;
+                }
+                None => self.zeros += release as u64,
+            }
+            self.tail.drain(..release);
+        }
+    }
+
+    /// Read the trailer and release the zero bytes that were data.
+    pub(crate) fn finish(self, out: &mut Vec<u8>) -> Result<(), CryptoError> {
+        let trailer: [u8; TRAILER_SIZE] = self.tail.as_slice().try_into().map_err(|_| {
+            CryptoError::DecryptionFailed("padded payload is shorter than its trailer".into())
+        })?;
+        let padding = u64::from_le_bytes(trailer);
+        let data_zeros = self.zeros.checked_sub(padding).ok_or_else(|| {
+            CryptoError::DecryptionFailed(format!(
+                "padding length {padding} exceeds the trailing zero bytes"
+            ))
+        })?;
+        out.resize(out.len() + data_zeros as usize, 0);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_padded_lengths() {
+        assert_eq!(Padding::Padme.padded_len(1000), 1024);
+        assert_eq!(Padding::Padme.padded_len(1_000_000), 1_015_808);
+        assert_eq!(Padding::PowerOfTwo.padded_len(1_000_000), 1 << 20);
+        assert_eq!(Padding::None.padded_len(1_000_000), 1_000_000);
+        for len in [0u64, 1, 2, 3, 100, 65_537, 30 << 20] {
+            let padded = Padding::Padme.padded_len(len);
+            assert!(
+                padded >= len && padded - len <= len / 8 + 1,
+                "{len} -> {padded}"
+            );
+        }
+        // Nearby lengths share a bucket.
+        assert_eq!(
+            Padding::Padme.padded_len(30_000_000),
+            Padding::Padme.padded_len(30_100_000)
+        );
+    }
+
+    #[test]
+    fn test_unpadder_keeps_trailing_zeros_of_data() {
+        let data = [b"abc".as_slice(), &[0u8; 20], b"d", &[0u8; 5]].concat();
+        let padding = padding_len(Padding::Padme, 500) + 17;
+        let mut padded = data.clone();
+        padded.resize(data.len() + padding as usize, 0);
+        padded.extend_from_slice(&padding.to_le_bytes());
+
+        for chunk in [1, 3, 9, 1000] {
+            let mut unpadder = Unpadder::default();
+            let mut out = Vec::new();
+            for piece in padded.chunks(chunk) {
+                unpadder.update(piece, &mut out);
+            }
+            unpadder.finish(&mut out).unwrap();
+            assert_eq!(out, data, "chunk size {chunk}");
+        }
+    }
+
+    #[test]
+    fn test_invalid_trailer_rejected() {
+        let mut unpadder = Unpadder::default();
+        let mut out = Vec::new();
+        unpadder.update(&[0, 0, 1], &mut out);
+        assert!(unpadder.finish(&mut out).is_err());
+
+        let mut unpadder = Unpadder::default();
+        unpadder.update(&[0, 0], &mut out);
+        unpadder.update(&5u64.to_le_bytes(), &mut out);
+        assert!(unpadder.finish(&mut out).is_err());
+        assert_eq!(
+            Padding::from_id(Padding::PowerOfTwo.id()).unwrap(),
+            Padding::PowerOfTwo
+        );
+        assert!(Padding::from_id(9).is_err());
+    }
+}
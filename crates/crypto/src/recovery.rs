@@ -0,0 +1,86 @@
+//! Recovery phrase for the master key.
+//!
+//! The 32-byte master key is encoded as a 24-word BIP-39 mnemonic (English wordlist): 256 bits
+//! of key plus an 8-bit SHA-256 checksum, 11 bits per word. A mistyped or swapped word is
+//! caught by the checksum (or by not being in the wordlist) instead of silently producing a
+//! different key.
+
+use bip39::{Language, Mnemonic};
+use zeroize::Zeroize;
+
+use crate::{CryptoError, MasterKey};
+
+/// Number of words in a master key phrase.
+pub const PHRASE_WORDS: usize = 24;
+
+/// Encode the master key as a 24-word recovery phrase, words separated by single spaces.
+pub fn master_key_to_phrase(master_key: &MasterKey) -> String {
+    Mnemonic::from_entropy_in(Language::English, master_key.as_bytes())
+        .expect("32 bytes is a valid BIP-39 entropy length")
+        .to_string()
+}
+
+/// Decode a recovery phrase back into the master key.
+///
+/// Case and whitespace (spaces, tabs, line breaks) are ignored, so a phrase copied from paper
+/// in rows is accepted as-is.
+pub fn master_key_from_phrase(phrase: &str) -> Result<MasterKey, CryptoError> {
+    let normalized = phrase
+        .split_whitespace()
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>();
+    if normalized.len() != PHRASE_WORDS {
+        return Err(CryptoError::InvalidPhrase(format!(
+            "expected {PHRASE_WORDS} words, got {}",
+            normalized.len()
+        )));
+    }
+
+    let mnemonic = Mnemonic::parse_in_normalized(Language::English, &normalized.join(" "))
+        .map_err(|e| CryptoError::InvalidPhrase(e.to_string()))?;
+    let (mut entropy, len) = mnemonic.to_entropy_array();
+    let key = entropy[..len]
+        .try_into()
+        .map(MasterKey::from_bytes)
+        .map_err(|_| CryptoError::InvalidPhrase("phrase does not encode a 32-byte key".into()));
+    entropy.zeroize();
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_phrase_roundtrip() {
+        let key = MasterKey::from_bytes([0x5au8; 32]);
+        let phrase = master_key_to_phrase(&key);
+        assert_eq!(phrase.split(' ').count(), PHRASE_WORDS);
+
+        // Rows on paper, re-typed in upper case.
+        let retyped = phrase.to_uppercase().replacen(' ', "\n", 6);
+        assert_eq!(
+            master_key_from_phrase(&retyped).unwrap().as_bytes(),
+            key.as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_checksum_catches_swapped_words() {
+        let key = MasterKey::from_bytes(std::array::from_fn(|i| i as u8));
+        let phrase = master_key_to_phrase(&key);
+        let mut words: Vec<&str> = phrase.split(' ').collect();
+        words.swap(0, 1);
+        assert!(master_key_from_phrase(&words.join(" ")).is_err());
+    }
+
+    #[test]
+    fn test_rejects_wrong_length_and_unknown_words() {
+        let phrase = master_key_to_phrase(&MasterKey::from_bytes([1u8; 32]));
+        let short = phrase.rsplit_once(' ').unwrap().0;
+        assert!(master_key_from_phrase(short).is_err());
+
+        let unknown = phrase.replacen(phrase.split(' ').next().unwrap(), "solidrop", 1);
+        assert!(master_key_from_phrase(&unknown).is_err());
+    }
+}
@@ -0,0 +1,320 @@
+//! Shamir secret sharing of the master key.
+//!
+//! The key is split byte-wise over GF(2^8): each byte is the constant term of a random
+//! polynomial of degree `threshold - 1`, and share `x` holds the polynomial values at `x`.
+//! Any `threshold` shares reconstruct the key by Lagrange interpolation at 0; fewer reveal
+//! nothing about it.
+
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use zeroize::Zeroize;
+
+use crate::hash::hex_encode;
+use crate::key_derivation::key_fingerprint;
+use crate::{CryptoError, MasterKey};
+
+/// Text prefix of an encoded share; also identifies the share format version.
+pub const SHARE_PREFIX: &str = "sdshare1:";
+
+const SHARE_VERSION: u8 = 1;
+const CHECKSUM_SIZE: usize = 4;
+/// version(1) + threshold(1) + index(1) + fingerprint(8) + data(32) + checksum(4) = 47 bytes
+const SHARE_SIZE: usize = 1 + 1 + 1 + 8 + 32 + CHECKSUM_SIZE;
+
+/// One share of a split master key.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Share {
+    /// Number of shares needed to reconstruct the key.
+    pub threshold: u8,
+    /// x coordinate of this share (1..=255).
+    pub index: u8,
+    /// `key_fingerprint` of the master key this share belongs to.
+    pub fingerprint: [u8; 8],
+    data: [u8; 32],
+}
+
+impl std::fmt::Debug for Share {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Share")
+            .field("threshold", &self.threshold)
+            .field("index", &self.index)
+            .field("fingerprint", &hex_encode(&self.fingerprint))
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for Share {
+    fn drop(&mut self) {
+        self.data.zeroize();
+    }
+}
+
+impl Share {
+    /// Encode as `sdshare1:` followed by lowercase hex. The last 4 bytes are a truncated
+    /// SHA-256 checksum of the rest.
+    pub fn encode(&self) -> String {
+        let mut bytes = Vec::with_capacity(SHARE_SIZE);
+        bytes.push(SHARE_VERSION);
+        bytes.push(self.threshold);
+        bytes.push(self.index);
+        bytes.extend_from_slice(&self.fingerprint);
+        bytes.extend_from_slice(&self.data);
+        let checksum = checksum(&bytes);
+        bytes.extend_from_slice(&checksum);
+        format!("{SHARE_PREFIX}{}", hex_encode(&bytes))
+    }
+
+    /// Parse an encoded share, verifying its checksum. Surrounding whitespace is ignored.
+    pub fn decode(encoded: &str) -> Result<Self, CryptoError> {
+        let body = encoded
+            .trim()
+            .strip_prefix(SHARE_PREFIX)
+            .ok_or_else(|| CryptoError::InvalidShare(format!("missing {SHARE_PREFIX} prefix")))?;
+        let bytes = unhex(body)
+            .filter(|b| b.len() == SHARE_SIZE)
+            .ok_or_else(|| {
+                CryptoError::InvalidShare(format!("expected {} hex characters", SHARE_SIZE * 2))
+            })?;
+
+        let (payload, stored_checksum) = bytes.split_at(SHARE_SIZE - CHECKSUM_SIZE);
+        if checksum(payload) != stored_checksum {
+            return Err(CryptoError::InvalidShare(
+                "checksum mismatch (share mistyped or corrupted)".into(),
+            ));
+        }
+        if payload[0] != SHARE_VERSION {
+            return Err(CryptoError::InvalidShare(format!(
+                "unsupported version: {}",
+                payload[0]
+            )));
+        }
+
+        let share = Self {
+            threshold: payload[1],
+            index: payload[2],
+            fingerprint: payload[3..11].try_into().unwrap(),
+            data: payload[11..43].try_into().unwrap(),
+        };
+        if share.threshold < 2 || share.index == 0 {
+            return Err(CryptoError::InvalidShare(
+                "invalid threshold or share index".into(),
+            ));
+        }
+        Ok(share)
+    }
+}
+
+/// Split `master_key` into `shares` shares, any `threshold` of which reconstruct it.
+pub fn split_master_key(
+    master_key: &MasterKey,
+    threshold: u8,
+    shares: u8,
+) -> Result<Vec<Share>, CryptoError> {
+    if threshold < 2 || threshold > shares {
+        return Err(CryptoError::InvalidShare(format!(
+            "need 2 <= threshold <= shares, got threshold {threshold} of {shares}"
+        )));
+    }
+
+    // coefficients[i] holds the random coefficients (degree 1..threshold) for key byte i.
+    let mut rng = rand::thread_rng();
+    let mut coefficients = vec![0u8; 32 * (threshold as usize - 1)];
+    rng.fill_bytes(&mut coefficients);
+
+    let fingerprint = key_fingerprint(master_key);
+    let result = (1..=shares)
+        .map(|x| {
+            let mut data = [0u8; 32];
+            for (i, byte) in data.iter_mut().enumerate() {
+                let coeffs =
+                    &coefficients[i * (threshold as usize - 1)..][..threshold as usize - 1];
+                // Horner's rule, highest degree first.
+                let mut y = 0u8;
+                for &c in coeffs.iter().rev() {
+                    y = gf_mul(y, x) ^ c;
+                }
+                *byte = gf_mul(y, x) ^ master_key.as_bytes()[i];
+            }
+            Share {
+                threshold,
+                index: x,
+                fingerprint,
+                data,
+            }
+        })
+        .collect();
+
+    coefficients.zeroize();
+    Ok(result)
+}
+
+/// Reconstruct the master key from at least `threshold` shares of the same key.
+///
+/// Shares with mismatching fingerprints or thresholds, duplicates, and too few shares are
+/// rejected up front. The result is checked against the shares' fingerprint, so a share that
+/// passed its checksum but belongs to a different split still cannot yield a wrong key.
+pub fn combine_shares(shares: &[Share]) -> Result<MasterKey, CryptoError> {
+    let first = shares
+        .first()
+        .ok_or_else(|| CryptoError::InvalidShare("no shares given".into()))?;
+
+    for share in shares {
+        if share.fingerprint != first.fingerprint {
+            return Err(CryptoError::InvalidShare(format!(
+                "share {} belongs to key {}, share {} to key {}",
+                first.index,
+                hex_encode(&first.fingerprint),
+                share.index,
+                hex_encode(&share.fingerprint)
+            )));
+        }
+        if share.threshold != first.threshold {
+            return Err(CryptoError::InvalidShare(
+                "shares come from splits with different thresholds".into(),
+            ));
+        }
+    }
+
+    let mut selected: Vec<&Share> = Vec::with_capacity(first.threshold as usize);
+    for share in shares {
+        match selected.iter().find(|s| s.index == share.index) {
+            Some(existing) if existing.data != share.data => {
+                return Err(CryptoError::InvalidShare(format!(
+                    "two different shares with index {}",
+                    share.index
+                )));
+            }
+            Some(_) => {}
+            None => selected.push(share),
+        }
+    }
+    if selected.len() < first.threshold as usize {
+        return Err(CryptoError::InvalidShare(format!(
+            "need {} distinct shares, got {}",
+            first.threshold,
+            selected.len()
+        )));
+    }
+    selected.truncate(first.threshold as usize);
+
+    let mut master_key = MasterKey::from_bytes([0u8; 32]);
+    for (j, share_j) in selected.iter().enumerate() {
+        // Lagrange basis polynomial for x_j evaluated at 0: prod_{m != j} x_m / (x_m - x_j).
+        let mut basis = 1u8;
+        for (m, share_m) in selected.iter().enumerate() {
+            if m != j {
+                basis = gf_mul(
+                    basis,
+                    gf_mul(share_m.index, gf_inv(share_m.index ^ share_j.index)),
+                );
+            }
+        }
+        for (byte, &y) in master_key
+            .as_mut_bytes()
+            .iter_mut()
+            .zip(share_j.data.iter())
+        {
+            *byte ^= gf_mul(basis, y);
+        }
+    }
+
+    if key_fingerprint(&master_key) != first.fingerprint {
+        return Err(CryptoError::InvalidShare(
+            "reconstructed key does not match the share fingerprint".into(),
+        ));
+    }
+    Ok(master_key)
+}
+
+/// Multiplication in GF(2^8) modulo x^8 + x^4 + x^3 + x + 1, without data-dependent branches.
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0u8;
+    for _ in 0..8 {
+        product ^= a & 0u8.wrapping_sub(b & 1);
+        let carry = 0u8.wrapping_sub(a >> 7);
+        a = (a << 1) ^ (0x1b & carry);
+        b >>= 1;
+    }
+    product
+}
+
+/// Multiplicative inverse in GF(2^8): a^254. Only called with non-zero `a`.
+fn gf_inv(a: u8) -> u8 {
+    let mut result = 1u8;
+    let mut base = a;
+    let mut exp = 254u8;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = gf_mul(result, base);
+        }
+        base = gf_mul(base, base);
+        exp >>= 1;
+    }
+    result
+}
+
+fn checksum(bytes: &[u8]) -> [u8; CHECKSUM_SIZE] {
+    Sha256::digest(bytes)[..CHECKSUM_SIZE].try_into().unwrap()
+}
+
+fn unhex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) || !s.is_ascii() {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_any_threshold_subset_reconstructs() {
+        let key = MasterKey::from_bytes(std::array::from_fn(|i| (i * 7 + 1) as u8));
+        let shares = split_master_key(&key, 3, 5).unwrap();
+        assert_eq!(shares.len(), 5);
+
+        for subset in [[0, 1, 2], [4, 2, 0], [1, 3, 4]] {
+            let picked: Vec<Share> = subset.iter().map(|&i| shares[i].clone()).collect();
+            assert_eq!(combine_shares(&picked).unwrap().as_bytes(), key.as_bytes());
+        }
+        assert!(combine_shares(&shares[..2]).is_err());
+        // A repeated share does not count twice.
+        assert!(
+            combine_shares(&[shares[0].clone(), shares[0].clone(), shares[1].clone()]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_encode_decode_and_checksum() {
+        let shares = split_master_key(&MasterKey::from_bytes([9u8; 32]), 2, 3).unwrap();
+        let encoded = shares[1].encode();
+        assert!(encoded.starts_with(SHARE_PREFIX));
+        assert_eq!(Share::decode(&format!("  {encoded}\n")).unwrap(), shares[1]);
+
+        // Flip one hex digit in the data.
+        let mut typo = encoded.into_bytes();
+        let pos = SHARE_PREFIX.len() + 30;
+        typo[pos] = if typo[pos] == b'0' { b'1' } else { b'0' };
+        let err = Share::decode(std::str::from_utf8(&typo).unwrap()).unwrap_err();
+        assert!(err.to_string().contains("checksum"), "{err}");
+    }
+
+    #[test]
+    fn test_shares_of_different_keys_rejected() {
+        let a = split_master_key(&MasterKey::from_bytes([1u8; 32]), 2, 2).unwrap();
+        let b = split_master_key(&MasterKey::from_bytes([2u8; 32]), 2, 2).unwrap();
+        let err = combine_shares(&[a[0].clone(), b[1].clone()]).unwrap_err();
+        assert!(err.to_string().contains("belongs to key"), "{err}");
+    }
+
+    #[test]
+    fn test_gf_inverse() {
+        for a in 1..=255u8 {
+            assert_eq!(gf_mul(a, gf_inv(a)), 1);
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use super::io::{to_io_error, READ_CHUNK_SIZE};
+use super::{Decryptor, Encryptor};
+use crate::{CryptoError, MasterKey};
+
+/// `AsyncWrite` adapter that encrypts everything written to it into `inner`.
+///
+/// `shutdown()` writes the final segment and then shuts down `inner`; a file whose writer
+/// was never shut down will not decrypt.
+pub struct AsyncEncryptWriter<W> {
+    inner: W,
+    encryptor: Option<Encryptor>,
+    /// Ciphertext produced but not yet accepted by `inner`.
+    pending: Vec<u8>,
+    pending_pos: usize,
+}
+
+impl<W: AsyncWrite + Unpin> AsyncEncryptWriter<W> {
+    pub fn new(master_key: &MasterKey, inner: W) -> Result<Self, CryptoError> {
+        Ok(Self::from_encryptor(Encryptor::new(master_key)?, inner))
+    }
+
+    pub fn from_encryptor(encryptor: Encryptor, inner: W) -> Self {
+        Self {
+            inner,
+            encryptor: Some(encryptor),
+            pending: Vec::new(),
+            pending_pos: 0,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while self.pending_pos < self.pending.len() {
+            let n = ready!(
+                Pin::new(&mut self.inner).poll_write(cx, &self.pending[self.pending_pos..])
+            )?;
+            if n == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.pending_pos += n;
+        }
+        self.pending.clear();
+        self.pending_pos = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for AsyncEncryptWriter<W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+
+        let encryptor = this
+            .encryptor
+            .as_mut()
+            .ok_or_else(|| io::Error::other("write after shutdown"))?;
+        encryptor
+            .update(buf, &mut this.pending)
+            .map_err(to_io_error)?;
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_drain(cx))?;
+
+        if let Some(encryptor) = this.encryptor.take() {
+            encryptor.finish(&mut this.pending).map_err(to_io_error)?;
+            ready!(this.poll_drain(cx))?;
+        }
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+/// `AsyncRead` adapter that decrypts a SoliDrop file read from `inner`.
+pub struct AsyncDecryptReader<R> {
+    inner: R,
+    decryptor: Option<Decryptor>,
+    plaintext: Vec<u8>,
+    pos: usize,
+    chunk: Box<[u8]>,
+}
+
+impl<R: AsyncRead + Unpin> AsyncDecryptReader<R> {
+    pub fn new(master_key: &MasterKey, inner: R) -> Self {
+        Self::from_decryptor(Decryptor::new(master_key), inner)
+    }
+
+    /// Use a preconfigured decryptor, e.g. [`Decryptor::with_keyring`].
+    pub fn from_decryptor(decryptor: Decryptor, inner: R) -> Self {
+        Self {
+            inner,
+            decryptor: Some(decryptor),
+            plaintext: Vec::new(),
+            pos: 0,
+            chunk: vec![0u8; READ_CHUNK_SIZE].into_boxed_slice(),
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for AsyncDecryptReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if this.pos < this.plaintext.len() {
+                let n = (this.plaintext.len() - this.pos).min(buf.remaining());
+                buf.put_slice(&this.plaintext[this.pos..this.pos + n]);
+                this.pos += n;
+                return Poll::Ready(Ok(()));
+            }
+
+            if this.decryptor.is_none() {
+                return Poll::Ready(Ok(()));
+            }
+
+            let mut chunk = ReadBuf::new(&mut this.chunk);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut chunk))?;
+            let n = chunk.filled().len();
+
+            this.plaintext.clear();
+            this.pos = 0;
+
+            if n == 0 {
+                let decryptor = this.decryptor.take().expect("checked above");
+                decryptor.finish(&mut this.plaintext).map_err(to_io_error)?;
+            } else {
+                let decryptor = this.decryptor.as_mut().expect("checked above");
+                decryptor
+                    .update(&this.chunk[..n], &mut this.plaintext)
+                    .map_err(to_io_error)?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn test_async_roundtrip() {
+        let key = MasterKey::from_bytes([6u8; 32]);
+        let plaintext: Vec<u8> = (0..200_000u32).map(|i| (i % 253) as u8).collect();
+
+        let mut writer = AsyncEncryptWriter::new(&key, Vec::new()).unwrap();
+        for piece in plaintext.chunks(4096) {
+            writer.write_all(piece).await.unwrap();
+        }
+        writer.shutdown().await.unwrap();
+        let encrypted = writer.into_inner();
+
+        // The async output is the same format the in-memory API reads.
+        assert_eq!(
+            crate::decrypt::decrypt(&key, &encrypted).unwrap(),
+            plaintext
+        );
+
+        let mut decrypted = Vec::new();
+        AsyncDecryptReader::new(&key, encrypted.as_slice())
+            .read_to_end(&mut decrypted)
+            .await
+            .unwrap();
+        assert_eq!(decrypted, plaintext);
+    }
+}
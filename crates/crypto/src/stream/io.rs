@@ -0,0 +1,176 @@
+use std::io::{self, Read, Write};
+
+use super::{Decryptor, Encryptor};
+use crate::{CryptoError, MasterKey};
+
+/// Size of the reads [`DecryptReader`] and `AsyncDecryptReader` issue against their inner
+/// reader.
+pub(super) const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+pub(super) fn to_io_error(err: CryptoError) -> io::Error {
+    let kind = match err {
+        CryptoError::Truncated { .. } => io::ErrorKind::UnexpectedEof,
+        _ => io::ErrorKind::InvalidData,
+    };
+    io::Error::new(kind, err)
+}
+
+/// `Write` adapter that encrypts everything written to it into `inner`.
+///
+/// [`finish`](Self::finish) must be called to write the final segment; dropping the writer
+/// without finishing leaves a file that will not decrypt.
+pub struct EncryptWriter<W: Write> {
+    inner: W,
+    encryptor: Option<Encryptor>,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> EncryptWriter<W> {
+    pub fn new(master_key: &MasterKey, inner: W) -> Result<Self, CryptoError> {
+        Ok(Self::from_encryptor(Encryptor::new(master_key)?, inner))
+    }
+
+    pub fn from_encryptor(encryptor: Encryptor, inner: W) -> Self {
+        Self {
+            inner,
+            encryptor: Some(encryptor),
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Write the final segment, flush, and return the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(encryptor) = self.encryptor.take() {
+            encryptor.finish(&mut self.buffer).map_err(to_io_error)?;
+            self.inner.write_all(&self.buffer)?;
+        }
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for EncryptWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let encryptor = self
+            .encryptor
+            .as_mut()
+            .ok_or_else(|| io::Error::other("write after finish"))?;
+
+        self.buffer.clear();
+        encryptor
+            .update(buf, &mut self.buffer)
+            .map_err(to_io_error)?;
+        self.inner.write_all(&self.buffer)?;
+        Ok(buf.len())
+    }
+
+    /// Flushes the inner writer. Plaintext of the current, unsealed segment stays buffered.
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// `Read` adapter that decrypts a SoliDrop file read from `inner`.
+///
+/// Errors surface as `io::ErrorKind::InvalidData` (`UnexpectedEof` for truncated input)
+/// wrapping the [`CryptoError`]. Plaintext is only returned once the segment containing it has verified.
+pub struct DecryptReader<R: Read> {
+    inner: R,
+    decryptor: Option<Decryptor>,
+    plaintext: Vec<u8>,
+    pos: usize,
+    chunk: Box<[u8]>,
+}
+
+impl<R: Read> DecryptReader<R> {
+    pub fn new(master_key: &MasterKey, inner: R) -> Self {
+        Self::from_decryptor(Decryptor::new(master_key), inner)
+    }
+
+    /// Use a preconfigured decryptor, e.g. [`Decryptor::with_keyring`].
+    pub fn from_decryptor(decryptor: Decryptor, inner: R) -> Self {
+        Self {
+            inner,
+            decryptor: Some(decryptor),
+            plaintext: Vec::new(),
+            pos: 0,
+            chunk: vec![0u8; READ_CHUNK_SIZE].into_boxed_slice(),
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for DecryptReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.pos < self.plaintext.len() {
+                let n = (self.plaintext.len() - self.pos).min(buf.len());
+                buf[..n].copy_from_slice(&self.plaintext[self.pos..self.pos + n]);
+                self.pos += n;
+                return Ok(n);
+            }
+
+            let Some(decryptor) = self.decryptor.as_mut() else {
+                return Ok(0);
+            };
+
+            self.plaintext.clear();
+            self.pos = 0;
+
+            let n = match self.inner.read(&mut self.chunk) {
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+
+            if n == 0 {
+                let decryptor = self.decryptor.take().expect("checked above");
+                decryptor.finish(&mut self.plaintext).map_err(to_io_error)?;
+            } else {
+                decryptor
+                    .update(&self.chunk[..n], &mut self.plaintext)
+                    .map_err(to_io_error)?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_writer_reader_roundtrip() {
+        let key = MasterKey::from_bytes([5u8; 32]);
+        let plaintext: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+
+        let mut writer = EncryptWriter::new(&key, Vec::new()).unwrap();
+        for piece in plaintext.chunks(10_000) {
+            writer.write_all(piece).unwrap();
+        }
+        let encrypted = writer.finish().unwrap();
+
+        let mut decrypted = Vec::new();
+        DecryptReader::new(&key, encrypted.as_slice())
+            .read_to_end(&mut decrypted)
+            .unwrap();
+        assert_eq!(decrypted, plaintext);
+    }
+
+    #[test]
+    fn test_reader_reports_truncation() {
+        let key = MasterKey::from_bytes([5u8; 32]);
+        let mut writer = EncryptWriter::new(&key, Vec::new()).unwrap();
+        writer.write_all(&[1u8; 1000]).unwrap();
+        let encrypted = writer.finish().unwrap();
+
+        let mut sink = Vec::new();
+        let err = DecryptReader::new(&key, &encrypted[..encrypted.len() - 1])
+            .read_to_end(&mut sink)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,1275 @@
+//! Segmented (STREAM) encryption for the v2 through v8 file formats.
+//!
+//! The plaintext is split into fixed-size segments, each sealed as its own AES-256-GCM
+//! message. Segment nonces are `nonce_prefix(7) || counter(u32 BE) || last_flag(1)`
+//! (the `StreamBE32` construction from the `aead` crate), so segments cannot be
+//! reordered, dropped, or truncated at a segment boundary without failing authentication.
+//! From v3 on, the complete header is passed as associated data to every segment, so any
+//! change to the header bytes (including the version) fails authentication as well.
+//! From v4 on, the header also names the master key by its fingerprint, so decrypting with
+//! the wrong key fails up front with `WrongKey` rather than as a generic authentication error.
+//! From v5 on, the header records whether the plaintext was compressed before being split
+//! into segments (see [`crate::compress`]); the decryptor decompresses authenticated
+//! segments as they arrive.
+//! From v6 on, an encrypted [`FileMetadata`] block sits between the header and the first
+//! segment. It is sealed with a separate key derived from the file salt, with the header
+//! as associated data, so it can be read without touching the content.
+//! From v7 on, the header records a padding policy (see [`crate::padding`]): zero bytes and
+//! a length trailer are appended after the payload, and stripped again after decryption.
+//! From v8 on, the final segment ends with a record holding the SHA-256 of the plaintext,
+//! computed while encrypting and checked once decryption completes.
+//!
+//! [`Encryptor`] and [`Decryptor`] are I/O-free state machines. The [`io`] adapters wrap
+//! them as `std::io::Write` / `std::io::Read`, and with the `async` feature the same is
+//! available for tokio's `AsyncWrite` / `AsyncRead`.
+
+use aes_gcm::aead::stream::{NewStream, StreamBE32, StreamPrimitive};
+use aes_gcm::aead::{AeadInPlace, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+use crate::compress::{Compression, Compressor, Decompressor};
+use crate::decrypt::decrypt_v1;
+use crate::encrypt::EncryptOptions;
+use crate::hash::hex_encode;
+use crate::header::{header_size, EncryptedHeader, SegmentedHeader, NONCE_PREFIX_SIZE};
+use crate::key_derivation::{derive_file_key, derive_metadata_key, generate_salt, key_fingerprint};
+use crate::keyring::Keyring;
+use crate::metadata::{pad_records, FileMetadata, MAX_METADATA_SIZE};
+use crate::padding::{padding_len, Padding, Unpadder, TRAILER_SIZE};
+use crate::{
+    format_key_id, CryptoError, FormatInfo, MasterKey, FORMAT_VERSION, FORMAT_VERSION_V1,
+    FORMAT_VERSION_V3, FORMAT_VERSION_V4, FORMAT_VERSION_V8, MAGIC_BYTES, V8_HEADER_SIZE,
+};
+
+#[cfg(feature = "async")]
+mod async_io;
+mod io;
+mod pipeline;
+
+#[cfg(feature = "async")]
+pub use async_io::{AsyncDecryptReader, AsyncEncryptWriter};
+pub use io::{DecryptReader, EncryptWriter};
+pub use pipeline::{encrypt_and_fingerprint, SealedFile};
+
+/// Default plaintext bytes per segment (64 KiB).
+pub const DEFAULT_SEGMENT_SIZE: u32 = 64 * 1024;
+
+/// Largest segment size a decryptor will accept from a header (16 MiB).
+///
+/// Bounds the buffer a crafted header can make the decryptor allocate.
+pub const MAX_SEGMENT_SIZE: u32 = 16 * 1024 * 1024;
+
+/// AES-256-GCM authentication tag appended to every segment.
+pub const TAG_SIZE: usize = 16;
+
+/// With padding enabled, the metadata block is padded to a multiple of this many bytes so
+/// that it does not reveal the length of the file name.
+const METADATA_PAD_BLOCK: usize = 256;
+
+/// Zero bytes written per call while padding.
+const ZEROS: [u8; 4096] = [0u8; 4096];
+
+/// Size of the record that ends the segment plaintext from v8 on: `tag(1) || length(u16 LE)
+/// || SHA-256(32)`, in the TLV encoding of the metadata block.
+pub(crate) const DIGEST_RECORD_SIZE: usize = 3 + 32;
+
+/// Tag of the plaintext SHA-256 record.
+const DIGEST_TAG_SHA256: u8 = 1;
+
+/// Total encrypted file size for a plaintext of `plaintext_len` bytes.
+///
+/// Useful for setting `Content-Length` before streaming an upload. Only exact for
+/// uncompressed, unpadded output without a metadata block; add [`metadata_block_size`] for
+/// one. With padding, pass the padded length (see [`padded_payload_len`]). A compressed
+/// file's size is known once it has been written. The SHA-256 record is included.
+pub fn encrypted_size(plaintext_len: u64, segment_size: u32) -> u64 {
+    let sealed = plaintext_len + DIGEST_RECORD_SIZE as u64;
+    // The final segment always holds at least the record, so there is at least one.
+    let segments = sealed.div_ceil(u64::from(segment_size));
+    V8_HEADER_SIZE as u64 + sealed + segments * TAG_SIZE as u64
+}
+
+/// Segment plaintext length for `payload_len` bytes of (compressed) plaintext under
+/// `padding`, padding and trailer included.
+pub fn padded_payload_len(payload_len: u64, padding: Padding) -> u64 {
+    match padding {
+        Padding::None => payload_len,
+        padding => payload_len + padding_len(padding, payload_len) + TRAILER_SIZE as u64,
+    }
+}
+
+/// Encrypted size of an unpadded metadata block, or 0 when there is no metadata.
+pub fn metadata_block_size(metadata: Option<&FileMetadata>) -> Result<u64, CryptoError> {
+    match metadata {
+        Some(metadata) => Ok((metadata.to_bytes()?.len() + TAG_SIZE) as u64),
+        None => Ok(0),
+    }
+}
+
+impl SegmentedHeader {
+    /// Associated data for every segment: the full header from v3 on, nothing for v2.
+    fn associated_data(&self) -> Vec<u8> {
+        if self.version >= FORMAT_VERSION_V3 {
+            self.to_bytes()
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn stream(&self, master_key: &MasterKey) -> Result<StreamBE32<Aes256Gcm>, CryptoError> {
+        let file_key = derive_file_key(master_key, &self.salt)?;
+        let cipher = Aes256Gcm::new_from_slice(file_key.as_bytes())
+            .map_err(|e| CryptoError::KeyDerivationFailed(e.to_string()))?;
+        Ok(StreamBE32::from_aead(
+            cipher,
+            self.nonce_prefix.as_slice().into(),
+        ))
+    }
+
+    /// Cipher for the metadata block. Its key is used for this one message only, so the
+    /// nonce is all zeros.
+    fn metadata_cipher(&self, master_key: &MasterKey) -> Result<Aes256Gcm, CryptoError> {
+        let metadata_key = derive_metadata_key(master_key, &self.salt)?;
+        Aes256Gcm::new_from_slice(metadata_key.as_bytes())
+            .map_err(|e| CryptoError::KeyDerivationFailed(e.to_string()))
+    }
+}
+
+const METADATA_NONCE: [u8; 12] = [0u8; 12];
+
+/// Incremental encryptor producing a file in the current format (`FORMAT_VERSION`).
+///
+/// Feed plaintext with [`update`](Self::update) and complete the file with
+/// [`finish`](Self::finish); output is appended to the caller's buffer. The header is
+/// emitted with the first output. A file whose encryptor was never finished fails to
+/// decrypt, because the final segment carries the STREAM "last" flag.
+///
+/// With compression, plaintext passes through the compressor first and the segments carry
+/// the compressed stream, so output lags behind input by whatever the compressor buffers.
+/// With padding, [`finish`](Self::finish) appends the padding and its trailer, and then
+/// the SHA-256 record of everything passed to [`update`](Self::update).
+///
+/// Segments are independent once their position is known, so with
+/// [`with_parallelism`](Self::with_parallelism) full segments are collected into batches
+/// and sealed together, on the rayon thread pool when the `parallel` feature is enabled.
+pub struct Encryptor {
+    stream: StreamBE32<Aes256Gcm>,
+    compressor: Option<Compressor>,
+    /// Compressor output waiting to be split into segments.
+    compressed: Vec<u8>,
+    padding: Padding,
+    /// Segment plaintext bytes pushed so far, before padding.
+    payload_len: u64,
+    /// SHA-256 of the plaintext so far, sealed in the final segment (v8 on).
+    plaintext_hash: Option<Sha256>,
+    /// Header followed by the sealed metadata block, if any.
+    header: Vec<u8>,
+    header_emitted: bool,
+    aad: Vec<u8>,
+    segment_size: usize,
+    position: u32,
+    buffer: Vec<u8>,
+    /// Full segments waiting to be sealed as one batch, from `position` on.
+    pending: Vec<Vec<u8>>,
+    /// Segment buffers to reuse once a batch has been written out.
+    spare: Vec<Vec<u8>>,
+    batch_segments: usize,
+}
+
+impl Encryptor {
+    /// Create an encryptor with a fresh salt and nonce prefix and the default segment size.
+    pub fn new(master_key: &MasterKey) -> Result<Self, CryptoError> {
+        Self::with_segment_size(master_key, DEFAULT_SEGMENT_SIZE)
+    }
+
+    /// Create an encryptor with an explicit segment size (`1..=MAX_SEGMENT_SIZE`).
+    pub fn with_segment_size(
+        master_key: &MasterKey,
+        segment_size: u32,
+    ) -> Result<Self, CryptoError> {
+        Self::with_version(
+            master_key,
+            segment_size,
+            FORMAT_VERSION,
+            &EncryptOptions::default(),
+        )
+    }
+
+    /// Create an encryptor that compresses the plaintext with `compression` before
+    /// encrypting it. Use [`worth_compressing`](crate::compress::worth_compressing) on a
+    /// sample first to avoid compressing data that will not shrink.
+    pub fn with_compression(
+        master_key: &MasterKey,
+        compression: Compression,
+    ) -> Result<Self, CryptoError> {
+        Self::with_options(
+            master_key,
+            &EncryptOptions {
+                compression,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Create an encryptor with compression, padding and a metadata block as given in `options`.
+    /// Unlike [`encrypt_with_options`](crate::encrypt::encrypt_with_options), the
+    /// compression setting is applied as-is, without sampling the input.
+    pub fn with_options(
+        master_key: &MasterKey,
+        options: &EncryptOptions,
+    ) -> Result<Self, CryptoError> {
+        Self::with_version(master_key, DEFAULT_SEGMENT_SIZE, FORMAT_VERSION, options)
+    }
+
+    /// Create an encryptor for a specific segmented version. Only tests write older versions.
+    fn with_version(
+        master_key: &MasterKey,
+        segment_size: u32,
+        version: u8,
+        options: &EncryptOptions,
+    ) -> Result<Self, CryptoError> {
+        if segment_size == 0 || segment_size > MAX_SEGMENT_SIZE {
+            return Err(CryptoError::EncryptionFailed(format!(
+                "invalid segment size: {segment_size}"
+            )));
+        }
+
+        let mut nonce_prefix = [0u8; NONCE_PREFIX_SIZE];
+        rand::thread_rng().fill_bytes(&mut nonce_prefix);
+
+        let mut metadata = match &options.metadata {
+            Some(metadata) => metadata.to_bytes()?,
+            None => Vec::new(),
+        };
+        if options.padding != Padding::None {
+            pad_records(&mut metadata, METADATA_PAD_BLOCK);
+        }
+        let metadata_len = if options.metadata.is_some() {
+            (metadata.len() + TAG_SIZE) as u32
+        } else {
+            0
+        };
+        if metadata_len > MAX_METADATA_SIZE {
+            return Err(CryptoError::InvalidMetadata(format!(
+                "metadata block is {metadata_len} bytes, limit is {MAX_METADATA_SIZE}"
+            )));
+        }
+
+        let header = SegmentedHeader {
+            version,
+            key_id: (version >= FORMAT_VERSION_V4).then(|| key_fingerprint(master_key)),
+            compression: options.compression,
+            padding: options.padding,
+            salt: generate_salt(),
+            nonce_prefix,
+            segment_size,
+            metadata_len,
+        };
+        let mut header_bytes = header.to_bytes();
+        if metadata_len > 0 {
+            header
+                .metadata_cipher(master_key)?
+                .encrypt_in_place(
+                    Nonce::from_slice(&METADATA_NONCE),
+                    &header_bytes,
+                    &mut metadata,
+                )
+                .map_err(|e| CryptoError::EncryptionFailed(e.to_string()))?;
+            header_bytes.extend_from_slice(&metadata);
+        }
+
+        Ok(Self {
+            stream: header.stream(master_key)?,
+            compressor: Compressor::new(options.compression)?,
+            compressed: Vec::new(),
+            padding: options.padding,
+            payload_len: 0,
+            plaintext_hash: (version >= FORMAT_VERSION_V8).then(Sha256::new),
+            header: header_bytes,
+            header_emitted: false,
+            aad: header.associated_data(),
+            segment_size: segment_size as usize,
+            position: 0,
+            buffer: Vec::with_capacity(segment_size as usize + TAG_SIZE),
+            pending: Vec::new(),
+            spare: Vec::new(),
+            batch_segments: 1,
+        })
+    }
+
+    /// Seal full segments `segments` at a time instead of one by one. Output then lags
+    /// behind input by up to one batch; the file is identical either way.
+    pub fn with_parallelism(mut self, segments: usize) -> Self {
+        self.batch_segments = segments.max(1);
+        self
+    }
+
+    /// Encrypt `plaintext`, appending any completed output to `out`.
+    ///
+    /// A full segment is only sealed once more plaintext arrives, since the encryptor
+    /// cannot know before then whether it is the last one.
+    pub fn update(&mut self, plaintext: &[u8], out: &mut Vec<u8>) -> Result<(), CryptoError> {
+        self.emit_header(out);
+        if let Some(hasher) = &mut self.plaintext_hash {
+            hasher.update(plaintext);
+        }
+
+        match self.compressor.as_mut() {
+            Some(compressor) => {
+                let mut compressed = std::mem::take(&mut self.compressed);
+                compressor.update(plaintext, &mut compressed)?;
+                let result = self.push(&compressed, out);
+                compressed.clear();
+                self.compressed = compressed;
+                result
+            }
+            None => self.push(plaintext, out),
+        }
+    }
+
+    /// Split segment plaintext (compressed, if enabled) into segments.
+    fn push(&mut self, mut plaintext: &[u8], out: &mut Vec<u8>) -> Result<(), CryptoError> {
+        self.payload_len += plaintext.len() as u64;
+        while !plaintext.is_empty() {
+            if self.buffer.len() == self.segment_size {
+                self.complete_segment(out)?;
+            }
+            let take = (self.segment_size - self.buffer.len()).min(plaintext.len());
+            self.buffer.extend_from_slice(&plaintext[..take]);
+            plaintext = &plaintext[take..];
+        }
+
+        Ok(())
+    }
+
+    /// Seal the final segment and append it to `out`.
+    pub fn finish(mut self, out: &mut Vec<u8>) -> Result<(), CryptoError> {
+        self.emit_header(out);
+        if let Some(compressor) = self.compressor.take() {
+            let mut compressed = std::mem::take(&mut self.compressed);
+            compressor.finish(&mut compressed)?;
+            self.push(&compressed, out)?;
+        }
+        if self.padding != Padding::None {
+            let padding = padding_len(self.padding, self.payload_len);
+            let mut remaining = padding;
+            while remaining > 0 {
+                let take = remaining.min(ZEROS.len() as u64);
+                self.push(&ZEROS[..take as usize], out)?;
+                remaining -= take;
+            }
+            self.push(&padding.to_le_bytes(), out)?;
+        }
+        if let Some(hasher) = self.plaintext_hash.take() {
+            self.push(&digest_record(&hasher.finalize().into()), out)?;
+        }
+        self.seal_pending(out)?;
+        self.seal_segment(true, out)
+    }
+
+    /// The current segment is full and not the last one: seal it now, or queue it for the
+    /// next batch.
+    fn complete_segment(&mut self, out: &mut Vec<u8>) -> Result<(), CryptoError> {
+        if self.batch_segments == 1 {
+            return self.seal_segment(false, out);
+        }
+        let next = self
+            .spare
+            .pop()
+            .unwrap_or_else(|| Vec::with_capacity(self.segment_size + TAG_SIZE));
+        self.pending.push(std::mem::replace(&mut self.buffer, next));
+        if self.pending.len() == self.batch_segments {
+            self.seal_pending(out)?;
+        }
+        Ok(())
+    }
+
+    /// Seal the queued segments and append them to `out` in order.
+    fn seal_pending(&mut self, out: &mut Vec<u8>) -> Result<(), CryptoError> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+        let first = self.position;
+        let next_position = u32::try_from(self.pending.len())
+            .ok()
+            .and_then(|count| first.checked_add(count))
+            .ok_or_else(|| CryptoError::EncryptionFailed("too many segments".into()))?;
+
+        let (stream, aad) = (&self.stream, self.aad.as_slice());
+        let seal = |(i, segment): (usize, &mut Vec<u8>)| {
+            stream
+                .encrypt_in_place(first + i as u32, false, aad, segment)
+                .map_err(|e| CryptoError::EncryptionFailed(e.to_string()))
+        };
+        #[cfg(feature = "parallel")]
+        {
+            use rayon::prelude::*;
+            self.pending.par_iter_mut().enumerate().try_for_each(seal)?;
+        }
+        #[cfg(not(feature = "parallel"))]
+        self.pending.iter_mut().enumerate().try_for_each(seal)?;
+
+        for mut segment in self.pending.drain(..) {
+            out.extend_from_slice(&segment);
+            segment.clear();
+            self.spare.push(segment);
+        }
+        self.position = next_position;
+        Ok(())
+    }
+
+    fn emit_header(&mut self, out: &mut Vec<u8>) {
+        if !self.header_emitted {
+            out.extend_from_slice(&self.header);
+            self.header_emitted = true;
+        }
+    }
+
+    fn seal_segment(&mut self, last: bool, out: &mut Vec<u8>) -> Result<(), CryptoError> {
+        self.stream
+            .encrypt_in_place(self.position, last, &self.aad, &mut self.buffer)
+            .map_err(|e| CryptoError::EncryptionFailed(e.to_string()))?;
+        out.extend_from_slice(&self.buffer);
+        self.buffer.clear();
+
+        self.position = self
+            .position
+            .checked_add(1)
+            .ok_or_else(|| CryptoError::EncryptionFailed("too many segments".into()))?;
+        Ok(())
+    }
+}
+
+/// Incremental decryptor accepting any supported format version.
+///
+/// Segmented (v2 through v8) files are decrypted one segment at a time; plaintext for a segment is
+/// only released after its tag verifies. Legacy v1 files are a single AES-GCM message, so
+/// they are buffered and decrypted in [`finish`](Self::finish).
+///
+/// With a [`Keyring`], v4 files select their key by the key ID in the header; files without
+/// a key ID are tried against every key in the keyring.
+pub struct Decryptor {
+    keyring: Keyring,
+    state: DecryptorState,
+}
+
+enum DecryptorState {
+    Header(Vec<u8>),
+    Segmented(Box<SegmentDecryptor>),
+    Legacy(Vec<u8>),
+}
+
+impl Decryptor {
+    pub fn new(master_key: &MasterKey) -> Self {
+        Self::with_keyring(Keyring::from_key(master_key))
+    }
+
+    pub fn with_keyring(keyring: Keyring) -> Self {
+        Self {
+            keyring,
+            state: DecryptorState::Header(Vec::with_capacity(V8_HEADER_SIZE)),
+        }
+    }
+
+    /// Format of the input, available once its header has been read.
+    pub fn format(&self) -> Option<FormatInfo> {
+        match &self.state {
+            DecryptorState::Header(_) => None,
+            DecryptorState::Segmented(segments) => Some(FormatInfo {
+                version: segments.version,
+                key_id: segments.key_id,
+                compression: segments.compression,
+                padding: segments.padding,
+            }),
+            DecryptorState::Legacy(_) => Some(FormatInfo {
+                version: FORMAT_VERSION_V1,
+                key_id: None,
+                compression: Compression::None,
+                padding: Padding::None,
+            }),
+        }
+    }
+
+    /// The file's metadata block, available once it has been read and authenticated.
+    /// `None` for files without one (all files before v6).
+    pub fn metadata(&self) -> Option<&FileMetadata> {
+        match &self.state {
+            DecryptorState::Segmented(segments) => segments.metadata.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Input offset where the metadata block ends, once the header has been read and
+    /// the file has a metadata block.
+    pub(crate) fn metadata_end(&self) -> Option<u64> {
+        match &self.state {
+            DecryptorState::Segmented(segments) if segments.metadata_len > 0 => {
+                Some(segments.header_len + u64::from(segments.metadata_len))
+            }
+            _ => None,
+        }
+    }
+
+    /// Decrypt `data`, appending any authenticated plaintext to `out`.
+    pub fn update(&mut self, mut data: &[u8], out: &mut Vec<u8>) -> Result<(), CryptoError> {
+        // Read magic + version first, then the rest of the header once the version is known.
+        while let DecryptorState::Header(buffer) = &mut self.state {
+            let want = header_bytes_wanted(buffer);
+            let take = (want - buffer.len()).min(data.len());
+            buffer.extend_from_slice(&data[..take]);
+            data = &data[take..];
+
+            if buffer.len() < want {
+                return Ok(());
+            }
+            if &buffer[..MAGIC_BYTES.len()] != MAGIC_BYTES.as_slice() {
+                return Err(CryptoError::InvalidHeader("invalid magic bytes".into()));
+            }
+
+            match buffer[MAGIC_BYTES.len()] {
+                FORMAT_VERSION_V1 => {
+                    self.state = DecryptorState::Legacy(std::mem::take(buffer));
+                }
+                version if header_size(version).is_some_and(|size| buffer.len() < size) => continue,
+                _ => {
+                    let EncryptedHeader::Segmented(header) = EncryptedHeader::parse(buffer)? else {
+                        unreachable!("v1 is handled above");
+                    };
+                    let candidates: Vec<&MasterKey> = match &header.key_id {
+                        Some(key_id) => {
+                            vec![self
+                                .keyring
+                                .get(key_id)
+                                .ok_or_else(|| CryptoError::WrongKey {
+                                    key_id: format_key_id(key_id),
+                                })?]
+                        }
+                        None => self.keyring.keys().collect(),
+                    };
+                    self.state = DecryptorState::Segmented(Box::new(SegmentDecryptor::new(
+                        &candidates,
+                        &header,
+                    )?));
+                }
+            }
+        }
+
+        match &mut self.state {
+            DecryptorState::Header(_) => unreachable!("header state resolved above"),
+            DecryptorState::Segmented(segments) => {
+                let start = out.len();
+                segments.update(data, out)?;
+                segments.hash_plaintext(&out[start..]);
+                Ok(())
+            }
+            DecryptorState::Legacy(buffer) => {
+                buffer.extend_from_slice(data);
+                Ok(())
+            }
+        }
+    }
+
+    /// Verify the final segment and append its plaintext to `out`.
+    ///
+    /// Fails if the input ended early, including exactly at a segment boundary, or with
+    /// [`CryptoError::HashMismatch`] if the plaintext does not match the SHA-256 record
+    /// sealed at the end of a v8 file.
+    pub fn finish(self, out: &mut Vec<u8>) -> Result<(), CryptoError> {
+        match self.state {
+            DecryptorState::Header(buffer) => Err(CryptoError::Truncated {
+                expected: header_bytes_wanted(&buffer) as u64,
+                actual: buffer.len() as u64,
+            }),
+            DecryptorState::Segmented(segments) => segments.finish(out),
+            DecryptorState::Legacy(buffer) => {
+                // v1 has no key ID: try each key, reporting the primary key's error if all fail.
+                let mut first_err = None;
+                for key in self.keyring.keys() {
+                    match decrypt_v1(key, &buffer) {
+                        Ok(plaintext) => {
+                            out.extend_from_slice(&plaintext);
+                            return Ok(());
+                        }
+                        Err(e) => {
+                            first_err.get_or_insert(e);
+                        }
+                    }
+                }
+                Err(first_err
+                    .unwrap_or_else(|| CryptoError::DecryptionFailed("keyring is empty".into())))
+            }
+        }
+    }
+}
+
+/// Header bytes needed so far: magic + version until the version is known, then the full
+/// header of that version.
+fn header_bytes_wanted(buffer: &[u8]) -> usize {
+    buffer
+        .get(MAGIC_BYTES.len())
+        .and_then(|&version| header_size(version))
+        .unwrap_or(MAGIC_BYTES.len() + 1)
+}
+
+struct SegmentDecryptor {
+    version: u8,
+    key_id: Option<[u8; 8]>,
+    compression: Compression,
+    padding: Padding,
+    /// Strips padding before the decompressor sees the payload.
+    unpadder: Option<Unpadder>,
+    /// Unpadded plaintext on its way to the decompressor.
+    unpadded: Vec<u8>,
+    /// Holds back the SHA-256 record at the end of the segment plaintext (v8 on).
+    digest: Option<DigestSplitter>,
+    /// Segment plaintext without the record, on its way to the unpadder.
+    payload: Vec<u8>,
+    decompressor: Option<Decompressor>,
+    metadata_len: u32,
+    /// Cipher for the metadata block while it is still being read.
+    metadata_cipher: Option<Aes256Gcm>,
+    metadata: Option<FileMetadata>,
+    /// SHA-256 of the plaintext released so far, to check against the record (v8 on).
+    plaintext_hash: Option<Sha256>,
+    /// One stream per candidate key. Headers without a key ID start with every keyring key;
+    /// the first segment that authenticates settles on one.
+    streams: Vec<StreamBE32<Aes256Gcm>>,
+    aad: Vec<u8>,
+    header_len: u64,
+    /// Ciphertext bytes per full segment, including the tag.
+    segment_len: usize,
+    position: u32,
+    buffer: Vec<u8>,
+}
+
+impl SegmentDecryptor {
+    fn new(candidates: &[&MasterKey], header: &SegmentedHeader) -> Result<Self, CryptoError> {
+        let segment_len = header.segment_size as usize + TAG_SIZE;
+        // Only v6 headers declare a metadata block, and those always carry a key ID, so
+        // there is exactly one candidate.
+        let metadata_cipher = match candidates {
+            [key] if header.metadata_len > 0 => Some(header.metadata_cipher(key)?),
+            _ => None,
+        };
+        Ok(Self {
+            version: header.version,
+            key_id: header.key_id,
+            compression: header.compression,
+            padding: header.padding,
+            unpadder: (header.padding != Padding::None).then(Unpadder::default),
+            unpadded: Vec::new(),
+            digest: (header.version >= FORMAT_VERSION_V8).then(DigestSplitter::default),
+            payload: Vec::new(),
+            decompressor: Decompressor::new(header.compression)?,
+            metadata_len: header.metadata_len,
+            metadata_cipher,
+            metadata: None,
+            plaintext_hash: (header.version >= FORMAT_VERSION_V8).then(Sha256::new),
+            streams: candidates
+                .iter()
+                .map(|key| header.stream(key))
+                .collect::<Result<_, _>>()?,
+            aad: header.associated_data(),
+            header_len: header_size(header.version).expect("parsed header has a size") as u64,
+            segment_len,
+            position: 0,
+            buffer: Vec::with_capacity(segment_len),
+        })
+    }
+
+    fn metadata_pending(&self) -> bool {
+        self.metadata_cipher.is_some()
+    }
+
+    fn update(&mut self, mut data: &[u8], out: &mut Vec<u8>) -> Result<(), CryptoError> {
+        if self.metadata_pending() {
+            let take = (self.metadata_len as usize - self.buffer.len()).min(data.len());
+            self.buffer.extend_from_slice(&data[..take]);
+            data = &data[take..];
+            if self.buffer.len() < self.metadata_len as usize {
+                return Ok(());
+            }
+            self.open_metadata()?;
+        }
+
+        while !data.is_empty() {
+            if self.buffer.len() == self.segment_len {
+                self.open_segment(false, out)?;
+            }
+            let take = (self.segment_len - self.buffer.len()).min(data.len());
+            self.buffer.extend_from_slice(&data[..take]);
+            data = &data[take..];
+        }
+        Ok(())
+    }
+
+    fn open_metadata(&mut self) -> Result<(), CryptoError> {
+        let cipher = self.metadata_cipher.take().expect("metadata is pending");
+        cipher
+            .decrypt_in_place(
+                Nonce::from_slice(&METADATA_NONCE),
+                &self.aad,
+                &mut self.buffer,
+            )
+            .map_err(|_| CryptoError::AuthenticationFailed("metadata block".into()))?;
+        self.metadata = Some(FileMetadata::from_bytes(&self.buffer)?);
+        self.buffer.clear();
+        Ok(())
+    }
+
+    fn hash_plaintext(&mut self, plaintext: &[u8]) {
+        if let Some(hasher) = &mut self.plaintext_hash {
+            hasher.update(plaintext);
+        }
+    }
+
+    /// Input bytes consumed so far, header and metadata block included.
+    fn bytes_read(&self) -> u64 {
+        if self.metadata_pending() {
+            return self.header_len + self.buffer.len() as u64;
+        }
+        self.header_len
+            + u64::from(self.metadata_len)
+            + u64::from(self.position) * self.segment_len as u64
+            + self.buffer.len() as u64
+    }
+
+    fn finish(mut self, out: &mut Vec<u8>) -> Result<(), CryptoError> {
+        if self.metadata_pending() {
+            return Err(CryptoError::Truncated {
+                expected: self.header_len + u64::from(self.metadata_len) + TAG_SIZE as u64,
+                actual: self.bytes_read(),
+            });
+        }
+        if self.buffer.len() < TAG_SIZE {
+            return Err(CryptoError::Truncated {
+                expected: self.bytes_read() - self.buffer.len() as u64 + TAG_SIZE as u64,
+                actual: self.bytes_read(),
+            });
+        }
+        let start = out.len();
+        self.open_segment(true, out)?;
+        let recorded = self.digest.take().map(DigestSplitter::finish).transpose()?;
+        if let Some(unpadder) = self.unpadder.take() {
+            let mut unpadded = std::mem::take(&mut self.unpadded);
+            unpadder.finish(&mut unpadded)?;
+            self.decompress(&unpadded, out)?;
+        }
+        if let Some(decompressor) = self.decompressor.take() {
+            decompressor.finish(out)?;
+        }
+        self.hash_plaintext(&out[start..]);
+        if let (Some(hasher), Some(expected)) = (self.plaintext_hash.take(), recorded) {
+            let actual: [u8; 32] = hasher.finalize().into();
+            if actual != expected {
+                return Err(CryptoError::HashMismatch {
+                    expected: format!("sha256:{}", hex_encode(&expected)),
+                    actual: format!("sha256:{}", hex_encode(&actual)),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn unpad(&mut self, data: &[u8], out: &mut Vec<u8>) -> Result<(), CryptoError> {
+        match self.unpadder.as_mut() {
+            Some(unpadder) => {
+                let mut unpadded = std::mem::take(&mut self.unpadded);
+                unpadder.update(data, &mut unpadded);
+                self.decompress(&unpadded, out)?;
+                unpadded.clear();
+                self.unpadded = unpadded;
+                Ok(())
+            }
+            None => self.decompress(data, out),
+        }
+    }
+
+    fn decompress(&mut self, data: &[u8], out: &mut Vec<u8>) -> Result<(), CryptoError> {
+        match self.decompressor.as_mut() {
+            Some(decompressor) => decompressor.update(data, out),
+            None => {
+                out.extend_from_slice(data);
+                Ok(())
+            }
+        }
+    }
+
+    fn open_segment(&mut self, last: bool, out: &mut Vec<u8>) -> Result<(), CryptoError> {
+        let opened = if let [stream] = self.streams.as_slice() {
+            stream
+                .decrypt_in_place(self.position, last, &self.aad, &mut self.buffer)
+                .is_ok()
+        } else {
+            self.open_with_any_candidate(last)
+        };
+        if !opened {
+            // A full segment that fails as the last one but opens as a middle one means the
+            // input was cut exactly at a segment boundary; at least one segment is missing.
+            if last && self.buffer.len() == self.segment_len && self.opens_as_middle_segment() {
+                return Err(CryptoError::Truncated {
+                    expected: self.bytes_read() + TAG_SIZE as u64,
+                    actual: self.bytes_read(),
+                });
+            }
+            return Err(CryptoError::AuthenticationFailed(format!(
+                "segment {}",
+                self.position
+            )));
+        }
+        let segment = std::mem::take(&mut self.buffer);
+        match self.digest.as_mut() {
+            Some(digest) => {
+                let mut payload = std::mem::take(&mut self.payload);
+                digest.update(&segment, &mut payload);
+                self.unpad(&payload, out)?;
+                payload.clear();
+                self.payload = payload;
+            }
+            None => self.unpad(&segment, out)?,
+        }
+        self.buffer = segment;
+        self.buffer.clear();
+
+        self.position = self
+            .position
+            .checked_add(1)
+            .ok_or_else(|| CryptoError::DecryptionFailed("too many segments".into()))?;
+        Ok(())
+    }
+
+    fn opens_as_middle_segment(&self) -> bool {
+        self.streams.iter().any(|stream| {
+            let mut trial = self.buffer.clone();
+            stream
+                .decrypt_in_place(self.position, false, &self.aad, &mut trial)
+                .is_ok()
+        })
+    }
+
+    /// Try each candidate key on the current segment and keep the one that authenticates.
+    fn open_with_any_candidate(&mut self, last: bool) -> bool {
+        for (i, stream) in self.streams.iter().enumerate() {
+            let mut trial = self.buffer.clone();
+            if stream
+                .decrypt_in_place(self.position, last, &self.aad, &mut trial)
+                .is_ok()
+            {
+                self.buffer = trial;
+                let stream = self.streams.swap_remove(i);
+                self.streams = vec![stream];
+                return true;
+            }
+        }
+        false
+    }
+}
+
+fn digest_record(sha256: &[u8; 32]) -> [u8; DIGEST_RECORD_SIZE] {
+    let mut record = [0u8; DIGEST_RECORD_SIZE];
+    record[0] = DIGEST_TAG_SHA256;
+    record[1..3].copy_from_slice(&32u16.to_le_bytes());
+    record[3..].copy_from_slice(sha256);
+    record
+}
+
+/// Holds back the last [`DIGEST_RECORD_SIZE`] bytes of authenticated segment plaintext,
+/// which are the SHA-256 record rather than payload.
+#[derive(Default)]
+struct DigestSplitter {
+    tail: Vec<u8>,
+}
+
+impl DigestSplitter {
+    fn update(&mut self, data: &[u8], out: &mut Vec<u8>) {
+        if data.len() >= DIGEST_RECORD_SIZE {
+            let (payload, tail) = data.split_at(data.len() - DIGEST_RECORD_SIZE);
+            out.extend_from_slice(&self.tail);
+            out.extend_from_slice(payload);
+            self.tail.clear();
+            self.tail.extend_from_slice(tail);
+        } else {
+            self.tail.extend_from_slice(data);
+            let release = self.tail.len().saturating_sub(DIGEST_RECORD_SIZE);
+            out.extend(self.tail.drain(..release));
+        }
+    }
+
+    /// The SHA-256 from the record, once the final segment has been opened.
+    fn finish(self) -> Result<[u8; 32], CryptoError> {
+        match self.tail.as_slice() {
+            [DIGEST_TAG_SHA256, 32, 0, sha256 @ ..] if sha256.len() == 32 => {
+                Ok(sha256.try_into().expect("length checked"))
+            }
+            _ => Err(CryptoError::DecryptionFailed(
+                "final segment does not end with a SHA-256 record".into(),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        FORMAT_VERSION_V2, FORMAT_VERSION_V5, FORMAT_VERSION_V6, FORMAT_VERSION_V7, V4_HEADER_SIZE,
+        V6_HEADER_SIZE, V7_HEADER_SIZE,
+    };
+
+    fn encrypt_chunked(key: &MasterKey, plaintext: &[u8], segment: u32, chunk: usize) -> Vec<u8> {
+        let mut encryptor = Encryptor::with_segment_size(key, segment).unwrap();
+        let mut out = Vec::new();
+        for piece in plaintext.chunks(chunk) {
+            encryptor.update(piece, &mut out).unwrap();
+        }
+        encryptor.finish(&mut out).unwrap();
+        out
+    }
+
+    fn decrypt_chunked(key: &MasterKey, data: &[u8], chunk: usize) -> Result<Vec<u8>, CryptoError> {
+        let mut decryptor = Decryptor::new(key);
+        let mut out = Vec::new();
+        for piece in data.chunks(chunk) {
+            decryptor.update(piece, &mut out)?;
+        }
+        decryptor.finish(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn test_roundtrip_across_segment_boundaries() {
+        let key = MasterKey::from_bytes([3u8; 32]);
+        for len in [0usize, 1, 63, 64, 65, 128, 1000] {
+            let plaintext: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            let encrypted = encrypt_chunked(&key, &plaintext, 64, 7);
+            assert_eq!(encrypted.len() as u64, encrypted_size(len as u64, 64));
+            assert_eq!(decrypt_chunked(&key, &encrypted, 5).unwrap(), plaintext);
+        }
+    }
+
+    /// Offset of the segment size field in a current-format header.
+    const SEGMENT_SIZE_OFFSET: usize = 42;
+
+    #[test]
+    fn test_truncation_at_segment_boundary_fails() {
+        let key = MasterKey::from_bytes([3u8; 32]);
+        let encrypted = encrypt_chunked(&key, &[1u8; 256], 64, 256);
+        // Drop the final segment entirely: what remains ends on a full, non-last segment.
+        let cut = V8_HEADER_SIZE + 3 * (64 + TAG_SIZE);
+        assert!(matches!(
+            decrypt_chunked(&key, &encrypted[..cut], 1024),
+            Err(CryptoError::Truncated { expected, actual })
+                if actual == cut as u64 && expected == (cut + TAG_SIZE) as u64
+        ));
+    }
+
+    #[test]
+    fn test_truncation_errors_report_lengths() {
+        let key = MasterKey::from_bytes([3u8; 32]);
+        let encrypted = encrypt_chunked(&key, &[1u8; 100], 64, 100);
+        // Inside the header, before and after the version byte is known.
+        for cut in [4, 20] {
+            let expected = if cut < 9 { 9 } else { V8_HEADER_SIZE };
+            assert!(matches!(
+                decrypt_chunked(&key, &encrypted[..cut], 1024),
+                Err(CryptoError::Truncated { expected: e, actual: a })
+                    if e == expected as u64 && a == cut as u64
+            ));
+        }
+        // A final segment shorter than a tag.
+        let cut = V8_HEADER_SIZE + (64 + TAG_SIZE) + 3;
+        assert!(matches!(
+            decrypt_chunked(&key, &encrypted[..cut], 1024),
+            Err(CryptoError::Truncated { .. })
+        ));
+
+        let mut future = encrypted.clone();
+        future[8] = 9;
+        assert!(matches!(
+            decrypt_chunked(&key, &future, 1024),
+            Err(CryptoError::UnsupportedVersion(9))
+        ));
+    }
+
+    #[test]
+    fn test_reordered_segments_fail() {
+        let key = MasterKey::from_bytes([3u8; 32]);
+        let mut encrypted = encrypt_chunked(&key, &[9u8; 256], 64, 256);
+        let seg = 64 + TAG_SIZE;
+        let (a, b) = (V8_HEADER_SIZE, V8_HEADER_SIZE + seg);
+        let first: Vec<u8> = encrypted[a..a + seg].to_vec();
+        encrypted.copy_within(b..b + seg, a);
+        encrypted[b..b + seg].copy_from_slice(&first);
+        assert!(decrypt_chunked(&key, &encrypted, 1024).is_err());
+    }
+
+    fn encrypt_version(key: &MasterKey, plaintext: &[u8], segment: u32, version: u8) -> Vec<u8> {
+        let mut encryptor =
+            Encryptor::with_version(key, segment, version, &EncryptOptions::default()).unwrap();
+        let mut out = Vec::new();
+        encryptor.update(plaintext, &mut out).unwrap();
+        encryptor.finish(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_header_tampering_detected() {
+        let key = MasterKey::from_bytes([3u8; 32]);
+        // Shrinking the declared segment size of a single-segment file is invisible to v2
+        // (the lone segment is still read as the last one) but breaks the v3+ AAD.
+        let mut v2 = encrypt_version(&key, b"short", 64, FORMAT_VERSION_V2);
+        v2[32..36].copy_from_slice(&32u32.to_le_bytes());
+        assert_eq!(decrypt_chunked(&key, &v2, 1024).unwrap(), b"short");
+
+        let mut v3 = encrypt_version(&key, b"short", 64, FORMAT_VERSION_V3);
+        v3[32..36].copy_from_slice(&32u32.to_le_bytes());
+        assert!(decrypt_chunked(&key, &v3, 1024).is_err());
+
+        let mut current = encrypt_chunked(&key, b"short", 64, 5);
+        assert_eq!(current[8], FORMAT_VERSION_V8);
+        current[SEGMENT_SIZE_OFFSET..SEGMENT_SIZE_OFFSET + 4].copy_from_slice(&32u32.to_le_bytes());
+        assert!(decrypt_chunked(&key, &current, 1024).is_err());
+    }
+
+    #[test]
+    fn test_version_downgrade_detected() {
+        let key = MasterKey::from_bytes([3u8; 32]);
+        for older in [
+            FORMAT_VERSION_V2,
+            FORMAT_VERSION_V3,
+            FORMAT_VERSION_V4,
+            FORMAT_VERSION_V5,
+            FORMAT_VERSION_V6,
+            FORMAT_VERSION_V7,
+        ] {
+            let mut encrypted = encrypt_chunked(&key, b"payload", 64, 7);
+            encrypted[8] = older;
+            assert!(decrypt_chunked(&key, &encrypted, 1024).is_err());
+        }
+    }
+
+    #[test]
+    fn test_format_reports_legacy_header() {
+        let key = MasterKey::from_bytes([3u8; 32]);
+        let mut decryptor = Decryptor::new(&key);
+        let mut out = Vec::new();
+        decryptor
+            .update(
+                &encrypt_version(&key, b"old", 64, FORMAT_VERSION_V2),
+                &mut out,
+            )
+            .unwrap();
+        let format = decryptor.format().unwrap();
+        assert_eq!(format.version, FORMAT_VERSION_V2);
+        assert!(!format.header_authenticated());
+        assert_eq!(format.key_id, None);
+
+        let mut decryptor = Decryptor::new(&key);
+        decryptor
+            .update(&encrypt_chunked(&key, b"new", 64, 3), &mut out)
+            .unwrap();
+        let format = decryptor.format().unwrap();
+        assert!(format.header_authenticated());
+        assert_eq!(format.key_id, Some(key_fingerprint(&key)));
+    }
+
+    #[test]
+    fn test_wrong_key_reported_from_key_id() {
+        let encrypted = encrypt_chunked(&MasterKey::from_bytes([3u8; 32]), b"data", 64, 4);
+        let err = decrypt_chunked(&MasterKey::from_bytes([4u8; 32]), &encrypted, 1024).unwrap_err();
+        assert!(matches!(err, CryptoError::WrongKey { .. }), "{err}");
+
+        // Corruption with the right key stays an authentication failure.
+        let mut corrupted = encrypted.clone();
+        *corrupted.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            decrypt_chunked(&MasterKey::from_bytes([3u8; 32]), &corrupted, 1024),
+            Err(CryptoError::AuthenticationFailed(_))
+        ));
+    }
+
+    #[test]
+    fn test_keyring_picks_key_per_file() {
+        let (old, new) = (
+            MasterKey::from_bytes([1u8; 32]),
+            MasterKey::from_bytes([2u8; 32]),
+        );
+        let mut keyring = Keyring::from_key(&new);
+        keyring.add(&old);
+
+        let decrypt = |data: &[u8]| {
+            let mut decryptor = Decryptor::with_keyring(keyring.clone());
+            let mut out = Vec::new();
+            decryptor.update(data, &mut out)?;
+            decryptor.finish(&mut out)?;
+            Ok::<_, CryptoError>(out)
+        };
+
+        // Key ID in the header selects the key directly.
+        let current = encrypt_chunked(&old, &[5u8; 200], 64, 200);
+        assert_eq!(decrypt(&current).unwrap(), [5u8; 200]);
+        // Without a key ID, the non-primary key is found by trial.
+        let legacy = encrypt_version(&old, &[6u8; 200], 64, FORMAT_VERSION_V3);
+        assert_eq!(decrypt(&legacy).unwrap(), [6u8; 200]);
+    }
+
+    #[test]
+    fn test_compressed_roundtrip_across_segments() {
+        let key = MasterKey::from_bytes([3u8; 32]);
+        let plaintext = b"stroke 0.25 0.75 pressure 0.5\n".repeat(5_000);
+        let mut encryptor = Encryptor::with_version(
+            &key,
+            64,
+            FORMAT_VERSION,
+            &EncryptOptions {
+                compression: Compression::Zstd,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let mut encrypted = Vec::new();
+        for piece in plaintext.chunks(1000) {
+            encryptor.update(piece, &mut encrypted).unwrap();
+        }
+        encryptor.finish(&mut encrypted).unwrap();
+        assert!(encrypted.len() < plaintext.len() / 10);
+
+        let mut decryptor = Decryptor::new(&key);
+        let mut out = Vec::new();
+        for piece in encrypted.chunks(33) {
+            decryptor.update(piece, &mut out).unwrap();
+        }
+        assert_eq!(decryptor.format().unwrap().compression, Compression::Zstd);
+        decryptor.finish(&mut out).unwrap();
+        assert_eq!(out, plaintext);
+
+        // Clearing the compression byte (after magic, version and key ID) breaks the AAD.
+        let mut tampered = encrypted.clone();
+        tampered[17] = Compression::None.id();
+        assert!(decrypt_chunked(&key, &tampered, 1024).is_err());
+    }
+
+    #[test]
+    fn test_decrypts_v4_without_compression_field() {
+        let key = MasterKey::from_bytes([3u8; 32]);
+        let v4 = encrypt_version(&key, &[8u8; 150], 64, FORMAT_VERSION_V4);
+        assert_eq!(v4.len(), V4_HEADER_SIZE + 150 + 3 * TAG_SIZE);
+        assert_eq!(decrypt_chunked(&key, &v4, 10).unwrap(), [8u8; 150]);
+    }
+
+    #[test]
+    fn test_batched_sealing_matches_layout() {
+        let key = MasterKey::from_bytes([3u8; 32]);
+        let plaintext: Vec<u8> = (0..1000).map(|i| i as u8).collect();
+        for batch in [2, 3, 16] {
+            let mut encryptor = Encryptor::with_segment_size(&key, 64)
+                .unwrap()
+                .with_parallelism(batch);
+            let mut encrypted = Vec::new();
+            for piece in plaintext.chunks(100) {
+                encryptor.update(piece, &mut encrypted).unwrap();
+            }
+            encryptor.finish(&mut encrypted).unwrap();
+            assert_eq!(encrypted.len() as u64, encrypted_size(1000, 64));
+            assert_eq!(decrypt_chunked(&key, &encrypted, 77).unwrap(), plaintext);
+        }
+    }
+
+    #[test]
+    fn test_padded_roundtrip_hides_length() {
+        let key = MasterKey::from_bytes([3u8; 32]);
+        let options = EncryptOptions {
+            padding: Padding::PowerOfTwo,
+            ..Default::default()
+        };
+        let encrypt = |plaintext: &[u8]| {
+            let mut encryptor =
+                Encryptor::with_version(&key, 64, FORMAT_VERSION, &options).unwrap();
+            let mut out = Vec::new();
+            for piece in plaintext.chunks(13) {
+                encryptor.update(piece, &mut out).unwrap();
+            }
+            encryptor.finish(&mut out).unwrap();
+            out
+        };
+
+        // Trailing zeros of the plaintext must survive the padding being stripped.
+        let a = [vec![7u8; 300], vec![0u8; 40]].concat();
+        let b = vec![9u8; 400];
+        let (encrypted_a, encrypted_b) = (encrypt(&a), encrypt(&b));
+        assert_eq!(encrypted_a.len(), encrypted_b.len());
+        assert_eq!(
+            encrypted_a.len() as u64,
+            encrypted_size(padded_payload_len(340, Padding::PowerOfTwo), 64)
+        );
+        assert_eq!(decrypt_chunked(&key, &encrypted_a, 17).unwrap(), a);
+        assert_eq!(decrypt_chunked(&key, &encrypted_b, 1024).unwrap(), b);
+        assert_eq!(decrypt_chunked(&key, &encrypt(b""), 5).unwrap(), b"");
+
+        // The padding byte follows the compression byte and is covered by the AAD.
+        let mut tampered = encrypted_a.clone();
+        tampered[18] = Padding::None.id();
+        assert!(decrypt_chunked(&key, &tampered, 1024).is_err());
+    }
+
+    #[test]
+    fn test_plaintext_sha256_record_checked() {
+        let key = MasterKey::from_bytes([3u8; 32]);
+        let plaintext = b"layer 3 opacity 0.8\n".repeat(40);
+        let options = EncryptOptions {
+            compression: Compression::Zstd,
+            padding: Padding::Padme,
+            ..Default::default()
+        };
+        let encrypt = |extra: &[u8]| {
+            let mut encryptor =
+                Encryptor::with_version(&key, 64, FORMAT_VERSION, &options).unwrap();
+            // Bytes hashed but never encrypted stand in for a record that does not match.
+            encryptor.plaintext_hash.as_mut().unwrap().update(extra);
+            let mut out = Vec::new();
+            encryptor.update(&plaintext, &mut out).unwrap();
+            encryptor.finish(&mut out).unwrap();
+            out
+        };
+
+        for chunk in [1, 7, 1024] {
+            assert_eq!(
+                decrypt_chunked(&key, &encrypt(b""), chunk).unwrap(),
+                plaintext
+            );
+        }
+        assert!(matches!(
+            decrypt_chunked(&key, &encrypt(b"x"), 1024),
+            Err(CryptoError::HashMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_decrypts_v7_without_sha256_record() {
+        let key = MasterKey::from_bytes([3u8; 32]);
+        let v7 = encrypt_version(&key, &[8u8; 150], 64, FORMAT_VERSION_V7);
+        assert_eq!(v7.len(), V7_HEADER_SIZE + 150 + 3 * TAG_SIZE);
+        assert_eq!(decrypt_chunked(&key, &v7, 10).unwrap(), [8u8; 150]);
+    }
+
+    #[test]
+    fn test_decrypts_v6_without_padding_field() {
+        let key = MasterKey::from_bytes([3u8; 32]);
+        let v6 = encrypt_version(&key, &[8u8; 150], 64, FORMAT_VERSION_V6);
+        assert_eq!(v6.len(), V6_HEADER_SIZE + 150 + 3 * TAG_SIZE);
+        assert_eq!(decrypt_chunked(&key, &v6, 10).unwrap(), [8u8; 150]);
+    }
+
+    #[test]
+    fn test_oversized_segment_header_rejected() {
+        let mut encrypted = encrypt_chunked(&MasterKey::from_bytes([3u8; 32]), b"data", 64, 4);
+        encrypted[SEGMENT_SIZE_OFFSET..SEGMENT_SIZE_OFFSET + 4]
+            .copy_from_slice(&(MAX_SEGMENT_SIZE + 1).to_le_bytes());
+        assert!(matches!(
+            decrypt_chunked(&MasterKey::from_bytes([3u8; 32]), &encrypted, 1024),
+            Err(CryptoError::InvalidHeader(_))
+        ));
+    }
+}
@@ -0,0 +1,198 @@
+//! Single-pass fingerprinting and encryption of a file for upload.
+
+use std::io::{self, Read, Write};
+
+use super::io::to_io_error;
+use super::{Encryptor, DEFAULT_SEGMENT_SIZE};
+use crate::compress::{worth_compressing, Compression};
+use crate::encrypt::EncryptOptions;
+use crate::hash::{ContentFingerprint, Fingerprinter};
+use crate::{CryptoError, MasterKey};
+
+/// Smallest read from the input. The first read is also the sample for the compression
+/// heuristic.
+const MIN_READ_SIZE: usize = 1024 * 1024;
+
+/// Segments sealed per batch for each worker thread, so that threads finishing early have
+/// more work to pick up.
+#[cfg(feature = "parallel")]
+const SEGMENTS_PER_THREAD: usize = 4;
+
+/// What [`encrypt_and_fingerprint`] read and wrote.
+#[derive(Debug, Clone)]
+pub struct SealedFile {
+    /// Keyed fingerprint of the plaintext, as sent along with the upload.
+    pub fingerprint: ContentFingerprint,
+    /// Compression actually applied: `None` when the first read did not shrink.
+    pub compression: Compression,
+    pub plaintext_len: u64,
+    pub encrypted_len: u64,
+}
+
+/// Encrypt everything from `reader` into `writer` and fingerprint it, reading the input once.
+///
+/// Produces the same format as [`encrypt_with_options`](crate::encrypt::encrypt_with_options),
+/// except that the compression heuristic only samples the first 1 MiB. With the `parallel`
+/// feature, segments are sealed in batches across the rayon thread pool while the
+/// fingerprint of the same input is computed alongside. Crypto errors are returned as
+/// `io::ErrorKind::InvalidData` wrapping the [`CryptoError`].
+pub fn encrypt_and_fingerprint<R: Read, W: Write>(
+    master_key: &MasterKey,
+    options: &EncryptOptions,
+    mut reader: R,
+    mut writer: W,
+) -> io::Result<SealedFile> {
+    let batch_segments = batch_segments();
+    let read_size = MIN_READ_SIZE.max(batch_segments * DEFAULT_SEGMENT_SIZE as usize);
+    let mut input = vec![0u8; read_size];
+    let mut filled = read_full(&mut reader, &mut input)?;
+
+    let compression = match options.compression {
+        Compression::None => Compression::None,
+        algorithm if worth_compressing(&input[..filled]) => algorithm,
+        _ => Compression::None,
+    };
+    let options = EncryptOptions {
+        compression,
+        ..options.clone()
+    };
+    let mut encryptor = Encryptor::with_options(master_key, &options)
+        .map_err(to_io_error)?
+        .with_parallelism(batch_segments);
+    let mut fingerprinter = Fingerprinter::new(master_key);
+
+    let mut output = Vec::new();
+    let (mut plaintext_len, mut encrypted_len) = (0u64, 0u64);
+    while filled > 0 {
+        fingerprint_and_encrypt(
+            &mut fingerprinter,
+            &mut encryptor,
+            &input[..filled],
+            &mut output,
+        )
+        .map_err(to_io_error)?;
+        writer.write_all(&output)?;
+        plaintext_len += filled as u64;
+        encrypted_len += output.len() as u64;
+        output.clear();
+        filled = read_full(&mut reader, &mut input)?;
+    }
+    encryptor.finish(&mut output).map_err(to_io_error)?;
+    writer.write_all(&output)?;
+    writer.flush()?;
+    encrypted_len += output.len() as u64;
+
+    Ok(SealedFile {
+        fingerprint: fingerprinter.finish(),
+        compression,
+        plaintext_len,
+        encrypted_len,
+    })
+}
+
+#[cfg(feature = "parallel")]
+fn batch_segments() -> usize {
+    rayon::current_num_threads() * SEGMENTS_PER_THREAD
+}
+
+#[cfg(not(feature = "parallel"))]
+fn batch_segments() -> usize {
+    1
+}
+
+fn fingerprint_and_encrypt(
+    fingerprinter: &mut Fingerprinter,
+    encryptor: &mut Encryptor,
+    data: &[u8],
+    out: &mut Vec<u8>,
+) -> Result<(), CryptoError> {
+    #[cfg(feature = "parallel")]
+    {
+        rayon::join(
+            || fingerprinter.update(data),
+            || encryptor.update(data, out),
+        )
+        .1
+    }
+    #[cfg(not(feature = "parallel"))]
+    {
+        fingerprinter.update(data);
+        encryptor.update(data, out)
+    }
+}
+
+/// Fill `buf` from `reader` unless it ends first; returns the bytes read.
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decrypt::{decrypt_with_info, DecryptedFile};
+    use crate::hash::content_fingerprint;
+    use crate::keyring::Keyring;
+    use crate::{FileMetadata, Padding};
+
+    #[test]
+    fn test_matches_separate_passes() {
+        let key = MasterKey::from_bytes([9u8; 32]);
+        // Several read batches and a partial last segment.
+        let plaintext: Vec<u8> = (0..3 * MIN_READ_SIZE + 12_345)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        let options = EncryptOptions {
+            metadata: Some(FileMetadata {
+                name: Some("layers.clip".into()),
+                ..Default::default()
+            }),
+            padding: Padding::Padme,
+            ..Default::default()
+        };
+
+        let mut encrypted = Vec::new();
+        let sealed =
+            encrypt_and_fingerprint(&key, &options, plaintext.as_slice(), &mut encrypted).unwrap();
+        assert_eq!(sealed.fingerprint, content_fingerprint(&key, &plaintext));
+        assert_eq!(sealed.plaintext_len, plaintext.len() as u64);
+        assert_eq!(sealed.encrypted_len, encrypted.len() as u64);
+
+        let DecryptedFile {
+            plaintext: decrypted,
+            metadata,
+            ..
+        } = crate::decrypt::decrypt_file(&Keyring::from_key(&key), &encrypted).unwrap();
+        assert_eq!(decrypted, plaintext);
+        assert_eq!(metadata.name.as_deref(), Some("layers.clip"));
+    }
+
+    #[test]
+    fn test_compression_sampled_from_first_read() {
+        let key = MasterKey::from_bytes([9u8; 32]);
+        let options = EncryptOptions {
+            compression: Compression::Zstd,
+            ..Default::default()
+        };
+        let text = b"brush stroke 0.5 0.5\n".repeat(200_000);
+        let mut encrypted = Vec::new();
+        let sealed =
+            encrypt_and_fingerprint(&key, &options, text.as_slice(), &mut encrypted).unwrap();
+        assert_eq!(sealed.compression, Compression::Zstd);
+        assert!(encrypted.len() < text.len() / 10);
+        assert_eq!(decrypt_with_info(&key, &encrypted).unwrap().0, text);
+
+        let mut encrypted = Vec::new();
+        let sealed = encrypt_and_fingerprint(&key, &options, &b""[..], &mut encrypted).unwrap();
+        assert_eq!(sealed.compression, Compression::None);
+        assert_eq!(decrypt_with_info(&key, &encrypted).unwrap().0, b"");
+    }
+}
@@ -0,0 +1,226 @@
+//! Python bindings: `import solidrop` for uploading, listing and downloading from scripts
+//! (a Blender render hook, for example) without shelling out to the CLI.
+//!
+//! Uses the CLI's config file, API client and master key sources, so a script behaves like
+//! `solidrop` run by the same user. The key is acquired once per process, on the first call
+//! that needs it; set `SOLIDROP_MASTER_KEY` or `SOLIDROP_PASSWORD` where there is no
+//! terminal to prompt on.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use anyhow::{Context, Result};
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use solidrop_cli::api_client::{ApiClient, FileEntry};
+use solidrop_cli::commands::download::{fetch_decrypted, local_file_name};
+use solidrop_cli::commands::upload::{prepare, upload_unless_stored};
+use solidrop_cli::config::CliConfig;
+use solidrop_cli::master_key::{acquire_master_key, keyring};
+use solidrop_crypto::{FileMetadata, MasterKey};
+use tokio::runtime::Runtime;
+
+create_exception!(solidrop, SolidropError, PyException);
+
+/// Everything a call needs, loaded on first use and kept for the life of the process.
+struct Session {
+    config: CliConfig,
+    api: ApiClient,
+    key: OnceLock<MasterKey>,
+}
+
+impl Session {
+    fn key(&self) -> Result<&MasterKey> {
+        if let Some(key) = self.key.get() {
+            return Ok(key);
+        }
+        let key = acquire_master_key(&self.config.crypto)?;
+        Ok(self.key.get_or_init(|| key))
+    }
+}
+
+fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| Runtime::new().expect("failed to start the tokio runtime"))
+}
+
+fn session() -> Result<Arc<Session>> {
+    static SESSION: Mutex<Option<Arc<Session>>> = Mutex::new(None);
+    let mut session = SESSION
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(session) = &*session {
+        return Ok(Arc::clone(session));
+    }
+    let config = CliConfig::load()?;
+    let api = runtime().block_on(ApiClient::from_config(&config))?;
+    let opened = Arc::new(Session {
+        config,
+        api,
+        key: OnceLock::new(),
+    });
+    *session = Some(Arc::clone(&opened));
+    Ok(opened)
+}
+
+/// Run `call` with the GIL released, turning errors into `SolidropError` with the full
+/// cause chain.
+fn call<T: Send>(py: Python<'_>, call: impl FnOnce() -> Result<T> + Send) -> PyResult<T> {
+    py.detach(call)
+        .map_err(|err| SolidropError::new_err(format!("{err:#}")))
+}
+
+/// Where to write a download: into `dest` if it is a directory, else to `dest` itself.
+fn destination(dest: &Path, remote_path: &str, metadata: &FileMetadata) -> PathBuf {
+    if !dest.is_dir() {
+        return dest.to_path_buf();
+    }
+    let encrypted_name = remote_path.rsplit('/').next().unwrap_or(remote_path);
+    dest.join(local_file_name(encrypted_name, metadata))
+}
+
+/// A remote object, as returned by `list`.
+#[pyclass(frozen, get_all, module = "solidrop")]
+struct RemoteFile {
+    key: String,
+    size: i64,
+    last_modified: Option<String>,
+    content_hash: Option<String>,
+}
+
+#[pymethods]
+impl RemoteFile {
+    fn __repr__(&self) -> String {
+        format!("RemoteFile(key={:?}, size={})", self.key, self.size)
+    }
+}
+
+impl From<FileEntry> for RemoteFile {
+    fn from(entry: FileEntry) -> Self {
+        Self {
+            key: entry.key,
+            size: entry.size,
+            last_modified: entry.last_modified,
+            content_hash: entry.content_hash,
+        }
+    }
+}
+
+/// Encrypt the file at `path` and upload it under `remote_prefix` (`transfer/` by default,
+/// where `solidrop sync` picks it up), unless its content is already stored, as
+/// `solidrop upload` does; content stored at another path is copied there instead. Returns
+/// the remote path.
+#[pyfunction]
+#[pyo3(signature = (path, remote_prefix = "transfer/"))]
+fn upload(py: Python<'_>, path: PathBuf, remote_prefix: &str) -> PyResult<String> {
+    call(py, || {
+        let session = session()?;
+        let file_path = path.to_str().context("path is not valid UTF-8")?;
+        let mut prepared = prepare(
+            session.key()?,
+            &session.config.crypto.encrypt_options(),
+            file_path,
+            remote_prefix,
+            session.config.storage.opaque_keys,
+        )?;
+        runtime().block_on(upload_unless_stored(&session.api, &mut prepared))?;
+        Ok(prepared.remote_path)
+    })
+}
+
+/// Every remote file under `prefix` (all files if `None`).
+#[pyfunction]
+#[pyo3(signature = (prefix = None))]
+fn list(py: Python<'_>, prefix: Option<&str>) -> PyResult<Vec<RemoteFile>> {
+    call(py, || {
+        let session = session()?;
+        runtime().block_on(async {
+            let mut files = Vec::new();
+            let mut next_token: Option<String> = None;
+            loop {
+                let (page, token) = session
+                    .api
+                    .list_files(prefix, Some(100), next_token.as_deref())
+                    .await?;
+                files.extend(page.into_iter().map(RemoteFile::from));
+                next_token = token;
+                if next_token.is_none() {
+                    return Ok(files);
+                }
+            }
+        })
+    })
+}
+
+/// Download and decrypt `path` into `dest`: a directory (the file keeps its original name)
+/// or a file path. Returns the path written.
+#[pyfunction]
+fn download(py: Python<'_>, path: &str, dest: PathBuf) -> PyResult<PathBuf> {
+    call(py, || {
+        let session = session()?;
+        let keyring = keyring(&session.config.crypto, session.key()?)?;
+        let file = runtime().block_on(fetch_decrypted(&session.api, &keyring, path, None))?;
+
+        let output_path = destination(&dest, path, &file.metadata);
+        if let Some(parent) = output_path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("failed to create directory: {}", parent.display()))?;
+        }
+        std::fs::write(&output_path, &file.plaintext)
+            .with_context(|| format!("failed to write file: {}", output_path.display()))?;
+        Ok(output_path)
+    })
+}
+
+/// The `solidrop` module. Public so tests can build it in an embedded interpreter.
+#[pymodule]
+pub fn solidrop(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("SolidropError", m.py().get_type::<SolidropError>())?;
+    m.add_class::<RemoteFile>()?;
+    m.add_function(wrap_pyfunction!(upload, m)?)?;
+    m.add_function(wrap_pyfunction!(list, m)?)?;
+    m.add_function(wrap_pyfunction!(download, m)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_destination() {
+        let dir = tempfile::tempdir().unwrap();
+        let named = FileMetadata {
+            name: Some("render.png".into()),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            destination(dir.path(), "transfer/0123.enc", &named),
+            dir.path().join("render.png")
+        );
+        assert_eq!(
+            destination(
+                dir.path(),
+                "transfer/frame.exr.enc",
+                &FileMetadata::default()
+            ),
+            dir.path().join("frame.exr")
+        );
+        let file = dir.path().join("out.png");
+        assert_eq!(destination(&file, "transfer/0123.enc", &named), file);
+    }
+
+    #[test]
+    fn test_module_exports() {
+        Python::initialize();
+        Python::attach(|py| {
+            let module = PyModule::new(py, "solidrop").unwrap();
+            solidrop(&module).unwrap();
+            for name in ["upload", "list", "download", "RemoteFile", "SolidropError"] {
+                assert!(module.hasattr(name).unwrap(), "missing {name}");
+            }
+        });
+    }
+}
@@ -0,0 +1,79 @@
+//! The module as a script uses it, against the in-memory API server. A binary of its own:
+//! the session reads its config from `$HOME` and the key from the environment, which is set
+//! here before any other thread exists.
+
+#![cfg(unix)]
+
+use std::path::PathBuf;
+
+use pyo3::prelude::*;
+use pyo3::types::PyModule;
+
+#[test]
+fn test_upload_list_download() {
+    let home = tempfile::tempdir().unwrap();
+    std::env::set_var("HOME", home.path());
+    std::env::remove_var("XDG_CONFIG_HOME");
+    std::env::set_var("SOLIDROP_MASTER_KEY", "42".repeat(32));
+    std::env::set_var("SOLIDROP_PY_TEST_API_KEY", "py-test-key");
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let server = runtime.block_on(solidrop_api_server::testing::spawn_in_memory("py-test-key"));
+    let config_dir = solidrop_cli::config::config_dir().unwrap();
+    std::fs::create_dir_all(&config_dir).unwrap();
+    std::fs::write(
+        config_dir.join("config.toml"),
+        format!(
+            "[server]\nendpoint = \"{}/api/v1\"\napi_key_env = \"SOLIDROP_PY_TEST_API_KEY\"\n\n\
+             [storage]\ndownload_dir = \"{}\"\n\n\
+             [crypto]\nkeychain_service = \"solidrop\"\nkeychain_account = \"test\"\n",
+            server.base_url,
+            home.path().display()
+        ),
+    )
+    .unwrap();
+    let source = home.path().join("render.png");
+    std::fs::write(&source, b"frame 1").unwrap();
+
+    Python::initialize();
+    Python::attach(|py| {
+        let module = PyModule::new(py, "solidrop").unwrap();
+        solidrop::solidrop(&module).unwrap();
+        let upload = |prefix: &str| -> String {
+            module
+                .getattr("upload")
+                .unwrap()
+                .call1((&source, prefix))
+                .unwrap()
+                .extract()
+                .unwrap()
+        };
+
+        let active_path = upload("active/");
+        assert_eq!(active_path, "active/render.png.enc");
+        // Already stored under active/: copied to transfer/, where sync looks.
+        let remote_path = upload("transfer/");
+        assert_eq!(remote_path, "transfer/render.png.enc");
+
+        let files = module.getattr("list").unwrap().call0().unwrap();
+        let mut keys: Vec<String> = files
+            .try_iter()
+            .unwrap()
+            .map(|file| file.unwrap().getattr("key").unwrap().extract().unwrap())
+            .collect();
+        keys.sort_unstable();
+        assert_eq!(keys, [active_path, remote_path.clone()]);
+
+        let dest = home.path().join("out");
+        std::fs::create_dir(&dest).unwrap();
+        let written: PathBuf = module
+            .getattr("download")
+            .unwrap()
+            .call1((&remote_path, &dest))
+            .unwrap()
+            .extract()
+            .unwrap();
+        assert_eq!(written, dest.join("render.png"));
+        assert_eq!(std::fs::read(written).unwrap(), b"frame 1");
+    });
+}